@@ -0,0 +1,157 @@
+use crate::resource_ref::{ResourceKind, find_resource_refs, percent_decode};
+use std::path::Path;
+
+/// The type of the resource items in a RAW export, in their `type_:` line
+const RESOURCE_TYPE: &str = "4";
+
+/// The body with the images of resources that have no alt text, `![](../_resources/diagram.png)`,
+/// given their file name as alt text, `![diagram.png](../_resources/diagram.png)`, so Bear has
+/// something to show and search for them. `None` when there are none. The file name of a
+/// resource named by its Joplin id says nothing, its title is taken from its item in the RAW
+/// export `metadata_dir`, and it's left as it is without one. `<img>` tags are left as well.
+pub fn fill_alt_text(body: &str, metadata_dir: Option<&Path>) -> Option<String> {
+    let mut alt_texts: Vec<_> = find_resource_refs(body)
+        .into_iter()
+        .filter(|resource_ref| {
+            resource_ref.kind == ResourceKind::Image
+                && resource_ref.resource_path().is_some()
+                && body[resource_ref.link.start..].starts_with("![](")
+        })
+        .filter_map(|resource_ref| {
+            let file_name = percent_decode(resource_ref.file_name());
+            let alt_text = match joplin_id(&file_name) {
+                Some(id) => {
+                    let item = std::fs::read_to_string(metadata_dir?.join(format!("{id}.md")));
+                    resource_title(&item.ok()?)?
+                }
+                None => file_name,
+            };
+            Some((resource_ref.link.start, alt_text))
+        })
+        .collect();
+    if alt_texts.is_empty() {
+        return None;
+    }
+
+    let mut body = body.to_string();
+    alt_texts.sort_by_key(|(start, _)| std::cmp::Reverse(*start));
+    for (start, alt_text) in alt_texts {
+        let alt_text = alt_text.replace('[', "\\[").replace(']', "\\]");
+        body.insert_str(start + 2, &alt_text);
+    }
+
+    Some(body)
+}
+
+/// The title of a resource item of a RAW export: its first line, usually the name of the file
+/// it was made from, then the `filename:` of its metadata. `None` for the other items. The
+/// metadata is the `key: value` lines at the end of the item.
+fn resource_title(item: &str) -> Option<String> {
+    let metadata: Vec<(&str, &str)> = item
+        .lines()
+        .rev()
+        .skip_while(|line| line.is_empty())
+        .map_while(|line| {
+            line.split_once(": ")
+                .or(line.strip_suffix(':').map(|key| (key, "")))
+        })
+        .collect();
+    if !metadata.contains(&("type_", RESOURCE_TYPE)) {
+        return None;
+    }
+
+    let title = item.lines().next().unwrap_or_default().trim();
+    let file_name = metadata
+        .iter()
+        .find_map(|(key, value)| (*key == "filename").then_some(value.trim()))
+        .unwrap_or_default();
+    [title, file_name]
+        .into_iter()
+        .find(|name| !name.is_empty() && !name.contains(": "))
+        .map(str::to_string)
+}
+
+/// The Joplin resource id the file is named by, with an extension, which says nothing
+fn joplin_id(name: &str) -> Option<&str> {
+    let stem = name.split('.').next().unwrap_or(name);
+    (stem.len() == 32 && stem.bytes().all(|b| b.is_ascii_hexdigit())).then_some(stem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_alt_text() {
+        let test_cases = vec![
+            (
+                "![](../_resources/diagram.png) and ![](../_resources/My%20[1].png)",
+                Some(
+                    "![diagram.png](../_resources/diagram.png) and \
+                    ![My \\[1\\].png](../_resources/My%20[1].png)",
+                ),
+            ),
+            (
+                "![Kept](../_resources/a.png) ![](../_resources/0123456789abcdef0123456789abcdef.png)",
+                None,
+            ),
+            (
+                "[](../_resources/a.pdf) ![](https://example.com/a.png)",
+                None,
+            ),
+            ("`![](../_resources/a.png)`", None),
+        ];
+
+        for (body, expected) in test_cases {
+            assert_eq!(fill_alt_text(body, None).as_deref(), expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_fill_alt_text_metadata() {
+        // arrange
+        let metadata_dir = std::env::temp_dir().join("jb_alt_text_metadata_test");
+        std::fs::create_dir_all(&metadata_dir).unwrap();
+        let id = "0123456789abcdef0123456789abcdef";
+        std::fs::write(
+            metadata_dir.join(format!("{id}.md")),
+            "diagram.png\n\nid: 0123456789abcdef0123456789abcdef\nmime: image/png\n\
+            filename: \nfile_extension: png\ntype_: 4",
+        )
+        .unwrap();
+        let body = format!(
+            "![](../_resources/{id}.png) ![](../_resources/{}.png)",
+            "f".repeat(32)
+        );
+
+        // act
+        let filled = fill_alt_text(&body, Some(&metadata_dir));
+
+        // assert
+        std::fs::remove_dir_all(&metadata_dir).unwrap();
+        assert_eq!(
+            filled,
+            Some(format!(
+                "![diagram.png](../_resources/{id}.png) ![](../_resources/{}.png)",
+                "f".repeat(32)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_resource_title() {
+        let test_cases = vec![
+            (
+                "diagram.png\n\nid: 01\nfilename: \ntype_: 4",
+                Some("diagram.png"),
+            ),
+            ("\n\nid: 01\nfilename: scan.jpg\ntype_: 4", Some("scan.jpg")),
+            ("A note\n\nBody\n\nid: 01\ntype_: 1", None),
+            ("id: 01\nfilename:\ntype_: 4", None),
+        ];
+
+        for (item, expected) in test_cases {
+            assert_eq!(resource_title(item).as_deref(), expected, "{}", item);
+        }
+    }
+}
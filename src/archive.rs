@@ -0,0 +1,403 @@
+use crate::joplin_file_io::{build_content, natural_path_cmp, target_paths};
+use crate::{Cancellation, Config, JoplinFile};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const RESOURCES_DIR: &str = "_resources";
+
+/// Whether the source is a zip of a Joplin export rather than the export directory
+pub fn is_zip<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// A zip of a Joplin export, read entry by entry without extracting it.
+///
+/// The export may be at the root of the zip or inside a directory, the directory holding
+/// `_resources` is taken as the root of the export.
+pub struct ZipSource {
+    archive: ZipArchive<File>,
+    /// Paths relative to the root of the export -> index of the entry
+    entries: BTreeMap<PathBuf, usize>,
+}
+
+impl ZipSource {
+    pub fn open<P: AsRef<Path>>(zip_path: P) -> Result<ZipSource, String> {
+        let zip_path = zip_path.as_ref();
+        let file = File::open(zip_path)
+            .map_err(|e| format!("Error opening zip file {:?}: {}", zip_path, e))?;
+        let mut archive = ZipArchive::new(file)
+            .map_err(|e| format!("Error reading zip file {:?}: {}", zip_path, e))?;
+
+        let mut files = Vec::new();
+        for index in 0..archive.len() {
+            let entry = archive
+                .by_index(index)
+                .map_err(|e| format!("Error reading zip file {:?}: {}", zip_path, e))?;
+            // Entries that would end up outside the export when extracted are left out
+            if let Some(path) = entry.enclosed_name().filter(|_| entry.is_file()) {
+                files.push((path, index));
+            }
+        }
+
+        let root = files
+            .iter()
+            .find_map(|(path, _)| {
+                let position = path
+                    .components()
+                    .position(|component| component.as_os_str() == RESOURCES_DIR)?;
+                Some(path.components().take(position).collect::<PathBuf>())
+            })
+            .unwrap_or_default();
+
+        let entries = files
+            .into_iter()
+            .filter_map(|(path, index)| {
+                let relative_path = path.strip_prefix(&root).ok()?.to_path_buf();
+                Some((relative_path, index))
+            })
+            .collect();
+
+        Ok(ZipSource { archive, entries })
+    }
+
+    /// The relative paths of the notes, in the same order `find_files` gives for a directory
+    pub fn note_paths(&self, config: &Config) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .entries
+            .keys()
+            .filter(|path| config.is_note(path))
+            .cloned()
+            .collect();
+        paths.sort_by(|a, b| natural_path_cmp(a, b));
+        paths
+    }
+
+    pub fn read<P: AsRef<Path>>(&mut self, relative_path: P) -> Result<Vec<u8>, String> {
+        let relative_path = relative_path.as_ref();
+        let &index = self
+            .entries
+            .get(relative_path)
+            .ok_or_else(|| format!("The zip has no entry {:?}", relative_path))?;
+
+        // Sized up front, growing it while reading would need up to twice the memory of the entry
+        let mut bytes = Vec::new();
+        self.archive
+            .by_index(index)
+            .and_then(|mut entry| {
+                bytes.reserve_exact(entry.size() as usize);
+                Ok(entry.read_to_end(&mut bytes)?)
+            })
+            .map_err(|e| format!("Error reading {:?} from the zip: {}", relative_path, e))?;
+
+        Ok(bytes)
+    }
+
+    /// The uncompressed size of an entry, without reading it
+    pub fn size<P: AsRef<Path>>(&mut self, relative_path: P) -> Result<u64, String> {
+        let relative_path = relative_path.as_ref();
+        let &index = self
+            .entries
+            .get(relative_path)
+            .ok_or_else(|| format!("The zip has no entry {:?}", relative_path))?;
+
+        self.archive
+            .by_index_raw(index)
+            .map(|entry| entry.size())
+            .map_err(|e| format!("Error reading {:?} from the zip: {}", relative_path, e))
+    }
+
+    /// Whether the zip has a resource, given its path relative to the resources directory
+    pub fn has_resource<P: AsRef<Path>>(&self, resource_path: P) -> bool {
+        self.entries
+            .contains_key(&Path::new(RESOURCES_DIR).join(resource_path))
+    }
+
+    /// The paths of the resources, relative to the resources directory
+    pub fn resource_paths(&self) -> Vec<PathBuf> {
+        self.resource_entries()
+            .into_iter()
+            .filter_map(|(path, _)| Some(path.strip_prefix(RESOURCES_DIR).ok()?.to_path_buf()))
+            .collect()
+    }
+
+    fn resource_entries(&self) -> Vec<(PathBuf, usize)> {
+        self.entries
+            .iter()
+            .filter(|(path, _)| path.starts_with(RESOURCES_DIR))
+            .map(|(path, &index)| (path.clone(), index))
+            .collect()
+    }
+
+    /// Extracts the resources into the `_resources` directory of the target
+    pub fn copy_resources<P: AsRef<Path>>(
+        &mut self,
+        target_dir: P,
+        cancellation: &Cancellation,
+    ) -> Result<(), String> {
+        let entries = self.resource_entries();
+        let total = entries.len();
+        for (done, (relative_path, index)) in entries.into_iter().enumerate() {
+            cancellation.check(done, total, "resources copied")?;
+            let target = target_dir.as_ref().join(&relative_path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Error creating directory {:?}: {}", parent, e))?;
+            }
+
+            // A link left by an earlier run linking the resources would be written through
+            if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_symlink()) {
+                fs::remove_file(&target)
+                    .map_err(|e| format!("Error removing link {:?}: {}", target, e))?;
+            }
+            let mut file = File::create(&target)
+                .map_err(|e| format!("Error creating file {:?}: {}", target, e))?;
+            self.archive
+                .by_index(index)
+                .and_then(|mut entry| Ok(std::io::copy(&mut entry, &mut file)?))
+                .map_err(|e| format!("Error extracting {:?}: {}", relative_path, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the resources into another zip as they are, without decompressing them
+    fn copy_resources_to_zip<W: Write + Seek>(
+        &mut self,
+        zip: &mut ZipWriter<W>,
+    ) -> Result<(), String> {
+        for (relative_path, index) in self.resource_entries() {
+            self.archive
+                .by_index_raw(index)
+                .and_then(|entry| zip.raw_copy_file_rename(entry, entry_name(&relative_path)))
+                .map_err(|e| format!("Error adding {:?} to the zip: {}", relative_path, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the notes and the resources of the source into a zip file instead of a directory, laid
+/// out the same way. Entries are written to the file as they are added, not kept in memory.
+pub fn write_zip<P: AsRef<Path>, Q: AsRef<Path>>(
+    zip_path: P,
+    source_dir: Q,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<(), String> {
+    let zip_path = zip_path.as_ref();
+    let file = File::create(zip_path)
+        .map_err(|e| format!("Error creating zip file {:?}: {}", zip_path, e))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+
+    // Entry paths are relative, the length only matters once the zip is extracted somewhere
+    let relative_paths = target_paths(joplin_files, 0, config)?;
+    for (joplin_file, relative_path) in joplin_files.iter().zip(relative_paths) {
+        let options =
+            SimpleFileOptions::default().last_modified_time(zip_time(joplin_file.updated));
+        zip.start_file(entry_name(&relative_path), options)
+            .map_err(|e| format!("Error adding {:?} to the zip: {}", relative_path, e))?;
+        zip.write_all(build_content(joplin_file, config).as_bytes())
+            .map_err(|e| format!("Error writing {:?} to the zip: {}", relative_path, e))?;
+    }
+
+    if is_zip(&source_dir) {
+        ZipSource::open(&source_dir)?.copy_resources_to_zip(&mut zip)?;
+        return finish(zip);
+    }
+
+    let source_resources_dir = source_dir.as_ref().join(RESOURCES_DIR);
+    // `check_resources_dir` tells about a missing resources directory
+    if !source_resources_dir.exists() {
+        return finish(zip);
+    }
+    if !source_resources_dir.is_dir() {
+        return Err(format!(
+            "The source path: {:?} is not a directory",
+            source_resources_dir
+        ));
+    }
+    add_dir(&mut zip, &source_resources_dir, Path::new(RESOURCES_DIR))
+        .map_err(|e| format!("Error adding resources to the zip: {}", e))?;
+
+    finish(zip)
+}
+
+fn finish<W: Write + Seek>(zip: ZipWriter<W>) -> Result<(), String> {
+    zip.finish()
+        .map_err(|e| format!("Error finishing zip file: {}", e))?
+        .flush()
+        .map_err(|e| format!("Error writing zip file: {}", e))
+}
+
+fn add_dir<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    dir: &Path,
+    entry_dir: &Path,
+) -> std::io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let entry_path = entry_dir.join(entry.file_name());
+        if path.is_dir() {
+            add_dir(zip, &path, &entry_path)?;
+        } else {
+            zip.start_file(entry_name(&entry_path), SimpleFileOptions::default())?;
+            std::io::copy(&mut File::open(&path)?, zip)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Zip entry names always use `/`, whatever the separator of the system
+fn entry_name(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Zip times have no time zone and can only hold 1980 to 2107, times outside that become 1980
+fn zip_time(time: DateTime<Utc>) -> zip::DateTime {
+    u16::try_from(time.year())
+        .ok()
+        .and_then(|year| {
+            zip::DateTime::from_date_and_time(
+                year,
+                time.month() as u8,
+                time.day() as u8,
+                time.hour() as u8,
+                time.minute() as u8,
+                time.second() as u8,
+            )
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_name() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("note.md", "note.md"),
+            ("a/b/note.md", "a/b/note.md"),
+            ("./a/note.md", "a/note.md"),
+        ];
+
+        for (relative_path, expected) in test_cases {
+            assert_eq!(entry_name(Path::new(relative_path)), expected);
+        }
+    }
+
+    #[test]
+    fn test_write_zip() {
+        // arrange
+        let temp_dir = std::env::temp_dir().join("jb_archive_test");
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir).unwrap();
+        }
+        let source_dir = temp_dir.join("source");
+        fs::create_dir_all(source_dir.join(RESOURCES_DIR)).unwrap();
+        fs::write(source_dir.join(RESOURCES_DIR).join("a.png"), "png").unwrap();
+
+        let joplin_file = JoplinFile::build(
+            "Work/note.md",
+            "---\ntitle: Note\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\nBody",
+            &Config::default(),
+        )
+        .unwrap();
+        let zip_path = temp_dir.join("notes.zip");
+
+        // act
+        let result = write_zip(&zip_path, &source_dir, &[joplin_file], &Config::default());
+
+        // assert
+        result.unwrap();
+        let mut archive = ZipArchive::new(File::open(&zip_path).unwrap()).unwrap();
+        let mut names: Vec<String> = archive
+            .file_names()
+            .map(|name| name.unwrap().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["Work/note.md", "_resources/a.png"]);
+
+        let mut content = String::new();
+        let mut note = archive.by_name("Work/note.md").unwrap();
+        note.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "Body\n\n#Work\n");
+        assert_eq!(
+            note.last_modified()
+                .map(|time| (time.year(), time.month(), time.day())),
+            Some((2024, 4, 7))
+        );
+        drop(note);
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_zip_source() {
+        // arrange
+        let temp_dir = std::env::temp_dir().join("jb_zip_source_test");
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir).unwrap();
+        }
+        fs::create_dir_all(&temp_dir).unwrap();
+        let zip_path = temp_dir.join("export.zip");
+
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        for (name, content) in [
+            ("export/b.md", "b"),
+            ("export/Work/a.md", "a"),
+            ("export/Work/a.png", "not a note"),
+            ("export/_resources/a.png", "png"),
+            ("../outside.md", "outside"),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        // act
+        let mut source = ZipSource::open(&zip_path).unwrap();
+
+        // assert
+        assert_eq!(
+            source.note_paths(&Config::default()),
+            vec![PathBuf::from("b.md"), PathBuf::from("Work/a.md")]
+        );
+        assert_eq!(source.read("Work/a.md").unwrap(), b"a");
+        assert_eq!(source.size("_resources/a.png").unwrap(), 3);
+        assert!(source.has_resource("a.png"));
+        assert!(!source.has_resource("b.png"));
+
+        let target_dir = temp_dir.join("target");
+        source
+            .copy_resources(&target_dir, &Cancellation::default())
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(target_dir.join(RESOURCES_DIR).join("a.png")).unwrap(),
+            "png"
+        );
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
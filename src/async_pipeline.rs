@@ -0,0 +1,283 @@
+//! The conversion as async tasks, for hosts that run on tokio and shouldn't block a thread for
+//! the whole conversion. Discovery, parsing and writing run as separate tasks connected by
+//! channels; the parts that are blocking by nature (walking the directory, reading a zip,
+//! running the hooks) run on the blocking pool.
+
+use crate::config::ExistingPolicy;
+use crate::joplin_file_io::{
+    Hooks, NoteSource, build_content, build_note, copy_resources, file_times, find_notes,
+    long_path, read_note, resolve_copies, target_paths,
+};
+use crate::manifest::Snapshot;
+use crate::state::State;
+use crate::{Config, JoplinFile};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::{JoinError, spawn_blocking};
+
+/// How many paths or notes may wait between two stages
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Converts the export into Bear notes in the target directory, like the `jb` command does
+/// without an output format, and saves the manifest of what it created. There's no journal, so
+/// an interrupted conversion can't be resumed
+pub async fn convert<P: AsRef<Path>>(
+    source_dir: P,
+    target_dir: P,
+    config: &Config,
+) -> Result<Vec<JoplinFile>, String> {
+    if !config.resource_volumes.is_empty() {
+        return Err("Resource volumes can't be used with the async pipeline".to_string());
+    }
+    if config.existing_policy != ExistingPolicy::Overwrite {
+        return Err("The async pipeline always overwrites existing notes".to_string());
+    }
+    if config.resume {
+        return Err("The async pipeline can't resume a conversion".to_string());
+    }
+
+    let snapshot_target = target_dir.as_ref().to_path_buf();
+    let snapshot = spawn_blocking(move || Snapshot::take(&snapshot_target))
+        .await
+        .map_err(task_error)??;
+
+    let joplin_files = build_joplin_files(&source_dir, config).await?;
+
+    let (source_dir, resources_target, resources_config) = (
+        source_dir.as_ref().to_path_buf(),
+        target_dir.as_ref().to_path_buf(),
+        config.clone(),
+    );
+    // The resources that fail to copy are recorded rather than stopping the copy
+    let io_errors = config.io_errors.len();
+    spawn_blocking(move || copy_resources(&source_dir, &resources_target, &resources_config))
+        .await
+        .map_err(task_error)??;
+    config.io_errors.check_since(io_errors)?;
+
+    let joplin_files = write_joplin_files(&target_dir, joplin_files, config).await?;
+
+    let target_dir = target_dir.as_ref().to_path_buf();
+    spawn_blocking(move || {
+        State::build(&joplin_files, chrono::Utc::now()).save(&target_dir)?;
+        snapshot.manifest()?.save()?;
+        Ok(joplin_files)
+    })
+    .await
+    .map_err(task_error)?
+}
+
+/// The async variant of `joplin_file_io::build_joplin_files`
+pub async fn build_joplin_files<P: AsRef<Path>>(
+    source_dir: P,
+    config: &Config,
+) -> Result<Vec<JoplinFile>, String> {
+    let (paths_tx, mut paths_rx) = mpsc::channel::<PathBuf>(CHANNEL_CAPACITY);
+    let (notes_tx, mut notes_rx) = mpsc::channel::<JoplinFile>(CHANNEL_CAPACITY);
+
+    let discovery = {
+        let (source_dir, config) = (source_dir.as_ref().to_path_buf(), config.clone());
+        spawn_blocking(move || {
+            let (_, relative_paths) = find_notes(&source_dir, &config)?;
+            for relative_path in relative_paths {
+                // The parser only stops early when it failed, its error is the one to report
+                if paths_tx.blocking_send(relative_path).is_err() {
+                    break;
+                }
+            }
+            Ok::<_, String>(())
+        })
+    };
+
+    // The hooks and a zip source can't move between threads, so the parser opens its own
+    let parsing = {
+        let (source_dir, config) = (source_dir.as_ref().to_path_buf(), config.clone());
+        spawn_blocking(move || {
+            let mut source = NoteSource::open(&source_dir)?;
+            let hooks = Hooks::load(&config)?;
+            let mut done = 0;
+            while let Some(relative_path) = paths_rx.blocking_recv() {
+                // The total isn't known while discovery is still going
+                config
+                    .cancellation
+                    .check(done, done + paths_rx.len() + 1, "notes read")?;
+                done += 1;
+                let Some(bytes) = read_note(&mut source, &relative_path, &config) else {
+                    continue;
+                };
+                if let Some(joplin_file) =
+                    build_note(&relative_path, bytes, &source, &hooks, &config)?
+                    && notes_tx.blocking_send(joplin_file).is_err()
+                {
+                    break;
+                }
+            }
+            Ok::<_, String>(())
+        })
+    };
+
+    let mut joplin_files = Vec::new();
+    while let Some(joplin_file) = notes_rx.recv().await {
+        joplin_files.push(joplin_file);
+    }
+    discovery.await.map_err(task_error)??;
+    parsing.await.map_err(task_error)??;
+
+    resolve_copies(&mut joplin_files, config);
+    Ok(joplin_files)
+}
+
+/// The async variant of `joplin_file_io::write_joplin_files`. Takes the notes and gives them
+/// back, so they can move to the writing task.
+pub async fn write_joplin_files<P: AsRef<Path>>(
+    target_dir: P,
+    joplin_files: Vec<JoplinFile>,
+    config: &Config,
+) -> Result<Vec<JoplinFile>, String> {
+    let target_dir = target_dir.as_ref();
+    let base_len = target_dir.to_string_lossy().chars().count() + 1;
+    let relative_paths = target_paths(&joplin_files, base_len, config)?;
+
+    let (files_tx, mut files_rx) =
+        mpsc::channel::<(PathBuf, String, std::fs::FileTimes)>(CHANNEL_CAPACITY);
+    let writing = tokio::spawn(async move {
+        while let Some((target_path, content, times)) = files_rx.recv().await {
+            write_note(&target_path, &content, times).await?;
+        }
+        Ok::<_, String>(())
+    });
+
+    for (done, (joplin_file, relative_path)) in joplin_files.iter().zip(relative_paths).enumerate()
+    {
+        config
+            .cancellation
+            .check(done, joplin_files.len(), "notes written")?;
+        let file = (
+            long_path(&target_dir.join(relative_path)),
+            build_content(joplin_file, config),
+            file_times(joplin_file),
+        );
+        if files_tx.send(file).await.is_err() {
+            break;
+        }
+    }
+    drop(files_tx);
+
+    writing.await.map_err(task_error)??;
+    Ok(joplin_files)
+}
+
+async fn write_note(
+    target_path: &Path,
+    content: &str,
+    times: std::fs::FileTimes,
+) -> Result<(), String> {
+    if let Some(parent) = target_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Error creating directory {:?}: {}", parent, e))?;
+    }
+
+    let mut file = tokio::fs::File::create(target_path)
+        .await
+        .map_err(|e| format!("Error creating file {:?}: {}", target_path, e))?;
+    file.write_all(content.as_bytes())
+        .await
+        .map_err(|e| format!("Error writing file: {}", e))?;
+    file.flush()
+        .await
+        .map_err(|e| format!("Error writing file: {}", e))?;
+
+    let file = file.into_std().await;
+    spawn_blocking(move || file.set_times(times))
+        .await
+        .map_err(task_error)?
+        .map_err(|e| format!("Error setting file times: {}", e))
+}
+
+fn task_error(e: JoinError) -> String {
+    format!("Error in a conversion task: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_convert() {
+        // arrange
+        let temp_dir = std::env::temp_dir().join("jb_async_pipeline_test");
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir).unwrap();
+        }
+        let source_dir = temp_dir.join("source");
+        let target_dir = temp_dir.join("target");
+        fs::create_dir_all(source_dir.join("Work")).unwrap();
+        fs::create_dir_all(source_dir.join("_resources")).unwrap();
+        fs::write(source_dir.join("_resources/a.png"), b"png").unwrap();
+        for (relative_path, title) in [("Work/a.md", "A"), ("b.md", "B")] {
+            fs::write(
+                source_dir.join(relative_path),
+                format!(
+                    "---\ntitle: {title}\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+                    ![](../_resources/a.png)"
+                ),
+            )
+            .unwrap();
+        }
+        let config = Config::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        // act
+        let result = runtime.block_on(convert(&source_dir, &target_dir, &config));
+
+        // assert
+        let joplin_files = result.unwrap();
+        let titles: Vec<&str> = joplin_files
+            .iter()
+            .map(|file| file.title.as_str())
+            .collect();
+        let note = fs::read_to_string(target_dir.join("Work/a.md")).unwrap();
+        let resource = fs::read(target_dir.join("_resources/a.png")).unwrap();
+        let state = State::load(&target_dir).unwrap();
+        let manifest =
+            crate::manifest::Manifest::load(target_dir.join(crate::manifest::MANIFEST_FILE_NAME))
+                .unwrap();
+        fs::remove_dir_all(&temp_dir).unwrap();
+        assert_eq!(titles, vec!["B", "A"]);
+        assert_eq!(note, "![](../_resources/a.png)\n\n#Work\n");
+        assert_eq!(resource, b"png");
+        assert!(state.is_some());
+        assert!(manifest.created.contains(&PathBuf::from("Work/a.md")));
+        assert!(
+            manifest
+                .created
+                .contains(&PathBuf::from("_resources/a.png"))
+        );
+    }
+
+    #[test]
+    fn test_convert_resume() {
+        // arrange
+        let config = Config {
+            resume: true,
+            ..Config::default()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        // act
+        let result = runtime.block_on(convert("source", "target", &config));
+
+        // assert
+        assert_eq!(
+            result.map(|_| ()),
+            Err("The async pipeline can't resume a conversion".to_string())
+        );
+    }
+}
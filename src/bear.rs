@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+
+/// Command line helper that calls an x-callback-url and prints the x-success parameters as JSON.
+/// See https://github.com/martinfinke/xcall
+const XCALL: &str = "xcall";
+
+#[derive(Debug, PartialEq)]
+pub struct BearNote {
+    pub identifier: String,
+    pub title: String,
+    pub text: String,
+    pub tags: Vec<String>,
+    pub created: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Imports a markdown file into Bear by opening it with the app in the background
+pub fn import_file<P: AsRef<Path>>(path: P) -> Result<(), String> {
+    let status = Command::new("open")
+        .args(["-g", "-a", "Bear"])
+        .arg(path.as_ref())
+        .status()
+        .map_err(|e| format!("Error running open: {}", e))?;
+
+    if !status.success() {
+        return Err(format!("Bear could not open {:?}", path.as_ref()));
+    }
+
+    Ok(())
+}
+
+/// Reads a note back from Bear by its title
+pub fn open_note(title: &str) -> Result<BearNote, String> {
+    let url = format!(
+        "bear://x-callback-url/open-note?title={}&show_window=no&open_note=no",
+        percent_encode(title)
+    );
+
+    let output = Command::new(XCALL)
+        .args(["-url", &url])
+        .output()
+        .map_err(|e| format!("Error running {XCALL}: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Bear could not find the note: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_note(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_note(json: &str) -> Result<BearNote, String> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| format!("Error parsing Bear response: {}", e))?;
+
+    let string = |key: &str| value[key].as_str().unwrap_or_default().to_string();
+    let date = |key: &str| {
+        value[key]
+            .as_str()
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+            .map(|date| date.to_utc())
+    };
+
+    // Bear returns the tags as a JSON encoded array inside a string
+    let tags = match &value["tags"] {
+        Value::String(tags) => serde_json::from_str(tags).unwrap_or_default(),
+        Value::Array(tags) => tags
+            .iter()
+            .filter_map(|tag| tag.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(BearNote {
+        identifier: string("identifier"),
+        title: string("title"),
+        text: string("note"),
+        tags,
+        created: date("creationDate"),
+        modified: date("modificationDate"),
+    })
+}
+
+pub(crate) fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_encode() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("abc", "abc"),
+            ("a b", "a%20b"),
+            ("a&b=c", "a%26b%3Dc"),
+            ("å", "%C3%A5"),
+        ];
+
+        for (value, expected) in test_cases {
+            assert_eq!(percent_encode(value), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_note() {
+        let json = r#"{
+            "identifier": "ABC",
+            "title": "Test",
+            "note": "The content",
+            "tags": "[\"foo/bar\"]",
+            "creationDate": "2024-03-07T23:22:26Z",
+            "modificationDate": "2024-04-07T08:34:52Z"
+        }"#;
+
+        let note = parse_note(json).unwrap();
+
+        assert_eq!(
+            note,
+            BearNote {
+                identifier: "ABC".to_string(),
+                title: "Test".to_string(),
+                text: "The content".to_string(),
+                tags: vec!["foo/bar".to_string()],
+                created: Some(
+                    DateTime::parse_from_rfc3339("2024-03-07T23:22:26Z")
+                        .unwrap()
+                        .to_utc()
+                ),
+                modified: Some(
+                    DateTime::parse_from_rfc3339("2024-04-07T08:34:52Z")
+                        .unwrap()
+                        .to_utc()
+                ),
+            }
+        );
+    }
+}
@@ -0,0 +1,491 @@
+use crate::bear::percent_encode;
+use crate::day_one::md5_hex;
+use crate::joplin_file_io::{NoteSource, build_content};
+use crate::resource_ref::{ResourceKind, ResourceRef, percent_decode};
+use crate::{Config, JoplinFile};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Seconds between the Unix epoch and 2001-01-01, the epoch of Core Data timestamps
+const CORE_DATA_EPOCH: f64 = 978_307_200.0;
+
+/// Where Bear keeps the attachments, relative to the directory of its database
+const IMAGES_DIR: &str = "Local Files/Note Images";
+const FILES_DIR: &str = "Local Files/Note Files";
+
+/// The Core Data entity of a table, and the highest primary key in use
+struct Entity {
+    id: i64,
+    max: i64,
+}
+
+/// Inserts the notes, their tags and their attachments straight into Bear's database, keeping
+/// the created and updated dates exactly. Bear has to be closed. The database is copied next to
+/// itself first, and the path of the copy is returned.
+///
+/// Notes already in the database, by the identifier derived from their id or path, are skipped so
+/// an interrupted run can be repeated.
+pub fn write_bear_db<P: AsRef<Path>>(
+    source_dir: P,
+    db_path: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<PathBuf, String> {
+    let db_path = db_path.as_ref();
+    let backup_path = backup(db_path)?;
+
+    let mut source = NoteSource::open(&source_dir)?;
+    let mut connection = Connection::open(db_path)
+        .map_err(|e| format!("Error opening Bear database {:?}: {}", db_path, e))?;
+    let transaction = connection
+        .transaction()
+        .map_err(|e| format!("Error starting transaction: {}", e))?;
+    let db_dir = db_path.parent().unwrap_or(Path::new("."));
+
+    let mut notes = entity(&transaction, "SFNote")?;
+    let mut tags = entity(&transaction, "SFNoteTag")?;
+    let mut files = entity(&transaction, "SFNoteFile")?;
+    let (tags_table, notes_column, tags_column) = note_tags_table(&transaction)?;
+    let mut tag_ids: HashMap<String, i64> = HashMap::new();
+
+    for joplin_file in joplin_files {
+        let identifier = bear_uuid(
+            joplin_file
+                .id
+                .as_deref()
+                .unwrap_or(&joplin_file.relative_path.to_string_lossy()),
+        );
+        if exists(&transaction, "ZSFNOTE", &identifier)? {
+            crate::notice!(
+                "Skipped {:?}, it is already in the Bear database",
+                joplin_file.relative_path
+            );
+            continue;
+        }
+
+        notes.max += 1;
+        let note_id = notes.max;
+
+        // Bear links attachments by file name, the files themselves go in a directory per file
+        let mut note = joplin_file.clone();
+        let mut refs = joplin_file.referenced_resources().to_vec();
+        refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+        for resource_ref in refs {
+            let Some(resource_path) = resource_ref.resource_path() else {
+                continue;
+            };
+            let Ok(bytes) = source.read_resource(Path::new(&percent_decode(resource_path))) else {
+                continue;
+            };
+
+            let file_name = percent_decode(resource_ref.file_name());
+            let file_identifier = bear_uuid(&format!("{identifier}\n{resource_path}"));
+            let text = link_text(&joplin_file.body, &resource_ref);
+            let (dir, link) = match resource_ref.kind {
+                ResourceKind::Image => (
+                    IMAGES_DIR,
+                    format!("![{}]({})", text.unwrap_or(""), percent_encode(&file_name)),
+                ),
+                ResourceKind::Link => (
+                    FILES_DIR,
+                    format!(
+                        "[{}]({})",
+                        text.filter(|text| !text.is_empty()).unwrap_or(&file_name),
+                        percent_encode(&file_name)
+                    ),
+                ),
+            };
+
+            let file_dir = db_dir.join(dir).join(&file_identifier);
+            fs::create_dir_all(&file_dir)
+                .and_then(|_| fs::write(file_dir.join(&file_name), &bytes))
+                .map_err(|e| format!("Error writing attachment {:?}: {}", file_name, e))?;
+
+            files.max += 1;
+            transaction
+                .execute(
+                    "INSERT INTO ZSFNOTEFILE (Z_PK, Z_ENT, Z_OPT, ZNOTE, ZFILENAME, \
+                    ZUNIQUEIDENTIFIER, ZFILESIZE, ZCREATIONDATE, ZMODIFICATIONDATE) \
+                    VALUES (?1, ?2, 1, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        files.max,
+                        files.id,
+                        note_id,
+                        file_name,
+                        file_identifier,
+                        bytes.len() as i64,
+                        core_data_date(joplin_file.created),
+                        core_data_date(joplin_file.updated),
+                    ],
+                )
+                .map_err(|e| format!("Error inserting attachment {:?}: {}", file_name, e))?;
+
+            note.body.replace_range(resource_ref.link, &link);
+        }
+
+        transaction
+            .execute(
+                "INSERT INTO ZSFNOTE (Z_PK, Z_ENT, Z_OPT, ZTITLE, ZTEXT, ZUNIQUEIDENTIFIER, \
+                ZCREATIONDATE, ZMODIFICATIONDATE, ZTRASHED, ZARCHIVED, ZPINNED, ZENCRYPTED) \
+                VALUES (?1, ?2, 1, ?3, ?4, ?5, ?6, ?7, 0, 0, 0, 0)",
+                params![
+                    note_id,
+                    notes.id,
+                    joplin_file.title,
+                    with_title(&build_content(&note, config), &joplin_file.title),
+                    identifier,
+                    core_data_date(joplin_file.created),
+                    core_data_date(joplin_file.updated),
+                ],
+            )
+            .map_err(|e| format!("Error inserting note {:?}: {}", joplin_file.title, e))?;
+
+        for tag in joplin_file
+            .tags
+            .iter()
+            .flat_map(|tags| tags.split_whitespace())
+            .flat_map(|tag| tag_with_ancestors(tag.trim_start_matches('#')))
+        {
+            let tag_id = match tag_ids.get(&tag) {
+                Some(tag_id) => *tag_id,
+                None => {
+                    let tag_id = find_or_insert_tag(&transaction, &mut tags, &tag, joplin_file)?;
+                    tag_ids.insert(tag.clone(), tag_id);
+                    tag_id
+                }
+            };
+            transaction
+                .execute(
+                    &format!(
+                        "INSERT OR IGNORE INTO {tags_table} ({notes_column}, {tags_column}) \
+                        VALUES (?1, ?2)"
+                    ),
+                    params![note_id, tag_id],
+                )
+                .map_err(|e| format!("Error tagging note {:?}: {}", joplin_file.title, e))?;
+        }
+    }
+
+    for (name, entity) in [
+        ("SFNote", &notes),
+        ("SFNoteTag", &tags),
+        ("SFNoteFile", &files),
+    ] {
+        transaction
+            .execute(
+                "UPDATE Z_PRIMARYKEY SET Z_MAX = ?1 WHERE Z_NAME = ?2",
+                params![entity.max, name],
+            )
+            .map_err(|e| format!("Error updating the primary keys: {}", e))?;
+    }
+
+    transaction
+        .commit()
+        .map_err(|e| format!("Error writing Bear database: {}", e))?;
+
+    Ok(backup_path)
+}
+
+/// The text of a markdown link or the alt text of a markdown image, `None` for an `<img>` tag
+fn link_text<'a>(body: &'a str, resource_ref: &ResourceRef) -> Option<&'a str> {
+    let before_target = &body[resource_ref.link.start..resource_ref.span.start];
+    let text = before_target.strip_prefix('!').unwrap_or(before_target);
+    let text = text.strip_prefix('[')?;
+    text.rfind("](").map(|end| &text[..end])
+}
+
+/// Copies the database next to it, with its write-ahead log and the index of the log when there
+/// are, so restoring them together gives back the database as it was
+fn backup(db_path: &Path) -> Result<PathBuf, String> {
+    if !db_path.is_file() {
+        return Err(format!("Could not find Bear database {:?}", db_path));
+    }
+
+    let suffix = format!("jb-backup-{}", Utc::now().format("%Y%m%d%H%M%S"));
+    let backup_path = db_path.with_extension(format!("sqlite.{suffix}"));
+    fs::copy(db_path, &backup_path)
+        .map_err(|e| format!("Error backing up Bear database: {}", e))?;
+
+    for extension in ["sqlite-wal", "sqlite-shm"] {
+        let path = db_path.with_extension(extension);
+        if path.is_file() {
+            fs::copy(
+                &path,
+                db_path.with_extension(format!("{extension}.{suffix}")),
+            )
+            .map_err(|e| format!("Error backing up Bear database: {}", e))?;
+        }
+    }
+
+    Ok(backup_path)
+}
+
+fn entity(transaction: &Transaction, name: &str) -> Result<Entity, String> {
+    transaction
+        .query_row(
+            "SELECT Z_ENT, Z_MAX FROM Z_PRIMARYKEY WHERE Z_NAME = ?1",
+            [name],
+            |row| {
+                Ok(Entity {
+                    id: row.get(0)?,
+                    max: row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+                })
+            },
+        )
+        .map_err(|e| format!("Error finding {} in the Bear database: {}", name, e))
+}
+
+/// The table joining notes and tags, and its two columns. Core Data numbers them after the
+/// entities, so the names differ between Bear versions.
+fn note_tags_table(transaction: &Transaction) -> Result<(String, String, String), String> {
+    let table: String = transaction
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'Z\\_%TAGS' \
+            ESCAPE '\\' ORDER BY name LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Error finding the note tags table: {}", e))?;
+
+    let mut statement = transaction
+        .prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))
+        .map_err(|e| format!("Error reading the note tags table: {}", e))?;
+    let columns: Vec<String> = statement
+        .query_map([], |row| row.get(0))
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Error reading the note tags table: {}", e))?;
+
+    let column = |suffix: &str| {
+        columns
+            .iter()
+            .find(|column| column.ends_with(suffix))
+            .cloned()
+            .ok_or(format!("Could not find the {suffix} column of {table}"))
+    };
+
+    Ok((table.clone(), column("NOTES")?, column("TAGS")?))
+}
+
+fn exists(transaction: &Transaction, table: &str, identifier: &str) -> Result<bool, String> {
+    transaction
+        .query_row(
+            &format!("SELECT 1 FROM {table} WHERE ZUNIQUEIDENTIFIER = ?1"),
+            [identifier],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|found| found.is_some())
+        .map_err(|e| format!("Error reading {}: {}", table, e))
+}
+
+fn find_or_insert_tag(
+    transaction: &Transaction,
+    tags: &mut Entity,
+    title: &str,
+    joplin_file: &JoplinFile,
+) -> Result<i64, String> {
+    let existing = transaction
+        .query_row(
+            "SELECT Z_PK FROM ZSFNOTETAG WHERE ZTITLE = ?1",
+            [title],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Error reading tag {:?}: {}", title, e))?;
+    if let Some(tag_id) = existing {
+        return Ok(tag_id);
+    }
+
+    tags.max += 1;
+    transaction
+        .execute(
+            "INSERT INTO ZSFNOTETAG (Z_PK, Z_ENT, Z_OPT, ZTITLE, ZUNIQUEIDENTIFIER, \
+            ZMODIFICATIONDATE) VALUES (?1, ?2, 1, ?3, ?4, ?5)",
+            params![
+                tags.max,
+                tags.id,
+                title,
+                bear_uuid(&format!("tag:{title}")),
+                core_data_date(joplin_file.updated),
+            ],
+        )
+        .map_err(|e| format!("Error inserting tag {:?}: {}", title, e))?;
+
+    Ok(tags.max)
+}
+
+/// The content starting with the `# Title` heading, Bear takes the first line of the text as the
+/// title and ZTITLE alone doesn't show
+fn with_title(content: &str, title: &str) -> String {
+    let heading = format!("# {title}");
+    if content.lines().next() == Some(heading.as_str()) {
+        content.to_string()
+    } else {
+        format!("{heading}\n\n{content}")
+    }
+}
+
+/// Bear lists `work/project` under `work`, which has to be a tag of its own
+fn tag_with_ancestors(tag: &str) -> Vec<String> {
+    tag.match_indices('/')
+        .map(|(pos, _)| tag[..pos].to_string())
+        .chain(std::iter::once(tag.to_string()))
+        .collect()
+}
+
+/// An uppercase uuid derived from `seed`, so a note gets the same one on every run
+fn bear_uuid(seed: &str) -> String {
+    let hex = md5_hex(seed.as_bytes());
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn core_data_date(date: DateTime<Utc>) -> f64 {
+    date.timestamp_millis() as f64 / 1000.0 - CORE_DATA_EPOCH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The parts of Bear's schema the writer touches
+    const SCHEMA: &str = "
+        CREATE TABLE Z_PRIMARYKEY (Z_ENT INTEGER PRIMARY KEY, Z_NAME VARCHAR, Z_SUPER INTEGER, Z_MAX INTEGER);
+        INSERT INTO Z_PRIMARYKEY VALUES (5, 'SFNote', 0, 0), (9, 'SFNoteFile', 0, 0), (13, 'SFNoteTag', 0, 3);
+        CREATE TABLE ZSFNOTE (Z_PK INTEGER PRIMARY KEY, Z_ENT INTEGER, Z_OPT INTEGER, ZTITLE VARCHAR,
+            ZTEXT VARCHAR, ZUNIQUEIDENTIFIER VARCHAR, ZCREATIONDATE TIMESTAMP, ZMODIFICATIONDATE TIMESTAMP,
+            ZTRASHED INTEGER, ZARCHIVED INTEGER, ZPINNED INTEGER, ZENCRYPTED INTEGER);
+        CREATE TABLE ZSFNOTETAG (Z_PK INTEGER PRIMARY KEY, Z_ENT INTEGER, Z_OPT INTEGER, ZTITLE VARCHAR,
+            ZUNIQUEIDENTIFIER VARCHAR, ZMODIFICATIONDATE TIMESTAMP);
+        INSERT INTO ZSFNOTETAG (Z_PK, Z_ENT, Z_OPT, ZTITLE) VALUES (3, 13, 1, 'work');
+        CREATE TABLE ZSFNOTEFILE (Z_PK INTEGER PRIMARY KEY, Z_ENT INTEGER, Z_OPT INTEGER, ZNOTE INTEGER,
+            ZFILENAME VARCHAR, ZUNIQUEIDENTIFIER VARCHAR, ZFILESIZE INTEGER, ZCREATIONDATE TIMESTAMP,
+            ZMODIFICATIONDATE TIMESTAMP);
+        CREATE TABLE Z_5TAGS (Z_5NOTES INTEGER, Z_13TAGS INTEGER, PRIMARY KEY (Z_5NOTES, Z_13TAGS));
+    ";
+
+    #[test]
+    fn test_write_bear_db() {
+        // arrange
+        let temp_dir = std::env::temp_dir().join("jb_bear_db_test");
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir).unwrap();
+        }
+        let source_dir = temp_dir.join("source");
+        fs::create_dir_all(source_dir.join("_resources")).unwrap();
+        fs::write(source_dir.join("_resources/a.png"), b"png").unwrap();
+        fs::write(source_dir.join("_resources/b.txt"), b"txt").unwrap();
+        let db_path = temp_dir.join("database.sqlite");
+        Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(SCHEMA)
+            .unwrap();
+        // Only copied, the database isn't in WAL mode
+        fs::write(db_path.with_extension("sqlite-shm"), b"shm").unwrap();
+
+        let config = Config::default();
+        let joplin_files = vec![
+            JoplinFile::build(
+                "work/project/a.md",
+                "---\ntitle: A\ncreated: 2024-03-07T23:22:26.5Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+                See ![The plan](../../_resources/a.png) and [the notes](../../_resources/b.txt)",
+                &config,
+            )
+            .unwrap(),
+        ];
+
+        // act
+        let backup_path = write_bear_db(&source_dir, &db_path, &joplin_files, &config).unwrap();
+        // A second run finds the note already there
+        write_bear_db(&source_dir, &db_path, &joplin_files, &config).unwrap();
+
+        // assert
+        let connection = Connection::open(&db_path).unwrap();
+        let (text, created): (String, f64) = connection
+            .query_row("SELECT ZTEXT, ZCREATIONDATE FROM ZSFNOTE", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        let tags: Vec<String> = connection
+            .prepare("SELECT ZTITLE FROM ZSFNOTETAG JOIN Z_5TAGS ON Z_13TAGS = Z_PK ORDER BY Z_PK")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let file_identifier: String = connection
+            .query_row(
+                "SELECT ZUNIQUEIDENTIFIER FROM ZSFNOTEFILE WHERE ZFILENAME = 'a.png'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let max: i64 = connection
+            .query_row(
+                "SELECT Z_MAX FROM Z_PRIMARYKEY WHERE Z_NAME = 'SFNoteTag'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let backed_up = backup_path.is_file();
+        let shm_backup = backup_path
+            .to_string_lossy()
+            .replacen(".sqlite.", ".sqlite-shm.", 1);
+        let shm = fs::read(shm_backup);
+        let image = fs::read(
+            temp_dir
+                .join(IMAGES_DIR)
+                .join(file_identifier)
+                .join("a.png"),
+        );
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+        assert_eq!(
+            text,
+            "# A\n\nSee ![The plan](a.png) and [the notes](b.txt)\n\n#work/project\n"
+        );
+        assert_eq!(created, 731546546.5);
+        assert_eq!(tags, vec!["work", "work/project"]);
+        assert_eq!(max, 4);
+        assert_eq!(image.unwrap(), b"png");
+        assert!(backed_up);
+        assert_eq!(shm.unwrap(), b"shm");
+    }
+
+    #[test]
+    fn test_with_title() {
+        let test_cases = vec![
+            ("Text\n", "# A\n\nText\n"),
+            ("# A\n#work\n\nText\n", "# A\n#work\n\nText\n"),
+            ("# Another\n", "# A\n\n# Another\n"),
+        ];
+
+        for (content, expected) in test_cases {
+            assert_eq!(with_title(content, "A"), expected, "{}", content);
+        }
+    }
+
+    #[test]
+    fn test_tag_with_ancestors() {
+        let test_cases: Vec<(&str, Vec<&str>)> = vec![
+            ("work", vec!["work"]),
+            (
+                "work/project/a",
+                vec!["work", "work/project", "work/project/a"],
+            ),
+        ];
+
+        for (tag, expected) in test_cases {
+            assert_eq!(tag_with_ancestors(tag), expected);
+        }
+    }
+}
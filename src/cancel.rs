@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Stops a running conversion from another thread, e.g. the cancel button of a GUI or a Ctrl-C
+/// handler. The pipeline checks it between notes and between resource copies, whatever was
+/// written until then stays where it is.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// An error reporting how far the conversion got once cancelled, `done` of `total` `items`
+    pub(crate) fn check(&self, done: usize, total: usize, items: &str) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err(format!("Cancelled after {done} of {total} {items}"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The same for the IO functions, which report `Interrupted`
+    pub(crate) fn check_io(&self) -> std::io::Result<()> {
+        if self.is_cancelled() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "cancelled",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let cancellation = Cancellation::default();
+        let clone = cancellation.clone();
+
+        assert_eq!(cancellation.check(1, 3, "notes"), Ok(()));
+        clone.cancel();
+
+        assert_eq!(
+            cancellation.check(1, 3, "notes"),
+            Err("Cancelled after 1 of 3 notes".to_string())
+        );
+        assert!(cancellation.check_io().is_err());
+    }
+}
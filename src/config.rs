@@ -0,0 +1,776 @@
+use crate::console::SkippedNotes;
+use crate::file_limit::FileLimit;
+use crate::path_template::PathTemplate;
+use crate::{Cancellation, IoErrors, Timings};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use unicode_normalization::UnicodeNormalization as _;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The file extensions of notes, without the dot, matched case insensitively
+    pub extensions: Vec<String>,
+    /// Whether the note's file name is the last component of its tag, otherwise the tag is built
+    /// from the directories only
+    pub tag_with_filename: bool,
+    /// The maximum number of components in a tag, `None` for no limit
+    pub max_tag_depth: Option<usize>,
+    /// What to do with the components of a tag beyond `max_tag_depth`
+    pub tag_overflow: TagOverflow,
+    /// Drop tags that are an ancestor of another tag of the same note, Bear already implies them
+    pub prune_ancestor_tags: bool,
+    /// Where the tags go in the Bear note
+    pub tag_placement: TagPlacement,
+    /// A Jinja template laying out the Bear notes instead of the tag placement, needs the
+    /// `templates` feature
+    pub note_template: Option<String>,
+    /// What to do with Joplin's `[toc]` markers, which render nothing in Bear
+    pub toc_marker: TocMarker,
+    /// Trim trailing whitespace and squeeze runs of three or more blank lines in the bodies, hard
+    /// breaks and code blocks aside
+    pub tidy_whitespace: bool,
+    /// How the line breaks inside paragraphs are written, code blocks aside
+    pub line_breaks: LineBreaks,
+    /// Whether the quotes and dashes of the text are made typographic or straight, code and
+    /// links aside
+    pub punctuation: Punctuation,
+    /// Replace the `:shortcode:` emoji Joplin renders with the emoji, Bear shows them as text
+    pub expand_emoji: bool,
+    /// Give the images of resources without alt text their file name as alt text
+    pub fill_alt_text: bool,
+    /// A RAW export of the same notes, the titles of its resource items are the alt text of the
+    /// resources named by their Joplin id
+    pub resource_metadata: Option<PathBuf>,
+    /// The tag of the notes with open `- [ ]` tasks or a due date, so they're easy to find
+    pub todo_tag: Option<String>,
+    /// List the open tasks and due dates of the notes after the summary
+    pub task_summary: bool,
+    /// Tags every note gets besides those of its path and front matter, set per notebook
+    pub extra_tags: Vec<String>,
+    /// The options of the notes under a directory of the export, from the least nested
+    /// directory to the most. `for_note` applies them
+    pub notebooks: Vec<(PathBuf, NotebookConfig)>,
+    /// The Unicode normalization form of titles, tags and target paths. macOS file names are
+    /// NFD while the front matter is NFC, so the same text can differ byte for byte
+    pub unicode_normalization: UnicodeNormalization,
+    /// Append a link that opens the original note in Joplin, for notes with an id
+    pub link_to_joplin: bool,
+    /// How the Joplin id of the note is kept in its body, for tools to match the Bear notes
+    /// with the Joplin ones
+    pub note_id: NoteIdMarker,
+    /// Notes with a fidelity score of at least this many problems get the `#jb/review` tag
+    pub review_threshold: Option<usize>,
+    /// A Rhai script run on every note, needs the `scripting` feature
+    pub script: Option<PathBuf>,
+    /// A shell command every note body is piped through, after the script
+    pub filter: Option<String>,
+    /// Directories to spill the resources across, in order, when they don't fit on the target.
+    /// Empty to copy the resources into the target directory
+    pub resource_volumes: Vec<PathBuf>,
+    /// What to do with the copies Joplin makes of notes with sync conflicts
+    pub conflict_policy: ConflictPolicy,
+    /// What to do with notes whose body is the same as that of another note
+    pub duplicate_policy: DuplicatePolicy,
+    /// Split the notes whose body is longer than this many bytes at their top-level headings,
+    /// into an index note linking to a note per section. `None` to keep them whole
+    pub split_notes: Option<usize>,
+    /// The notebooks whose notes are merged into a single note, by their path in the export
+    pub merge_notebooks: Vec<PathBuf>,
+    /// What to do with notes whose target file already exists with other content
+    pub existing_policy: ExistingPolicy,
+    /// What to do with notes updated before they were created, beyond a minute of clock skew
+    pub inverted_dates: InvertedDates,
+    /// Replaces the characters in target file and directory names that aren't valid on the
+    /// common filesystems
+    pub filename_replacement: char,
+    /// The maximum length of target note paths in characters, longer ones are shortened
+    pub max_path_len: Option<usize>,
+    /// Where the notes go in the target, built from their title and dates instead of their
+    /// path in the export. `None` to keep the notebooks
+    pub path_template: Option<PathTemplate>,
+    /// What to write to the target: Bear notes, a zip of them, JSON, a CSV index, HTML, an
+    /// Obsidian vault, a Day One import, an Evernote export, a Standard Notes import or Bear's
+    /// database
+    pub output_format: OutputFormat,
+    /// Where to write a CSV index of the notes, next to the converted notes
+    pub csv_index: Option<PathBuf>,
+    /// Glob patterns of the relative paths of the notes to convert, all notes when empty
+    pub include: Vec<String>,
+    /// The app that wrote the front matter of the notes, it decides which keys are read
+    pub source_flavor: SourceFlavor,
+    /// Skip the notes and the resources an interrupted conversion into the same target already
+    /// wrote, as its journals record them
+    pub resume: bool,
+    /// Link the resources into the target directory instead of copying them, Bear copies them
+    /// again on import anyway. `None` to copy them
+    pub link_resources: Option<ResourceLink>,
+    /// Scale down the JPEG and PNG resources larger than this as they're copied from an export
+    /// directory, needs the `image` feature. `None` to copy them as they are
+    pub downscale_images: Option<Downscale>,
+    /// Drop the GPS position and the serial numbers from the EXIF data of the JPEG and PNG
+    /// resources as they're copied from an export directory, needs the `image` feature
+    pub strip_exif: bool,
+    /// The extensions of the attachments Bear can't preview, without the dot, matched case
+    /// insensitively. The notes linking to them get a warning
+    pub unsupported_attachments: Vec<String>,
+    /// Convert the WebP resources to PNG as they're copied, and point the links at the PNG
+    /// files, needs the `image` feature. Only for notes written to a directory
+    pub convert_webp: bool,
+    /// Give the resources without an extension the one of their content as they're copied, and
+    /// point the links at them, so Bear can preview them. Only for notes written to a directory
+    /// from an export directory
+    pub sniff_resource_types: bool,
+    /// The directories of an export directory holding resources besides `_resources`, relative
+    /// to it. Their resources are merged into `_resources` in the target, or `attachments` in an
+    /// Obsidian vault, and the links to them follow
+    pub resource_dirs: Vec<PathBuf>,
+    /// Stop when an export directory has no resources directory, rather than warn when notes
+    /// link to resources
+    pub require_resources: bool,
+    /// The resources bigger than this many bytes are over the limits, `None` for no limit
+    pub max_resource_size: Option<u64>,
+    /// The resources beyond this many, in path order, are over the limits, `None` for no limit
+    pub max_resources: Option<usize>,
+    /// What to do with the resources over the limits, only for notes written to a directory
+    /// from an export directory
+    pub resource_limit_policy: ResourceLimitPolicy,
+    /// Inline the resources of at most this many bytes into the notes as base64 `data:` URIs
+    /// instead of copying them, only for the HTML and JSON output formats. `None` to link them
+    pub inline_resources: Option<u64>,
+    /// Append the Wayback Machine snapshots of the web pages each note links to, needs the
+    /// `wayback` feature. Holds the file the lookups are cached in, `None` to leave the links
+    pub archive_links: Option<PathBuf>,
+    /// Compare the resources already in the target with the source by content rather than by
+    /// size and modification time, to decide whether to copy them again
+    pub paranoid: bool,
+    /// Keep the extended attributes of the resources copied from an export directory, the Finder
+    /// tags and comments on macOS, on the files written in their place too: scaled down,
+    /// stripped of their EXIF data or converted to PNG. A plain copy keeps them on macOS anyway
+    pub preserve_xattrs: bool,
+    /// Remove the quarantine macOS gives downloaded files from the resources copied from an
+    /// export directory, so Bear doesn't prompt before opening them. Only on macOS
+    pub strip_quarantine: bool,
+    /// The time zone dates are shown in: in the HTML pages, the CSV index and the reports. File
+    /// times are instants, they don't depend on it
+    pub timezone: Tz,
+    /// Stops the conversion when cancelled from another thread
+    pub cancellation: Cancellation,
+    /// Records how long each phase and note took when enabled
+    pub timings: Timings,
+    /// Bounds the files open at once while reading notes, writing them and copying resources
+    pub open_files: FileLimit,
+    /// The notes that couldn't be read and the resources that couldn't be copied, the
+    /// conversion goes on without them
+    pub io_errors: IoErrors,
+    /// The notes left out: not text files, linked in from outside the source, vetoed by the
+    /// script or kept as the target has them
+    pub skipped_notes: SkippedNotes,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            extensions: vec!["md".to_string()],
+            tag_with_filename: false,
+            max_tag_depth: None,
+            tag_overflow: TagOverflow::default(),
+            prune_ancestor_tags: false,
+            tag_placement: TagPlacement::default(),
+            note_template: None,
+            toc_marker: TocMarker::default(),
+            tidy_whitespace: false,
+            line_breaks: LineBreaks::default(),
+            punctuation: Punctuation::default(),
+            expand_emoji: false,
+            fill_alt_text: false,
+            resource_metadata: None,
+            todo_tag: None,
+            task_summary: false,
+            extra_tags: Vec::new(),
+            notebooks: Vec::new(),
+            unicode_normalization: UnicodeNormalization::default(),
+            link_to_joplin: false,
+            note_id: NoteIdMarker::default(),
+            review_threshold: None,
+            script: None,
+            filter: None,
+            resource_volumes: Vec::new(),
+            conflict_policy: ConflictPolicy::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            split_notes: None,
+            merge_notebooks: Vec::new(),
+            existing_policy: ExistingPolicy::default(),
+            inverted_dates: InvertedDates::default(),
+            filename_replacement: '_',
+            max_path_len: None,
+            path_template: None,
+            output_format: OutputFormat::default(),
+            csv_index: None,
+            include: Vec::new(),
+            source_flavor: SourceFlavor::default(),
+            resume: false,
+            link_resources: None,
+            downscale_images: None,
+            strip_exif: false,
+            unsupported_attachments: vec!["webp".to_string(), "svg".to_string()],
+            convert_webp: false,
+            sniff_resource_types: false,
+            resource_dirs: Vec::new(),
+            require_resources: false,
+            max_resource_size: None,
+            max_resources: None,
+            resource_limit_policy: ResourceLimitPolicy::default(),
+            inline_resources: None,
+            archive_links: None,
+            paranoid: false,
+            preserve_xattrs: false,
+            strip_quarantine: false,
+            timezone: Tz::UTC,
+            cancellation: Cancellation::default(),
+            timings: Timings::default(),
+            open_files: FileLimit::default(),
+            io_errors: IoErrors::default(),
+            skipped_notes: SkippedNotes::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Strips the note extension from a file name, whichever of the configured ones it has
+    pub fn strip_extension<'a>(&self, file_name: &'a str) -> &'a str {
+        match file_name.rsplit_once('.') {
+            Some((stem, _)) if self.is_note(Path::new(file_name)) => stem,
+            _ => file_name,
+        }
+    }
+
+    /// Whether Bear can't preview the attachment, WebP files converted to PNG aside
+    pub fn is_unsupported_attachment(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|found| {
+            !(self.convert_webp && is_webp(path))
+                && self
+                    .unsupported_attachments
+                    .iter()
+                    .any(|extension| found.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// The options of the note at the relative path, with those of the notebooks it's in, the
+    /// most nested last, applied over the ones given for all notes
+    pub fn for_note(&self, relative_path: &Path) -> Cow<'_, Config> {
+        let relative_path = match relative_path.to_str() {
+            Some(path) => PathBuf::from(self.unicode_normalization.normalize(path)),
+            None => relative_path.to_path_buf(),
+        };
+        let mut matching = self
+            .notebooks
+            .iter()
+            .filter(|(notebook, _)| {
+                relative_path
+                    .parent()
+                    .is_some_and(|dir| dir.starts_with(notebook))
+            })
+            .map(|(_, notebook_config)| notebook_config)
+            .peekable();
+        if matching.peek().is_none() {
+            return Cow::Borrowed(self);
+        }
+
+        let mut config = self.clone();
+        for notebook_config in matching {
+            notebook_config.apply(&mut config);
+        }
+        Cow::Owned(config)
+    }
+
+    /// Whether the path has one of the configured note extensions
+    pub fn is_note(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|found| {
+            self.extensions
+                .iter()
+                .any(|extension| found.eq_ignore_ascii_case(extension))
+        })
+    }
+}
+
+/// Whether the path is a WebP image, by its extension
+pub fn is_webp(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("webp"))
+}
+
+/// Settings read from a JSON file with `--config`, for those that are easier to keep in a file
+/// than to repeat on every run. Options given on the command line win.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// A Rhai script run on every note, relative to the config file
+    pub script: Option<PathBuf>,
+    /// A shell command every note body is piped through
+    pub filter: Option<String>,
+    /// The options of the notes under a directory of the export, by its path relative to the
+    /// export, like `Journal` or `Work/Clips`
+    #[serde(default)]
+    pub notebooks: BTreeMap<String, NotebookConfig>,
+}
+
+impl ConfigFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ConfigFile, String> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading config file {:?}: {}", path, e))?;
+        let mut config_file: ConfigFile = serde_json::from_str(&json)
+            .map_err(|e| format!("Error parsing config file {:?}: {}", path, e))?;
+
+        if let (Some(script), Some(dir)) = (&config_file.script, path.parent()) {
+            config_file.script = Some(dir.join(script));
+        }
+
+        Ok(config_file)
+    }
+
+    /// The notebook options with their directories, the least nested first
+    pub fn notebooks(&self, form: UnicodeNormalization) -> Vec<(PathBuf, NotebookConfig)> {
+        let mut notebooks: Vec<(PathBuf, NotebookConfig)> = self
+            .notebooks
+            .iter()
+            .map(|(notebook, notebook_config)| {
+                let notebook = PathBuf::from(form.normalize(notebook.trim_matches(['/', '\\'])));
+                (notebook, notebook_config.clone())
+            })
+            .collect();
+        notebooks.sort_by_key(|(notebook, _)| {
+            notebook
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .count()
+        });
+        notebooks
+    }
+}
+
+/// The options a notebook can set for its notes in the config file, over the ones given for
+/// all notes. Those left out are kept.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotebookConfig {
+    /// Tags added to the notes, like `#journal`. The `#` is added when left out, and spaces are
+    /// turned into dashes as they are for the tags of the front matter
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub tag_with_filename: Option<bool>,
+    pub max_tag_depth: Option<usize>,
+    pub tag_placement: Option<TagPlacement>,
+    pub toc_marker: Option<TocMarker>,
+    pub tidy_whitespace: Option<bool>,
+    pub line_breaks: Option<LineBreaks>,
+    pub punctuation: Option<Punctuation>,
+    pub expand_emoji: Option<bool>,
+    pub todo_tag: Option<String>,
+    pub link_to_joplin: Option<bool>,
+}
+
+impl NotebookConfig {
+    fn apply(&self, config: &mut Config) {
+        config.extra_tags.extend(
+            self.tags
+                .iter()
+                .map(|tag| tag.trim())
+                .filter(|tag| !tag.trim_start_matches('#').is_empty())
+                .map(crate::JoplinFile::sanitize_tag),
+        );
+        if let Some(tag_with_filename) = self.tag_with_filename {
+            config.tag_with_filename = tag_with_filename;
+        }
+        if let Some(max_tag_depth) = self.max_tag_depth {
+            config.max_tag_depth = Some(max_tag_depth);
+        }
+        if let Some(tag_placement) = self.tag_placement {
+            config.tag_placement = tag_placement;
+        }
+        if let Some(toc_marker) = self.toc_marker {
+            config.toc_marker = toc_marker;
+        }
+        if let Some(tidy_whitespace) = self.tidy_whitespace {
+            config.tidy_whitespace = tidy_whitespace;
+        }
+        if let Some(line_breaks) = self.line_breaks {
+            config.line_breaks = line_breaks;
+        }
+        if let Some(punctuation) = self.punctuation {
+            config.punctuation = punctuation;
+        }
+        if let Some(expand_emoji) = self.expand_emoji {
+            config.expand_emoji = expand_emoji;
+        }
+        if let Some(todo_tag) = &self.todo_tag {
+            config.todo_tag = Some(todo_tag.clone());
+        }
+        if let Some(link_to_joplin) = self.link_to_joplin {
+            config.link_to_joplin = link_to_joplin;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TagOverflow {
+    /// Drop the components beyond the maximum depth: `#a/b/c/d` becomes `#a/b`
+    #[default]
+    Truncate,
+    /// Join the components beyond the maximum depth with dashes: `#a/b/c/d` becomes `#a/b-c-d`
+    Join,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagPlacement {
+    /// After the body
+    #[default]
+    Bottom,
+    /// Above the body, below a `# title` heading built from the front matter title
+    Top,
+    /// On the line after the first line of the body
+    AfterTitle,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TocMarker {
+    /// Leave the `[toc]` markers in the notes, Bear shows them as text
+    #[default]
+    Keep,
+    /// Remove the `[toc]` markers
+    Strip,
+    /// Replace each `[toc]` marker with a list of links to the headings of the note
+    Expand,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineBreaks {
+    /// Leave the line breaks as they are
+    #[default]
+    Keep,
+    /// Turn the hard breaks made of two trailing spaces into backslashes, which survive editors
+    /// trimming whitespace
+    Backslash,
+    /// Break the line at every newline inside a paragraph, for notes written in Joplin with
+    /// soft breaks rendered as breaks
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Punctuation {
+    /// Leave the punctuation as it is
+    #[default]
+    Keep,
+    /// Curly quotes, em dashes for `--` and ellipses for `...`
+    Smart,
+    /// Straight quotes, `--` for em dashes and `...` for ellipses
+    Straight,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A directory tree of notes, with the resources in `_resources`
+    #[default]
+    Dir,
+    /// A single zip file with the same layout as the directory tree
+    Zip,
+    /// A JSON array of the parsed notes, without the resources
+    Json,
+    /// Newline delimited JSON, one parsed note per line
+    Ndjson,
+    /// Only a CSV index of the notes
+    Csv,
+    /// A read-only HTML snapshot with a page per note and an index page
+    Html,
+    /// An Obsidian vault, with wikilinks between notes and the resources in `attachments/`
+    Obsidian,
+    /// A zip in the Day One JSON import format, with the images as photos
+    DayOne,
+    /// An Evernote export file with the resources embedded
+    Enex,
+    /// The Standard Notes import JSON, without the resources
+    StandardNotes,
+    /// Straight into Bear's database, keeping the dates exactly. Experimental, needs the
+    /// `bear-db` feature and Bear closed
+    BearDb,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SourceFlavor {
+    /// A Joplin export: `created`, `updated` and `tags`
+    #[default]
+    Joplin,
+    /// A Notable data directory: `modified` instead of `updated`
+    Notable,
+    /// A Zettlr directory: `modified` instead of `updated`, and `keywords` next to `tags`
+    Zettlr,
+}
+
+impl SourceFlavor {
+    /// The front matter keys holding the value Joplin writes under `key`, in order of preference
+    pub fn keys(&self, key: &'static str) -> Vec<&'static str> {
+        match (self, key) {
+            (SourceFlavor::Notable | SourceFlavor::Zettlr, "updated:") => {
+                vec!["modified:", "updated:"]
+            }
+            (SourceFlavor::Zettlr, "tags:") => vec!["tags:", "keywords:"],
+            _ => vec![key],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum NoteIdMarker {
+    /// Leave the id out of the note
+    #[default]
+    Omit,
+    /// An HTML comment at the end of the note, `<!-- joplin-id: … -->`, hidden in Bear's preview
+    Comment,
+    /// A `joplin-id:…` line at the end of the note, which Bear's search finds
+    Footer,
+}
+
+impl NoteIdMarker {
+    /// The marker of the id at the end of the note, `None` when it's left out
+    pub fn format(&self, id: &str) -> Option<String> {
+        match self {
+            NoteIdMarker::Omit => None,
+            NoteIdMarker::Comment => Some(format!("<!-- {JOPLIN_ID_KEY}: {id} -->")),
+            NoteIdMarker::Footer => Some(format!("{JOPLIN_ID_KEY}:{id}")),
+        }
+    }
+}
+
+/// The key of the Joplin id in the notes, see `NoteIdMarker`
+pub const JOPLIN_ID_KEY: &str = "joplin-id";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ResourceLimitPolicy {
+    /// Leave them out of the target, with a warning
+    #[default]
+    Skip,
+    /// Put a symbolic link to them in the source in the target, with a warning
+    Link,
+    /// Stop before anything is written
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResourceLink {
+    /// A hard link, or a copy when the source is on another filesystem
+    HardLink,
+    /// A symbolic link to the absolute path of the resource in the source
+    Symlink,
+}
+
+/// How oversized images are scaled down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Downscale {
+    /// The most pixels either side may have, the aspect ratio is kept
+    pub max_dimension: u32,
+    /// The JPEG quality, from 1 to 100
+    pub quality: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Import conflict copies like any other note
+    #[default]
+    Import,
+    /// Leave conflict copies out
+    Skip,
+    /// Import conflict copies tagged with `#joplin/conflict`
+    Tag,
+    /// Keep only the newer of a conflict copy and its original
+    Newer,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Import duplicates like any other note
+    #[default]
+    Import,
+    /// Keep only the oldest of the notes with the same body
+    Skip,
+    /// Import duplicates tagged with `#jb/duplicate`, the oldest note is left untagged
+    Tag,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExistingPolicy {
+    /// Replace the file with the converted note
+    #[default]
+    Overwrite,
+    /// Leave the file as it is
+    Skip,
+    /// Write the converted note next to it, numbered: `note 2.md`
+    KeepBoth,
+    /// Ask for each file, needs a terminal
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InvertedDates {
+    /// Keep the dates as they are, with a warning
+    #[default]
+    Warn,
+    /// Set the updated date to the created one, with a warning
+    Repair,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum UnicodeNormalization {
+    /// Leave the text as it is in the source
+    Off,
+    /// Composed form, `é` is a single code point
+    #[default]
+    Nfc,
+    /// Decomposed form, `é` is `e` followed by a combining accent
+    Nfd,
+}
+
+impl UnicodeNormalization {
+    pub fn normalize(&self, value: &str) -> String {
+        match self {
+            UnicodeNormalization::Off => value.to_string(),
+            UnicodeNormalization::Nfc => value.nfc().collect(),
+            UnicodeNormalization::Nfd => value.nfd().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_extension() {
+        let config = Config {
+            extensions: vec!["md".to_string(), "markdown".to_string(), "txt".to_string()],
+            ..Config::default()
+        };
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("note.md", "note"),
+            ("note.MD", "note"),
+            ("note.markdown", "note"),
+            ("note.txt", "note"),
+            ("note.v2.md", "note.v2"),
+            ("note.md.txt", "note.md"),
+            ("note.png", "note.png"),
+            ("note", "note"),
+        ];
+
+        for (file_name, expected) in test_cases {
+            assert_eq!(config.strip_extension(file_name), expected);
+        }
+    }
+
+    #[test]
+    fn test_config_file_load() {
+        let dir = std::env::temp_dir().join("jb_config_file_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let test_cases: Vec<(&str, Result<ConfigFile, &str>)> = vec![
+            ("{}", Ok(ConfigFile::default())),
+            (
+                r#"{"script": "notes.rhai", "filter": "cat"}"#,
+                Ok(ConfigFile {
+                    script: Some(dir.join("notes.rhai")),
+                    filter: Some("cat".to_string()),
+                    ..ConfigFile::default()
+                }),
+            ),
+            (
+                r##"{"notebooks": {"Journal": {"tags": ["#journal"], "tag_placement": "after-title"}}}"##,
+                Ok(ConfigFile {
+                    notebooks: BTreeMap::from([(
+                        "Journal".to_string(),
+                        NotebookConfig {
+                            tags: vec!["#journal".to_string()],
+                            tag_placement: Some(TagPlacement::AfterTitle),
+                            ..NotebookConfig::default()
+                        },
+                    )]),
+                    ..ConfigFile::default()
+                }),
+            ),
+            (
+                r##"{"notebooks": {"Journal": {"tag_prefix": "#journal"}}}"##,
+                Err("Error parsing config file"),
+            ),
+            (
+                r#"{"scripts": "notes.rhai"}"#,
+                Err("Error parsing config file"),
+            ),
+        ];
+
+        for (json, expected) in test_cases {
+            let path = dir.join("jb.json");
+            std::fs::write(&path, json).unwrap();
+
+            let result = ConfigFile::load(&path);
+
+            match expected {
+                Ok(config_file) => assert_eq!(result, Ok(config_file)),
+                Err(e) => assert!(result.unwrap_err().starts_with(e)),
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_for_note() {
+        let config_file = ConfigFile {
+            notebooks: BTreeMap::from([
+                (
+                    "Work/Clips/".to_string(),
+                    NotebookConfig {
+                        tags: vec!["clip".to_string(), "to read".to_string(), "#".to_string()],
+                        punctuation: Some(Punctuation::Straight),
+                        ..NotebookConfig::default()
+                    },
+                ),
+                (
+                    "Work".to_string(),
+                    NotebookConfig {
+                        tags: vec!["#work".to_string()],
+                        punctuation: Some(Punctuation::Smart),
+                        expand_emoji: Some(true),
+                        ..NotebookConfig::default()
+                    },
+                ),
+            ]),
+            ..ConfigFile::default()
+        };
+        let config = Config {
+            notebooks: config_file.notebooks(UnicodeNormalization::Nfc),
+            ..Config::default()
+        };
+        let test_cases = vec![
+            ("a.md", vec![], Punctuation::Keep, false),
+            ("Workshop/a.md", vec![], Punctuation::Keep, false),
+            ("Work/a.md", vec!["#work"], Punctuation::Smart, true),
+            (
+                "Work/Clips/Old/a.md",
+                vec!["#work", "#clip", "#to-read"],
+                Punctuation::Straight,
+                true,
+            ),
+        ];
+
+        for (relative_path, extra_tags, punctuation, expand_emoji) in test_cases {
+            let note_config = config.for_note(Path::new(relative_path));
+
+            assert_eq!(note_config.extra_tags, extra_tags, "{}", relative_path);
+            assert_eq!(note_config.punctuation, punctuation, "{}", relative_path);
+            assert_eq!(note_config.expand_emoji, expand_emoji, "{}", relative_path);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::JoplinFile;
+use crate::config::ConflictPolicy;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Added to conflict copies with `ConflictPolicy::Tag`
+pub const CONFLICT_TAG: &str = "#joplin/conflict";
+
+/// What was done with a conflict copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictDecision {
+    Imported,
+    Skipped,
+    Tagged,
+    /// The conflict copy was newer and took the place of its original
+    ReplacedOriginal,
+    /// The original was at least as new, the conflict copy was left out
+    KeptOriginal,
+}
+
+impl fmt::Display for ConflictDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decision = match self {
+            ConflictDecision::Imported => "imported",
+            ConflictDecision::Skipped => "skipped",
+            ConflictDecision::Tagged => "imported with the tag #joplin/conflict",
+            ConflictDecision::ReplacedOriginal => "newer than the original, replaced it",
+            ConflictDecision::KeptOriginal => "older than the original, skipped",
+        };
+        write!(f, "{}", decision)
+    }
+}
+
+/// The title of the note a conflict copy was made from, `None` if the title isn't that of a
+/// conflict copy. Joplin suffixes the titles of conflict copies with `(conflict)`, or
+/// `(conflict 2)` and so on when there are several.
+pub fn original_title(title: &str) -> Option<&str> {
+    let inner = title.trim_end().strip_suffix(')')?;
+    let (original, suffix) = inner.rsplit_once('(')?;
+    let number = suffix.strip_prefix("conflict")?.trim();
+
+    number
+        .chars()
+        .all(|c| c.is_ascii_digit())
+        .then(|| original.trim_end())
+}
+
+/// Applies the policy to the conflict copies among the notes. Returns the relative path of every
+/// conflict copy with what was done with it.
+///
+/// The original of a conflict copy is the note in the same directory with the title the copy was
+/// made from.
+pub fn resolve_conflicts(
+    joplin_files: &mut Vec<JoplinFile>,
+    policy: ConflictPolicy,
+) -> Vec<(PathBuf, ConflictDecision)> {
+    let mut decisions = Vec::new();
+    let mut removed = vec![false; joplin_files.len()];
+
+    for i in 0..joplin_files.len() {
+        let Some(original) = original_title(&joplin_files[i].title) else {
+            continue;
+        };
+        let original = original.to_string();
+        let relative_path = joplin_files[i].relative_path.clone();
+
+        let decision = match policy {
+            ConflictPolicy::Import => ConflictDecision::Imported,
+            ConflictPolicy::Skip => {
+                removed[i] = true;
+                ConflictDecision::Skipped
+            }
+            ConflictPolicy::Tag => {
+                joplin_files[i].add_tag(CONFLICT_TAG);
+                ConflictDecision::Tagged
+            }
+            ConflictPolicy::Newer => {
+                let found = (0..joplin_files.len()).find(|&j| {
+                    j != i
+                        && !removed[j]
+                        && joplin_files[j].title == original
+                        && joplin_files[j].relative_path.parent() == relative_path.parent()
+                });
+
+                match found {
+                    None => ConflictDecision::Imported,
+                    Some(j) if joplin_files[j].updated >= joplin_files[i].updated => {
+                        removed[i] = true;
+                        ConflictDecision::KeptOriginal
+                    }
+                    Some(j) => {
+                        removed[j] = true;
+                        joplin_files[i].title = joplin_files[j].title.clone();
+                        joplin_files[i].relative_path = joplin_files[j].relative_path.clone();
+                        ConflictDecision::ReplacedOriginal
+                    }
+                }
+            }
+        };
+
+        decisions.push((relative_path, decision));
+    }
+
+    let mut i = 0;
+    joplin_files.retain(|_| {
+        let keep = !removed[i];
+        i += 1;
+        keep
+    });
+
+    decisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn joplin_file(relative_path: &str, title: &str, updated: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: {title}\ncreated: 2024-03-07T23:22:26Z\nupdated: {updated}\n---\n"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_original_title() {
+        let test_cases: Vec<(&str, Option<&str>)> = vec![
+            ("Note", None),
+            ("Note (conflict)", Some("Note")),
+            ("Note (conflict 2)", Some("Note")),
+            ("Note (conflict) ", Some("Note")),
+            ("Note (conflicted)", None),
+            ("Note (2)", None),
+            ("(conflict) Note", None),
+        ];
+
+        for (title, expected) in test_cases {
+            assert_eq!(original_title(title), expected, "title: {title}");
+        }
+    }
+
+    #[test]
+    fn test_resolve_conflicts() {
+        let test_cases = vec![
+            (
+                ConflictPolicy::Import,
+                vec![("a/A.md", "A"), ("a/A (conflict).md", "A (conflict)")],
+                ConflictDecision::Imported,
+            ),
+            (
+                ConflictPolicy::Skip,
+                vec![("a/A.md", "A")],
+                ConflictDecision::Skipped,
+            ),
+            (
+                ConflictPolicy::Tag,
+                vec![("a/A.md", "A"), ("a/A (conflict).md", "A (conflict)")],
+                ConflictDecision::Tagged,
+            ),
+            (
+                ConflictPolicy::Newer,
+                vec![("a/A.md", "A")],
+                ConflictDecision::ReplacedOriginal,
+            ),
+        ];
+
+        for (policy, expected, expected_decision) in test_cases {
+            // arrange
+            let mut joplin_files = vec![
+                joplin_file("a/A.md", "A", "2024-04-07T08:34:52Z"),
+                joplin_file("a/A (conflict).md", "A (conflict)", "2024-05-07T08:34:52Z"),
+            ];
+
+            // act
+            let decisions = resolve_conflicts(&mut joplin_files, policy);
+
+            // assert
+            let result: Vec<(&str, &str)> = joplin_files
+                .iter()
+                .map(|f| (f.relative_path.to_str().unwrap(), f.title.as_str()))
+                .collect();
+            assert_eq!(result, expected, "policy: {policy:?}");
+            assert_eq!(
+                decisions,
+                vec![(PathBuf::from("a/A (conflict).md"), expected_decision)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_conflicts_newer_keeps_original() {
+        // arrange
+        let mut joplin_files = vec![
+            joplin_file("a/A.md", "A", "2024-05-07T08:34:52Z"),
+            joplin_file("a/A (conflict).md", "A (conflict)", "2024-04-07T08:34:52Z"),
+            joplin_file("b/A (conflict).md", "A (conflict)", "2024-04-07T08:34:52Z"),
+        ];
+
+        // act
+        let decisions = resolve_conflicts(&mut joplin_files, ConflictPolicy::Newer);
+
+        // assert
+        let result: Vec<&str> = joplin_files
+            .iter()
+            .map(|f| f.relative_path.to_str().unwrap())
+            .collect();
+        assert_eq!(result, vec!["a/A.md", "b/A (conflict).md"]);
+        assert_eq!(
+            decisions,
+            vec![
+                (
+                    PathBuf::from("a/A (conflict).md"),
+                    ConflictDecision::KeptOriginal
+                ),
+                (
+                    PathBuf::from("b/A (conflict).md"),
+                    ConflictDecision::Imported
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use crate::tasks::NoteTasks;
+use crate::warning::Warning;
+use crate::{IoErrors, JoplinFile};
+use anstyle::{AnsiColor, Style};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+pub const ERROR: Style = AnsiColor::Red.on_default().bold();
+pub const WARNING: Style = AnsiColor::Yellow.on_default();
+pub const ADDED: Style = AnsiColor::Green.on_default();
+pub const REMOVED: Style = AnsiColor::Red.on_default();
+pub const HUNK: Style = AnsiColor::Cyan.on_default();
+const HEADING: Style = Style::new().bold();
+
+/// The width of the labels in the summary, the counts are aligned after it
+const LABEL_WIDTH: usize = 24;
+
+/// The `Output` of the process, as its index
+static OUTPUT: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    /// Always color, even when piped
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Makes it the choice for everything printed from now on
+    pub fn apply(self) {
+        let choice = match self {
+            ColorChoice::Auto => anstream::ColorChoice::Auto,
+            ColorChoice::Always => anstream::ColorChoice::Always,
+            ColorChoice::Never => anstream::ColorChoice::Never,
+        };
+        choice.write_global();
+    }
+}
+
+/// What gets printed besides the errors, which always are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Output {
+    /// Progress, warnings and the summary, for people
+    #[default]
+    Normal,
+    /// Only the errors
+    Quiet,
+    /// Only the errors, and a `STATUS\tsource\ttarget` line per note on stdout for scripts
+    Porcelain,
+}
+
+impl Output {
+    /// Makes it the output for everything printed from now on
+    pub fn apply(self) {
+        OUTPUT.store(self as u8, Ordering::Relaxed);
+    }
+
+    pub fn current() -> Output {
+        match OUTPUT.load(Ordering::Relaxed) {
+            1 => Output::Quiet,
+            2 => Output::Porcelain,
+            _ => Output::Normal,
+        }
+    }
+
+    pub fn is_normal() -> bool {
+        Output::current() == Output::Normal
+    }
+}
+
+/// What happened to a note, in the porcelain output. The names are stable across versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The note was written to the target
+    Written,
+    /// An interrupted conversion had written the note already, it was left as it is
+    Resumed,
+    /// The note was left out: it's not a text file, the script vetoed it, or the file the
+    /// target already has was kept
+    Skipped,
+    /// The note couldn't be read
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Written => write!(f, "WRITTEN"),
+            Status::Resumed => write!(f, "RESUMED"),
+            Status::Skipped => write!(f, "SKIPPED"),
+            Status::Failed => write!(f, "FAILED"),
+        }
+    }
+}
+
+/// How many notes were left out, see `Status::Skipped`, so the run can tell it didn't convert
+/// them all; clones share the count
+#[derive(Debug, Clone, Default)]
+pub struct SkippedNotes(Arc<AtomicUsize>);
+
+impl SkippedNotes {
+    pub fn record(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Prints the porcelain line of a note to stdout, when that's the output. The target is empty
+/// for notes that weren't written.
+pub fn porcelain(status: Status, source: &Path, target: Option<&Path>) {
+    if Output::current() == Output::Porcelain {
+        println!("{}", porcelain_line(status, source, target));
+    }
+}
+
+fn porcelain_line(status: Status, source: &Path, target: Option<&Path>) -> String {
+    let target = target
+        .map(|target| target.to_string_lossy())
+        .unwrap_or_default();
+    format!("{}\t{}\t{}", status, source.to_string_lossy(), target)
+}
+
+/// Prints an error to stderr, in red when colored
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {{
+        let style = $crate::console::ERROR;
+        anstream::eprintln!("{style}{}{style:#}", format_args!($($arg)*));
+    }};
+}
+
+/// Prints a warning to stderr, in yellow when colored. Only with the normal output
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        if $crate::console::Output::is_normal() {
+            let style = $crate::console::WARNING;
+            anstream::eprintln!("{style}Warning: {}{style:#}", format_args!($($arg)*));
+        }
+    }};
+}
+
+/// Prints the progress of the conversion to stdout. Only with the normal output
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {{
+        if $crate::console::Output::is_normal() {
+            println!($($arg)*);
+        }
+    }};
+}
+
+/// Prints what the conversion did about a note to stderr. Only with the normal output
+#[macro_export]
+macro_rules! notice {
+    ($($arg:tt)*) => {{
+        if $crate::console::Output::is_normal() {
+            eprintln!($($arg)*);
+        }
+    }};
+}
+
+/// What a conversion ran into, counted by category and printed as a table at the end
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub notes: usize,
+    pub warnings: BTreeMap<&'static str, usize>,
+    pub errors: BTreeMap<&'static str, usize>,
+    /// What's left to do in the notes, listed after the counts when asked for
+    pub tasks: Vec<NoteTasks>,
+}
+
+impl Summary {
+    /// Counts the warnings of the notes and those about the export as a whole, like orphaned
+    /// resources
+    pub fn build(joplin_files: &[JoplinFile], warnings: &[Warning], io_errors: &IoErrors) -> Self {
+        let mut summary = Summary {
+            notes: joplin_files.len(),
+            ..Summary::default()
+        };
+        let note_warnings = joplin_files
+            .iter()
+            .flat_map(|joplin_file| &joplin_file.warnings);
+        for warning in note_warnings.chain(warnings) {
+            *summary.warnings.entry(warning.category()).or_default() += 1;
+        }
+        if !io_errors.is_empty() {
+            summary.errors.insert("read or write", io_errors.len());
+        }
+        summary
+    }
+}
+
+/// The table, with the styles of the categories; print it with `anstream` so they are dropped
+/// when color is off
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADING}Summary{HEADING:#}")?;
+        writeln!(f, "  {:<LABEL_WIDTH$}{:>6}", "notes", self.notes)?;
+        for (label, counts, style) in [
+            ("warnings", &self.warnings, WARNING),
+            ("errors", &self.errors, ERROR),
+        ] {
+            let total: usize = counts.values().sum();
+            let style = if total > 0 { style } else { Style::new() };
+            writeln!(f, "  {style}{:<LABEL_WIDTH$}{:>6}{style:#}", label, total)?;
+            for (category, count) in counts {
+                writeln!(
+                    f,
+                    "    {:<width$}{:>6}",
+                    category,
+                    count,
+                    width = LABEL_WIDTH - 2
+                )?;
+            }
+        }
+
+        if !self.tasks.is_empty() {
+            writeln!(f, "{HEADING}Tasks{HEADING:#}")?;
+        }
+        for tasks in &self.tasks {
+            let due = tasks
+                .due
+                .map(|due| due.format("%Y-%m-%d").to_string())
+                .unwrap_or_default();
+            writeln!(
+                f,
+                "  {:<12}{} ({})",
+                due,
+                tasks.title,
+                tasks.relative_path.display()
+            )?;
+            for task in &tasks.open {
+                writeln!(f, "  {:<12}- [ ] {}", "", task)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_porcelain_line() {
+        let test_cases: Vec<(Status, &str, Option<&str>, &str)> = vec![
+            (
+                Status::Written,
+                "Work/a.md",
+                Some("/out/Work/a.md"),
+                "WRITTEN\tWork/a.md\t/out/Work/a.md",
+            ),
+            (Status::Failed, "b.md", None, "FAILED\tb.md\t"),
+        ];
+
+        for (status, source, target, expected) in test_cases {
+            let line = porcelain_line(status, Path::new(source), target.map(Path::new));
+            assert_eq!(line, expected);
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        // arrange
+        let content = "---\ntitle: Test\ncreated: 2024-03-07\nupdated: 2024-04-07T08:34:52Z\n---\n";
+        let joplin_files =
+            vec![JoplinFile::build("a.md", content, &crate::Config::default()).unwrap()];
+        let warnings = vec![Warning::OrphanedResource(PathBuf::from("a.png"))];
+        let io_errors = IoErrors::default();
+        io_errors.record(Path::new("b.md"), "Permission denied");
+
+        // act
+        let summary = Summary::build(&joplin_files, &warnings, &io_errors);
+
+        // assert
+        let table = anstream::adapter::strip_str(&summary.to_string()).to_string();
+        assert_eq!(
+            table,
+            "Summary\n\
+            \x20 notes                        1\n\
+            \x20 warnings                     2\n\
+            \x20   date assumed UTC           1\n\
+            \x20   orphaned resource          1\n\
+            \x20 errors                       1\n\
+            \x20   read or write              1\n"
+        );
+    }
+
+    #[test]
+    fn test_summary_tasks() {
+        let summary = Summary {
+            tasks: vec![
+                NoteTasks {
+                    title: "Trip".to_string(),
+                    relative_path: PathBuf::from("Plans/Trip.md"),
+                    due: "2024-05-01T10:00:00Z".parse().ok(),
+                    open: vec!["Book hotel".to_string()],
+                },
+                NoteTasks {
+                    title: "Shopping".to_string(),
+                    relative_path: PathBuf::from("Shopping.md"),
+                    due: None,
+                    open: vec!["Milk".to_string()],
+                },
+            ],
+            ..Summary::default()
+        };
+
+        let table = anstream::adapter::strip_str(&summary.to_string()).to_string();
+
+        assert!(table.ends_with(
+            "Tasks\n\
+            \x20 2024-05-01  Trip (Plans/Trip.md)\n\
+            \x20             - [ ] Book hotel\n\
+            \x20             Shopping (Shopping.md)\n\
+            \x20             - [ ] Milk\n"
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::JoplinFile;
+use chrono_tz::Tz;
+use std::io::Write;
+
+const HEADER: [&str; 8] = [
+    "title",
+    "created",
+    "updated",
+    "tags",
+    "path",
+    "word_count",
+    "character_count",
+    "reading_minutes",
+];
+
+/// Writes a CSV index of the notes, one row per note, for reviewing what is converted in a
+/// spreadsheet. The dates are in the time zone.
+pub fn write_csv_index<W: Write>(
+    mut writer: W,
+    joplin_files: &[JoplinFile],
+    timezone: Tz,
+) -> Result<(), String> {
+    write_row(&mut writer, &HEADER.map(String::from))?;
+
+    for joplin_file in joplin_files {
+        write_row(
+            &mut writer,
+            &[
+                joplin_file.title.clone(),
+                joplin_file.created.with_timezone(&timezone).to_rfc3339(),
+                joplin_file.updated.with_timezone(&timezone).to_rfc3339(),
+                joplin_file.tags.clone().unwrap_or_default(),
+                joplin_file.relative_path.to_string_lossy().to_string(),
+                joplin_file.stats.word_count.to_string(),
+                joplin_file.stats.character_count.to_string(),
+                joplin_file.stats.reading_minutes.to_string(),
+            ],
+        )?;
+    }
+
+    writer
+        .flush()
+        .map_err(|e| format!("Error writing CSV index: {}", e))
+}
+
+fn write_row<W: Write>(writer: &mut W, fields: &[String]) -> Result<(), String> {
+    let row: Vec<String> = fields.iter().map(|field| escape(field)).collect();
+    writeln!(writer, "{}", row.join(",")).map_err(|e| format!("Error writing CSV index: {}", e))
+}
+
+/// Quotes a field when it has a comma, quote or line break, doubling the quotes inside it
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_escape() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("plain", "plain"),
+            ("a, b", "\"a, b\""),
+            ("say \"hi\"", "\"say \"\"hi\"\"\""),
+            ("two\nlines", "\"two\nlines\""),
+        ];
+
+        for (field, expected) in test_cases {
+            assert_eq!(escape(field), expected);
+        }
+    }
+
+    #[test]
+    fn test_write_csv_index() {
+        // arrange
+        let joplin_file = JoplinFile::build(
+            "Work/Proj/a.md",
+            "---\ntitle: Plans, maybe\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            Three words here",
+            &Config::default(),
+        )
+        .unwrap();
+        let test_cases: Vec<(Tz, &str)> = vec![
+            (
+                Tz::UTC,
+                "\"Plans, maybe\",2024-03-07T23:22:26+00:00,2024-04-07T08:34:52+00:00,#Work/Proj,Work/Proj/a.md,3,16,1\n",
+            ),
+            (
+                Tz::Europe__Stockholm,
+                "\"Plans, maybe\",2024-03-08T00:22:26+01:00,2024-04-07T10:34:52+02:00,#Work/Proj,Work/Proj/a.md,3,16,1\n",
+            ),
+        ];
+
+        for (timezone, expected) in test_cases {
+            let mut output = Vec::new();
+
+            // act
+            write_csv_index(&mut output, std::slice::from_ref(&joplin_file), timezone).unwrap();
+
+            // assert
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                format!(
+                    "title,created,updated,tags,path,word_count,character_count,reading_minutes\n{}",
+                    expected
+                )
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::JoplinFile;
+use crate::joplin_file_io::NoteSource;
+use crate::resource_ref::{ResourceTarget, mime_type, percent_decode};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::path::Path;
+
+/// Points the links to resources of at most `max_size` bytes at base64 `data:` URIs of their
+/// content, so the notes need no files next to them. The larger ones and those that can't be
+/// read stay linked. The size is checked before a resource is read, so the large ones aren't
+/// loaded. Returns how many links were inlined.
+pub fn inline_resources<P: AsRef<Path>>(
+    source_dir: P,
+    joplin_files: &mut [JoplinFile],
+    max_size: u64,
+) -> Result<usize, String> {
+    let mut source = NoteSource::open(&source_dir)?;
+
+    let mut inlined = 0;
+    for joplin_file in joplin_files {
+        let (body, count) = inline_note(joplin_file, max_size, |resource_path| {
+            let resource_path = percent_decode(resource_path);
+            let size = source.resource_size(Path::new(&resource_path)).ok()?;
+            if size > max_size {
+                return None;
+            }
+            source.read_resource(Path::new(&resource_path)).ok()
+        });
+        if count > 0 {
+            joplin_file.set_body(body);
+        }
+        inlined += count;
+    }
+
+    Ok(inlined)
+}
+
+/// Whether any of the notes links to a file in the resources directory
+pub fn links_resources(joplin_files: &[JoplinFile]) -> bool {
+    joplin_files.iter().any(|joplin_file| {
+        joplin_file
+            .referenced_resources()
+            .iter()
+            .any(|resource_ref| matches!(resource_ref.target, ResourceTarget::Path(_)))
+    })
+}
+
+/// The body of the note with the small resources inlined, and how many links were inlined
+fn inline_note(
+    joplin_file: &JoplinFile,
+    max_size: u64,
+    mut read_resource: impl FnMut(&str) -> Option<Vec<u8>>,
+) -> (String, usize) {
+    let mut body = joplin_file.body.clone();
+    let mut inlined = 0;
+
+    let mut refs = joplin_file.referenced_resources().to_vec();
+    refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+    for resource_ref in refs {
+        let Some(resource_path) = resource_ref.resource_path() else {
+            continue;
+        };
+
+        let Some(bytes) = read_resource(resource_path) else {
+            continue;
+        };
+        if bytes.len() as u64 > max_size {
+            continue;
+        }
+
+        let data_uri = format!(
+            "data:{};base64,{}",
+            mime_type(resource_path),
+            STANDARD.encode(&bytes)
+        );
+        body.replace_range(resource_ref.span, &data_uri);
+        inlined += 1;
+    }
+
+    (body, inlined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_inline_note() {
+        let test_cases: Vec<(&str, u64, &str, usize)> = vec![
+            (
+                "![](../_resources/a.png) [b](../_resources/b.pdf)",
+                3,
+                "![](data:image/png;base64,cG5n) [b](../_resources/b.pdf)",
+                1,
+            ),
+            (
+                "![](../_resources/a.png) [b](../_resources/b.pdf)",
+                100,
+                "![](data:image/png;base64,cG5n) [b](data:application/pdf;base64,YSBwZGY=)",
+                2,
+            ),
+            (
+                "[c](../_resources/missing.txt) [d](:/0123456789abcdef0123456789abcdef)",
+                100,
+                "[c](../_resources/missing.txt) [d](:/0123456789abcdef0123456789abcdef)",
+                0,
+            ),
+        ];
+
+        for (body, max_size, expected_body, expected_inlined) in test_cases {
+            let content = format!(
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+            );
+            let joplin_file = JoplinFile::build("a.md", &content, &Config::default()).unwrap();
+
+            let (body, inlined) =
+                inline_note(
+                    &joplin_file,
+                    max_size,
+                    |resource_path| match resource_path {
+                        "a.png" => Some(b"png".to_vec()),
+                        "b.pdf" => Some(b"a pdf".to_vec()),
+                        _ => None,
+                    },
+                );
+
+            assert_eq!(body, expected_body);
+            assert_eq!(inlined, expected_inlined);
+        }
+    }
+}
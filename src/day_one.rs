@@ -0,0 +1,260 @@
+use crate::JoplinFile;
+use crate::joplin_file_io::NoteSource;
+use crate::resource_ref::{ResourceKind, percent_decode};
+use chrono::{DateTime, SecondsFormat, Utc};
+use md5::{Digest, Md5};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+const JOURNAL_FILE_NAME: &str = "Journal.json";
+const PHOTOS_DIR: &str = "photos";
+
+#[derive(Debug, Serialize)]
+struct Journal {
+    metadata: Metadata,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    version: &'static str,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    uuid: String,
+    creation_date: String,
+    modified_date: String,
+    time_zone: &'static str,
+    text: String,
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    photos: Vec<Photo>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Photo {
+    identifier: String,
+    /// Day One finds the photo in the zip by this, the file is `photos/<md5>.<type>`
+    md5: String,
+    #[serde(rename = "type")]
+    kind: String,
+    order_in_entry: usize,
+}
+
+/// Writes the notes into a zip Day One can import: `Journal.json` with an entry per note, and
+/// the images of the notes as photos
+pub fn write_day_one<P: AsRef<Path>>(
+    source_dir: P,
+    zip_path: P,
+    joplin_files: &[JoplinFile],
+) -> Result<(), String> {
+    let mut source = NoteSource::open(&source_dir)?;
+
+    let zip_path = zip_path.as_ref();
+    let file = File::create(zip_path)
+        .map_err(|e| format!("Error creating zip file {:?}: {}", zip_path, e))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+
+    let mut entries = Vec::new();
+    for joplin_file in joplin_files {
+        let (entry, photos) = build_entry(joplin_file, |resource_path| {
+            source
+                .read_resource(Path::new(&percent_decode(resource_path)))
+                .ok()
+        });
+        for (photo, bytes) in entry.photos.iter().zip(photos) {
+            let name = format!("{PHOTOS_DIR}/{}.{}", photo.md5, photo.kind);
+            zip.start_file(&name, SimpleFileOptions::default())
+                .and_then(|_| Ok(zip.write_all(&bytes)?))
+                .map_err(|e| format!("Error adding {} to the zip: {}", name, e))?;
+        }
+        entries.push(entry);
+    }
+
+    let journal = Journal {
+        metadata: Metadata { version: "1.0" },
+        entries,
+    };
+    zip.start_file(JOURNAL_FILE_NAME, SimpleFileOptions::default())
+        .map_err(|e| format!("Error adding {} to the zip: {}", JOURNAL_FILE_NAME, e))?;
+    serde_json::to_writer_pretty(&mut zip, &journal)
+        .map_err(|e| format!("Error writing {}: {}", JOURNAL_FILE_NAME, e))?;
+
+    zip.finish()
+        .map_err(|e| format!("Error finishing zip file: {}", e))?
+        .flush()
+        .map_err(|e| format!("Error writing zip file: {}", e))
+}
+
+/// Builds the entry of a note, and returns the content of its photos in the same order as
+/// `Entry::photos`. Images `read_resource` can't find stay links in the text.
+fn build_entry(
+    joplin_file: &JoplinFile,
+    mut read_resource: impl FnMut(&str) -> Option<Vec<u8>>,
+) -> (Entry, Vec<Vec<u8>>) {
+    let mut body = joplin_file.body.clone();
+    let mut photos = Vec::new();
+    let mut contents = Vec::new();
+
+    let mut refs = joplin_file.referenced_resources().to_vec();
+    refs.retain(|resource_ref| resource_ref.kind == ResourceKind::Image);
+    // Replaced from the end so the earlier spans stay valid, the photos are reversed after
+    refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+
+    for resource_ref in refs {
+        let Some(resource_path) = resource_ref.resource_path() else {
+            continue;
+        };
+        let Some(bytes) = read_resource(resource_path) else {
+            continue;
+        };
+
+        let identifier = md5_hex(
+            format!(
+                "{}\n{}",
+                joplin_file.relative_path.display(),
+                resource_ref.span.start
+            )
+            .as_bytes(),
+        );
+        let kind = Path::new(resource_path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .map(|extension| {
+                if extension == "jpg" {
+                    "jpeg".to_string()
+                } else {
+                    extension
+                }
+            })
+            .unwrap_or_else(|| "jpeg".to_string());
+
+        body.replace_range(
+            resource_ref.link,
+            &format!("![](dayone-moment://{identifier})"),
+        );
+        photos.push(Photo {
+            identifier,
+            md5: md5_hex(&bytes).to_lowercase(),
+            kind,
+            order_in_entry: 0,
+        });
+        contents.push(bytes);
+    }
+
+    photos.reverse();
+    contents.reverse();
+    for (order, photo) in photos.iter_mut().enumerate() {
+        photo.order_in_entry = order;
+    }
+
+    // Day One takes the first line as the title
+    let text = if body.is_empty() {
+        format!("# {}", joplin_file.title)
+    } else {
+        format!("# {}\n\n{}", joplin_file.title, body)
+    };
+
+    let entry = Entry {
+        uuid: uuid(joplin_file),
+        creation_date: day_one_date(joplin_file.created),
+        modified_date: day_one_date(joplin_file.updated),
+        time_zone: "UTC",
+        text,
+        tags: joplin_file
+            .tags
+            .iter()
+            .flat_map(|tags| tags.split_whitespace())
+            .map(|tag| tag.trim_start_matches('#').to_string())
+            .collect(),
+        photos,
+    };
+
+    (entry, contents)
+}
+
+/// Day One uuids are 32 uppercase hex digits, like Joplin ids. Notes without an id get one
+/// derived from their path, so converting again gives the same uuid.
+fn uuid(joplin_file: &JoplinFile) -> String {
+    match &joplin_file.id {
+        Some(id) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => {
+            id.to_uppercase()
+        }
+        _ => md5_hex(joplin_file.relative_path.to_string_lossy().as_bytes()),
+    }
+}
+
+pub(crate) fn md5_hex(bytes: &[u8]) -> String {
+    Md5::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect()
+}
+
+fn day_one_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_build_entry() {
+        // arrange
+        let joplin_file = JoplinFile::build(
+            "Journal/2024/day.md",
+            "---\ntitle: A day\nid: 0123456789abcdef0123456789abcdef\n\
+            created: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            Morning ![](../../_resources/a.jpg) and ![](../../_resources/missing.png)\n\
+            [a pdf](../../_resources/b.pdf)",
+            &Config::default(),
+        )
+        .unwrap();
+
+        // act
+        let (entry, contents) = build_entry(&joplin_file, |resource_path| {
+            (resource_path == "a.jpg").then(|| b"jpeg".to_vec())
+        });
+
+        // assert
+        assert_eq!(entry.uuid, "0123456789ABCDEF0123456789ABCDEF");
+        assert_eq!(entry.creation_date, "2024-03-07T23:22:26Z");
+        assert_eq!(entry.tags, vec!["Journal/2024".to_string()]);
+        assert_eq!(entry.photos.len(), 1);
+        assert_eq!(entry.photos[0].kind, "jpeg");
+        assert_eq!(entry.photos[0].md5, md5_hex(b"jpeg").to_lowercase());
+        assert_eq!(
+            entry.text,
+            format!(
+                "# A day\n\nMorning ![](dayone-moment://{}) and ![](../../_resources/missing.png)\n\
+                [a pdf](../../_resources/b.pdf)",
+                entry.photos[0].identifier
+            )
+        );
+        assert_eq!(contents, vec![b"jpeg".to_vec()]);
+    }
+
+    #[test]
+    fn test_uuid_without_id() {
+        let joplin_file = JoplinFile::build(
+            "a.md",
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+            &Config::default(),
+        )
+        .unwrap();
+
+        let uuid = uuid(&joplin_file);
+
+        assert_eq!(uuid.len(), 32);
+        assert_eq!(uuid, uuid.to_uppercase());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::joplin_file_io::{build_content, long_path, target_paths};
+use crate::{Config, JoplinFile};
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiffKind {
+    /// The target has no note at the path the note would be written to
+    Missing,
+    /// The target note is older than the note was last updated
+    Stale,
+    /// The target note is as new as the note but its content differs, it was edited there or
+    /// converted with other settings
+    Diverged,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct NoteDiff {
+    pub kind: DiffKind,
+    /// Relative to the target
+    pub relative_path: PathBuf,
+    pub title: String,
+}
+
+/// Compares the notes with what a conversion wrote into the target, without writing anything.
+/// Notes whose target has the content a conversion would write now are left out.
+pub fn diff<P: AsRef<Path>>(
+    target_dir: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<Vec<NoteDiff>, String> {
+    let target_dir = target_dir.as_ref();
+    let base_len = target_dir.to_string_lossy().chars().count() + 1;
+    let relative_paths = target_paths(joplin_files, base_len, config)?;
+
+    let mut diffs = Vec::new();
+    for (joplin_file, relative_path) in joplin_files.iter().zip(relative_paths) {
+        let target_path = long_path(&target_dir.join(&relative_path));
+        let kind = match std::fs::read(&target_path) {
+            Err(_) => DiffKind::Missing,
+            Ok(content) if content == build_content(joplin_file, config).as_bytes() => continue,
+            Ok(_) => {
+                let modified = std::fs::metadata(&target_path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|e| format!("Error reading {:?}: {}", target_path, e))?;
+                if modified < SystemTime::from(joplin_file.updated) {
+                    DiffKind::Stale
+                } else {
+                    DiffKind::Diverged
+                }
+            }
+        };
+
+        diffs.push(NoteDiff {
+            kind,
+            relative_path,
+            title: joplin_file.title.clone(),
+        });
+    }
+
+    diffs.sort_by(|a, b| (a.kind, &a.relative_path).cmp(&(b.kind, &b.relative_path)));
+    Ok(diffs)
+}
+
+/// A unified diff of two texts by line, empty when they're the same
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_name, new_name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joplin_file_io::write_joplin_files;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_unified_diff() {
+        let test_cases: Vec<(&str, &str, &str)> = vec![
+            ("a\nb\n", "a\nb\n", ""),
+            (
+                "a\nb\n",
+                "a\nc\n",
+                "--- old\n+++ new\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n",
+            ),
+        ];
+
+        for (old, new, expected) in test_cases {
+            assert_eq!(unified_diff(old, new, "old", "new"), expected);
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        // arrange
+        let target_dir = std::env::temp_dir().join("jb_diff_test");
+        if target_dir.exists() {
+            fs::remove_dir_all(&target_dir).unwrap();
+        }
+        let config = Config::default();
+        let joplin_files: Vec<JoplinFile> = ["same.md", "stale.md", "diverged.md", "missing.md"]
+            .iter()
+            .map(|relative_path| {
+                JoplinFile::build(
+                    relative_path,
+                    "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\nBody",
+                    &config,
+                )
+                .unwrap()
+            })
+            .collect();
+        write_joplin_files(&target_dir, &joplin_files[..3], &config).unwrap();
+        let stale = fs::File::options()
+            .write(true)
+            .open(target_dir.join("stale.md"))
+            .unwrap();
+        stale.set_len(2).unwrap();
+        stale
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            .unwrap();
+        // Edited in the target after the conversion
+        fs::write(target_dir.join("diverged.md"), "Edited").unwrap();
+
+        // act
+        let diffs = diff(&target_dir, &joplin_files, &config).unwrap();
+
+        // assert
+        fs::remove_dir_all(&target_dir).unwrap();
+        let kinds: Vec<(DiffKind, &str)> = diffs
+            .iter()
+            .map(|diff| (diff.kind, diff.relative_path.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (DiffKind::Missing, "missing.md"),
+                (DiffKind::Stale, "stale.md"),
+                (DiffKind::Diverged, "diverged.md"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use crate::JoplinFile;
+use crate::config::DuplicatePolicy;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+
+/// Added to duplicates with `DuplicatePolicy::Tag`
+pub const DUPLICATE_TAG: &str = "#jb/duplicate";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateDecision {
+    Imported,
+    Skipped,
+    Tagged,
+}
+
+impl fmt::Display for DuplicateDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decision = match self {
+            DuplicateDecision::Imported => "imported",
+            DuplicateDecision::Skipped => "skipped",
+            DuplicateDecision::Tagged => "imported with the tag #jb/duplicate",
+        };
+        write!(f, "{}", decision)
+    }
+}
+
+/// A note with the same body as an older note
+#[derive(Debug, PartialEq)]
+pub struct Duplicate {
+    pub relative_path: PathBuf,
+    /// The relative path of the oldest note with the body
+    pub original: PathBuf,
+    pub decision: DuplicateDecision,
+}
+
+/// The body with the whitespace at the ends of lines and of the body removed, so bodies that only
+/// differ in those count as the same
+fn normalized_lines(body: &str) -> impl Iterator<Item = &str> {
+    body.trim().lines().map(str::trim_end)
+}
+
+fn content_hash(body: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for line in normalized_lines(body) {
+        line.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn same_content(a: &str, b: &str) -> bool {
+    normalized_lines(a).eq(normalized_lines(b))
+}
+
+/// Applies the policy to notes with the same body as another note. The oldest note with a body,
+/// by creation time, is the original and every other note with it is a duplicate. Notes with an
+/// empty body are never duplicates.
+pub fn resolve_duplicates(
+    joplin_files: &mut Vec<JoplinFile>,
+    policy: DuplicatePolicy,
+) -> Vec<Duplicate> {
+    let mut by_age: Vec<usize> = (0..joplin_files.len())
+        .filter(|&i| !joplin_files[i].body.trim().is_empty())
+        .collect();
+    // The sort is stable, notes created at the same time stay in path order
+    by_age.sort_by_key(|&i| joplin_files[i].created);
+
+    let mut originals: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut duplicates = Vec::new();
+    let mut removed = vec![false; joplin_files.len()];
+
+    for i in by_age {
+        let body = &joplin_files[i].body;
+        let candidates = originals.entry(content_hash(body)).or_default();
+        let Some(&original) = candidates
+            .iter()
+            .find(|&&j| same_content(&joplin_files[j].body, body))
+        else {
+            candidates.push(i);
+            continue;
+        };
+
+        let decision = match policy {
+            DuplicatePolicy::Import => DuplicateDecision::Imported,
+            DuplicatePolicy::Skip => {
+                removed[i] = true;
+                DuplicateDecision::Skipped
+            }
+            DuplicatePolicy::Tag => {
+                joplin_files[i].add_tag(DUPLICATE_TAG);
+                DuplicateDecision::Tagged
+            }
+        };
+
+        duplicates.push(Duplicate {
+            relative_path: joplin_files[i].relative_path.clone(),
+            original: joplin_files[original].relative_path.clone(),
+            decision,
+        });
+    }
+
+    let mut i = 0;
+    joplin_files.retain(|_| {
+        let keep = !removed[i];
+        i += 1;
+        keep
+    });
+
+    duplicates
+}
+
+/// The hash a note is compared to the others by, `None` for an empty body, which has no
+/// duplicates
+pub fn body_hash(body: &str) -> Option<u64> {
+    (!body.trim().is_empty()).then(|| content_hash(body))
+}
+
+/// The duplicates among notes held without their bodies, by the hash of each, see `body_hash`,
+/// as `resolve_duplicates` finds them with `DuplicatePolicy::Import`. Notes with the same hash
+/// are taken to have the same body, there's no body left to compare
+pub fn find_duplicates(joplin_files: &[JoplinFile], hashes: &[Option<u64>]) -> Vec<Duplicate> {
+    let mut by_age: Vec<usize> = (0..joplin_files.len())
+        .filter(|&i| hashes[i].is_some())
+        .collect();
+    by_age.sort_by_key(|&i| joplin_files[i].created);
+
+    let mut originals: HashMap<u64, usize> = HashMap::new();
+    by_age
+        .into_iter()
+        .filter_map(|i| {
+            let original = *originals.entry(hashes[i]?).or_insert(i);
+            (original != i).then(|| Duplicate {
+                relative_path: joplin_files[i].relative_path.clone(),
+                original: joplin_files[original].relative_path.clone(),
+                decision: DuplicateDecision::Imported,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn joplin_file(relative_path: &str, created: &str, body: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: Test\ncreated: {created}\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_duplicates() {
+        let test_cases = vec![
+            (
+                DuplicatePolicy::Import,
+                vec!["a.md", "b.md", "c.md", "d.md", "e.md"],
+                DuplicateDecision::Imported,
+            ),
+            (
+                DuplicatePolicy::Skip,
+                vec!["b.md", "d.md", "e.md"],
+                DuplicateDecision::Skipped,
+            ),
+            (
+                DuplicatePolicy::Tag,
+                vec!["a.md", "b.md", "c.md", "d.md", "e.md"],
+                DuplicateDecision::Tagged,
+            ),
+        ];
+
+        for (policy, expected, expected_decision) in test_cases {
+            // arrange
+            let mut joplin_files = vec![
+                joplin_file("a.md", "2024-03-08T00:00:00Z", "same\nbody\n"),
+                joplin_file("b.md", "2024-03-07T00:00:00Z", "same  \nbody"),
+                joplin_file("c.md", "2024-03-09T00:00:00Z", "\nsame\nbody\n\n"),
+                joplin_file("d.md", "2024-03-07T00:00:00Z", "\n"),
+                joplin_file("e.md", "2024-03-07T00:00:00Z", "\n"),
+            ];
+
+            // act
+            let duplicates = resolve_duplicates(&mut joplin_files, policy);
+
+            // assert
+            let result: Vec<&str> = joplin_files
+                .iter()
+                .map(|f| f.relative_path.to_str().unwrap())
+                .collect();
+            assert_eq!(result, expected, "policy: {policy:?}");
+            assert_eq!(
+                duplicates,
+                vec![
+                    Duplicate {
+                        relative_path: PathBuf::from("a.md"),
+                        original: PathBuf::from("b.md"),
+                        decision: expected_decision,
+                    },
+                    Duplicate {
+                        relative_path: PathBuf::from("c.md"),
+                        original: PathBuf::from("b.md"),
+                        decision: expected_decision,
+                    },
+                ]
+            );
+
+            let tagged = joplin_files
+                .iter()
+                .filter(|f| f.tags.as_deref() == Some(DUPLICATE_TAG))
+                .count();
+            let expected_tagged = if policy == DuplicatePolicy::Tag { 2 } else { 0 };
+            assert_eq!(tagged, expected_tagged, "policy: {policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        // arrange
+        let mut joplin_files = vec![
+            joplin_file("a.md", "2024-03-08T00:00:00Z", "same\nbody\n"),
+            joplin_file("b.md", "2024-03-07T00:00:00Z", "same  \nbody"),
+            joplin_file("c.md", "2024-03-09T00:00:00Z", "\nsame\nbody\n\n"),
+            joplin_file("d.md", "2024-03-07T00:00:00Z", "\n"),
+            joplin_file("e.md", "2024-03-07T00:00:00Z", "\n"),
+        ];
+        let hashes: Vec<Option<u64>> = joplin_files
+            .iter()
+            .map(|joplin_file| body_hash(&joplin_file.body))
+            .collect();
+
+        // act
+        let duplicates = find_duplicates(&joplin_files, &hashes);
+
+        // assert
+        assert_eq!(
+            duplicates,
+            resolve_duplicates(&mut joplin_files, DuplicatePolicy::Import)
+        );
+        assert_eq!(duplicates.len(), 2);
+    }
+}
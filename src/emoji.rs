@@ -0,0 +1,61 @@
+use crate::punctuation::map_text;
+
+/// The body with the `:shortcode:` emoji Joplin renders, like `:smile:`, replaced with the emoji
+/// themselves, `None` when it has none. Shortcodes are GitHub's, code is left as it is and so
+/// are the unknown ones, like the `:30:` of a time.
+pub fn expand_shortcodes(body: &str) -> Option<String> {
+    if !body.contains(':') {
+        return None;
+    }
+
+    map_text(body, |_, text, converted| {
+        let mut rest = text;
+        while let Some(start) = rest.find(':') {
+            converted.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let emoji = after
+                .find(':')
+                .map(|end| &after[..end])
+                .filter(|shortcode| shortcode.chars().all(is_shortcode_char))
+                .and_then(|shortcode| Some((shortcode, emojis::get_by_shortcode(shortcode)?)));
+
+            match emoji {
+                Some((shortcode, emoji)) => {
+                    converted.push_str(emoji.as_str());
+                    rest = &after[shortcode.len() + 1..];
+                }
+                None => {
+                    converted.push(':');
+                    rest = after;
+                }
+            }
+        }
+        converted.push_str(rest);
+    })
+}
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '+' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_shortcodes() {
+        let test_cases = vec![
+            (
+                "Nice :smile: :+1::tada: done",
+                Some("Nice \u{1f604} \u{1f44d}\u{1f389} done"),
+            ),
+            ("At 10:30:45, :not_an_emoji: `:smile:`", None),
+            ("```\n:smile:\n```", None),
+            ("No colons", None),
+        ];
+
+        for (body, expected) in test_cases {
+            assert_eq!(expand_shortcodes(body).as_deref(), expected, "{}", body);
+        }
+    }
+}
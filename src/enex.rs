@@ -0,0 +1,179 @@
+use crate::JoplinFile;
+use crate::day_one::md5_hex;
+use crate::html_export::{escape_html, markdown_options};
+use crate::joplin_file_io::NoteSource;
+use crate::resource_ref::{mime_type, percent_decode};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{Parser, html};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Writes the notes into a single Evernote export file, the resources embedded in it
+pub fn write_enex<P: AsRef<Path>>(
+    source_dir: P,
+    enex_path: P,
+    joplin_files: &[JoplinFile],
+) -> Result<(), String> {
+    let mut source = NoteSource::open(&source_dir)?;
+
+    let enex_path = enex_path.as_ref();
+    let file = File::create(enex_path)
+        .map_err(|e| format!("Error creating file {:?}: {}", enex_path, e))?;
+    let mut writer = BufWriter::new(file);
+    let write_error = |e: std::io::Error| format!("Error writing file {:?}: {}", enex_path, e);
+
+    write!(
+        writer,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <!DOCTYPE en-export SYSTEM \"http://xml.evernote.com/pub/evernote-export4.dtd\">\n\
+        <en-export export-date=\"{}\" application=\"jb\" version=\"{}\">\n",
+        enex_date(export_date(joplin_files)),
+        env!("CARGO_PKG_VERSION")
+    )
+    .map_err(write_error)?;
+
+    for joplin_file in joplin_files {
+        let note = build_note(joplin_file, |resource_path| {
+            source
+                .read_resource(Path::new(&percent_decode(resource_path)))
+                .ok()
+        });
+        writer.write_all(note.as_bytes()).map_err(write_error)?;
+    }
+
+    writer
+        .write_all(b"</en-export>\n")
+        .and_then(|_| writer.flush())
+        .map_err(write_error)
+}
+
+/// The `<note>` element of a note. The resources `read_resource` finds are embedded and shown
+/// where the note links to them, the links to others are left as they are.
+fn build_note(
+    joplin_file: &JoplinFile,
+    mut read_resource: impl FnMut(&str) -> Option<Vec<u8>>,
+) -> String {
+    let mut body = joplin_file.body.clone();
+    let mut media = Vec::new();
+    let mut resources = String::new();
+    let mut embedded = HashSet::new();
+
+    let mut refs = joplin_file.referenced_resources().to_vec();
+    refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+
+    for resource_ref in refs {
+        let Some(resource_path) = resource_ref.resource_path() else {
+            continue;
+        };
+        let Some(bytes) = read_resource(resource_path) else {
+            continue;
+        };
+
+        let hash = md5_hex(&bytes).to_lowercase();
+        let mime = mime_type(resource_path);
+        // The markdown renderer would mangle the element, it goes in after rendering
+        let placeholder = format!("jbmedia{}x", media.len());
+        body.replace_range(resource_ref.link.clone(), &placeholder);
+        media.push((
+            placeholder,
+            format!("<en-media type=\"{mime}\" hash=\"{hash}\"/>"),
+        ));
+
+        if embedded.insert(hash) {
+            let file_name = percent_decode(resource_ref.file_name());
+            resources.push_str(&format!(
+                "<resource>\n<data encoding=\"base64\">{}</data>\n<mime>{mime}</mime>\n\
+                <resource-attributes><file-name>{}</file-name></resource-attributes>\n\
+                </resource>\n",
+                STANDARD.encode(&bytes),
+                escape_html(&file_name)
+            ));
+        }
+    }
+
+    let mut enml = String::new();
+    html::push_html(&mut enml, Parser::new_ext(&body, markdown_options()));
+    for (placeholder, element) in media {
+        enml = enml.replace(&placeholder, &element);
+    }
+
+    let tags: String = joplin_file
+        .tags
+        .iter()
+        .flat_map(|tags| tags.split_whitespace())
+        .map(|tag| format!("<tag>{}</tag>\n", escape_html(tag.trim_start_matches('#'))))
+        .collect();
+
+    format!(
+        "<note>\n<title>{}</title>\n<created>{}</created>\n<updated>{}</updated>\n{}\
+        <content><![CDATA[<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n\
+        <!DOCTYPE en-note SYSTEM \"http://xml.evernote.com/pub/enml2.dtd\">\n\
+        <en-note>{}</en-note>]]></content>\n{}</note>\n",
+        escape_html(&joplin_file.title),
+        enex_date(joplin_file.created),
+        enex_date(joplin_file.updated),
+        tags,
+        // A CDATA section ends at the first `]]>`, so one in the note has to be split
+        enml.replace("]]>", "]]]]><![CDATA[>"),
+        resources
+    )
+}
+
+/// The date of the export, that of the last update of the notes rather than the time of the
+/// run, so converting the same notes again gives the same file
+fn export_date(joplin_files: &[JoplinFile]) -> DateTime<Utc> {
+    joplin_files
+        .iter()
+        .map(|joplin_file| joplin_file.updated)
+        .max()
+        .unwrap_or_default()
+}
+
+fn enex_date(date: DateTime<Utc>) -> String {
+    date.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_build_note() {
+        // arrange
+        let joplin_file = JoplinFile::build(
+            "Work/a.md",
+            "---\ntitle: A & B\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            See ![](../_resources/a.png) and ![](../_resources/a.png) and [b](../_resources/b.pdf)",
+            &Config::default(),
+        )
+        .unwrap();
+
+        // act
+        let note = build_note(&joplin_file, |resource_path| {
+            (resource_path == "a.png").then(|| b"png".to_vec())
+        });
+
+        // assert
+        let hash = md5_hex(b"png").to_lowercase();
+        assert_eq!(
+            note,
+            format!(
+                "<note>\n<title>A &amp; B</title>\n<created>20240307T232226Z</created>\n\
+                <updated>20240407T083452Z</updated>\n<tag>Work</tag>\n\
+                <content><![CDATA[<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n\
+                <!DOCTYPE en-note SYSTEM \"http://xml.evernote.com/pub/enml2.dtd\">\n\
+                <en-note><p>See <en-media type=\"image/png\" hash=\"{hash}\"/> and \
+                <en-media type=\"image/png\" hash=\"{hash}\"/> and \
+                <a href=\"../_resources/b.pdf\">b</a></p>\n</en-note>]]></content>\n\
+                <resource>\n<data encoding=\"base64\">cG5n</data>\n<mime>image/png</mime>\n\
+                <resource-attributes><file-name>a.png</file-name></resource-attributes>\n\
+                </resource>\n</note>\n"
+            )
+        );
+    }
+}
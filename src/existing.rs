@@ -0,0 +1,165 @@
+use crate::config::ExistingPolicy;
+use crate::diff::unified_diff;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// What is done with a note whose target file already exists with other content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Overwrite,
+    Skip,
+    /// The note is written next to the existing file, under a numbered name
+    KeepBoth,
+}
+
+/// Applies the policy to the target files that already exist, asking for each one with
+/// `ExistingPolicy::Ask` until an answer is given for all of them
+#[derive(Debug)]
+pub struct ExistingResolver {
+    policy: ExistingPolicy,
+    /// The answer given for all the files left
+    for_all: Option<Resolution>,
+}
+
+impl ExistingResolver {
+    pub fn new(policy: ExistingPolicy) -> Self {
+        Self {
+            policy,
+            for_all: None,
+        }
+    }
+
+    /// What to do with the note, when its target already exists with other content
+    pub fn resolve(&mut self, target_path: &Path, content: &str) -> Result<Resolution, String> {
+        match (self.policy, self.for_all) {
+            (ExistingPolicy::Overwrite, _) => Ok(Resolution::Overwrite),
+            (ExistingPolicy::Skip, _) => Ok(Resolution::Skip),
+            (ExistingPolicy::KeepBoth, _) => Ok(Resolution::KeepBoth),
+            (ExistingPolicy::Ask, Some(resolution)) => Ok(resolution),
+            (ExistingPolicy::Ask, None) => {
+                let existing = std::fs::read(target_path)
+                    .map_err(|e| format!("Error reading {:?}: {}", target_path, e))?;
+                let (resolution, for_all) = ask(
+                    std::io::stdin().lock(),
+                    std::io::stderr(),
+                    target_path,
+                    &String::from_utf8_lossy(&existing),
+                    content,
+                )
+                .map_err(|e| format!("Error asking about {:?}: {}", target_path, e))?;
+                if for_all {
+                    self.for_all = Some(resolution);
+                }
+                Ok(resolution)
+            }
+        }
+    }
+}
+
+/// Asks what to do with an existing file until one of the answers is given, showing the diff
+/// when asked. Returns the answer and whether it's for all the files left.
+fn ask<R: BufRead, W: Write>(
+    mut input: R,
+    mut output: W,
+    target_path: &Path,
+    existing: &str,
+    content: &str,
+) -> std::io::Result<(Resolution, bool)> {
+    loop {
+        write!(
+            output,
+            "{:?} already exists. [o]verwrite, [s]kip, [k]eep both, [d]iff \
+            (capital for all the files left): ",
+            target_path
+        )?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "no answer",
+            ));
+        }
+        let resolution = match answer.trim().to_lowercase().as_str() {
+            "o" => Resolution::Overwrite,
+            "s" => Resolution::Skip,
+            "k" => Resolution::KeepBoth,
+            "d" => {
+                let name = target_path.to_string_lossy();
+                write!(
+                    output,
+                    "{}",
+                    unified_diff(existing, content, &name, "converted")
+                )?;
+                continue;
+            }
+            _ => continue,
+        };
+        let for_all = answer.trim().chars().all(|c| c.is_uppercase());
+        return Ok((resolution, for_all));
+    }
+}
+
+/// The first of `name 2.md`, `name 3.md` and so on next to the file that doesn't exist yet
+pub fn free_path(target_path: &Path) -> PathBuf {
+    let stem = target_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = target_path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|n| target_path.with_file_name(format!("{} {}{}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .expect("there is a free number")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ask() {
+        let test_cases: Vec<(&str, (Resolution, bool), &str)> = vec![
+            ("o\n", (Resolution::Overwrite, false), ""),
+            ("S\n", (Resolution::Skip, true), ""),
+            ("x\nk\n", (Resolution::KeepBoth, false), ""),
+            ("d\nK\n", (Resolution::KeepBoth, true), "-Old\n+New\n"),
+        ];
+
+        for (answers, expected, shown) in test_cases {
+            let mut output = Vec::new();
+
+            let result = ask(
+                answers.as_bytes(),
+                &mut output,
+                Path::new("a.md"),
+                "Old\n",
+                "New\n",
+            );
+
+            assert_eq!(result.unwrap(), expected);
+            assert!(String::from_utf8(output).unwrap().contains(shown));
+        }
+    }
+
+    #[test]
+    fn test_free_path() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_free_path_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.md"), "").unwrap();
+        std::fs::write(dir.join("a 2.md"), "").unwrap();
+
+        // act
+        let path = free_path(&dir.join("a.md"));
+
+        // assert
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(path, dir.join("a 3.md"));
+    }
+}
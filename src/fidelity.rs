@@ -0,0 +1,125 @@
+use crate::JoplinFile;
+use crate::resource_ref::{ResourceTarget, percent_decode};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Added to notes whose score reaches the review threshold
+pub const REVIEW_TAG: &str = "#jb/review";
+
+/// Markdown that Joplin renders but Bear doesn't
+const UNSUPPORTED_CONSTRUCTS: [&str; 8] = [
+    "[toc]",
+    "```mermaid",
+    "$$",
+    "<details",
+    "<iframe",
+    "<video",
+    "<audio",
+    "<object",
+];
+
+/// How faithfully a note could be converted, every field counts a kind of problem
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fidelity {
+    pub unsupported_constructs: usize,
+    pub unresolved_links: usize,
+    pub missing_resources: usize,
+    pub fallbacks: usize,
+}
+
+impl Fidelity {
+    /// Looks for problems in the body of a note, resolving resources against the resources
+    /// directory of the source
+    pub fn assess<P: AsRef<Path>>(joplin_file: &JoplinFile, resources_dir: P) -> Fidelity {
+        Fidelity::assess_with(joplin_file, |resource_path| {
+            resources_dir.as_ref().join(resource_path).exists()
+        })
+    }
+
+    /// Like `assess`, but whether a resource exists is up to `resource_exists`, which gets the
+    /// path of the resource relative to the resources directory
+    pub fn assess_with(
+        joplin_file: &JoplinFile,
+        resource_exists: impl Fn(&Path) -> bool,
+    ) -> Fidelity {
+        let body = joplin_file.body.to_lowercase();
+        let unsupported_constructs = UNSUPPORTED_CONSTRUCTS
+            .iter()
+            .map(|construct| body.matches(construct).count())
+            .sum();
+
+        let mut unresolved_links = 0;
+        let mut missing_resources = 0;
+        for resource_ref in joplin_file.referenced_resources() {
+            match &resource_ref.target {
+                // Bear has no way to resolve a Joplin id
+                ResourceTarget::Id(_) => unresolved_links += 1,
+                ResourceTarget::Path(_) => {
+                    let exists = resource_ref.resource_path().is_some_and(|resource_path| {
+                        resource_exists(Path::new(&percent_decode(resource_path)))
+                    });
+                    if !exists {
+                        missing_resources += 1;
+                    }
+                }
+            }
+        }
+
+        Fidelity {
+            unsupported_constructs,
+            unresolved_links,
+            missing_resources,
+            fallbacks: 0,
+        }
+    }
+
+    /// The total number of problems, 0 for a faithful conversion
+    pub fn score(&self) -> usize {
+        self.unsupported_constructs
+            + self.unresolved_links
+            + self.missing_resources
+            + self.fallbacks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::fs;
+
+    #[test]
+    fn test_assess() {
+        // arrange
+        let resources_dir = std::env::temp_dir().join("jb_fidelity_test");
+        fs::create_dir_all(&resources_dir).unwrap();
+        fs::write(resources_dir.join("a b.png"), "a").unwrap();
+
+        let joplin_file = JoplinFile::build(
+            "note.md",
+            "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            [TOC]\n\
+            ![](../_resources/a%20b.png) ![](../_resources/missing.png)\n\
+            [other note](:/0123456789abcdef0123456789abcdef)\n\
+            ```mermaid\ngraph TD;\n```\n",
+            &Config::default(),
+        )
+        .unwrap();
+
+        // act
+        let fidelity = Fidelity::assess(&joplin_file, &resources_dir);
+
+        // assert
+        fs::remove_dir_all(&resources_dir).unwrap();
+        assert_eq!(
+            fidelity,
+            Fidelity {
+                unsupported_constructs: 2,
+                unresolved_links: 1,
+                missing_resources: 1,
+                fallbacks: 0,
+            }
+        );
+        assert_eq!(fidelity.score(), 4);
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+/// The most files open at once by default, well below the usual limit of 256 on macOS
+pub const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+/// Bounds how many files the conversion has open at once, across the threads reading notes,
+/// writing them and copying resources, so a large vault doesn't run out of file descriptors.
+/// Clones share the same bound.
+#[derive(Debug, Clone)]
+pub struct FileLimit(Arc<(Mutex<usize>, Condvar)>);
+
+/// Files that may be open, given back when dropped
+pub struct FilePermit<'a> {
+    limit: &'a FileLimit,
+    files: usize,
+}
+
+impl FileLimit {
+    /// At least 2, a copy has its source and its target open
+    pub fn new(max_open_files: usize) -> FileLimit {
+        FileLimit(Arc::new((
+            Mutex::new(max_open_files.max(2)),
+            Condvar::new(),
+        )))
+    }
+
+    /// Waits until `files` more files may be open. They're taken all at once, so two copies
+    /// can't each hold one file and wait for the other's
+    pub fn acquire(&self, files: usize) -> FilePermit<'_> {
+        let (available, released) = &*self.0;
+        let mut available = available.lock().unwrap_or_else(|e| e.into_inner());
+        while *available < files {
+            available = released.wait(available).unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= files;
+
+        FilePermit { limit: self, files }
+    }
+
+    pub fn available(&self) -> usize {
+        *self.0.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for FileLimit {
+    fn default() -> Self {
+        FileLimit::new(DEFAULT_MAX_OPEN_FILES)
+    }
+}
+
+impl Drop for FilePermit<'_> {
+    fn drop(&mut self) {
+        let (available, released) = &*self.limit.0;
+        *available.lock().unwrap_or_else(|e| e.into_inner()) += self.files;
+        released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_acquire() {
+        // arrange
+        let limit = FileLimit::new(3);
+        let open = AtomicUsize::new(0);
+        let most_open = AtomicUsize::new(0);
+
+        // act
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let _permit = limit.acquire(2);
+                    let now_open = open.fetch_add(2, Ordering::SeqCst) + 2;
+                    most_open.fetch_max(now_open, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    open.fetch_sub(2, Ordering::SeqCst);
+                });
+            }
+        });
+
+        // assert
+        assert_eq!(most_open.load(Ordering::SeqCst), 2);
+        assert_eq!(limit.available(), 3);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::JoplinFile;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// An external command every note body is piped through, e.g. `sed s/foo/bar/` or
+/// `pandoc -f markdown -t gfm`. It runs in the shell, reads the body on stdin and writes the new
+/// body to stdout. The rest of the note is in the environment: `JB_TITLE`, `JB_PATH`, `JB_TAGS`
+/// (space separated, without the leading `#`), `JB_CREATED`, `JB_UPDATED` and `JB_ID`.
+pub struct FilterHook {
+    command: String,
+}
+
+impl FilterHook {
+    pub fn new(command: &str) -> FilterHook {
+        FilterHook {
+            command: command.to_string(),
+        }
+    }
+
+    pub fn apply(&self, joplin_file: &mut JoplinFile) -> Result<(), String> {
+        let error = |e: String| {
+            format!(
+                "Error running filter on {:?}: {}",
+                joplin_file.relative_path, e
+            )
+        };
+
+        let tags: Vec<&str> = joplin_file
+            .tags
+            .iter()
+            .flat_map(|tags| tags.split_whitespace())
+            .map(|tag| tag.trim_start_matches('#'))
+            .collect();
+
+        let mut child = shell(&self.command)
+            .env("JB_TITLE", &joplin_file.title)
+            .env("JB_PATH", &joplin_file.relative_path)
+            .env("JB_TAGS", tags.join(" "))
+            .env("JB_CREATED", joplin_file.created.to_rfc3339())
+            .env("JB_UPDATED", joplin_file.updated.to_rfc3339())
+            .env("JB_ID", joplin_file.id.as_deref().unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| error(e.to_string()))?;
+
+        // Written from another thread, a filter that writes before it has read everything would
+        // otherwise block on a full pipe while we block on its stdin
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // Taken rather than copied, the filter's output replaces it
+        let body = std::mem::take(&mut joplin_file.body);
+        let writer = std::thread::spawn(move || stdin.write_all(body.as_bytes()));
+
+        let output = child.wait_with_output().map_err(|e| error(e.to_string()))?;
+        // A filter may exit without reading all of its input, that's not an error
+        let _ = writer.join();
+
+        if !output.status.success() {
+            return Err(error(format!("the command exited with {}", output.status)));
+        }
+        let body = String::from_utf8(output.stdout)
+            .map_err(|_| error("the output is not valid UTF-8".to_string()))?;
+
+        joplin_file.set_body(body.trim().to_string());
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_apply() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            ("tr a-z A-Z", Ok("BODY TEXT")),
+            (
+                "cat; echo; echo \"$JB_TITLE|$JB_TAGS|$JB_CREATED\"",
+                Ok("Body text\nTest|work/project|2024-03-07T23:22:26+00:00"),
+            ),
+            ("head -c 4", Ok("Body")),
+            ("exit 3", Err("the command exited with exit status: 3")),
+        ];
+
+        for (command, expected) in test_cases {
+            // arrange
+            let mut joplin_file = JoplinFile::build(
+                "work/project/a.md",
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\nBody text",
+                &Config::default(),
+            )
+            .unwrap();
+
+            // act
+            let result = FilterHook::new(command).apply(&mut joplin_file);
+
+            // assert
+            match expected {
+                Ok(body) => {
+                    assert_eq!(result, Ok(()));
+                    assert_eq!(joplin_file.body, body);
+                }
+                Err(e) => assert!(result.unwrap_err().ends_with(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::html_export::markdown_options;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+
+/// A heading of a note and the anchor links to it use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// 1 for `#`, up to 6
+    pub level: usize,
+    pub text: String,
+    /// Byte offset of the heading in the body
+    pub start: usize,
+    /// The anchor, unique within the note: the text lowercased, with spaces turned into
+    /// dashes and punctuation dropped, and `-1`, `-2`... added to the ones already taken
+    pub slug: String,
+}
+
+/// The headings of a markdown body in order, with their slugs
+pub fn find_headings(body: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut taken: HashMap<String, usize> = HashMap::new();
+    let mut current: Option<(usize, String, usize)> = None;
+
+    for (event, range) in Parser::new_ext(body, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some((level as usize, String::new(), range.start));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading_text, _)) = &mut current {
+                    heading_text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                let Some((level, text, start)) = current.take() else {
+                    continue;
+                };
+                let base = slugify(&text);
+                let count = taken.entry(base.clone()).or_default();
+                let slug = match *count {
+                    0 => base,
+                    n => format!("{base}-{n}"),
+                };
+                *count += 1;
+                headings.push(Heading {
+                    level,
+                    text,
+                    start,
+                    slug,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+/// The slugs that change between the headings of `before` and those of `after`, the same body
+/// with headings added or removed. Headings are matched by their text from the last one up, as
+/// headings are added at the top, like the title.
+pub fn moved_slugs(before: &str, after: &str) -> HashMap<String, String> {
+    let after = find_headings(after);
+    let mut remaining = after.iter().rev();
+
+    let mut moved = HashMap::new();
+    for heading in find_headings(before).into_iter().rev() {
+        let Some(matched) = remaining.find(|candidate| candidate.text == heading.text) else {
+            break;
+        };
+        if matched.slug != heading.slug {
+            moved.insert(heading.slug, matched.slug.clone());
+        }
+    }
+
+    moved
+}
+
+fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_headings() {
+        let headings = find_headings(
+            "# Title\n\nText\n\n## The `jb` tool, again!\n\n```\n# not a heading\n```\n\n# Title\n",
+        );
+
+        let found: Vec<(usize, &str, &str)> = headings
+            .iter()
+            .map(|heading| (heading.level, heading.text.as_str(), heading.slug.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, "Title", "title"),
+                (2, "The jb tool, again!", "the-jb-tool-again"),
+                (1, "Title", "title-1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_moved_slugs() {
+        let before = "Intro\n\n## Title\n\n## Other";
+        let after = "# Title\n#tag\n\nIntro\n\n## Title\n\n## Other\n";
+
+        let moved = moved_slugs(before, after);
+
+        assert_eq!(
+            moved,
+            HashMap::from([("title".to_string(), "title-1".to_string())])
+        );
+    }
+}
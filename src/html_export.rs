@@ -0,0 +1,200 @@
+use crate::bear::percent_encode;
+use crate::data_uri::links_resources;
+use crate::joplin_file_io::{copy_resources, target_paths};
+use crate::{Config, JoplinFile};
+use chrono_tz::Tz;
+use pulldown_cmark::{Options, Parser, html};
+use std::collections::BTreeMap;
+use std::fs::{self, create_dir_all};
+use std::path::{Component, Path, PathBuf};
+
+const INDEX_FILE_NAME: &str = "index.html";
+
+/// Writes a read-only HTML snapshot of the notes: a page per note laid out like the export, the
+/// resources, and an index page listing the notes by notebook. The resources that fail to copy
+/// are recorded in `io_errors` for the caller to report, the others are copied anyway
+pub fn write_html<P: AsRef<Path>>(
+    source_dir: P,
+    target_dir: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<(), String> {
+    let target_dir = target_dir.as_ref();
+    let base_len = target_dir.to_string_lossy().chars().count() + 1;
+    let relative_paths: Vec<PathBuf> = target_paths(joplin_files, base_len, config)?
+        .into_iter()
+        .map(|relative_path| relative_path.with_extension("html"))
+        .collect();
+
+    for (joplin_file, relative_path) in joplin_files.iter().zip(&relative_paths) {
+        let target_path = target_dir.join(relative_path);
+        if let Some(parent) = target_path.parent() {
+            create_dir_all(parent)
+                .map_err(|e| format!("Error creating directory {:?}: {}", parent, e))?;
+        }
+
+        fs::write(&target_path, render_note(joplin_file, config.timezone))
+            .map_err(|e| format!("Error writing file {:?}: {}", target_path, e))?;
+    }
+
+    let index_path = target_dir.join(INDEX_FILE_NAME);
+    fs::write(&index_path, render_index(joplin_files, &relative_paths))
+        .map_err(|e| format!("Error writing file {:?}: {}", index_path, e))?;
+
+    // Inlined resources are only copied when some are still linked, too large or unreadable
+    if config.inline_resources.is_some() && !links_resources(joplin_files) {
+        return Ok(());
+    }
+    copy_resources(source_dir.as_ref(), target_dir, config)
+}
+
+fn render_note(joplin_file: &JoplinFile, timezone: Tz) -> String {
+    let mut body = String::new();
+    html::push_html(
+        &mut body,
+        Parser::new_ext(&joplin_file.body, markdown_options()),
+    );
+
+    let tags = joplin_file
+        .tags
+        .as_ref()
+        .map(|tags| format!("<p class=\"tags\">{}</p>\n", escape_html(tags)))
+        .unwrap_or_default();
+
+    page(
+        &joplin_file.title,
+        &format!(
+            "<h1>{}</h1>\n<p class=\"dates\">Created {}, updated {}</p>\n{}{}",
+            escape_html(&joplin_file.title),
+            joplin_file
+                .created
+                .with_timezone(&timezone)
+                .format("%Y-%m-%d %H:%M"),
+            joplin_file
+                .updated
+                .with_timezone(&timezone)
+                .format("%Y-%m-%d %H:%M"),
+            tags,
+            body
+        ),
+    )
+}
+
+fn render_index(joplin_files: &[JoplinFile], relative_paths: &[PathBuf]) -> String {
+    let mut notebooks: BTreeMap<String, Vec<(&str, &Path)>> = BTreeMap::new();
+    for (joplin_file, relative_path) in joplin_files.iter().zip(relative_paths) {
+        let notebook = joplin_file
+            .relative_path
+            .parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default();
+        notebooks
+            .entry(notebook)
+            .or_default()
+            .push((&joplin_file.title, relative_path));
+    }
+
+    let mut content = String::from("<h1>Notes</h1>\n");
+    for (notebook, notes) in notebooks {
+        if !notebook.is_empty() {
+            content.push_str(&format!("<h2>{}</h2>\n", escape_html(&notebook)));
+        }
+        content.push_str("<ul>\n");
+        for (title, relative_path) in notes {
+            content.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                href(relative_path),
+                escape_html(title)
+            ));
+        }
+        content.push_str("</ul>\n");
+    }
+
+    page("Notes", &content)
+}
+
+fn page(title: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+        <body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        content
+    )
+}
+
+pub(crate) fn markdown_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+fn href(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(percent_encode(&name.to_string_lossy())),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joplin_file(relative_path: &str, title: &str, body: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: {title}\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_render_note() {
+        let html = render_note(
+            &joplin_file(
+                "Work/a.md",
+                "A <b>",
+                "Some **bold**\n\n![](../_resources/a.png)",
+            ),
+            Tz::America__New_York,
+        );
+
+        assert!(html.contains("<title>A &lt;b&gt;</title>"));
+        assert!(html.contains("<p class=\"tags\">#Work</p>"));
+        assert!(html.contains("<strong>bold</strong>"));
+        assert!(html.contains("Created 2024-03-07 18:22, updated 2024-04-07 04:34"));
+        assert!(html.contains("<img src=\"../_resources/a.png\""));
+    }
+
+    #[test]
+    fn test_render_index() {
+        // arrange
+        let joplin_files = vec![
+            joplin_file("Work/a b.md", "A", ""),
+            joplin_file("Home/c.md", "C", ""),
+            joplin_file("d.md", "D", ""),
+        ];
+        let relative_paths: Vec<PathBuf> = ["Work/a b.html", "Home/c.html", "d.html"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        // act
+        let html = render_index(&joplin_files, &relative_paths);
+
+        // assert
+        assert!(html.contains(
+            "<body>\n<h1>Notes</h1>\n<ul>\n<li><a href=\"d.html\">D</a></li>\n</ul>\n\
+            <h2>Home</h2>\n<ul>\n<li><a href=\"Home/c.html\">C</a></li>\n</ul>\n\
+            <h2>Work</h2>\n<ul>\n<li><a href=\"Work/a%20b.html\">A</a></li>\n</ul>\n</body>"
+        ));
+    }
+}
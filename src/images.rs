@@ -0,0 +1,402 @@
+//! Scaling down the image resources and stripping their EXIF data as they're copied, for the
+//! `image` feature
+
+use crate::config::Downscale;
+use exif::experimental::Writer;
+use exif::{Context, In, Reader, Tag};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, ImageResult};
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The tags that tell who took a photo with what, dropped with the GPS ones. Maker notes are
+/// in each vendor's own format and often hold the serial number, they go as well.
+const SERIAL_NUMBER_TAGS: [Tag; 4] = [
+    Tag::BodySerialNumber,
+    Tag::LensSerialNumber,
+    Tag::CameraOwnerName,
+    Tag::MakerNote,
+];
+
+const JPEG_EXIF_ID: &[u8] = b"Exif\0\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Writes a JPEG or PNG image larger than the maximum dimension to the target, scaled down to
+/// fit and in the same format so its file name stays right. Returns `false` for other files and
+/// for images that already fit, they're copied as they are.
+///
+/// The target keeps the modification time of the source, a target with the same time as the
+/// source that fits is taken to be downscaled already. A plain copy, from a run without
+/// downscaling, has the time too but not the size.
+pub fn downscale(source: &Path, target: &Path, downscale: Downscale) -> ImageResult<bool> {
+    let reader = ImageReader::open(source)?.with_guessed_format()?;
+    let Some(format @ (ImageFormat::Jpeg | ImageFormat::Png)) = reader.format() else {
+        return Ok(false);
+    };
+    let mut decoder = reader.into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    if width.max(height) <= downscale.max_dimension {
+        return Ok(false);
+    }
+
+    let modified = std::fs::metadata(source)?.modified()?;
+    let fits = |(width, height): (u32, u32)| width.max(height) <= downscale.max_dimension;
+    if is_written(target, modified) && image::image_dimensions(target).is_ok_and(fits) {
+        return Ok(true);
+    }
+
+    // Re-encoding drops the EXIF orientation, the pixels are turned instead
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    let image = image.resize(
+        downscale.max_dimension,
+        downscale.max_dimension,
+        FilterType::Lanczos3,
+    );
+
+    let mut writer = BufWriter::new(File::create(target)?);
+    match format {
+        ImageFormat::Jpeg => image.write_with_encoder(JpegEncoder::new_with_quality(
+            &mut writer,
+            downscale.quality,
+        ))?,
+        _ => image.write_to(&mut writer, format)?,
+    }
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .set_modified(modified)?;
+
+    Ok(true)
+}
+
+/// Writes a WebP image to the target as a PNG. Like with `downscale`, a target with the same
+/// modification time as the source is taken to be converted already.
+pub fn convert_to_png(source: &Path, target: &Path) -> ImageResult<()> {
+    let modified = std::fs::metadata(source)?.modified()?;
+    if is_written(target, modified) {
+        return Ok(());
+    }
+
+    let image = ImageReader::open(source)?.with_guessed_format()?.decode()?;
+    let mut writer = BufWriter::new(File::create(target)?);
+    image.write_to(&mut writer, ImageFormat::Png)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .set_modified(modified)?;
+    Ok(())
+}
+
+/// Writes a JPEG or PNG image to the target without the GPS position and the serial numbers of
+/// its EXIF data, keeping the rest like the orientation. The EXIF thumbnail is dropped with
+/// them. Returns `false` for other files and for images without those, they're copied as they
+/// are.
+///
+/// Unlike with `downscale`, the modification time doesn't tell a stripped target from a copy of
+/// the source made before, which would keep what's stripped. The target is compared with the
+/// stripped image instead, and written unless it's the same.
+pub fn strip_exif(source: &Path, target: &Path) -> std::io::Result<bool> {
+    let format = ImageReader::open(source)?.with_guessed_format()?.format();
+    if !matches!(format, Some(ImageFormat::Jpeg | ImageFormat::Png)) {
+        return Ok(false);
+    }
+
+    let modified = std::fs::metadata(source)?.modified()?;
+    let bytes = std::fs::read(source)?;
+    let stripped = match format {
+        Some(ImageFormat::Jpeg) => strip_jpeg(&bytes),
+        _ => strip_png(&bytes),
+    }
+    .map_err(std::io::Error::other)?;
+    let Some(stripped) = stripped else {
+        return Ok(false);
+    };
+    if std::fs::read(target).is_ok_and(|written| written == stripped) {
+        return Ok(true);
+    }
+
+    std::fs::write(target, stripped)?;
+    File::options()
+        .write(true)
+        .open(target)?
+        .set_modified(modified)?;
+    Ok(true)
+}
+
+/// Whether the target was written from a source with this modification time
+fn is_written(target: &Path, modified: SystemTime) -> bool {
+    std::fs::metadata(target)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|target_modified| target_modified == modified)
+}
+
+/// The JPEG with its EXIF segment stripped, `None` when there's nothing to strip
+fn strip_jpeg(bytes: &[u8]) -> Result<Option<Vec<u8>>, exif::Error> {
+    let broken = || exif::Error::InvalidFormat("Broken JPEG file");
+    let mut stripped = bytes.get(..2).ok_or_else(broken)?.to_vec();
+    let mut pos = 2;
+    let mut found = false;
+    // The segments come one after the other up to the start of the scan, the image data
+    loop {
+        let [0xff, marker, high, low, ..] = bytes[pos..] else {
+            return Err(broken());
+        };
+        if marker == 0xda {
+            break;
+        }
+        let end = pos + 2 + usize::from(u16::from_be_bytes([high, low]));
+        let segment = bytes.get(pos + 4..end).ok_or_else(broken)?;
+
+        match segment.strip_prefix(JPEG_EXIF_ID) {
+            Some(tiff) if marker == 0xe1 => {
+                let Some(tiff) = strip_tiff(tiff)? else {
+                    return Ok(None);
+                };
+                found = true;
+                if !tiff.is_empty() {
+                    let len =
+                        u16::try_from(2 + JPEG_EXIF_ID.len() + tiff.len()).map_err(|_| broken())?;
+                    stripped.extend([0xff, marker]);
+                    stripped.extend(len.to_be_bytes());
+                    stripped.extend(JPEG_EXIF_ID);
+                    stripped.extend(tiff);
+                }
+            }
+            _ => stripped.extend(&bytes[pos..end]),
+        }
+        pos = end;
+    }
+
+    stripped.extend(&bytes[pos..]);
+    Ok(found.then_some(stripped))
+}
+
+/// The PNG with its `eXIf` chunk stripped, `None` when there's nothing to strip
+fn strip_png(bytes: &[u8]) -> Result<Option<Vec<u8>>, exif::Error> {
+    let broken = || exif::Error::InvalidFormat("Broken PNG file");
+    let mut stripped = bytes
+        .get(..PNG_SIGNATURE.len())
+        .ok_or_else(broken)?
+        .to_vec();
+    let mut pos = PNG_SIGNATURE.len();
+    let mut found = false;
+    // Each chunk is its length, type, data and the CRC of the type and data
+    while let [a, b, c, d, ..] = bytes[pos..] {
+        let len = u32::from_be_bytes([a, b, c, d]) as usize;
+        let end = pos + 12 + len;
+        let chunk = bytes.get(pos..end).ok_or_else(broken)?;
+        let (chunk_type, data) = (&chunk[4..8], &chunk[8..8 + len]);
+
+        match chunk_type {
+            b"eXIf" => {
+                let Some(tiff) = strip_tiff(data)? else {
+                    return Ok(None);
+                };
+                found = true;
+                if !tiff.is_empty() {
+                    let len = u32::try_from(tiff.len()).map_err(|_| broken())?;
+                    let mut crc = crc32fast::Hasher::new();
+                    crc.update(b"eXIf");
+                    crc.update(&tiff);
+                    stripped.extend(len.to_be_bytes());
+                    stripped.extend(b"eXIf");
+                    stripped.extend(&tiff);
+                    stripped.extend(crc.finalize().to_be_bytes());
+                }
+            }
+            _ => stripped.extend(chunk),
+        }
+        pos = end;
+    }
+
+    Ok(found.then_some(stripped))
+}
+
+/// The EXIF data, as a TIFF structure, without the GPS and serial number fields and the
+/// thumbnail. Empty when nothing is left, `None` when there's nothing to strip.
+fn strip_tiff(tiff: &[u8]) -> Result<Option<Vec<u8>>, exif::Error> {
+    let exif = Reader::new().read_raw(tiff.to_vec())?;
+    let is_private = |tag: Tag| tag.context() == Context::Gps || SERIAL_NUMBER_TAGS.contains(&tag);
+    if !exif.fields().any(|field| is_private(field.tag)) {
+        return Ok(None);
+    }
+
+    let mut writer = Writer::new();
+    let mut kept = 0;
+    for field in exif.fields() {
+        if field.ifd_num == In::PRIMARY && !is_private(field.tag) {
+            writer.push_field(field);
+            // The writer makes the pointers to the other directories itself
+            if !matches!(
+                field.tag,
+                Tag::ExifIFDPointer | Tag::GPSInfoIFDPointer | Tag::InteropIFDPointer
+            ) {
+                kept += 1;
+            }
+        }
+    }
+    if kept == 0 {
+        return Ok(Some(Vec::new()));
+    }
+
+    let mut stripped = Cursor::new(Vec::new());
+    writer.write(&mut stripped, exif.little_endian())?;
+    Ok(Some(stripped.into_inner()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    /// Width and height in pixels
+    type Dimensions = (u32, u32);
+
+    #[test]
+    fn test_downscale() {
+        let test_cases: Vec<(&str, Dimensions, Option<Dimensions>)> = vec![
+            ("a.png", (40, 20), Some((10, 5))),
+            ("b.jpg", (20, 40), Some((5, 10))),
+            ("c.png", (10, 8), None),
+        ];
+
+        let dir = std::env::temp_dir().join("jb_downscale_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = Downscale {
+            max_dimension: 10,
+            quality: 80,
+        };
+
+        for (name, (width, height), expected) in test_cases {
+            // arrange
+            let source = dir.join(name);
+            let target = dir.join(format!("downscaled {}", name));
+            let _ = std::fs::remove_file(&target);
+            RgbImage::new(width, height).save(&source).unwrap();
+            // A copy made before, with the time of the source
+            crate::reflink::copy(&source, &target).unwrap();
+
+            // act
+            let downscaled = downscale(&source, &target, options).unwrap();
+
+            // assert
+            assert_eq!(downscaled, expected.is_some());
+            if let Some(dimensions) = expected {
+                assert_eq!(image::image_dimensions(&target).unwrap(), dimensions);
+                assert_eq!(
+                    std::fs::metadata(&target).unwrap().modified().unwrap(),
+                    std::fs::metadata(&source).unwrap().modified().unwrap()
+                );
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert_to_png() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_convert_to_png_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.webp");
+        let target = dir.join("a.webp.png");
+        RgbImage::new(6, 3).save(&source).unwrap();
+
+        // act
+        convert_to_png(&source, &target).unwrap();
+
+        // assert
+        let converted = ImageReader::open(&target)
+            .unwrap()
+            .with_guessed_format()
+            .unwrap();
+        assert_eq!(converted.format(), Some(ImageFormat::Png));
+        assert_eq!(converted.into_dimensions().unwrap(), (6, 3));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// An image in the format with EXIF data: an orientation, a camera, a GPS position and a
+    /// serial number
+    pub(crate) fn image_with_exif(format: ImageFormat) -> Vec<u8> {
+        let fields = [
+            (Tag::Orientation, exif::Value::Short(vec![6])),
+            (Tag::Make, exif::Value::Ascii(vec![b"Acme".to_vec()])),
+            (Tag::GPSLatitudeRef, exif::Value::Ascii(vec![b"N".to_vec()])),
+            (
+                Tag::BodySerialNumber,
+                exif::Value::Ascii(vec![b"1234".to_vec()]),
+            ),
+        ]
+        .map(|(tag, value)| exif::Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        });
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut image = Cursor::new(Vec::new());
+        DynamicImage::new_rgb8(4, 4)
+            .write_to(&mut image, format)
+            .unwrap();
+        let image = image.into_inner();
+        match format {
+            ImageFormat::Jpeg => {
+                let len = (2 + JPEG_EXIF_ID.len() + tiff.len()) as u16;
+                let segment = [&[0xff, 0xe1], &len.to_be_bytes()[..], JPEG_EXIF_ID, &tiff];
+                [&image[..2], &segment.concat(), &image[2..]].concat()
+            }
+            _ => {
+                let mut crc = crc32fast::Hasher::new();
+                crc.update(b"eXIf");
+                crc.update(&tiff);
+                let len = (tiff.len() as u32).to_be_bytes();
+                let chunk = [&len[..], b"eXIf", &tiff, &crc.finalize().to_be_bytes()];
+                // After the signature and the header chunk
+                [&image[..33], &chunk.concat(), &image[33..]].concat()
+            }
+        }
+    }
+
+    #[test]
+    fn test_strip_exif() {
+        let test_cases: Vec<(&str, ImageFormat)> =
+            vec![("a.jpg", ImageFormat::Jpeg), ("b.png", ImageFormat::Png)];
+
+        let dir = std::env::temp_dir().join("jb_strip_exif_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (name, format) in test_cases {
+            // arrange
+            let source = dir.join(name);
+            let target = dir.join(format!("stripped {}", name));
+            let _ = std::fs::remove_file(&target);
+            std::fs::write(&source, image_with_exif(format)).unwrap();
+            // A copy made before, with the time of the source
+            crate::reflink::copy(&source, &target).unwrap();
+
+            // act
+            let stripped = strip_exif(&source, &target).unwrap();
+
+            // assert
+            assert!(stripped);
+            let mut reader = std::io::BufReader::new(File::open(&target).unwrap());
+            let exif = Reader::new().read_from_container(&mut reader).unwrap();
+            let tags: Vec<Tag> = exif.fields().map(|field| field.tag).collect();
+            assert_eq!(tags, vec![Tag::Make, Tag::Orientation]);
+            assert_eq!(image::image_dimensions(&target).unwrap(), (4, 4));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
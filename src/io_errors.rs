@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How many times an operation that failed for a reason that may pass is tried again
+const RETRIES: u32 = 3;
+/// The wait before the first retry, doubled for each one after
+const BACKOFF: Duration = Duration::from_millis(50);
+
+/// The files that couldn't be read or written during a conversion. The conversion goes on
+/// without them and they're reported together at the end, rather than the first one stopping
+/// it; clones share what was recorded.
+#[derive(Debug, Clone, Default)]
+pub struct IoErrors(Arc<Mutex<Vec<(PathBuf, String)>>>);
+
+impl IoErrors {
+    pub fn record<E: fmt::Display>(&self, path: &Path, error: E) {
+        let mut errors = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        errors.push((path.to_path_buf(), error.to_string()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// An error listing the errors recorded after the first `before`, for the callers that
+    /// return a `Result` rather than report them at the end
+    pub fn check_since(&self, before: usize) -> Result<(), String> {
+        let errors = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(recorded) = errors.get(before..).filter(|recorded| !recorded.is_empty()) else {
+            return Ok(());
+        };
+
+        let listed: Vec<String> = recorded
+            .iter()
+            .map(|(path, error)| format!("{:?}: {}", path, error))
+            .collect();
+        Err(format!(
+            "{} files couldn't be read or written: {}",
+            recorded.len(),
+            listed.join(", ")
+        ))
+    }
+
+    /// The errors by the directory of their file, each directory and its files in order
+    pub fn by_directory(&self) -> BTreeMap<PathBuf, Vec<(String, String)>> {
+        let errors = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let mut by_directory: BTreeMap<PathBuf, Vec<(String, String)>> = BTreeMap::new();
+        for (path, error) in errors.iter() {
+            let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+            let file_name = path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            by_directory
+                .entry(directory)
+                .or_default()
+                .push((file_name, error.clone()));
+        }
+
+        for files in by_directory.values_mut() {
+            files.sort();
+        }
+        by_directory
+    }
+}
+
+impl fmt::Display for IoErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} files couldn't be read or written:", self.len())?;
+        for (directory, files) in self.by_directory() {
+            writeln!(f, "  {}", directory.display())?;
+            for (file_name, error) in files {
+                writeln!(f, "    {}: {}", file_name, error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs an IO operation, trying it again a few times when it fails for a reason that may pass:
+/// too many open files, or a timeout
+pub fn retry<T>(mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut wait = BACKOFF;
+    for _ in 0..RETRIES {
+        match operation() {
+            Err(e) if is_transient(&e) => {
+                std::thread::sleep(wait);
+                wait *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    operation()
+}
+
+fn is_transient(error: &io::Error) -> bool {
+    // EMFILE and ENFILE, the process or the system ran out of file descriptors
+    #[cfg(unix)]
+    if matches!(error.raw_os_error(), Some(23 | 24)) {
+        return true;
+    }
+
+    matches!(
+        error.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry() {
+        let test_cases: Vec<(io::ErrorKind, usize)> = vec![
+            (io::ErrorKind::TimedOut, 3),
+            (io::ErrorKind::PermissionDenied, 1),
+        ];
+
+        for (kind, expected_attempts) in test_cases {
+            let mut attempts = 0;
+
+            // Fails twice, then succeeds if it's tried again
+            let result = retry(|| {
+                attempts += 1;
+                if attempts < 3 {
+                    Err(io::Error::from(kind))
+                } else {
+                    Ok(())
+                }
+            });
+
+            assert_eq!(attempts, expected_attempts);
+            assert_eq!(result.is_ok(), expected_attempts == 3);
+        }
+    }
+
+    #[test]
+    fn test_check_since() {
+        let errors = IoErrors::default();
+        errors.record(Path::new("a.md"), "Permission denied");
+        let before = errors.len();
+        let unchanged = errors.check_since(before);
+        errors.record(Path::new("_resources/b.png"), "No space left on device");
+
+        let result = errors.check_since(before);
+
+        assert_eq!(unchanged, Ok(()));
+        assert_eq!(
+            result,
+            Err(
+                "1 files couldn't be read or written: \"_resources/b.png\": No space left on device"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_by_directory() {
+        let errors = IoErrors::default();
+        errors.record(Path::new("Work/b.md"), "Permission denied");
+        errors.record(Path::new("a.md"), "Permission denied");
+        errors.record(Path::new("Work/a.md"), "Too many open files");
+
+        let by_directory = errors.by_directory();
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            by_directory.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    PathBuf::new(),
+                    vec![("a.md".to_string(), "Permission denied".to_string())]
+                ),
+                (
+                    PathBuf::from("Work"),
+                    vec![
+                        ("a.md".to_string(), "Too many open files".to_string()),
+                        ("b.md".to_string(), "Permission denied".to_string()),
+                    ]
+                ),
+            ]
+        );
+    }
+}
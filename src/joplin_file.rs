@@ -1,9 +1,16 @@
-use chrono::{DateTime, Utc};
-use std::path::{Path, PathBuf};
+use crate::config::{Config, InvertedDates, SourceFlavor, TagOverflow, UnicodeNormalization};
+use crate::fidelity::Fidelity;
+use crate::resource_ref::{ResourceRef, find_resource_refs, percent_decode};
+use crate::text_stats::TextStats;
+use crate::warning::Warning;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use std::path::{Component, Path, PathBuf};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct JoplinFile {
     pub title: String,
+    /// The Joplin note id, only present when the export includes it in the front matter
+    pub id: Option<String>,
     pub created: DateTime<Utc>,
     pub updated: DateTime<Utc>,
 
@@ -12,67 +19,214 @@ pub struct JoplinFile {
     pub front_matter_end_pos: usize,
 
     pub body: String,
+    /// The length of the body as it was read, before scripts and filters change it
+    pub stats: TextStats,
+    /// The references to resources in the body, with their byte ranges in it. Kept in step
+    /// with the body by `set_body`
+    pub resources: Vec<ResourceRef>,
 
     pub tags: Option<String>,
 
     pub relative_path: PathBuf,
+
+    /// Problems found while converting the note, filled in by `build_joplin_files`
+    pub fidelity: Fidelity,
+
+    /// What was worked around to convert the note
+    pub warnings: Vec<Warning>,
 }
 
 impl JoplinFile {
-    const MARKER: &'static str = "---\n";
-    const MARKER_LEN: usize = Self::MARKER.len();
+    const MARKERS: [&'static str; 2] = ["---\n", "---\r\n"];
+    const BOM: char = '\u{feff}';
+    /// How much earlier than created the updated date can be before it's taken as inverted,
+    /// the clocks of the devices that synced the note may not have agreed
+    const CLOCK_SKEW: TimeDelta = TimeDelta::minutes(1);
 
     pub fn build<P: AsRef<Path>>(
         relative_path: P,
         content: &str,
+        config: &Config,
+    ) -> Result<JoplinFile, &'static str> {
+        Self::from_content(relative_path, content.to_string(), config)
+    }
+
+    /// The same as `build`, but the body reuses the memory of the content instead of being
+    /// copied out of it, which matters for notes of tens of MB: it halves what a note takes
+    /// while it's built
+    pub fn from_content<P: AsRef<Path>>(
+        relative_path: P,
+        mut content: String,
+        config: &Config,
     ) -> Result<JoplinFile, &'static str> {
-        let front_matter_start_pos = Self::find_front_matter_start(content)?;
+        // Positions are relative to the content after the BOM
+        if content.starts_with(Self::BOM) {
+            content.drain(..Self::BOM.len_utf8());
+        }
+
+        let front_matter_start_pos = Self::find_front_matter_start(&content)?;
 
-        let front_matter_end_pos = Self::find_front_matter_end(front_matter_start_pos, content)?;
+        let front_matter_end_pos = Self::find_front_matter_end(front_matter_start_pos, &content)?;
 
         let front_matter = content
             .get(front_matter_start_pos..front_matter_end_pos)
-            .ok_or("Could not find front matter")?;
+            .ok_or("Could not find front matter")?
+            .to_string();
 
-        let body = content[front_matter_end_pos..].trim().to_string();
+        // The body is what's left of the content once the front matter and the surrounding
+        // whitespace are cut off
+        let rest = &content[front_matter_end_pos..];
+        let body_end = front_matter_end_pos + rest.trim_end().len();
+        let body_start =
+            (front_matter_end_pos + rest.len() - rest.trim_start().len()).min(body_end);
+        content.truncate(body_end);
+        content.drain(..body_start);
+        let body = content;
 
-        let title = Self::find_title(front_matter)?;
+        let mut warnings = Vec::new();
+        let form = config.unicode_normalization;
+        let title = match Self::find_title(&front_matter) {
+            Ok(title) => form.normalize(title),
+            Err(e) => {
+                let file_name = relative_path.as_ref().file_name().ok_or(e)?;
+                warnings.push(Warning::TitleFromFileName);
+                form.normalize(config.strip_extension(&file_name.to_string_lossy()))
+            }
+        };
+        let id = Self::find_id(&front_matter);
+
+        let flavor = config.source_flavor;
+        let created = match Self::find_created(&front_matter, flavor) {
+            Ok(created) => created,
+            Err(e) => {
+                let keys = flavor.keys("created:");
+                let created = Self::find_date_without_offset(&front_matter, keys).ok_or(e)?;
+                warnings.push(Warning::DateAssumedUtc("created"));
+                created
+            }
+        };
+        let mut updated = match Self::find_updated(&front_matter, flavor) {
+            Ok(updated) => updated,
+            Err(e) => {
+                let keys = flavor.keys("updated:");
+                let updated = Self::find_date_without_offset(&front_matter, keys).ok_or(e)?;
+                warnings.push(Warning::DateAssumedUtc("updated"));
+                updated
+            }
+        };
+        if let Some(warning) = Self::check_dates(created, &mut updated, config.inverted_dates) {
+            warnings.push(warning);
+        }
 
-        let created = Self::find_created(front_matter)?;
-        let updated = Self::find_updated(front_matter)?;
+        let relative_path = Self::normalize_path(relative_path.as_ref(), form);
+        let mut tags: Vec<String> = Self::build_tags(&relative_path, config)
+            .into_iter()
+            .collect();
+        tags.extend(Self::find_tags(&front_matter, flavor, &mut warnings));
+        tags.extend(config.extra_tags.iter().cloned());
+        let tags = Self::merge_tags(tags, config).map(|tags| form.normalize(&tags));
 
-        let relative_path = relative_path.as_ref().to_path_buf();
-        let tags = Self::build_tags(&relative_path);
+        let resources = find_resource_refs(&body);
+        let unsupported: Vec<String> = resources
+            .iter()
+            .filter(|resource_ref| {
+                config.is_unsupported_attachment(Path::new(resource_ref.file_name()))
+            })
+            .map(|resource_ref| percent_decode(resource_ref.file_name()))
+            .collect();
+        if !unsupported.is_empty() {
+            warnings.push(Warning::UnsupportedAttachments(unsupported));
+        }
 
         Ok(JoplinFile {
-            title: title.to_string(),
+            title,
+            id,
             created,
             updated,
-            front_matter: front_matter.to_string(),
+            front_matter,
             front_matter_start_pos,
             front_matter_end_pos,
+            stats: TextStats::count(&body),
+            resources,
             body,
             relative_path,
             tags,
+            fidelity: Fidelity::default(),
+            warnings,
         })
     }
 
+    /// Adds a tag to the tag line, unless the note already has it
+    pub fn add_tag(&mut self, tag: &str) {
+        match &mut self.tags {
+            Some(tags) if tags.split_whitespace().any(|t| t == tag) => {}
+            Some(tags) => {
+                tags.push(' ');
+                tags.push_str(tag);
+            }
+            None => self.tags = Some(tag.to_string()),
+        }
+    }
+
+    /// When the note is due, for Joplin to-dos that have a due date and aren't completed
+    pub fn due(&self) -> Option<DateTime<Utc>> {
+        const DUE_KEY: &str = "due:";
+        const COMPLETED_KEY: &str = "completed?:";
+        if Self::find_front_matter_value(&self.front_matter, COMPLETED_KEY) == Some("yes") {
+            return None;
+        }
+
+        let due = Self::find_front_matter_value(&self.front_matter, DUE_KEY)?;
+        DateTime::parse_from_rfc3339(due)
+            .ok()
+            .map(|due| due.to_utc())
+    }
+
+    /// All references to resources (attachments and Joplin `:/id` links) found in the body
+    pub fn referenced_resources(&self) -> &[ResourceRef] {
+        &self.resources
+    }
+
+    /// Replaces the body, finding its references to resources again
+    pub fn set_body(&mut self, body: String) {
+        self.resources = find_resource_refs(&body);
+        self.body = body;
+    }
+
+    /// The front matter has to be the first thing in the file, only blank lines may precede it.
+    /// A `---` further down is a horizontal rule, not front matter.
     fn find_front_matter_start(content: &str) -> Result<usize, &'static str> {
-        content
-            .find(Self::MARKER)
-            .ok_or("Could not find front matter start marker")
+        let blank_len: usize = content
+            .split_inclusive('\n')
+            .take_while(|line| line.trim().is_empty() && line.ends_with('\n'))
+            .map(str::len)
+            .sum();
+
+        match Self::find_marker(&content[blank_len..]) {
+            Some((0, _)) => Ok(blank_len),
+            _ => Err("Could not find front matter start marker"),
+        }
     }
 
     fn find_front_matter_end(fm_start_pos: usize, content: &str) -> Result<usize, &'static str> {
-        let after_start_pos = fm_start_pos + Self::MARKER_LEN;
+        let start_marker_len = Self::find_marker(content.get(fm_start_pos..).unwrap_or_default())
+            .filter(|(pos, _)| *pos == 0)
+            .map_or(Self::MARKERS[0].len(), |(_, len)| len);
+        let after_start_pos = fm_start_pos + start_marker_len;
         let content_after_start = &content
             .get(after_start_pos..)
             .ok_or("Could not find front matter after start marker")?;
 
-        let end_relative = content_after_start
-            .find(Self::MARKER)
-            .ok_or("Could not find end of front matter")?;
-        let end_pos = after_start_pos + end_relative + Self::MARKER_LEN;
+        let Some((end_relative, end_marker_len)) = Self::find_marker(content_after_start) else {
+            // Some editors don't end the file with a newline, the end marker is then the last line
+            let rest = content_after_start.trim_end();
+            return if rest == "---" || rest.ends_with("\n---") {
+                Ok(content.len())
+            } else {
+                Err("Could not find end of front matter")
+            };
+        };
+        let end_pos = after_start_pos + end_relative + end_marker_len;
 
         if end_pos > content.len() {
             Err("Could not find end of front matter")
@@ -81,23 +235,48 @@ impl JoplinFile {
         }
     }
 
+    /// The position and length of the first marker, whichever line ending it uses
+    fn find_marker(content: &str) -> Option<(usize, usize)> {
+        Self::MARKERS
+            .iter()
+            .filter_map(|marker| content.find(marker).map(|pos| (pos, marker.len())))
+            .min()
+    }
+
     fn find_title(front_matter: &str) -> Result<&str, &'static str> {
         const TITLE_KEY: &str = "title:";
         Self::find_front_matter_value(front_matter, TITLE_KEY).ok_or("Could not find title")
     }
 
-    fn find_created(front_matter: &str) -> Result<DateTime<Utc>, &'static str> {
+    fn find_id(front_matter: &str) -> Option<String> {
+        const ID_KEY: &str = "id:";
+        Self::find_front_matter_value(front_matter, ID_KEY).map(str::to_string)
+    }
+
+    fn find_created(
+        front_matter: &str,
+        flavor: SourceFlavor,
+    ) -> Result<DateTime<Utc>, &'static str> {
         const CREATED_KEY: &str = "created:";
-        let created = Self::find_front_matter_value(front_matter, CREATED_KEY)
+        let created = flavor
+            .keys(CREATED_KEY)
+            .into_iter()
+            .find_map(|key| Self::find_front_matter_value(front_matter, key))
             .ok_or("Could not find created")?;
 
         DateTime::parse_from_rfc3339(created)
             .map(|result| result.to_utc())
             .map_err(|_| "Could not parse created date")
     }
-    fn find_updated(front_matter: &str) -> Result<DateTime<Utc>, &'static str> {
+    fn find_updated(
+        front_matter: &str,
+        flavor: SourceFlavor,
+    ) -> Result<DateTime<Utc>, &'static str> {
         const UPDATED_KEY: &str = "updated:";
-        let updated = Self::find_front_matter_value(front_matter, UPDATED_KEY)
+        let updated = flavor
+            .keys(UPDATED_KEY)
+            .into_iter()
+            .find_map(|key| Self::find_front_matter_value(front_matter, key))
             .ok_or("Could not find updated")?;
 
         DateTime::parse_from_rfc3339(updated)
@@ -105,14 +284,47 @@ impl JoplinFile {
             .map_err(|_| "Could not parse updated date")
     }
 
+    /// A date without a UTC offset, like `2024-03-07 23:22:26` or `2024-03-07`, taken as UTC.
+    /// Other apps than Joplin write those.
+    fn find_date_without_offset(front_matter: &str, keys: Vec<&str>) -> Option<DateTime<Utc>> {
+        let value = keys
+            .into_iter()
+            .find_map(|key| Self::find_front_matter_value(front_matter, key))?;
+
+        ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+            .map(|date| date.and_utc())
+    }
+
+    /// The warning for an updated date before the created one, beyond the clock skew. Incremental
+    /// conversions compare the updated dates, so the repair sets it to the created date.
+    fn check_dates(
+        created: DateTime<Utc>,
+        updated: &mut DateTime<Utc>,
+        inverted_dates: InvertedDates,
+    ) -> Option<Warning> {
+        if created - *updated <= Self::CLOCK_SKEW {
+            return None;
+        }
+
+        let repaired = inverted_dates == InvertedDates::Repair;
+        if repaired {
+            *updated = created;
+        }
+        Some(Warning::UpdatedBeforeCreated { repaired })
+    }
+
     fn find_front_matter_value<'a>(front_matter: &'a str, key: &'a str) -> Option<&'a str> {
         let value = front_matter.lines().find_map(|line| {
-            let trimmed = line.trim();
-            if trimmed.starts_with(key) {
-                Some(trimmed[key.len()..].trim_start())
-            } else {
-                None
-            }
+            line.trim()
+                .strip_prefix(key)
+                .map(|value| value.trim_start())
         });
 
         match value {
@@ -121,27 +333,148 @@ impl JoplinFile {
         }
     }
 
-    fn build_tags<P: AsRef<Path>>(relative_path: P) -> Option<String> {
+    /// The tags listed in the front matter, either inline (`tags: a, b` or `tags: [a, b]`) or as
+    /// a YAML list on the following lines. Tags with spaces add a warning.
+    fn find_tags(
+        front_matter: &str,
+        flavor: SourceFlavor,
+        warnings: &mut Vec<Warning>,
+    ) -> Vec<String> {
+        const TAGS_KEY: &str = "tags:";
+
+        flavor
+            .keys(TAGS_KEY)
+            .into_iter()
+            .flat_map(|key| Self::find_tags_under(front_matter, key))
+            .map(|value| {
+                let tag = Self::sanitize_tag(value);
+                if value.contains(' ') {
+                    warnings.push(Warning::TagSanitized {
+                        tag: value.to_string(),
+                        sanitized: tag.clone(),
+                    });
+                }
+                tag
+            })
+            .collect()
+    }
+
+    /// The tag as Bear reads it: starting with `#`, its spaces turned into dashes as a space
+    /// would end it
+    pub(crate) fn sanitize_tag(value: &str) -> String {
+        format!("#{}", value.trim_start_matches('#').replace(" ", "-"))
+    }
+
+    /// The values listed under the key, trimmed and unquoted
+    fn find_tags_under<'a>(front_matter: &'a str, key: &str) -> Vec<&'a str> {
+        let mut lines = front_matter
+            .lines()
+            .skip_while(|line| !line.starts_with(key));
+        let Some(inline) = lines.next().map(|line| line[key.len()..].trim()) else {
+            return Vec::new();
+        };
+
+        let values: Vec<&str> = if inline.is_empty() {
+            lines
+                .map_while(|line| line.trim().strip_prefix("- "))
+                .collect()
+        } else {
+            inline
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .collect()
+        };
+
+        values
+            .into_iter()
+            .map(|value| value.trim().trim_matches(['"', '\'']).trim())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    /// Joins the tags into a single line, dropping duplicates and, when configured, any tag that
+    /// is an ancestor of another tag
+    fn merge_tags(tags: Vec<String>, config: &Config) -> Option<String> {
+        let mut merged: Vec<String> = Vec::new();
+        for tag in tags {
+            if !merged.contains(&tag) {
+                merged.push(tag);
+            }
+        }
+
+        if config.prune_ancestor_tags {
+            let all = merged.clone();
+            merged.retain(|tag| {
+                !all.iter()
+                    .any(|other| other.starts_with(&format!("{tag}/")))
+            });
+        }
+
+        if merged.is_empty() {
+            None
+        } else {
+            Some(merged.join(" "))
+        }
+    }
+
+    fn normalize_path(path: &Path, form: UnicodeNormalization) -> PathBuf {
+        match path.to_str() {
+            Some(path) => PathBuf::from(form.normalize(path)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    fn build_tags<P: AsRef<Path>>(relative_path: P, config: &Config) -> Option<String> {
         let path = relative_path.as_ref();
 
-        let tag_count = path.components().count();
-        if tag_count == 0 {
+        // Split on both separators, an export copied between macOS and Windows can contain
+        // either one whatever platform we're running on
+        let mut components: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(component) => Some(component),
+                _ => None,
+            })
+            .flat_map(|component| {
+                component
+                    .to_string_lossy()
+                    .split(['/', '\\'])
+                    .filter(|part| !part.is_empty())
+                    .map(|part| part.replace(" ", "-"))
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+
+        let file_name = components.pop()?;
+        if config.tag_with_filename {
+            components.push(config.strip_extension(&file_name).to_string());
+        }
+
+        if components.is_empty() {
             return None;
         }
 
-        let mut tags = "#".to_string();
-        path.iter().enumerate().for_each(|(i, component)| {
-            let component = component.to_str().unwrap().replace(" ", "-");
+        if let Some(max_depth) = config.max_tag_depth.filter(|&max| max > 0) {
+            Self::limit_tag_depth(&mut components, max_depth, config.tag_overflow);
+        }
 
-            if i < tag_count - 1 {
-                tags.push_str(&component);
-                tags.push('/')
-            } else {
-                tags.push_str(component.trim_end_matches(".md"));
-            }
-        });
+        Some(format!("#{}", components.join("/")))
+    }
+
+    fn limit_tag_depth(components: &mut Vec<String>, max_depth: usize, overflow: TagOverflow) {
+        if components.len() <= max_depth {
+            return;
+        }
 
-        Some(tags)
+        match overflow {
+            TagOverflow::Truncate => components.truncate(max_depth),
+            TagOverflow::Join => {
+                let joined = components[max_depth - 1..].join("-");
+                components.truncate(max_depth - 1);
+                components.push(joined);
+            }
+        }
     }
 }
 
@@ -156,6 +489,15 @@ mod tests {
             ("\n---\n", Ok(1)),
             ("", Err("Could not find front matter start marker")),
             ("---", Err("Could not find front matter start marker")),
+            ("---\r\n", Ok(0)),
+            ("\r\n---\r\n", Ok(2)),
+            ("\n  \n---\n", Ok(4)),
+            (
+                "Some text\n\n---\nMore text\n",
+                Err("Could not find front matter start marker"),
+            ),
+            (" ---\n", Err("Could not find front matter start marker")),
+            ("\n\n", Err("Could not find front matter start marker")),
         ];
 
         for (test_case, expected) in test_cases {
@@ -175,6 +517,17 @@ mod tests {
                 0,
                 Err("Could not find end of front matter"),
             ),
+            ("---\r\n blah\r\n---\r\n", 0, Ok(17)),
+            ("---\r\n blah\n---\n", 0, Ok(15)),
+            ("---\n blah\r\n---\r\n", 0, Ok(16)),
+            ("---\n blah\n---", 0, Ok(13)),
+            ("---\r\n blah\r\n---\r", 0, Ok(16)),
+            ("---\n---", 0, Ok(7)),
+            (
+                "---\n blah\n----",
+                0,
+                Err("Could not find end of front matter"),
+            ),
         ];
 
         for (test_case, start_pos, expected) in test_cases {
@@ -198,6 +551,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn find_id() {
+        let test_cases: Vec<(&str, Option<String>)> = vec![
+            (
+                "---\nid: 0123456789abcdef0123456789abcdef\n---\n",
+                Some("0123456789abcdef0123456789abcdef".to_string()),
+            ),
+            ("---\nid:\n---\n", None),
+            ("---\ntitle: Test\n---\n", None),
+        ];
+
+        for (test_case, expected) in test_cases {
+            let result = JoplinFile::find_id(test_case);
+            assert_eq!(result, expected);
+        }
+    }
+
     #[test]
     fn find_created() {
         let test_cases: Vec<(&str, Result<DateTime<Utc>, &'static str>)> = vec![
@@ -226,7 +596,7 @@ mod tests {
         ];
 
         for (test_case, expected) in test_cases {
-            let result = JoplinFile::find_created(test_case);
+            let result = JoplinFile::find_created(test_case, SourceFlavor::Joplin);
             assert_eq!(result, expected);
         }
     }
@@ -259,29 +629,428 @@ mod tests {
         ];
 
         for (test_case, expected) in test_cases {
-            let result = JoplinFile::find_updated(test_case);
+            let result = JoplinFile::find_updated(test_case, SourceFlavor::Joplin);
             assert_eq!(result, expected);
         }
     }
 
     #[test]
     fn test_build_tags() {
-        let test_cases: Vec<(&str, Option<String>)> = vec![
-            ("", None),
-            ("blah.md", Some("#blah".to_string())),
-            ("foo/bar/baz.md", Some("#foo/bar/baz".to_string())),
+        let test_cases: Vec<(&str, bool, Option<String>)> = vec![
+            ("", true, None),
+            ("blah.md", true, Some("#blah".to_string())),
+            ("foo/bar/baz.md", true, Some("#foo/bar/baz".to_string())),
+            ("", false, None),
+            ("blah.md", false, None),
+            ("foo/bar/baz.md", false, Some("#foo/bar".to_string())),
+            ("foo\\bar\\baz.md", true, Some("#foo/bar/baz".to_string())),
+            ("foo\\bar/baz.md", false, Some("#foo/bar".to_string())),
+            (
+                "foo/bar baz\\qux.md",
+                true,
+                Some("#foo/bar-baz/qux".to_string()),
+            ),
+            ("./foo/bar.md", false, Some("#foo".to_string())),
+            ("foo\\\\bar.md", false, Some("#foo".to_string())),
+            ("foo/bar.markdown", true, Some("#foo/bar".to_string())),
+            ("foo/bar.TXT", true, Some("#foo/bar".to_string())),
+            ("foo/bar.md.txt", true, Some("#foo/bar.md".to_string())),
+        ];
+
+        for (relative_path, tag_with_filename, expected) in test_cases {
+            let config = Config {
+                extensions: vec!["md".to_string(), "markdown".to_string(), "txt".to_string()],
+                tag_with_filename,
+                ..Config::default()
+            };
+            let result = JoplinFile::build_tags(relative_path, &config);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_find_tags() {
+        let test_cases: Vec<(&str, Vec<&str>)> = vec![
+            ("---\ntitle: Test\n---\n", vec![]),
+            ("---\ntags:\n---\n", vec![]),
+            ("---\ntags: foo, bar baz\n---\n", vec!["#foo", "#bar-baz"]),
+            ("---\ntags: [foo, \"bar\"]\n---\n", vec!["#foo", "#bar"]),
+            (
+                "---\ntags:\n  - foo\n  - work/project\ntitle: Test\n---\n",
+                vec!["#foo", "#work/project"],
+            ),
+        ];
+
+        for (front_matter, expected) in test_cases {
+            let result = JoplinFile::find_tags(front_matter, SourceFlavor::Joplin, &mut Vec::new());
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_build_source_flavor() {
+        let content = "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\n\
+            modified: 2024-04-07T08:34:52Z\nkeywords: foo, bar\n---\nBody";
+        let test_cases: Vec<(SourceFlavor, Result<Option<&str>, &'static str>)> = vec![
+            (SourceFlavor::Joplin, Err("Could not find updated")),
+            (SourceFlavor::Notable, Ok(None)),
+            (SourceFlavor::Zettlr, Ok(Some("#foo #bar"))),
+        ];
+
+        for (source_flavor, expected) in test_cases {
+            let config = Config {
+                source_flavor,
+                ..Config::default()
+            };
+
+            let result = JoplinFile::build("a.md", content, &config);
+
+            match expected {
+                Ok(tags) => {
+                    let joplin_file = result.unwrap();
+                    assert_eq!(
+                        joplin_file.updated.to_rfc3339(),
+                        "2024-04-07T08:34:52+00:00"
+                    );
+                    assert_eq!(joplin_file.tags.as_deref(), tags);
+                }
+                Err(e) => assert_eq!(result.unwrap_err(), e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_tags() {
+        let test_cases: Vec<(Vec<&str>, bool, Option<&str>)> = vec![
+            (vec![], false, None),
+            (vec!["#work"], true, Some("#work")),
+            (vec!["#work", "#work"], false, Some("#work")),
+            (
+                vec!["#work", "#work/project", "#workshop"],
+                false,
+                Some("#work #work/project #workshop"),
+            ),
+            (
+                vec!["#work", "#work/project", "#workshop"],
+                true,
+                Some("#work/project #workshop"),
+            ),
+            (vec!["#a", "#a/b", "#a/b/c", "#d"], true, Some("#a/b/c #d")),
+        ];
+
+        for (tags, prune_ancestor_tags, expected) in test_cases {
+            let config = Config {
+                prune_ancestor_tags,
+                ..Config::default()
+            };
+            let tags = tags.into_iter().map(str::to_string).collect();
+            let result = JoplinFile::merge_tags(tags, &config);
+            assert_eq!(result.as_deref(), expected);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_tags_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let config = Config::default();
+        let path = Path::new(OsStr::from_bytes(b"foo\xff/bar.md"));
+
+        let result = JoplinFile::build_tags(path, &config);
+
+        assert_eq!(result, Some("#foo\u{fffd}".to_string()));
+    }
+
+    #[test]
+    fn test_build_tags_max_depth() {
+        let test_cases: Vec<(&str, Option<usize>, TagOverflow, Option<String>)> = vec![
+            (
+                "a/b/c/d/e.md",
+                None,
+                TagOverflow::Truncate,
+                Some("#a/b/c/d/e".to_string()),
+            ),
+            (
+                "a/b/c/d/e.md",
+                Some(0),
+                TagOverflow::Truncate,
+                Some("#a/b/c/d/e".to_string()),
+            ),
+            (
+                "a/b/c/d/e.md",
+                Some(2),
+                TagOverflow::Truncate,
+                Some("#a/b".to_string()),
+            ),
+            (
+                "a/b/c/d/e.md",
+                Some(2),
+                TagOverflow::Join,
+                Some("#a/b-c-d-e".to_string()),
+            ),
+            (
+                "a/b/c/d/e.md",
+                Some(1),
+                TagOverflow::Join,
+                Some("#a-b-c-d-e".to_string()),
+            ),
+            (
+                "a/b.md",
+                Some(3),
+                TagOverflow::Join,
+                Some("#a/b".to_string()),
+            ),
         ];
 
-        for (relative_path, expected) in test_cases {
-            let result = JoplinFile::build_tags(relative_path);
+        for (relative_path, max_tag_depth, tag_overflow, expected) in test_cases {
+            let config = Config {
+                max_tag_depth,
+                tag_overflow,
+                tag_with_filename: true,
+                ..Config::default()
+            };
+            let result = JoplinFile::build_tags(relative_path, &config);
             assert_eq!(result, expected);
         }
     }
 
+    #[test]
+    fn test_build_unicode_normalization() {
+        let nfc = "Caf\u{e9}";
+        let nfd = "Cafe\u{301}";
+        let test_cases: Vec<(UnicodeNormalization, &str, &str)> = vec![
+            (UnicodeNormalization::Nfc, nfd, nfc),
+            (UnicodeNormalization::Nfc, nfc, nfc),
+            (UnicodeNormalization::Nfd, nfc, nfd),
+            (UnicodeNormalization::Off, nfd, nfd),
+        ];
+
+        for (unicode_normalization, source, expected) in test_cases {
+            let config = Config {
+                unicode_normalization,
+                ..Config::default()
+            };
+            let content = format!(
+                "---\ntitle: {source}\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n"
+            );
+
+            let result =
+                JoplinFile::build(format!("{source}/{source}.md"), &content, &config).unwrap();
+
+            assert_eq!(result.title, expected);
+            assert_eq!(result.tags, Some(format!("#{expected}")));
+            assert_eq!(
+                result.relative_path,
+                PathBuf::from(format!("{expected}/{expected}.md"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_crlf_and_bom() {
+        let test_cases: Vec<&str> = vec![
+            "---\r\ntitle: Test\r\ncreated: 2024-03-07T23:22:26Z\r\nupdated: 2024-04-07T08:34:52Z\r\n---\r\n\r\nThe content\r\n",
+            "\u{feff}---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\nThe content\n",
+            "\u{feff}---\r\ntitle: Test\r\ncreated: 2024-03-07T23:22:26Z\r\nupdated: 2024-04-07T08:34:52Z\r\n---\r\nThe content",
+        ];
+
+        for content in test_cases {
+            let result = JoplinFile::build("note.md", content, &Config::default());
+
+            let joplin_file = result.unwrap();
+            assert_eq!(joplin_file.title, "Test");
+            assert_eq!(joplin_file.front_matter_start_pos, 0);
+            assert_eq!(
+                joplin_file.updated,
+                DateTime::parse_from_rfc3339("2024-04-07T08:34:52Z")
+                    .unwrap()
+                    .to_utc()
+            );
+            assert_eq!(joplin_file.body, "The content");
+        }
+    }
+
+    #[test]
+    fn test_build_warnings() {
+        let test_cases: Vec<(&str, &str, Vec<Warning>)> = vec![
+            (
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+                "Test",
+                vec![],
+            ),
+            (
+                "---\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+                "note",
+                vec![Warning::TitleFromFileName],
+            ),
+            (
+                "---\ntitle: Test\ncreated: 2024-03-07 23:22:26\nupdated: 2024-04-07\n---\n",
+                "Test",
+                vec![
+                    Warning::DateAssumedUtc("created"),
+                    Warning::DateAssumedUtc("updated"),
+                ],
+            ),
+            (
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n\
+                tags: foo, bar baz\n---\n",
+                "Test",
+                vec![Warning::TagSanitized {
+                    tag: "bar baz".to_string(),
+                    sanitized: "#bar-baz".to_string(),
+                }],
+            ),
+            (
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+                ![](../_resources/a.png) ![](../_resources/b%20c.SVG) [d](../_resources/d.webp)",
+                "Test",
+                vec![Warning::UnsupportedAttachments(vec![
+                    "b c.SVG".to_string(),
+                    "d.webp".to_string(),
+                ])],
+            ),
+        ];
+
+        for (content, title, expected) in test_cases {
+            let joplin_file = JoplinFile::build("note.md", content, &Config::default()).unwrap();
+
+            assert_eq!(joplin_file.title, title);
+            assert_eq!(joplin_file.warnings, expected);
+        }
+    }
+
+    #[test]
+    fn test_resources() {
+        // arrange
+        let mut joplin_file = JoplinFile::build(
+            "note.md",
+            "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            See ![](../_resources/a.png) and [b](:/0123456789abcdef0123456789abcdef)",
+            &Config::default(),
+        )
+        .unwrap();
+        let spans = |joplin_file: &JoplinFile| -> Vec<String> {
+            joplin_file
+                .referenced_resources()
+                .iter()
+                .map(|resource_ref| joplin_file.body[resource_ref.span.clone()].to_string())
+                .collect()
+        };
+        let found = spans(&joplin_file);
+
+        // act
+        joplin_file.set_body("Only ![](../_resources/c.png)".to_string());
+
+        // assert
+        assert_eq!(
+            found,
+            vec!["../_resources/a.png", ":/0123456789abcdef0123456789abcdef"]
+        );
+        assert_eq!(spans(&joplin_file), vec!["../_resources/c.png"]);
+    }
+
+    #[test]
+    fn test_check_dates() {
+        let test_cases: Vec<(&str, InvertedDates, &str, Option<Warning>)> = vec![
+            (
+                "2024-04-07T08:34:52Z",
+                InvertedDates::Repair,
+                "2024-04-07T08:34:52Z",
+                None,
+            ),
+            (
+                "2024-03-07T23:21:30Z",
+                InvertedDates::Repair,
+                "2024-03-07T23:21:30Z",
+                None,
+            ),
+            (
+                "2024-03-06T10:00:00Z",
+                InvertedDates::Warn,
+                "2024-03-06T10:00:00Z",
+                Some(Warning::UpdatedBeforeCreated { repaired: false }),
+            ),
+            (
+                "2024-03-06T10:00:00Z",
+                InvertedDates::Repair,
+                "2024-03-07T23:22:26Z",
+                Some(Warning::UpdatedBeforeCreated { repaired: true }),
+            ),
+        ];
+
+        for (updated, inverted_dates, expected_updated, expected_warning) in test_cases {
+            let created = "2024-03-07T23:22:26Z".parse().unwrap();
+            let mut updated: DateTime<Utc> = updated.parse().unwrap();
+
+            let warning = JoplinFile::check_dates(created, &mut updated, inverted_dates);
+
+            assert_eq!(warning, expected_warning);
+            assert_eq!(updated, expected_updated.parse::<DateTime<Utc>>().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_from_content_reuses_content() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            (
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\n  The content \n",
+                "The content",
+            ),
+            (
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n \n",
+                "",
+            ),
+            (
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---",
+                "",
+            ),
+        ];
+
+        for (content, expected) in test_cases {
+            let content = content.to_string();
+            let buffer = content.as_ptr();
+
+            let joplin_file = JoplinFile::from_content("note.md", content, &Config::default());
+
+            let joplin_file = joplin_file.unwrap();
+            assert_eq!(joplin_file.body, expected);
+            assert_eq!(joplin_file.body.as_ptr(), buffer);
+        }
+    }
+
+    #[test]
+    fn test_add_tag() {
+        let test_cases: Vec<(Option<&str>, &str, Option<&str>)> = vec![
+            (None, "#jb/review", Some("#jb/review")),
+            (Some("#foo"), "#jb/review", Some("#foo #jb/review")),
+            (
+                Some("#jb/review #foo"),
+                "#jb/review",
+                Some("#jb/review #foo"),
+            ),
+        ];
+
+        for (tags, tag, expected) in test_cases {
+            let mut joplin_file = JoplinFile::build(
+                "note.md",
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+                &Config::default(),
+            )
+            .unwrap();
+            joplin_file.tags = tags.map(str::to_string);
+
+            joplin_file.add_tag(tag);
+
+            assert_eq!(joplin_file.tags.as_deref(), expected);
+        }
+    }
+
     #[test]
     fn test_build() {
         // arrange
-        let test_cases: Vec<(&str, &str, &str, &str)> = vec![
+        let test_cases: Vec<(&str, &str, &str, Option<&str>)> = vec![
             (
                 "foo.md",
                 "\
@@ -291,7 +1060,7 @@ created: 2024-03-07T23:22:26Z
 updated: 2024-04-07T08:34:52Z
 ---\n",
                 "",
-                "#foo",
+                None,
             ),
             (
                 "blah bah/foo.md",
@@ -304,13 +1073,13 @@ updated: 2024-04-07T08:34:52Z
 
 The content\n",
                 "The content",
-                "#blah-bah/foo",
+                Some("#blah-bah"),
             ),
         ];
 
         for (relative_path, content, body, expected_tags) in test_cases {
             // act
-            let result = JoplinFile::build(relative_path, content);
+            let result = JoplinFile::build(relative_path, content, &Config::default());
 
             // assert
             assert!(result.is_ok());
@@ -334,7 +1103,7 @@ The content\n",
                     .unwrap()
                     .to_utc()
             );
-            assert_eq!(joplin_file.tags, Some(expected_tags.to_string()));
+            assert_eq!(joplin_file.tags.as_deref(), expected_tags);
         }
     }
 }
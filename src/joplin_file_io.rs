@@ -1,200 +1,2813 @@
-use crate::JoplinFile;
-use glob::MatchOptions;
-use glob::glob_with;
+use crate::alt_text::fill_alt_text;
+use crate::archive::{ZipSource, is_zip};
+use crate::bear::percent_encode;
+use crate::config::{ResourceLimitPolicy, ResourceLink, TagPlacement, is_webp};
+use crate::conflict::resolve_conflicts;
+use crate::console::{Status, porcelain};
+use crate::duplicate::resolve_duplicates;
+use crate::emoji::expand_shortcodes;
+use crate::existing::{ExistingResolver, Resolution, free_path};
+use crate::fidelity::{Fidelity, REVIEW_TAG};
+use crate::filter::FilterHook;
+use crate::io_errors::retry;
+use crate::journal::{Journal, fingerprint};
+use crate::merge::merge_notebooks;
+use crate::note_links::{has_scheme, link_notes, resolve};
+use crate::path_template::{number_duplicates, render_unique};
+use crate::punctuation::convert_punctuation;
+use crate::reflink;
+use crate::resource_limits::over_limits;
+use crate::resource_ref::{ResourceRef, ResourceTarget, find_links, percent_decode};
+use crate::sanitize::{fit_paths, is_valid_replacement, sanitize_path};
+use crate::sniff::sniff_file_extension;
+use crate::split::split_large_notes;
+use crate::tasks::NoteTasks;
+use crate::tidy::{convert_line_breaks, tidy_whitespace};
+use crate::timings::Phase;
+use crate::toc::replace_toc_markers;
+use crate::warning::Warning;
+use crate::xattrs;
+use crate::{Cancellation, Config, JoplinFile};
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use md5::{Digest, Md5};
+use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fs::File;
 use std::fs::create_dir_all;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::iter::Peekable;
 #[cfg(target_os = "macos")]
 use std::os::darwin::fs::FileTimesExt;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
 #[cfg(target_os = "windows")]
 use std::os::windows::fs::FileTimesExt;
-use std::path::{Path, PathBuf};
+#[cfg(windows)]
+use std::os::windows::fs::symlink_file as symlink;
+use std::path::{Component, Path, PathBuf};
+use std::str::Chars;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::time::SystemTime;
 
-pub fn build_joplin_files<P: AsRef<Path>>(source_dir: P) -> Result<Vec<JoplinFile>, String> {
-    let paths = find_files(source_dir.as_ref().to_str().unwrap())
-        .map_err(|e| format!("Error finding files: {}", e))?;
+/// The most resources copied at once. Copies wait on the disk rather than the CPU, more than a
+/// few at a time only adds contention
+const COPY_WORKERS: usize = 8;
 
-    let mut joplin_files = Vec::new();
-    for path in paths {
-        let content = std::fs::read_to_string(&path)
-            .map_err(|e| format!("Error reading file: {}", e.to_string()))?;
+/// Appended to the names of the WebP resources converted to PNG, so they can't take the name of
+/// another resource
+const PNG_SUFFIX: &str = ".png";
+/// How many directories deep a copied directory tree may go
+const MAX_DIR_DEPTH: usize = 64;
+/// The names of the resource directories of the tools that don't call it `_resources`
+const COMMON_RESOURCE_DIRS: [&str; 3] = ["resources", "assets", "attachments"];
+
+/// Where the notes and resources are read from
+pub(crate) enum NoteSource {
+    /// The export directory, canonical when the notes are found in it
+    Dir(PathBuf),
+    Zip(ZipSource),
+}
+
+impl NoteSource {
+    pub(crate) fn open<P: AsRef<Path>>(source: P) -> Result<NoteSource, String> {
+        if is_zip(&source) {
+            Ok(NoteSource::Zip(ZipSource::open(&source)?))
+        } else {
+            Ok(NoteSource::Dir(source.as_ref().to_path_buf()))
+        }
+    }
+
+    /// The content of a resource, given its path relative to the resources directory
+    pub(crate) fn read_resource(&mut self, resource_path: &Path) -> Result<Vec<u8>, String> {
+        self.read(&Path::new("_resources").join(resource_path))
+    }
+
+    /// The size of a resource in bytes, given its path relative to the resources directory,
+    /// without reading it
+    pub(crate) fn resource_size(&mut self, resource_path: &Path) -> Result<u64, String> {
+        let relative_path = Path::new("_resources").join(resource_path);
+        match self {
+            NoteSource::Dir(dir) => std::fs::metadata(dir.join(&relative_path))
+                .map(|metadata| metadata.len())
+                .map_err(|e| format!("Error reading file: {}", e)),
+            NoteSource::Zip(zip) => zip.size(&relative_path),
+        }
+    }
+
+    pub(crate) fn read(&mut self, relative_path: &Path) -> Result<Vec<u8>, String> {
+        match self {
+            NoteSource::Dir(dir) => retry(|| std::fs::read(dir.join(relative_path)))
+                .map_err(|e| format!("Error reading file: {}", e)),
+            NoteSource::Zip(zip) => zip.read(relative_path),
+        }
+    }
+
+    /// The paths of the resources, relative to the resources directory, with those of the other
+    /// resource directories merged into it. Empty when there's no resources directory
+    fn resource_paths(&self, config: &Config) -> Result<Vec<PathBuf>, String> {
+        match self {
+            NoteSource::Dir(dir) => {
+                let mut resource_paths = Vec::new();
+                for resources_dir in resource_dirs(dir, config) {
+                    if resources_dir.is_dir() {
+                        list_files(&resources_dir, Path::new(""), &mut resource_paths)?;
+                    }
+                }
+                // Directory order depends on the filesystem, as the zip entries are sorted
+                resource_paths.sort();
+                resource_paths.dedup();
+                Ok(resource_paths)
+            }
+            NoteSource::Zip(zip) => Ok(zip.resource_paths()),
+        }
+    }
+
+    /// Where a resource of an export directory is, in the first resource directory that has it
+    fn resource_file(&self, resource_path: &Path, config: &Config) -> Option<PathBuf> {
+        match self {
+            NoteSource::Dir(dir) => resource_dirs(dir, config)
+                .map(|resources_dir| resources_dir.join(resource_path))
+                .find(|path| path.exists()),
+            NoteSource::Zip(_) => None,
+        }
+    }
+
+    /// The extension a resource without one should have by its content, for an export
+    /// directory only
+    fn sniff_resource(&self, resource_path: &Path, config: &Config) -> Option<&'static str> {
+        let path = self.resource_file(resource_path, config)?;
+        sniff_file_extension(&path).ok().flatten()
+    }
+
+    fn has_resource(&self, resource_path: &Path, config: &Config) -> bool {
+        match self {
+            NoteSource::Dir(_) => self.resource_file(resource_path, config).is_some(),
+            NoteSource::Zip(zip) => zip.has_resource(resource_path),
+        }
+    }
+}
+
+/// The resource directories of an export directory: `_resources`, then the ones of
+/// `resource_dirs`, whose resources are merged into it
+pub(crate) fn resource_dirs<'a>(
+    dir: &'a Path,
+    config: &'a Config,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    std::iter::once(dir.join("_resources")).chain(
+        config
+            .resource_dirs
+            .iter()
+            .map(|resource_dir| dir.join(resource_dir)),
+    )
+}
+
+/// The directories of an export directory that hold resources under another name than
+/// `_resources`, as some tools export them: a `resources`, `assets` or `attachments` directory
+/// at the top with no notes in it
+pub fn find_resource_dirs<P: AsRef<Path>>(source_dir: P, config: &Config) -> Vec<PathBuf> {
+    if is_zip(&source_dir) {
+        return Vec::new();
+    }
 
-        let joplin_file = JoplinFile::build(&path.strip_prefix(&source_dir).unwrap(), &content)
-            .map_err(|e| format!("Error building JoplinFile: {}", e))?;
+    COMMON_RESOURCE_DIRS
+        .iter()
+        .map(PathBuf::from)
+        .filter(|resource_dir| {
+            let dir = source_dir.as_ref().join(resource_dir);
+            let mut relative_paths = Vec::new();
+            dir.is_dir()
+                && list_files(&dir, Path::new(""), &mut relative_paths).is_ok()
+                && !relative_paths
+                    .iter()
+                    .any(|relative_path| config.is_note(relative_path))
+        })
+        .collect()
+}
+
+/// Builds the notes of an export directory, or of a zip of one. They're all held in memory until
+/// they're written, the links, duplicates and conflicts are resolved across them; a conversion
+/// into a directory without the passes that need their bodies at once goes a note at a time
+/// instead, see `streaming`.
+pub fn build_joplin_files<P: AsRef<Path>>(
+    source_dir: P,
+    config: &Config,
+) -> Result<Vec<JoplinFile>, String> {
+    let (mut source, relative_paths) = config
+        .timings
+        .time(Phase::Discovery, || find_notes(source_dir, config))?;
+    let hooks = Hooks::load(config)?;
 
-        joplin_files.push(joplin_file);
+    let mut joplin_files = Vec::new();
+    for (done, relative_path) in relative_paths.iter().enumerate() {
+        config
+            .cancellation
+            .check(done, relative_paths.len(), "notes read")?;
+        let Some(bytes) = config
+            .timings
+            .time_note(Phase::Parse, Some(relative_path), || {
+                read_note(&mut source, relative_path, config)
+            })
+        else {
+            continue;
+        };
+        if let Some(joplin_file) = build_note(relative_path, bytes, &source, &hooks, config)? {
+            joplin_files.push(joplin_file);
+        }
     }
 
+    resolve_copies(&mut joplin_files, config);
     Ok(joplin_files)
 }
 
-pub fn write_joplin_files<P: AsRef<Path>>(
-    target_dir: P,
+/// A warning for each resource of the export that no note links to, by path or by Joplin id
+pub fn find_orphaned_resources<P: AsRef<Path>>(
+    source_dir: P,
     joplin_files: &[JoplinFile],
-) -> Result<(), String> {
-    for joplin_file in joplin_files {
-        let target_path = target_dir.as_ref().join(&joplin_file.relative_path);
+    config: &Config,
+) -> Result<Vec<Warning>, String> {
+    let resource_refs: Vec<ResourceRef> = joplin_files
+        .iter()
+        .flat_map(|joplin_file| joplin_file.referenced_resources())
+        .cloned()
+        .collect();
+    let linked_paths: HashSet<PathBuf> = resource_refs
+        .iter()
+        .filter_map(|resource_ref| resource_ref.resource_path())
+        .map(|resource_path| PathBuf::from(percent_decode(resource_path)))
+        .collect();
+    // The links to resources without an extension may have been given the one they sniffed as
+    let linked_without_extension: HashSet<PathBuf> = linked_paths
+        .iter()
+        .map(|linked_path| linked_path.with_extension(""))
+        .collect();
+    let linked_ids: HashSet<&str> = resource_refs
+        .iter()
+        .filter(|resource_ref| matches!(resource_ref.target, ResourceTarget::Id(_)))
+        .map(|resource_ref| resource_ref.file_name())
+        .collect();
+
+    let resource_paths = NoteSource::open(&source_dir)?.resource_paths(config)?;
+    Ok(resource_paths
+        .into_iter()
+        .filter(|resource_path| {
+            let id = resource_path.file_stem().and_then(|stem| stem.to_str());
+            let typed = resource_path.extension().is_none()
+                && linked_without_extension.contains(resource_path);
+            !linked_paths.contains(resource_path)
+                && !typed
+                && !id.is_some_and(|id| linked_ids.contains(id))
+        })
+        .map(Warning::OrphanedResource)
+        .collect())
+}
 
-        if let Some(parent) = target_path.parent() {
-            create_dir_all(parent)
-                .map_err(|e| format!("Error creating directory: {}", e.to_string()))?;
+/// Adds the paths of the files under `dir` to `relative_paths`, relative to the directory the
+/// walk started at. The directories `create_dirs` leaves out are too
+fn list_files(dir: &Path, prefix: &Path, relative_paths: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Error reading directory {:?}: {}", dir, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Error reading directory {:?}: {}", dir, e))?;
+        let relative_path = prefix.join(entry.file_name());
+        if entry.path().is_dir() {
+            if prefix.components().count() >= MAX_DIR_DEPTH || links_up(dir, &entry) {
+                continue;
+            }
+            list_files(&entry.path(), &relative_path, relative_paths)?;
+        } else {
+            relative_paths.push(relative_path);
         }
+    }
+    Ok(())
+}
+
+/// Whether the entry of the directory is a symbolic link to it or to a directory it's in
+fn links_up(dir: &Path, entry: &std::fs::DirEntry) -> bool {
+    let is_symlink = entry
+        .file_type()
+        .is_ok_and(|file_type| file_type.is_symlink());
+    match (is_symlink, dir.canonicalize(), entry.path().canonicalize()) {
+        (true, Ok(dir), Ok(linked)) => dir.starts_with(linked),
+        _ => false,
+    }
+}
+
+/// The content of a note, `None` when it can't be read; the error is recorded in `io_errors`
+pub(crate) fn read_note(
+    source: &mut NoteSource,
+    relative_path: &Path,
+    config: &Config,
+) -> Option<Vec<u8>> {
+    let _permit = config.open_files.acquire(1);
+    source
+        .read(relative_path)
+        .map_err(|e| {
+            porcelain(Status::Failed, relative_path, None);
+            config.io_errors.record(relative_path, e)
+        })
+        .ok()
+}
 
-        let mut file = File::create(&target_path)
-            .map_err(|e| format!("Error creating file: {}", e.to_string()))?;
+/// Opens the source and finds the notes in it that match the include patterns, by their path
+/// relative to the source
+pub(crate) fn find_notes<P: AsRef<Path>>(
+    source_dir: P,
+    config: &Config,
+) -> Result<(NoteSource, Vec<PathBuf>), String> {
+    let (source, relative_paths) = if is_zip(&source_dir) {
+        let zip = ZipSource::open(&source_dir)?;
+        let relative_paths = zip.note_paths(config);
+        (NoteSource::Zip(zip), relative_paths)
+    } else {
+        // find_files returns canonical paths, so the source has to be canonical too for the
+        // relative paths to work out. It may be relative or go through a symlink (/tmp on macOS)
+        let source_dir = source_dir.as_ref().canonicalize().map_err(|e| {
+            format!(
+                "Error canonicalizing source path {:?}: {}",
+                source_dir.as_ref(),
+                e
+            )
+        })?;
+        let paths =
+            find_files(&source_dir, config).map_err(|e| format!("Error finding files: {}", e))?;
 
-        let mut content = String::new();
-        content.push_str(&joplin_file.body);
-        content.push_str("\n");
-        if let Some(tags) = &joplin_file.tags {
-            content.push_str("\n");
-            content.push_str(tags);
-            content.push_str("\n");
-        }
+        // A note linked in from outside the source has no place in it, it's left out
+        let relative_paths = paths
+            .iter()
+            .filter_map(|path| match path.strip_prefix(&source_dir) {
+                Ok(relative_path) => Some(relative_path.to_path_buf()),
+                Err(_) => {
+                    crate::warning!(
+                        "skipping {:?}, it links to a file outside the source {:?}",
+                        path,
+                        source_dir
+                    );
+                    config.skipped_notes.record();
+                    None
+                }
+            })
+            .collect();
+        (NoteSource::Dir(source_dir), relative_paths)
+    };
+
+    let include = config
+        .include
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format!("Error in include pattern {:?}: {}", pattern, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let relative_paths: Vec<PathBuf> = relative_paths
+        .into_iter()
+        .filter(|relative_path| {
+            include.is_empty()
+                || include
+                    .iter()
+                    .any(|pattern| pattern.matches_path(relative_path))
+        })
+        .collect();
+
+    Ok((source, relative_paths))
+}
 
-        file.write_all(content.as_bytes())
-            .map_err(|e| format!("Error writing file: {}", e))?;
+/// The user's script and filter, run on every note in that order
+pub(crate) struct Hooks {
+    #[cfg(feature = "scripting")]
+    script: Option<crate::script::ScriptHook>,
+    filter: Option<FilterHook>,
+}
+
+impl Hooks {
+    pub(crate) fn load(config: &Config) -> Result<Hooks, String> {
+        #[cfg(not(feature = "scripting"))]
+        if config.script.is_some() {
+            return Err("Scripts need jb to be built with the scripting feature".to_string());
+        }
 
-        let created_time = SystemTime::UNIX_EPOCH
-            + std::time::Duration::from_secs(joplin_file.created.timestamp() as u64);
-        let modified_time = SystemTime::UNIX_EPOCH
-            + std::time::Duration::from_secs(joplin_file.updated.timestamp() as u64);
+        Ok(Hooks {
+            #[cfg(feature = "scripting")]
+            script: config
+                .script
+                .as_ref()
+                .map(crate::script::ScriptHook::load)
+                .transpose()?,
+            filter: config.filter.as_deref().map(FilterHook::new),
+        })
+    }
 
-        let mut times = std::fs::FileTimes::new()
-            .set_accessed(modified_time)
-            .set_modified(modified_time);
-        // On macOS and Windows, also set creation time
-        // Adding Windows is a bit pointless because Bear is a macOS and iOS app only
-        #[cfg(any(target_os = "macos", target_os = "windows"))]
+    /// Runs the hooks on the note, returns `false` when the script vetoed it
+    fn apply(&self, joplin_file: &mut JoplinFile) -> Result<bool, String> {
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &self.script
+            && !script.apply(joplin_file)?
         {
-            times = times.set_created(created_time);
+            return Ok(false);
         }
-        file.set_times(times)
-            .map_err(|e| format!("Error setting file times: {}", e.to_string()))?;
+        if let Some(filter) = &self.filter {
+            filter.apply(joplin_file)?;
+        }
+
+        Ok(true)
     }
+}
 
-    Ok(())
+/// Builds a note from the content of its file, runs the hooks on it and assesses its fidelity.
+/// `None` when it's skipped: it's not a text file or the script vetoed it.
+pub(crate) fn build_note(
+    relative_path: &Path,
+    bytes: Vec<u8>,
+    source: &NoteSource,
+    hooks: &Hooks,
+    config: &Config,
+) -> Result<Option<JoplinFile>, String> {
+    let config = &config.for_note(relative_path);
+    let Some(joplin_file) = parse_note(relative_path, bytes, config)? else {
+        crate::warning!("skipping {:?}, it is not a text file", relative_path);
+        config.skipped_notes.record();
+        porcelain(Status::Skipped, relative_path, None);
+        return Ok(None);
+    };
+    for warning in &joplin_file.warnings {
+        crate::warning!("{:?}: {}", relative_path, warning);
+    }
+
+    let joplin_file = config
+        .timings
+        .time_note(Phase::Transform, Some(relative_path), || {
+            transform_note(joplin_file, source, hooks, config)
+        })?;
+    if joplin_file.is_none() {
+        crate::notice!("Skipped {:?}, the script vetoed it", relative_path);
+        config.skipped_notes.record();
+        porcelain(Status::Skipped, relative_path, None);
+    }
+
+    Ok(joplin_file)
 }
 
-pub fn copy_resources<P: AsRef<Path>>(source_dir: P, target_dir: P) -> Result<(), String> {
-    let source_resources_dir = source_dir.as_ref().join("_resources");
-    let target_resources_dir = target_dir.as_ref().join("_resources");
+/// Builds a note again, as `build_note` did before, without reporting on it a second time
+pub(crate) fn rebuild_note(
+    relative_path: &Path,
+    bytes: Vec<u8>,
+    source: &NoteSource,
+    hooks: &Hooks,
+    config: &Config,
+) -> Result<Option<JoplinFile>, String> {
+    let config = &config.for_note(relative_path);
+    match parse_note(relative_path, bytes, config)? {
+        Some(joplin_file) => {
+            config
+                .timings
+                .time_note(Phase::Transform, Some(relative_path), || {
+                    transform_note(joplin_file, source, hooks, config)
+                })
+        }
+        None => Ok(None),
+    }
+}
 
-    if !source_resources_dir.exists() {
-        return Err(format!(
-            "The source path: {:?} does not exist",
-            source_resources_dir
-        ));
+/// Decodes and parses a note, with the warnings about its encoding and its path. `None` when
+/// it's not a text file
+fn parse_note(
+    relative_path: &Path,
+    bytes: Vec<u8>,
+    config: &Config,
+) -> Result<Option<JoplinFile>, String> {
+    let timings = &config.timings;
+    let (content, transcoded) =
+        match timings.time_note(Phase::Parse, Some(relative_path), || decode_note(bytes)) {
+            Decoded::Utf8(content) => (content, None),
+            Decoded::Transcoded(content, encoding) => (content, Some(encoding)),
+            Decoded::Binary => return Ok(None),
+        };
+
+    let mut joplin_file = timings
+        .time_note(Phase::Parse, Some(relative_path), || {
+            JoplinFile::from_content(relative_path, content, config)
+        })
+        .map_err(|e| format!("Error building JoplinFile: {}", e))?;
+    if let Some(encoding) = transcoded {
+        joplin_file.warnings.push(Warning::Transcoded(encoding));
+    }
+    if relative_path.to_str().is_none() {
+        joplin_file.warnings.push(Warning::LossyPath);
     }
 
-    if !source_resources_dir.is_dir() {
-        return Err(format!(
-            "The source path: {:?} is not a directory",
-            source_resources_dir
-        ));
+    Ok(Some(joplin_file))
+}
+
+/// Runs the hooks on a parsed note, points its links into the other resource directories at
+/// `_resources`, replaces its `[toc]` markers and emoji shortcodes, tidies its whitespace, line
+/// breaks and punctuation, fills in the alt text of its images, tags it when it has something
+/// to do and assesses its fidelity, counting each of its warnings as a fallback. `None` when
+/// the script vetoed it.
+fn transform_note(
+    mut joplin_file: JoplinFile,
+    source: &NoteSource,
+    hooks: &Hooks,
+    config: &Config,
+) -> Result<Option<JoplinFile>, String> {
+    if !hooks.apply(&mut joplin_file)? {
+        return Ok(None);
+    }
+    if let Some(body) = merged_resource_links(&joplin_file, &config.resource_dirs) {
+        joplin_file.set_body(body);
+    }
+    if let Some(body) = replace_toc_markers(&joplin_file.body, config.toc_marker) {
+        joplin_file.set_body(body);
+    }
+    if config.tidy_whitespace {
+        joplin_file.set_body(tidy_whitespace(&joplin_file.body));
+    }
+    if let Some(body) = convert_line_breaks(&joplin_file.body, config.line_breaks) {
+        joplin_file.set_body(body);
+    }
+    if let Some(body) = convert_punctuation(&joplin_file.body, config.punctuation) {
+        joplin_file.set_body(body);
+    }
+    if let Some(body) = config
+        .expand_emoji
+        .then(|| expand_shortcodes(&joplin_file.body))
+        .flatten()
+    {
+        joplin_file.set_body(body);
+    }
+    if let Some(body) = config
+        .fill_alt_text
+        .then(|| fill_alt_text(&joplin_file.body, config.resource_metadata.as_deref()))
+        .flatten()
+    {
+        joplin_file.set_body(body);
+    }
+    if let Some(todo_tag) = &config.todo_tag
+        && NoteTasks::find(&joplin_file).is_some()
+    {
+        joplin_file.add_tag(todo_tag);
     }
 
-    copy_dir_recursively(source_resources_dir, target_resources_dir)
-        .map_err(|e| format!("Error copying resources: {}", e))?;
+    joplin_file.fidelity = Fidelity::assess_with(&joplin_file, |resource_path| {
+        source.has_resource(resource_path, config)
+    });
+    joplin_file.fidelity.fallbacks += joplin_file.warnings.len();
+    if config
+        .review_threshold
+        .is_some_and(|threshold| joplin_file.fidelity.score() >= threshold)
+    {
+        joplin_file.add_tag(REVIEW_TAG);
+    }
+    // After the fidelity, which looks for the resources as they are in the source
+    if config.sniff_resource_types
+        && let Some(body) = typed_links(&joplin_file, source, config)
+    {
+        joplin_file.set_body(body);
+    }
 
-    Ok(())
+    Ok(Some(joplin_file))
 }
 
-pub fn copy_dir_recursively<P: AsRef<Path>>(source_dir: P, target_dir: P) -> std::io::Result<()> {
-    let source_dir = source_dir.as_ref();
-    let target_dir = target_dir.as_ref();
+/// The body with the links into the other resource directories pointing into `_resources`,
+/// where their resources are merged, `None` when there are none
+fn merged_resource_links(joplin_file: &JoplinFile, resource_dirs: &[PathBuf]) -> Option<String> {
+    if resource_dirs.is_empty() {
+        return None;
+    }
 
-    create_dir_all(target_dir)?;
-    for entry in std::fs::read_dir(source_dir)? {
-        let entry = entry?;
-        let source = entry.path();
-        let target = target_dir.join(entry.file_name());
+    let depth = joplin_file.relative_path.components().count();
+    let mut links: Vec<(std::ops::Range<usize>, String)> = find_links(&joplin_file.body)
+        .into_iter()
+        .filter_map(|link| {
+            let dest = &joplin_file.body[link.span.clone()];
+            let path = dest.split('#').next().unwrap_or(dest);
+            if path.starts_with(['/', '\\']) || has_scheme(path) {
+                return None;
+            }
+            let resolved = resolve(&joplin_file.relative_path, &percent_decode(path))?;
+            let resource_path = resource_dirs
+                .iter()
+                .find_map(|resource_dir| resolved.strip_prefix(resource_dir).ok())?;
 
-        if source.is_dir() {
-            copy_dir_recursively(&source, &target)?;
-        } else {
-            std::fs::copy(&source, &target)?;
+            let encoded: Vec<String> = resource_path
+                .iter()
+                .map(|component| percent_encode(&component.to_string_lossy()))
+                .collect();
+            let merged = format!(
+                "{}_resources/{}",
+                "../".repeat(depth.saturating_sub(1)),
+                encoded.join("/")
+            );
+            Some((link.span.start..link.span.start + path.len(), merged))
+        })
+        .collect();
+    if links.is_empty() {
+        return None;
+    }
+
+    let mut body = joplin_file.body.clone();
+    links.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+    for (span, merged) in links {
+        body.replace_range(span, &merged);
+    }
+    Some(body)
+}
+
+/// The body with the links to resources without an extension given the one of their content,
+/// as they're copied, `None` when there are none
+fn typed_links(joplin_file: &JoplinFile, source: &NoteSource, config: &Config) -> Option<String> {
+    let mut body = joplin_file.body.clone();
+    let mut refs = joplin_file.referenced_resources().to_vec();
+    refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+
+    let mut typed = false;
+    for resource_ref in refs {
+        let Some(resource_path) = resource_ref.resource_path() else {
+            continue;
+        };
+        let resource_path = PathBuf::from(percent_decode(resource_path));
+        if resource_path.extension().is_some() {
+            continue;
+        }
+        if let Some(extension) = source.sniff_resource(&resource_path, config) {
+            body.insert_str(resource_ref.span.end, &format!(".{extension}"));
+            typed = true;
         }
     }
+    typed.then_some(body)
+}
 
-    Ok(())
+/// Applies the conflict and duplicate policies, merges and splits notes and checks the links
+/// between the notes left, which need all the notes at once
+pub(crate) fn resolve_copies(joplin_files: &mut Vec<JoplinFile>, config: &Config) {
+    config
+        .timings
+        .time(Phase::Transform, || resolve_policies(joplin_files, config));
 }
 
-pub fn find_files(dir: &str) -> Result<Vec<PathBuf>, String> {
-    let path = Path::new(dir);
-    if !path.exists() {
-        return Err(format!("The path {dir} does not exist"));
+fn resolve_policies(joplin_files: &mut Vec<JoplinFile>, config: &Config) {
+    for (relative_path, decision) in resolve_conflicts(joplin_files, config.conflict_policy) {
+        crate::notice!("Conflict copy {:?}: {}", relative_path, decision);
+    }
+
+    for duplicate in resolve_duplicates(joplin_files, config.duplicate_policy) {
+        crate::notice!(
+            "Duplicate {:?} of {:?}: {}",
+            duplicate.relative_path,
+            duplicate.original,
+            duplicate.decision
+        );
     }
 
-    if !path.is_dir() {
-        return Err(format!("The path {dir} is not a directory"));
+    merge_notebooks(joplin_files, &config.merge_notebooks, config);
+    if let Some(max_len) = config.split_notes {
+        split_large_notes(joplin_files, max_len, config);
     }
+    link_notes(joplin_files, config);
+}
 
-    let options = MatchOptions {
-        case_sensitive: false,
-        require_literal_separator: false,
-        require_literal_leading_dot: false,
+/// Converts a single note without touching the filesystem, returning the content of the Bear
+/// note. The relative path is where the note would be in the export, its tags are built from it.
+pub fn convert_one<P: AsRef<Path>>(
+    relative_path: P,
+    bytes: &[u8],
+    config: &Config,
+) -> Result<String, String> {
+    let content = match decode_note(bytes.to_vec()) {
+        Decoded::Utf8(content) | Decoded::Transcoded(content, _) => content,
+        Decoded::Binary => return Err("The note is not a text file".to_string()),
     };
+    let config = &config.for_note(relative_path.as_ref());
 
-    let glob_result = glob_with(&format!("{dir}/**/*.md"), options)
-        .map_err(|e| format!("Error while searching for files: {}", e))?;
+    let mut joplin_file = JoplinFile::from_content(relative_path, content, config)
+        .map_err(|e| format!("Error building JoplinFile: {}", e))?;
 
-    let mut paths = Vec::new();
-    for path in glob_result {
-        match path {
-            Ok(path) => match path.canonicalize() {
-                Ok(abs) => paths.push(abs),
-                Err(e) => return Err(format!("Error canonicalizing path: {}", e.to_string())),
-            },
-            Err(e) => return Err(format!("Error reading path: {}", e.to_string())),
+    if !Hooks::load(config)?.apply(&mut joplin_file)? {
+        return Err("The script vetoed the note".to_string());
+    }
+
+    Ok(build_content(&joplin_file, config))
+}
+
+#[derive(Debug, PartialEq)]
+enum Decoded {
+    Utf8(String),
+    /// The content and the name of the encoding it was transcoded from
+    Transcoded(String, &'static str),
+    Binary,
+}
+
+/// Decodes a note as UTF-8, transcoding it when it has a BOM for, or looks like, another encoding.
+/// UTF-8 keeps the memory of the bytes, only transcoding allocates.
+fn decode_note(mut bytes: Vec<u8>) -> Decoded {
+    const SNIFF_LEN: usize = 8192;
+
+    if let Some((encoding, bom_len)) = encoding_rs::Encoding::for_bom(&bytes) {
+        if encoding != encoding_rs::UTF_8 {
+            let (content, _, _) = encoding.decode(&bytes);
+            return Decoded::Transcoded(content.into_owned(), encoding.name());
         }
+
+        bytes.drain(..bom_len);
+        return Decoded::Utf8(
+            String::from_utf8(bytes)
+                .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
+        );
     }
 
-    Ok(paths)
+    // Text files in any of the encodings we can detect don't contain NUL bytes, even when the
+    // rest of the file happens to be valid UTF-8
+    if bytes[..bytes.len().min(SNIFF_LEN)].contains(&0) {
+        return Decoded::Binary;
+    }
+
+    let bytes = match String::from_utf8(bytes) {
+        Ok(content) => return Decoded::Utf8(content),
+        Err(e) => e.into_bytes(),
+    };
+    let bytes = bytes.as_slice();
+
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Allow);
+    detector.feed(bytes, true);
+    // Valid UTF-8 was handled above
+    let encoding = detector.guess(None, Utf8Detection::Deny);
+    let (content, _, _) = encoding.decode(bytes);
+
+    Decoded::Transcoded(content.into_owned(), encoding.name())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+pub fn write_joplin_files<P: AsRef<Path>>(
+    target_dir: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<(), String> {
+    let written = write_joplin_files_until_cancelled(target_dir, joplin_files, config)?;
+    config
+        .cancellation
+        .check(written, joplin_files.len(), "notes written")
+}
 
-    struct TestFixture {
-        temp_dir: PathBuf,
+/// Writes the notes in order until the conversion is cancelled, and returns how many it wrote.
+/// The note being written when it's cancelled is finished first. The journal in the target
+/// records each note as it's written; with `config.resume` the notes it lists are skipped.
+pub fn write_joplin_files_until_cancelled<P: AsRef<Path>>(
+    target_dir: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<usize, String> {
+    let relative_paths = target_paths(joplin_files, base_len(&target_dir), config)?;
+    let mut writer = NoteWriter::open(&target_dir, config)?;
+
+    for (done, (joplin_file, relative_path)) in joplin_files.iter().zip(relative_paths).enumerate()
+    {
+        if config.cancellation.is_cancelled() {
+            return Ok(done);
+        }
+        writer.write(joplin_file, &relative_path, config)?;
     }
 
-    impl TestFixture {
-        fn new() -> Self {
-            let temp_dir = std::env::temp_dir().join("joplin_file_finder_test");
-            if temp_dir.exists() {
-                fs::remove_dir_all(&temp_dir).unwrap();
+    writer.finish()?;
+    Ok(joplin_files.len())
+}
+
+/// The length the target directory adds to the paths of the notes, with the separator after it
+pub(crate) fn base_len<P: AsRef<Path>>(target_dir: P) -> usize {
+    target_dir.as_ref().to_string_lossy().chars().count() + 1
+}
+
+/// Writes notes into the target directory one after the other, keeping the journal and asking
+/// about the files the target has already
+pub(crate) struct NoteWriter {
+    target_dir: PathBuf,
+    journal: Journal,
+    resolver: ExistingResolver,
+}
+
+impl NoteWriter {
+    pub(crate) fn open<P: AsRef<Path>>(target_dir: P, config: &Config) -> Result<Self, String> {
+        create_dir_all(&target_dir)
+            .map_err(|e| format!("Error creating directory {:?}: {}", target_dir.as_ref(), e))?;
+
+        Ok(NoteWriter {
+            target_dir: target_dir.as_ref().to_path_buf(),
+            journal: Journal::open(&target_dir, config.resume)?,
+            resolver: ExistingResolver::new(config.existing_policy),
+        })
+    }
+
+    /// Writes the note to its path relative to the target, see `target_paths`
+    pub(crate) fn write(
+        &mut self,
+        joplin_file: &JoplinFile,
+        relative_path: &Path,
+        config: &Config,
+    ) -> Result<(), String> {
+        let target_path = self.target_dir.join(relative_path);
+        let (status, target_path) =
+            config
+                .timings
+                .time_note(Phase::Write, Some(&joplin_file.relative_path), || {
+                    write_joplin_file(
+                        &target_path,
+                        joplin_file,
+                        &mut self.journal,
+                        &mut self.resolver,
+                        config,
+                    )
+                })?;
+        porcelain(status, &joplin_file.relative_path, Some(&target_path));
+        Ok(())
+    }
+
+    /// Removes the journal once all the notes are written
+    pub(crate) fn finish(self) -> Result<(), String> {
+        self.journal.finish()
+    }
+}
+
+/// Writes a note unless the journal shows an interrupted conversion already wrote it as it is,
+/// or the resolver keeps the file the target already has there. Returns what was done and
+/// where the note is.
+fn write_joplin_file(
+    target_path: &Path,
+    joplin_file: &JoplinFile,
+    journal: &mut Journal,
+    resolver: &mut ExistingResolver,
+    config: &Config,
+) -> Result<(Status, PathBuf), String> {
+    let content = build_content(joplin_file, config);
+    if journal.is_written(&joplin_file.relative_path, &content) && long_path(target_path).exists() {
+        return Ok((Status::Resumed, target_path.to_path_buf()));
+    }
+    let note_path = match std::fs::read(long_path(target_path)) {
+        Ok(existing) if existing != content.as_bytes() => {
+            match resolver.resolve(&long_path(target_path), &content)? {
+                Resolution::Overwrite => target_path.to_path_buf(),
+                Resolution::Skip => {
+                    config.skipped_notes.record();
+                    return Ok((Status::Skipped, target_path.to_path_buf()));
+                }
+                Resolution::KeepBoth => free_path(target_path),
             }
+        }
+        _ => target_path.to_path_buf(),
+    };
+    let target_path = &long_path(&note_path);
+    let _permit = config.open_files.acquire(1);
 
-            create_dir_all(&temp_dir).unwrap();
-            Self { temp_dir }
+    if let Some(parent) = target_path.parent() {
+        create_dir_all(parent)
+            .map_err(|e| format!("Error creating directory {:?}: {}", parent, e))?;
+    }
+
+    let mut file = File::create(target_path)
+        .map_err(|e| format!("Error creating file {:?}: {}", target_path, e))?;
+
+    file.write_all(content.as_bytes())
+        .map_err(|e| format!("Error writing file: {}", e))?;
+
+    file.set_times(file_times(joplin_file))
+        .map_err(|e| format!("Error setting file times: {}", e))?;
+    journal.record(&joplin_file.relative_path, &content)?;
+    Ok((Status::Written, note_path))
+}
+
+/// The times of the note's file: modified when the note was updated, and on macOS and Windows
+/// created when the note was. To the millisecond Joplin keeps, or finer
+pub(crate) fn file_times(joplin_file: &JoplinFile) -> std::fs::FileTimes {
+    let modified_time = SystemTime::from(joplin_file.updated);
+
+    let times = std::fs::FileTimes::new()
+        .set_accessed(modified_time)
+        .set_modified(modified_time);
+    // On macOS and Windows, also set creation time
+    // Adding Windows is a bit pointless because Bear is a macOS and iOS app only
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    let times = { times.set_created(SystemTime::from(joplin_file.created)) };
+    times
+}
+
+/// The paths the notes are written to, relative to the target: sanitized, and shortened when
+/// they'd be longer than the maximum path length with the `base_len` characters of the target
+pub(crate) fn target_paths(
+    joplin_files: &[JoplinFile],
+    base_len: usize,
+    config: &Config,
+) -> Result<Vec<PathBuf>, String> {
+    if !is_valid_replacement(config.filename_replacement) {
+        return Err(format!(
+            "The filename replacement {:?} is not valid in file names itself",
+            config.filename_replacement
+        ));
+    }
+
+    let relative_paths = note_paths(joplin_files, config);
+    if let Some(relative_path) = relative_paths
+        .iter()
+        .find(|relative_path| !is_below(relative_path))
+    {
+        return Err(format!(
+            "The note path {:?} leads outside the target",
+            relative_path
+        ));
+    }
+    let relative_paths = match config.max_path_len {
+        Some(max_len) => fit_paths(&relative_paths, base_len, max_len)?,
+        None => relative_paths,
+    };
+
+    for (joplin_file, relative_path) in joplin_files.iter().zip(&relative_paths) {
+        if *relative_path != joplin_file.relative_path {
+            crate::notice!(
+                "Renamed {:?} to {:?}",
+                joplin_file.relative_path,
+                relative_path
+            );
         }
+    }
 
-        fn create_file(&self, name: &PathBuf, content: &str) {
-            fs::write(self.temp_dir.join(name), content).unwrap();
+    Ok(relative_paths)
+}
+
+/// Whether the relative path stays below the directory it's joined to: no root, no `..`
+fn is_below(relative_path: &Path) -> bool {
+    relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// The sanitized paths of the notes relative to the target, before they're shortened: their
+/// paths in the export, or those the path template gives them
+pub(crate) fn note_paths(joplin_files: &[JoplinFile], config: &Config) -> Vec<PathBuf> {
+    match &config.path_template {
+        Some(template) => render_unique(template, joplin_files, config),
+        // Names that only differ by the characters sanitized, `a?.md` and `a_.md`, or by case
+        None => number_duplicates(joplin_files.iter().map(|joplin_file| {
+            sanitize_path(&joplin_file.relative_path, config.filename_replacement)
+        })),
+    }
+}
+
+/// On Windows, the extended-length form of a path, so it isn't limited to MAX_PATH (260)
+/// characters. Other systems have no such limit and get the path as it is.
+pub(crate) fn long_path(path: &Path) -> PathBuf {
+    #[cfg(target_os = "windows")]
+    if let Some(path) = std::path::absolute(path)
+        .ok()
+        .and_then(|path| path.to_str().map(extended_length))
+    {
+        return PathBuf::from(path);
+    }
+
+    path.to_path_buf()
+}
+
+/// Prefixes an absolute Windows path with `\\?\`, or `\\?\UNC\` for network shares
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn extended_length(path: &str) -> String {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        path.to_string()
+    } else if let Some(share) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{share}")
+    } else {
+        format!(r"\\?\{path}")
+    }
+}
+
+/// Builds the content of the Bear note: the body with the tags placed according to the config
+/// of its notebook, or laid out by the note template
+pub fn build_content(joplin_file: &JoplinFile, config: &Config) -> String {
+    let config = &config.for_note(&joplin_file.relative_path);
+    let body = if config.convert_webp {
+        png_links(joplin_file)
+    } else {
+        joplin_file.body.clone()
+    };
+    let mut footer = Vec::new();
+    if let Some(id) = &joplin_file.id {
+        if config.link_to_joplin {
+            footer.push(format!(
+                "[Open in Joplin](joplin://x-callback-url/openNote?id={id})"
+            ));
+        }
+        footer.extend(config.note_id.format(id));
+    }
+    let body = &match footer.join("\n\n") {
+        footer if footer.is_empty() => body,
+        footer if body.is_empty() => footer,
+        footer => format!("{body}\n\n{footer}"),
+    };
+    #[cfg(feature = "templates")]
+    if let Some(template) = &config.note_template {
+        match crate::template::render_note(template, joplin_file, body) {
+            Ok(content) => return content,
+            Err(e) => crate::warning!("{:?}: {}", joplin_file.relative_path, e),
         }
+    }
+    let Some(tags) = &joplin_file.tags else {
+        return format!("{body}\n");
+    };
 
-        fn create_sub_directory(&self, name: &str) {
-            create_dir_all(self.temp_dir.join(name)).unwrap();
+    match config.tag_placement {
+        TagPlacement::Bottom => format!("{body}\n\n{tags}\n"),
+        TagPlacement::Top => {
+            // Bear uses the first line as the title, so it has to stay above the tags
+            let heading = format!("# {}", joplin_file.title);
+            let body = match body.strip_prefix(&heading) {
+                Some(rest) if rest.is_empty() || rest.starts_with('\n') => rest.trim_start(),
+                _ => body,
+            };
+
+            if body.is_empty() {
+                format!("{heading}\n{tags}\n")
+            } else {
+                format!("{heading}\n{tags}\n\n{body}\n")
+            }
         }
+        TagPlacement::AfterTitle => match body.split_once('\n') {
+            Some((title, rest)) => format!("{title}\n{tags}\n{rest}\n"),
+            None if body.is_empty() => format!("{tags}\n"),
+            None => format!("{body}\n{tags}\n"),
+        },
     }
+}
 
-    impl Drop for TestFixture {
-        fn drop(&mut self) {
-            if self.temp_dir.exists() {
-                fs::remove_dir_all(&self.temp_dir).unwrap()
+/// The body with the links to WebP resources pointing at their PNG conversions, `a.webp.png`
+fn png_links(joplin_file: &JoplinFile) -> String {
+    let mut body = joplin_file.body.clone();
+    let mut refs = joplin_file.referenced_resources().to_vec();
+    refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+
+    for resource_ref in refs {
+        if resource_ref
+            .resource_path()
+            .is_some_and(|resource_path| is_webp(Path::new(resource_path)))
+        {
+            body.insert_str(resource_ref.span.end, PNG_SUFFIX);
+        }
+    }
+    body
+}
+
+/// Copies the resources of an export directory, or of a zip of one, into the target directory.
+/// Resources the target already has as they are in the source aren't copied again. With
+/// `link_resources` they're all linked instead; there's nothing to link to in a zip though. The
+/// resources over the limits are left out or linked to, as `resource_limit_policy` says. The
+/// journal of the resources records each one copied from a directory until they all are; with
+/// `config.resume` those it lists, unchanged in the source since, are skipped without comparing
+/// them, which matters for those written anew, scaled down or stripped.
+pub fn copy_resources<P: AsRef<Path>>(
+    source_dir: P,
+    target_dir: P,
+    config: &Config,
+) -> Result<(), String> {
+    if is_zip(&source_dir) {
+        return ZipSource::open(&source_dir)?.copy_resources(&target_dir, &config.cancellation);
+    }
+
+    let source_resources_dir = source_dir.as_ref().join("_resources");
+    let target_resources_dir = target_dir.as_ref().join("_resources");
+
+    if config.require_resources && !has_resources_dir(source_dir.as_ref(), config) {
+        return Err(format!(
+            "The source path: {:?} does not exist",
+            source_resources_dir
+        ));
+    }
+
+    let mut files = Vec::new();
+    for resources_dir in resource_dirs(source_dir.as_ref(), config) {
+        if !resources_dir.exists() {
+            if resources_dir != source_resources_dir {
+                crate::warning!("The resource directory {:?} does not exist", resources_dir);
             }
+            continue;
         }
+        if !resources_dir.is_dir() {
+            return Err(format!(
+                "The source path: {:?} is not a directory",
+                resources_dir
+            ));
+        }
+
+        create_dirs(&resources_dir, &target_resources_dir, &mut files, config)
+            .map_err(|e| format!("Error copying resources: {}", e))?;
+    }
+
+    let files = limit_resources(merge_resource_files(files), config)
+        .map_err(|e| format!("Error copying resources: {}", e))?;
+    if files.is_empty() {
+        return Ok(());
+    }
+    let journal = Mutex::new(Journal::open_resources(&target_dir, config.resume)?);
+    copy_files(&files, Some(&journal), config)
+        .map_err(|e| format!("Error copying resources: {}", e))?;
+
+    // Kept when the copy is cancelled, for the run that resumes it
+    journal.into_inner().unwrap().finish()
+}
+
+/// Links the resources of an export directory into the target directory instead of copying
+/// them. There's nothing to link to in a zip, its resources are extracted.
+pub fn link_resources<P: AsRef<Path>>(
+    source_dir: P,
+    target_dir: P,
+    link: ResourceLink,
+    cancellation: &Cancellation,
+) -> Result<(), String> {
+    let config = Config {
+        link_resources: Some(link),
+        cancellation: cancellation.clone(),
+        ..Config::default()
+    };
+    copy_resources(source_dir, target_dir, &config)
+}
+
+/// Whether an export directory has `_resources` or one of the other resource directories
+fn has_resources_dir(dir: &Path, config: &Config) -> bool {
+    resource_dirs(dir, config).any(|resources_dir| resources_dir.exists())
+}
+
+/// A warning when an export directory has no resources directory but notes link to resources,
+/// there's nothing to copy then. An error with `require_resources` set, whether notes link to
+/// resources or not. The notes of an export of text only need no resources directory
+pub fn check_resources_dir<P: AsRef<Path>>(
+    source_dir: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<Vec<Warning>, String> {
+    if is_zip(&source_dir) || has_resources_dir(source_dir.as_ref(), config) {
+        return Ok(Vec::new());
+    }
+    if config.require_resources {
+        return Err(format!(
+            "The source path: {:?} does not exist",
+            source_dir.as_ref().join("_resources")
+        ));
+    }
+
+    let links_resources = joplin_files
+        .iter()
+        .any(|joplin_file| !joplin_file.referenced_resources().is_empty());
+    Ok(links_resources
+        .then_some(Warning::NoResourcesDir)
+        .into_iter()
+        .collect())
+}
+
+/// The files to copy without those going to the same target as a file of a resource directory
+/// before, which wins as it does for the links
+fn merge_resource_files(files: Vec<(PathBuf, PathBuf)>) -> Vec<(PathBuf, PathBuf)> {
+    let mut targets = HashSet::new();
+    files
+        .into_iter()
+        .filter(|(source, target)| {
+            let first = targets.insert(target.clone());
+            if !first {
+                crate::warning!(
+                    "{:?} has the same name as a resource copied before, it's not copied",
+                    source
+                );
+            }
+            first
+        })
+        .collect()
+}
+
+/// A warning for each resource of an export directory over the limits set with
+/// `max_resource_size` and `max_resources`, in path order. The resources of a zip aren't
+/// limited
+pub fn find_resources_over_limits<P: AsRef<Path>>(
+    source_dir: P,
+    config: &Config,
+) -> Result<Vec<Warning>, String> {
+    let source = NoteSource::open(&source_dir)?;
+    let NoteSource::Dir(dir) = &source else {
+        return Ok(Vec::new());
+    };
+    if config.max_resource_size.is_none() && config.max_resources.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let resource_paths = source.resource_paths(config)?;
+    let sizes = resource_paths
+        .iter()
+        .map(|resource_path| {
+            let path = source
+                .resource_file(resource_path, config)
+                .unwrap_or_else(|| dir.join("_resources").join(resource_path));
+            std::fs::metadata(&path)
+                .map(|metadata| metadata.len())
+                .map_err(|e| format!("Error reading {:?}: {}", path, e))
+        })
+        .collect::<Result<Vec<u64>, String>>()?;
+
+    let over = over_limits(&sizes, config);
+    Ok(resource_paths
+        .into_iter()
+        .zip(sizes)
+        .enumerate()
+        .filter(|(index, _)| over.contains(index))
+        .map(|(_, (resource_path, size))| Warning::ResourceOverLimits {
+            resource_path,
+            size,
+            linked: config.resource_limit_policy == ResourceLimitPolicy::Link,
+        })
+        .collect())
+}
+
+/// Counts the links of the notes to the resources left out for being over the limits, as
+/// `find_resources_over_limits` has them, as links to missing resources, and warns about them.
+/// Notes that reach the review threshold with them are tagged for review
+pub fn mark_left_out_resources(
+    joplin_files: &mut [JoplinFile],
+    over_limits: &[Warning],
+    config: &Config,
+) {
+    let left_out: HashSet<&Path> = over_limits
+        .iter()
+        .filter_map(|warning| match warning {
+            Warning::ResourceOverLimits {
+                resource_path,
+                linked: false,
+                ..
+            } => Some(resource_path.as_path()),
+            _ => None,
+        })
+        .collect();
+    if left_out.is_empty() {
+        return;
+    }
+
+    for joplin_file in joplin_files.iter_mut() {
+        let links: Vec<String> = joplin_file
+            .referenced_resources()
+            .iter()
+            .filter_map(|resource_ref| resource_ref.resource_path())
+            .filter(|resource_path| left_out.contains(Path::new(&percent_decode(resource_path))))
+            .map(|resource_path| resource_path.to_string())
+            .collect();
+        if links.is_empty() {
+            continue;
+        }
+
+        joplin_file.fidelity.missing_resources += links.len();
+        joplin_file.warnings.push(Warning::LeftOutResources(links));
+        if config
+            .review_threshold
+            .is_some_and(|threshold| joplin_file.fidelity.score() >= threshold)
+        {
+            joplin_file.add_tag(REVIEW_TAG);
+        }
+    }
+}
+
+/// The files to copy left once the ones over the resource limits are taken out, and linked to
+/// with a symbolic link with `ResourceLimitPolicy::Link`. The files are in the order of their
+/// path in the resources directory, as `find_resources_over_limits` has them
+fn limit_resources(
+    mut files: Vec<(PathBuf, PathBuf)>,
+    config: &Config,
+) -> std::io::Result<Vec<(PathBuf, PathBuf)>> {
+    if config.max_resource_size.is_none() && config.max_resources.is_none() {
+        return Ok(files);
+    }
+
+    // By target, the resources of all the resource directories merged
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    let sizes = files
+        .iter()
+        .map(|(source, _)| std::fs::metadata(source).map(|metadata| metadata.len()))
+        .collect::<std::io::Result<Vec<u64>>>()?;
+    let over = over_limits(&sizes, config);
+    if over.is_empty() {
+        return Ok(files);
+    }
+    if config.resource_limit_policy == ResourceLimitPolicy::Abort {
+        return Err(std::io::Error::other(format!(
+            "{} resources are over the limits",
+            over.len()
+        )));
+    }
+
+    let mut kept = Vec::with_capacity(files.len() - over.len());
+    for (index, (source, target)) in files.into_iter().enumerate() {
+        if !over.contains(&index) {
+            kept.push((source, target));
+        } else if config.resource_limit_policy == ResourceLimitPolicy::Link {
+            link_file(&source, &target, ResourceLink::Symlink)?;
+        }
+    }
+    Ok(kept)
+}
+
+/// Copies a directory tree. The directories are created first, in order, then the files are
+/// copied a few at a time; a file that fails to copy doesn't stop the others, it's recorded in
+/// `io_errors`. So are the directories left out for being too deep or linking back up the
+/// tree.
+pub fn copy_dir_recursively<P: AsRef<Path>>(
+    source_dir: P,
+    target_dir: P,
+    config: &Config,
+) -> std::io::Result<()> {
+    let mut files = Vec::new();
+    create_dirs(source_dir.as_ref(), target_dir.as_ref(), &mut files, config)?;
+
+    copy_files(&files, None, config)
+}
+
+/// Copies or links a file, unless the target already is the same as the source. With
+/// `preserve_xattrs` the copy keeps the extended attributes of the source, and with
+/// `strip_quarantine` it loses its quarantine. A link is the source, it's left as it is
+fn copy_file(source: &Path, target: &Path, config: &Config) -> std::io::Result<()> {
+    let target = &typed_target(source, target, config)?;
+    let Some(copy) = write_file(source, target, config)? else {
+        return Ok(());
+    };
+    if config.preserve_xattrs {
+        xattrs::copy_xattrs(source, &copy)?;
+    }
+    if config.strip_quarantine {
+        xattrs::strip_quarantine(&copy)?;
+    }
+
+    Ok(())
+}
+
+/// `copy_file` for a typed target, returns the copy: the target, or the PNG next to it, whether
+/// it was written now or already the same as the source. `None` when it's linked to the source
+fn write_file(source: &Path, target: &Path, config: &Config) -> std::io::Result<Option<PathBuf>> {
+    #[cfg(feature = "image")]
+    if config.convert_webp && is_webp(target) {
+        let mut png_target = target.as_os_str().to_owned();
+        png_target.push(PNG_SUFFIX);
+        reflink::unlink_target(source, Path::new(&png_target))?;
+        crate::images::convert_to_png(source, Path::new(&png_target))
+            .map_err(std::io::Error::other)?;
+        return Ok(Some(PathBuf::from(png_target)));
+    }
+    // Links would be written through onto the source
+    reflink::unlink_target(source, target)?;
+    // An image that can't be decoded is copied as it is
+    #[cfg(feature = "image")]
+    if let Some(downscale) = config.downscale_images
+        && crate::images::downscale(source, target, downscale).is_ok_and(|downscaled| downscaled)
+    {
+        return Ok(Some(target.to_path_buf()));
+    }
+    // Unlike a failed downscale, a failed strip doesn't fall back to a copy, which would keep
+    // what was asked to be dropped
+    #[cfg(feature = "image")]
+    if config.strip_exif && crate::images::strip_exif(source, target)? {
+        return Ok(Some(target.to_path_buf()));
+    }
+    // A link is made as fast as the target is compared, and a copy the same as the source is
+    // still a copy
+    if config.link_resources.is_none() && is_identical(source, target, config.paranoid) {
+        return Ok(Some(target.to_path_buf()));
+    }
+    match config.link_resources {
+        Some(link) => link_file(source, target, link).map(|_| None),
+        None => reflink::copy(source, target).map(|_| Some(target.to_path_buf())),
+    }
+}
+
+/// Links the target to the source, in place of what's there
+fn link_file(source: &Path, target: &Path, link: ResourceLink) -> std::io::Result<()> {
+    // Unlike a copy, a link doesn't replace what's already there
+    match std::fs::remove_file(target) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    match link {
+        // A hard link can't cross filesystems
+        ResourceLink::HardLink => {
+            std::fs::hard_link(source, target).or_else(|_| reflink::copy(source, target))
+        }
+        ResourceLink::Symlink => symlink(&std::path::absolute(source)?, target),
+    }
+}
+
+/// The target with the extension of the content of the source appended when it has none and
+/// `sniff_resource_types` is set, as `typed_links` points the links at it
+fn typed_target(source: &Path, target: &Path, config: &Config) -> std::io::Result<PathBuf> {
+    if !config.sniff_resource_types || source.extension().is_some() {
+        return Ok(target.to_path_buf());
+    }
+
+    Ok(match sniff_file_extension(source)? {
+        Some(extension) => {
+            let mut typed = target.as_os_str().to_owned();
+            typed.push(format!(".{extension}"));
+            PathBuf::from(typed)
+        }
+        None => target.to_path_buf(),
+    })
+}
+
+/// Whether the target is a file of the same size and modification time as the source, copies
+/// keep the modification time. `paranoid` compares the content instead of the time.
+fn is_identical(source: &Path, target: &Path, paranoid: bool) -> bool {
+    let (Ok(source_metadata), Ok(target_metadata)) =
+        (std::fs::metadata(source), std::fs::symlink_metadata(target))
+    else {
+        return false;
+    };
+    if !target_metadata.is_file() || source_metadata.len() != target_metadata.len() {
+        return false;
+    }
+
+    if paranoid {
+        return matches!(
+            (file_md5(source), file_md5(target)),
+            (Ok(source_md5), Ok(target_md5)) if source_md5 == target_md5
+        );
+    }
+    matches!(
+        (source_metadata.modified(), target_metadata.modified()),
+        (Ok(source_modified), Ok(target_modified)) if source_modified == target_modified
+    )
+}
+
+/// The md5 of a file, read a block at a time so resources of any size fit in memory
+fn file_md5(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut md5 = Md5::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(md5.finalize().to_vec()),
+            read => md5.update(&buffer[..read]),
+        }
+    }
+}
+
+/// Creates the directories of the source tree in the target, and collects the files to copy as
+/// pairs of source and target paths. Sockets, FIFOs and device nodes are left out with a warning
+fn create_dirs(
+    source_dir: &Path,
+    target_dir: &Path,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+    config: &Config,
+) -> std::io::Result<()> {
+    let mut ancestors = HashSet::new();
+    create_dirs_below(source_dir, target_dir, 0, &mut ancestors, files, config)
+}
+
+/// `create_dirs` for a directory `depth` levels below the top of the tree. The directories
+/// deeper than `MAX_DIR_DEPTH`, and those that are one of their `ancestors` through a symbolic
+/// link, are left out and recorded in `io_errors`, they would never end
+fn create_dirs_below(
+    source_dir: &Path,
+    target_dir: &Path,
+    depth: usize,
+    ancestors: &mut HashSet<PathBuf>,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+    config: &Config,
+) -> std::io::Result<()> {
+    if depth > MAX_DIR_DEPTH {
+        config.io_errors.record(
+            source_dir,
+            format!("more than {MAX_DIR_DEPTH} directories deep, not copied"),
+        );
+        return Ok(());
+    }
+    let canonical = source_dir.canonicalize()?;
+    if ancestors.contains(&canonical) {
+        config.io_errors.record(
+            source_dir,
+            "links to a directory it's in, not copied".to_string(),
+        );
+        return Ok(());
+    }
+
+    ancestors.insert(canonical.clone());
+    create_dir_all(target_dir)?;
+    for entry in std::fs::read_dir(source_dir)? {
+        config.cancellation.check_io()?;
+        let entry = entry?;
+        let source = entry.path();
+        let target = target_dir.join(entry.file_name());
+
+        if source.is_dir() {
+            create_dirs_below(&source, &target, depth + 1, ancestors, files, config)?;
+        } else if let Some(kind) = reflink::special_file(&source) {
+            crate::warning!("{:?} is a {kind}, it's not copied", source);
+        } else {
+            files.push((source, target));
+        }
+    }
+    ancestors.remove(&canonical);
+
+    Ok(())
+}
+
+/// Copies the files a few at a time. With a journal, those it lists as copied are skipped and
+/// the others recorded as they're copied
+fn copy_files(
+    files: &[(PathBuf, PathBuf)],
+    journal: Option<&Mutex<Journal>>,
+    config: &Config,
+) -> std::io::Result<()> {
+    let cancellation = &config.cancellation;
+    let next = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        for _ in 0..files.len().min(COPY_WORKERS) {
+            scope.spawn(|| {
+                while let Some((source, target)) =
+                    files.get(next.fetch_add(1, AtomicOrdering::Relaxed))
+                {
+                    if cancellation.is_cancelled() {
+                        break;
+                    }
+                    let copied = fingerprint(source).ok();
+                    let journal = journal.zip(copied.as_ref());
+                    if let Some((journal, copied)) = journal
+                        && journal.lock().unwrap().is_written(target, copied)
+                        && long_path(target).exists()
+                    {
+                        continue;
+                    }
+                    let permit = config.open_files.acquire(2);
+                    let result = retry(|| copy_file(source, target, config)).and_then(|_| {
+                        journal.map_or(Ok(()), |(journal, copied)| {
+                            let mut journal = journal.lock().unwrap();
+                            journal
+                                .record(target, copied)
+                                .map_err(std::io::Error::other)
+                        })
+                    });
+                    drop(permit);
+                    if let Err(e) = result {
+                        config.io_errors.record(source, e);
+                    }
+                }
+            });
+        }
+    });
+
+    cancellation.check_io()
+}
+
+/// The notes under the directory, by their canonical path. They're listed with `read_dir`, as a
+/// glob leaves out the names that aren't valid UTF-8 without a word
+pub fn find_files<P: AsRef<Path>>(dir: P, config: &Config) -> Result<Vec<PathBuf>, String> {
+    let dir = dir.as_ref();
+    if !dir.exists() {
+        return Err(format!("The path {:?} does not exist", dir));
+    }
+
+    if !dir.is_dir() {
+        return Err(format!("The path {:?} is not a directory", dir));
+    }
+
+    let mut relative_paths = Vec::new();
+    list_files(dir, Path::new(""), &mut relative_paths)?;
+
+    let mut paths = Vec::new();
+    for path in relative_paths
+        .iter()
+        .filter(|relative_path| config.is_note(relative_path))
+        .map(|relative_path| dir.join(relative_path))
+        .filter(|path| path.is_file())
+    {
+        match path.canonicalize() {
+            Ok(abs) => paths.push(abs),
+            Err(e) => return Err(format!("Error canonicalizing path: {}", e)),
+        }
+    }
+
+    // Directory order depends on the OS, sorting keeps reports and target names reproducible
+    paths.sort_by(|a, b| natural_path_cmp(a, b));
+
+    Ok(paths)
+}
+
+/// Compares paths component by component in natural order, so `note2.md` comes before
+/// `note10.md`. The order doesn't depend on the locale.
+pub(crate) fn natural_path_cmp(a: &Path, b: &Path) -> Ordering {
+    let components = |path: &Path| -> Vec<String> {
+        path.components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect()
+    };
+    let a_components = components(a);
+    let b_components = components(b);
+
+    a_components
+        .iter()
+        .zip(&b_components)
+        .map(|(a, b)| natural_cmp(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a_components.len().cmp(&b_components.len()))
+        .then_with(|| a.cmp(b))
+}
+
+/// Case insensitive comparison where runs of digits compare by their numeric value
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        let ordering = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a_chars);
+                let y = take_number(&mut b_chars);
+                x.len().cmp(&y.len()).then_with(|| x.cmp(&y))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                a_chars.next();
+                b_chars.next();
+                ordering
+            }
+        };
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+
+    // Only differ in case or leading zeros, fall back to byte order to stay deterministic
+    a.cmp(b)
+}
+
+/// Takes a run of digits without its leading zeros
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        if !(number.is_empty() && c == '0') {
+            number.push(c);
+        }
+    }
+
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{NoteIdMarker, ResourceLimitPolicy};
+    use std::fs;
+
+    struct TestFixture {
+        temp_dir: PathBuf,
+    }
+
+    impl TestFixture {
+        fn new(name: &str) -> Self {
+            let temp_dir = std::env::temp_dir().join(name);
+            if temp_dir.exists() {
+                fs::remove_dir_all(&temp_dir).unwrap();
+            }
+
+            create_dir_all(&temp_dir).unwrap();
+            Self { temp_dir }
+        }
+
+        fn create_file(&self, name: &PathBuf, content: &str) {
+            fs::write(self.temp_dir.join(name), content).unwrap();
+        }
+
+        fn create_sub_directory(&self, name: &str) {
+            create_dir_all(self.temp_dir.join(name)).unwrap();
+        }
+    }
+
+    impl Drop for TestFixture {
+        fn drop(&mut self) {
+            if self.temp_dir.exists() {
+                fs::remove_dir_all(&self.temp_dir).unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_note() {
+        let test_cases: Vec<(&[u8], Decoded)> = vec![
+            (b"caf\xc3\xa9", Decoded::Utf8("café".to_string())),
+            (
+                b"\xef\xbb\xbfcaf\xc3\xa9",
+                Decoded::Utf8("café".to_string()),
+            ),
+            (
+                b"\xff\xfec\x00a\x00f\x00\xe9\x00",
+                Decoded::Transcoded("café".to_string(), "UTF-16LE"),
+            ),
+            (
+                b"Un caf\xe9 tr\xe8s cher \xe0 la gare",
+                Decoded::Transcoded("Un café très cher à la gare".to_string(), "windows-1252"),
+            ),
+            (b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\xff", Decoded::Binary),
+            (b"\0\0\0\0text", Decoded::Binary),
+        ];
+
+        for (bytes, expected) in test_cases {
+            assert_eq!(decode_note(bytes.to_vec()), expected);
+        }
+    }
+
+    #[test]
+    fn test_convert_one() {
+        let content = "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\nBody";
+        let test_cases: Vec<(&str, &[u8], Result<String, String>)> = vec![
+            (
+                "foo/a.md",
+                content.as_bytes(),
+                Ok("Body\n\n#foo\n".to_string()),
+            ),
+            ("a.md", content.as_bytes(), Ok("Body\n".to_string())),
+            (
+                "a.md",
+                b"\0\xff",
+                Err("The note is not a text file".to_string()),
+            ),
+            (
+                "a.md",
+                b"\0\0\0\0text",
+                Err("The note is not a text file".to_string()),
+            ),
+        ];
+
+        for (relative_path, bytes, expected) in test_cases {
+            let result = convert_one(relative_path, bytes, &Config::default());
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_build_content() {
+        let front_matter =
+            "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n";
+        let test_cases: Vec<(&str, &str, TagPlacement, &str)> = vec![
+            (
+                "foo/a.md",
+                "Line 1\nLine 2",
+                TagPlacement::Bottom,
+                "Line 1\nLine 2\n\n#foo\n",
+            ),
+            ("foo/a.md", "", TagPlacement::Bottom, "\n\n#foo\n"),
+            (
+                "foo/a.md",
+                "Line 1\nLine 2",
+                TagPlacement::Top,
+                "# Test\n#foo\n\nLine 1\nLine 2\n",
+            ),
+            (
+                "foo/a.md",
+                "# Test\n\nLine 2",
+                TagPlacement::Top,
+                "# Test\n#foo\n\nLine 2\n",
+            ),
+            (
+                "foo/a.md",
+                "# Tests\nLine 2",
+                TagPlacement::Top,
+                "# Test\n#foo\n\n# Tests\nLine 2\n",
+            ),
+            ("foo/a.md", "", TagPlacement::Top, "# Test\n#foo\n"),
+            (
+                "foo/a.md",
+                "# Title\nLine 2",
+                TagPlacement::AfterTitle,
+                "# Title\n#foo\nLine 2\n",
+            ),
+            (
+                "foo/a.md",
+                "# Title",
+                TagPlacement::AfterTitle,
+                "# Title\n#foo\n",
+            ),
+            ("foo/a.md", "", TagPlacement::AfterTitle, "#foo\n"),
+            ("", "Line 1", TagPlacement::Top, "Line 1\n"),
+        ];
+
+        for (relative_path, body, tag_placement, expected) in test_cases {
+            let config = Config {
+                tag_placement,
+                ..Config::default()
+            };
+            let joplin_file =
+                JoplinFile::build(relative_path, &format!("{front_matter}{body}"), &config)
+                    .unwrap();
+
+            let result = build_content(&joplin_file, &config);
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_build_content_link_to_joplin() {
+        let test_cases: Vec<(&str, &str, bool, &str)> = vec![
+            ("", "Line 1", true, "Line 1\n\n#foo\n"),
+            (
+                "id: abc\n",
+                "Line 1",
+                true,
+                "Line 1\n\n[Open in Joplin](joplin://x-callback-url/openNote?id=abc)\n\n#foo\n",
+            ),
+            ("id: abc\n", "Line 1", false, "Line 1\n\n#foo\n"),
+            (
+                "id: abc\n",
+                "",
+                true,
+                "[Open in Joplin](joplin://x-callback-url/openNote?id=abc)\n\n#foo\n",
+            ),
+        ];
+
+        for (id, body, link_to_joplin, expected) in test_cases {
+            let config = Config {
+                link_to_joplin,
+                ..Config::default()
+            };
+            let content = format!(
+                "---\ntitle: Test\n{id}created: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+            );
+            let joplin_file = JoplinFile::build("foo/a.md", &content, &config).unwrap();
+
+            let result = build_content(&joplin_file, &config);
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_build_content_note_id() {
+        let test_cases: Vec<(&str, &str, NoteIdMarker, bool, &str)> = vec![
+            (
+                "id: abc\n",
+                "Line 1",
+                NoteIdMarker::Omit,
+                false,
+                "Line 1\n\n#foo\n",
+            ),
+            (
+                "id: abc\n",
+                "Line 1",
+                NoteIdMarker::Comment,
+                false,
+                "Line 1\n\n<!-- joplin-id: abc -->\n\n#foo\n",
+            ),
+            (
+                "id: abc\n",
+                "",
+                NoteIdMarker::Footer,
+                false,
+                "joplin-id:abc\n\n#foo\n",
+            ),
+            (
+                "id: abc\n",
+                "Line 1",
+                NoteIdMarker::Footer,
+                true,
+                "Line 1\n\n[Open in Joplin](joplin://x-callback-url/openNote?id=abc)\n\njoplin-id:abc\n\n#foo\n",
+            ),
+            (
+                "",
+                "Line 1",
+                NoteIdMarker::Comment,
+                false,
+                "Line 1\n\n#foo\n",
+            ),
+        ];
+
+        for (id, body, note_id, link_to_joplin, expected) in test_cases {
+            let config = Config {
+                note_id,
+                link_to_joplin,
+                ..Config::default()
+            };
+            let content = format!(
+                "---\ntitle: Test\n{id}created: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+            );
+            let joplin_file = JoplinFile::build("foo/a.md", &content, &config).unwrap();
+
+            let result = build_content(&joplin_file, &config);
+
+            assert_eq!(result, expected, "{:?}", note_id);
+        }
+    }
+
+    #[test]
+    fn test_build_content_convert_webp() {
+        let test_cases: Vec<(bool, &str)> = vec![
+            (
+                false,
+                "![](../_resources/a.webp) ![](../_resources/b.png) [c](../_resources/c.WebP)\n",
+            ),
+            (
+                true,
+                "![](../_resources/a.webp.png) ![](../_resources/b.png) [c](../_resources/c.WebP.png)\n",
+            ),
+        ];
+
+        for (convert_webp, expected) in test_cases {
+            let config = Config {
+                convert_webp,
+                ..Config::default()
+            };
+            let content = "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+                ![](../_resources/a.webp) ![](../_resources/b.png) [c](../_resources/c.WebP)";
+            let joplin_file = JoplinFile::build("a.md", content, &config).unwrap();
+
+            let result = build_content(&joplin_file, &config);
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_write_joplin_files_front_matter_at_eof() {
+        // arrange
+        let fixture = TestFixture::new("joplin_file_eof_test");
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        fixture.create_sub_directory("source/foo");
+        fixture.create_file(
+            &source_dir.join("foo").join("a.md"),
+            "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---",
+        );
+        let config = Config::default();
+
+        // act
+        // A path that isn't canonical must work as well
+        let joplin_files = build_joplin_files(source_dir.join("foo").join(".."), &config).unwrap();
+        write_joplin_files(&target_dir, &joplin_files, &config).unwrap();
+
+        // assert
+        assert_eq!(joplin_files[0].title, "Test");
+        assert_eq!(joplin_files[0].body, "");
+        assert_eq!(
+            fs::read_to_string(target_dir.join("foo").join("a.md")).unwrap(),
+            "\n\n#foo\n"
+        );
+    }
+
+    #[test]
+    fn test_conversion_is_deterministic() {
+        // arrange
+        let fixture = TestFixture::new("jb_deterministic_test");
+        let source_dir = fixture.temp_dir.join("source");
+        fixture.create_sub_directory("source/Work/Sub");
+        fixture.create_sub_directory("source/_resources/nested");
+        let notes = [
+            (
+                "Work/b.md",
+                "tags:\n  - z\n  - a\n",
+                "[a](a.md) [toc]\n\n# One\n\n# One",
+            ),
+            (
+                "Work/a.md",
+                "tags: [m, b]\n",
+                "![](../_resources/x.png) [b](b.md#one)",
+            ),
+            ("Work/Sub/c?.md", "", "Same"),
+            ("Work/Sub/d.md", "", "Same"),
+        ];
+        for (relative_path, tags, body) in notes {
+            fixture.create_file(
+                &source_dir.join(relative_path),
+                &format!(
+                    "---\ntitle: {relative_path}\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n{tags}---\n{body}"
+                ),
+            );
+        }
+        fixture.create_file(&source_dir.join("_resources/x.png"), "png");
+        fixture.create_file(&source_dir.join("_resources/nested/y.png"), "png");
+        let config = Config {
+            toc_marker: crate::config::TocMarker::Expand,
+            duplicate_policy: crate::config::DuplicatePolicy::Tag,
+            ..Config::default()
+        };
+
+        // act
+        let mut outputs = Vec::new();
+        for run in ["a", "b"] {
+            let target_dir = fixture.temp_dir.join(run);
+            let joplin_files = build_joplin_files(&source_dir, &config).unwrap();
+            write_joplin_files(&target_dir, &joplin_files, &config).unwrap();
+            copy_resources(&source_dir, &target_dir, &config).unwrap();
+            crate::enex::write_enex(&source_dir, &target_dir.join("notes.enex"), &joplin_files)
+                .unwrap();
+            let orphaned = find_orphaned_resources(&source_dir, &joplin_files, &config).unwrap();
+
+            let mut relative_paths = Vec::new();
+            list_files(&target_dir, Path::new(""), &mut relative_paths).unwrap();
+            relative_paths.sort();
+            let files: Vec<(PathBuf, Vec<u8>)> = relative_paths
+                .into_iter()
+                .map(|relative_path| {
+                    let content = fs::read(target_dir.join(&relative_path)).unwrap();
+                    (relative_path, content)
+                })
+                .collect();
+            outputs.push((files, orphaned));
+        }
+
+        // assert
+        assert_eq!(outputs[0].0.len(), 7);
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn test_write_joplin_files_times() {
+        // arrange
+        let fixture = TestFixture::new("jb_file_times_test");
+        let target_dir = fixture.temp_dir.join("target");
+        let config = Config::default();
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("a.md", "2024-04-07T08:34:52Z"),
+            ("b.md", "2024-04-07T08:34:52.123Z"),
+            ("c.md", "2024-04-07T08:34:52.123456+02:00"),
+        ];
+
+        for (relative_path, updated) in test_cases {
+            let content = format!(
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: {}\n---\n",
+                updated
+            );
+            let joplin_file = JoplinFile::build(relative_path, &content, &config).unwrap();
+
+            // act
+            write_joplin_files(&target_dir, std::slice::from_ref(&joplin_file), &config).unwrap();
+
+            // assert
+            let modified = fs::metadata(target_dir.join(relative_path))
+                .unwrap()
+                .modified()
+                .unwrap();
+            assert_eq!(modified, SystemTime::from(joplin_file.updated));
+        }
+    }
+
+    #[test]
+    fn test_copy_dir_recursively() {
+        // arrange
+        let fixture = TestFixture::new("jb_copy_dir_test");
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        fixture.create_sub_directory("source/sub");
+        fixture.create_sub_directory("target/b.png");
+        for name in ["a.png", "b.png", "c.png", "sub/d.png"] {
+            fixture.create_file(&source_dir.join(name), name);
+        }
+
+        let config = Config::default();
+
+        // act
+        let result = copy_dir_recursively(&source_dir, &target_dir, &config);
+
+        // assert
+        assert!(result.is_ok());
+        let failed: Vec<PathBuf> = config.io_errors.by_directory().into_keys().collect();
+        assert_eq!(failed, vec![source_dir.clone()]);
+        assert_eq!(config.io_errors.len(), 1);
+        assert!(config.io_errors.to_string().contains("b.png"));
+        for name in ["a.png", "c.png", "sub/d.png"] {
+            assert_eq!(fs::read_to_string(target_dir.join(name)).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_copy_resources_skips_identical() {
+        // arrange
+        let fixture = TestFixture::new("jb_skip_identical_test");
+        fixture.create_sub_directory("source/_resources");
+        fixture.create_file(&PathBuf::from("source/_resources/a.png"), "png");
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        let target = target_dir.join("_resources/a.png");
+        copy_resources(&source_dir, &target_dir, &Config::default()).unwrap();
+        // Same size and time, different content
+        let modified = fs::metadata(&target).unwrap().modified().unwrap();
+        fs::write(&target, "gif").unwrap();
+        File::options()
+            .write(true)
+            .open(&target)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let test_cases: Vec<(bool, &str)> = vec![(false, "gif"), (true, "png")];
+
+        for (paranoid, expected) in test_cases {
+            let config = Config {
+                paranoid,
+                ..Config::default()
+            };
+
+            // act
+            let result = copy_resources(&source_dir, &target_dir, &config);
+
+            // assert
+            assert_eq!(result, Ok(()));
+            assert_eq!(fs::read_to_string(&target).unwrap(), expected);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_dir_recursively_cycle() {
+        // arrange
+        let fixture = TestFixture::new("jb_copy_dir_cycle_test");
+        fixture.create_sub_directory("source/sub");
+        fixture.create_file(&PathBuf::from("source/sub/a.png"), "png");
+        symlink(
+            fixture.temp_dir.join("source"),
+            fixture.temp_dir.join("source/sub/up"),
+        )
+        .unwrap();
+        let config = Config::default();
+
+        // act
+        let result = copy_dir_recursively(
+            fixture.temp_dir.join("source"),
+            fixture.temp_dir.join("target"),
+            &config,
+        );
+        let mut relative_paths = Vec::new();
+        let listed = list_files(
+            &fixture.temp_dir.join("source"),
+            Path::new(""),
+            &mut relative_paths,
+        );
+
+        // assert
+        assert!(result.is_ok());
+        assert_eq!(listed, Ok(()));
+        assert_eq!(relative_paths, vec![PathBuf::from("sub/a.png")]);
+        assert!(fixture.temp_dir.join("target/sub/a.png").is_file());
+        assert!(!fixture.temp_dir.join("target/sub/up").exists());
+        let by_directory = config.io_errors.by_directory();
+        let errors: Vec<&(String, String)> = by_directory.values().flatten().collect();
+        assert_eq!(
+            errors,
+            vec![&(
+                "up".to_string(),
+                "links to a directory it's in, not copied".to_string()
+            )]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_dir_recursively_special_file() {
+        // arrange
+        let fixture = TestFixture::new("jb_copy_dir_special_test");
+        fixture.create_sub_directory("source");
+        fixture.create_file(&PathBuf::from("source/a.png"), "png");
+        let _listener =
+            std::os::unix::net::UnixListener::bind(fixture.temp_dir.join("source/b.sock")).unwrap();
+        let config = Config::default();
+
+        // act
+        let result = copy_dir_recursively(
+            fixture.temp_dir.join("source"),
+            fixture.temp_dir.join("target"),
+            &config,
+        );
+
+        // assert
+        assert!(result.is_ok());
+        assert!(fixture.temp_dir.join("target/a.png").is_file());
+        assert!(!fixture.temp_dir.join("target/b.sock").exists());
+        assert!(config.io_errors.is_empty());
+    }
+
+    #[test]
+    fn test_find_orphaned_resources() {
+        // arrange
+        let fixture = TestFixture::new("jb_orphaned_resources_test");
+        fixture.create_sub_directory("_resources/sub");
+        for name in [
+            "_resources/a b.png",
+            "_resources/sub/c.png",
+            "_resources/0123456789abcdef0123456789abcdef.pdf",
+            "_resources/d.png",
+        ] {
+            fixture.create_file(&PathBuf::from(name), "");
+        }
+        let body = "![](../_resources/a%20b.png) [doc](:/0123456789abcdef0123456789abcdef)";
+        let content = format!(
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{}",
+            body
+        );
+        let joplin_files = vec![JoplinFile::build("a.md", &content, &Config::default()).unwrap()];
+
+        // act
+        let result = find_orphaned_resources(&fixture.temp_dir, &joplin_files, &Config::default());
+
+        // assert
+        let mut warnings = result.unwrap();
+        warnings.sort_by_key(|warning| warning.to_string());
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::OrphanedResource(PathBuf::from("d.png")),
+                Warning::OrphanedResource(PathBuf::from("sub/c.png")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_resources_dir() {
+        // arrange
+        let fixture = TestFixture::new("jb_missing_resources_dir_test");
+        fixture.create_sub_directory("source");
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        let note = |body: &str| {
+            let content = format!(
+                "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+            );
+            JoplinFile::build("a.md", &content, &Config::default()).unwrap()
+        };
+        let test_cases: Vec<(&str, bool, Option<Vec<Warning>>)> = vec![
+            ("Text only", false, Some(vec![])),
+            (
+                "![](../_resources/a.png)",
+                false,
+                Some(vec![Warning::NoResourcesDir]),
+            ),
+            ("Text only", true, None),
+        ];
+
+        for (body, require_resources, expected) in test_cases {
+            let config = Config {
+                require_resources,
+                ..Config::default()
+            };
+
+            // act
+            let warnings = check_resources_dir(&source_dir, &[note(body)], &config);
+            let result = copy_resources(&source_dir, &target_dir, &config);
+
+            // assert
+            assert_eq!(warnings.ok(), expected, "{}", body);
+            assert_eq!(result.is_ok(), !require_resources, "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_copy_resources_over_limits() {
+        // arrange
+        let fixture = TestFixture::new("jb_resource_limits_test");
+        fixture.create_sub_directory("source/_resources/sub");
+        for (name, content) in [
+            ("a.png", "small"),
+            ("b.mov", "much bigger"),
+            ("sub/c.png", "c"),
+        ] {
+            fixture.create_file(&PathBuf::from("source/_resources").join(name), content);
+        }
+        let source_dir = fixture.temp_dir.join("source");
+        let test_cases: Vec<(ResourceLimitPolicy, Option<bool>, bool)> = vec![
+            (ResourceLimitPolicy::Skip, Some(false), false),
+            (ResourceLimitPolicy::Link, Some(true), true),
+            (ResourceLimitPolicy::Abort, None, false),
+        ];
+
+        for (policy, linked, expected_b) in test_cases {
+            let target_dir = fixture.temp_dir.join(format!("{:?}", policy));
+            let config = Config {
+                max_resource_size: Some(5),
+                max_resources: Some(1),
+                resource_limit_policy: policy,
+                ..Config::default()
+            };
+
+            // act
+            let warnings = find_resources_over_limits(&source_dir, &config);
+            let result = copy_resources(&source_dir, &target_dir, &config);
+
+            // assert
+            let expected = |resource_path: &str, size| Warning::ResourceOverLimits {
+                resource_path: PathBuf::from(resource_path),
+                size,
+                linked: linked.unwrap_or_default(),
+            };
+            assert_eq!(
+                warnings,
+                Ok(vec![expected("b.mov", 11), expected("sub/c.png", 1)]),
+                "{:?}",
+                policy
+            );
+            assert_eq!(result.is_ok(), linked.is_some(), "{:?}", policy);
+            let resources_dir = target_dir.join("_resources");
+            assert_eq!(resources_dir.join("a.png").exists(), linked.is_some());
+            assert_eq!(resources_dir.join("b.mov").exists(), expected_b);
+            assert_eq!(resources_dir.join("sub/c.png").exists(), expected_b);
+        }
+    }
+
+    #[test]
+    fn test_mark_left_out_resources() {
+        let content = "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\n\
+            updated: 2024-04-07T08:34:52Z\n---\n\
+            ![](../_resources/a.png) [b](../_resources/b%20c.mov)";
+        let test_cases: Vec<(bool, usize)> = vec![(false, 1), (true, 0)];
+
+        for (linked, expected) in test_cases {
+            let mut joplin_files =
+                vec![JoplinFile::build("Notes/a.md", content, &Config::default()).unwrap()];
+            let over_limits = [Warning::ResourceOverLimits {
+                resource_path: PathBuf::from("b c.mov"),
+                size: 11,
+                linked,
+            }];
+            let config = Config {
+                review_threshold: Some(1),
+                ..Config::default()
+            };
+
+            mark_left_out_resources(&mut joplin_files, &over_limits, &config);
+
+            let joplin_file = &joplin_files[0];
+            assert_eq!(joplin_file.fidelity.missing_resources, expected);
+            assert_eq!(
+                joplin_file
+                    .warnings
+                    .contains(&Warning::LeftOutResources(vec!["b%20c.mov".to_string()])),
+                !linked
+            );
+            assert_eq!(
+                joplin_file
+                    .tags
+                    .as_deref()
+                    .is_some_and(|tags| tags.contains(REVIEW_TAG)),
+                !linked
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_resource_dirs() {
+        // arrange
+        let fixture = TestFixture::new("jb_resource_dirs_test");
+        fixture.create_sub_directory("source/_resources");
+        fixture.create_sub_directory("source/assets/sub");
+        fixture.create_sub_directory("source/Notes");
+        fixture.create_sub_directory("source/attachments");
+        for (name, content) in [
+            ("_resources/c.png", "from _resources"),
+            ("assets/a b.png", "a"),
+            ("assets/sub/d.pdf", "d"),
+            ("assets/c.png", "from assets"),
+            (
+                "attachments/Not a resource dir.md",
+                "---\ntitle: N\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+            ),
+        ] {
+            fixture.create_file(&PathBuf::from("source").join(name), content);
+        }
+        fixture.create_file(
+            &PathBuf::from("source/Notes/a.md"),
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            ![](../assets/a%20b.png) [d](../assets/sub/d.pdf) ![](../_resources/c.png) \
+            [web](https://example.com/assets/x.png)",
+        );
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+
+        // act
+        let resource_dirs = find_resource_dirs(&source_dir, &Config::default());
+        let config = Config {
+            resource_dirs: resource_dirs.clone(),
+            ..Config::default()
+        };
+        let joplin_files = build_joplin_files(&source_dir, &config).unwrap();
+        let result = copy_resources(&source_dir, &target_dir, &config);
+
+        // assert
+        assert_eq!(resource_dirs, vec![PathBuf::from("assets")]);
+        let joplin_file = joplin_files
+            .iter()
+            .find(|joplin_file| joplin_file.title == "A")
+            .unwrap();
+        assert_eq!(
+            joplin_file.body,
+            "![](../_resources/a%20b.png) [d](../_resources/sub/d.pdf) \
+            ![](../_resources/c.png) [web](https://example.com/assets/x.png)"
+        );
+        assert_eq!(joplin_file.fidelity.missing_resources, 0);
+        assert_eq!(result, Ok(()));
+        let resources_dir = target_dir.join("_resources");
+        assert_eq!(
+            fs::read_to_string(resources_dir.join("a b.png")).unwrap(),
+            "a"
+        );
+        assert!(resources_dir.join("sub/d.pdf").is_file());
+        assert_eq!(
+            fs::read_to_string(resources_dir.join("c.png")).unwrap(),
+            "from _resources"
+        );
+        assert_eq!(
+            find_orphaned_resources(&source_dir, &joplin_files, &config),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    fn test_build_joplin_files_skipped() {
+        // arrange
+        let fixture = TestFixture::new("jb_build_skipped_test");
+        fixture.create_sub_directory("source");
+        fixture.create_file(
+            &PathBuf::from("source/a.md"),
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+        );
+        fs::write(fixture.temp_dir.join("source/b.md"), b"\x89PNG\r\n\x1a\n\0").unwrap();
+        let config = Config::default();
+
+        // act
+        let joplin_files = build_joplin_files(fixture.temp_dir.join("source"), &config).unwrap();
+
+        // assert
+        assert_eq!(joplin_files.len(), 1);
+        assert_eq!(config.skipped_notes.count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_joplin_files_linked_outside() {
+        // arrange
+        let fixture = TestFixture::new("jb_build_linked_outside_test");
+        fixture.create_sub_directory("source");
+        fixture.create_sub_directory("outside");
+        let content =
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n";
+        fixture.create_file(&PathBuf::from("source/a.md"), content);
+        fixture.create_file(&PathBuf::from("outside/b.md"), content);
+        symlink(
+            fixture.temp_dir.join("outside/b.md"),
+            fixture.temp_dir.join("source/b.md"),
+        )
+        .unwrap();
+        let config = Config::default();
+
+        // act
+        let result = build_joplin_files(fixture.temp_dir.join("source"), &config);
+
+        // assert
+        let joplin_files = result.unwrap();
+        assert_eq!(joplin_files.len(), 1);
+        assert_eq!(joplin_files[0].relative_path, PathBuf::from("a.md"));
+        assert_eq!(config.skipped_notes.count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_joplin_files_lossy_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        // arrange
+        let fixture = TestFixture::new("jb_build_lossy_path_test");
+        let content =
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n";
+        let lossy_dir = fixture.temp_dir.join(OsStr::from_bytes(b"caf\xe9"));
+        create_dir_all(&lossy_dir).unwrap();
+        fs::write(lossy_dir.join(OsStr::from_bytes(b"n\xe9.md")), content).unwrap();
+        fixture.create_file(&PathBuf::from("ok.md"), content);
+        let config = Config::default();
+
+        // act
+        let result = build_joplin_files(&fixture.temp_dir, &config);
+
+        // assert
+        let joplin_files = result.unwrap();
+        assert_eq!(joplin_files.len(), 2);
+        assert!(joplin_files[0].warnings.contains(&Warning::LossyPath));
+        assert!(joplin_files[1].warnings.is_empty());
+    }
+
+    #[test]
+    fn test_sniff_resource_types() {
+        // arrange
+        let fixture = TestFixture::new("jb_sniff_resource_types_test");
+        fixture.create_sub_directory("source/_resources");
+        fixture.create_sub_directory("source/Notes");
+        fs::write(
+            fixture.temp_dir.join("source/_resources/diagram"),
+            b"\x89PNG\r\n",
+        )
+        .unwrap();
+        fixture.create_file(&PathBuf::from("source/_resources/plain"), "Plain text");
+        fixture.create_file(
+            &PathBuf::from("source/Notes/a.md"),
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            ![](../_resources/diagram) [](../_resources/plain)",
+        );
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        let config = Config {
+            sniff_resource_types: true,
+            ..Config::default()
+        };
+
+        // act
+        let joplin_files = build_joplin_files(&source_dir, &config).unwrap();
+        let result = copy_resources(&source_dir, &target_dir, &config);
+
+        // assert
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            joplin_files[0].body,
+            "![](../_resources/diagram.png) [](../_resources/plain)"
+        );
+        assert_eq!(joplin_files[0].fidelity.missing_resources, 0);
+        assert!(target_dir.join("_resources/diagram.png").is_file());
+        assert!(target_dir.join("_resources/plain").is_file());
+        assert_eq!(
+            find_orphaned_resources(&source_dir, &joplin_files, &config),
+            Ok(vec![])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_resources() {
+        use std::os::unix::fs::MetadataExt;
+
+        // arrange
+        let fixture = TestFixture::new("jb_link_resources_test");
+        fixture.create_sub_directory("source/_resources/sub");
+        fixture.create_file(&PathBuf::from("source/_resources/sub/a.png"), "png");
+        let source = fixture.temp_dir.join("source/_resources/sub/a.png");
+        let test_cases: Vec<(ResourceLink, &str)> = vec![
+            (ResourceLink::HardLink, "hard"),
+            (ResourceLink::Symlink, "symbolic"),
+        ];
+
+        for (link, name) in test_cases {
+            let target_dir = fixture.temp_dir.join(name);
+            fixture.create_sub_directory(&format!("{name}/_resources/sub"));
+            fixture.create_file(
+                &PathBuf::from(format!("{name}/_resources/sub/a.png")),
+                "old",
+            );
+
+            // act
+            let result = link_resources(
+                &fixture.temp_dir.join("source"),
+                &target_dir,
+                link,
+                &Cancellation::default(),
+            );
+
+            // assert
+            assert_eq!(result, Ok(()));
+            let target = target_dir.join("_resources/sub/a.png");
+            assert_eq!(fs::read_to_string(&target).unwrap(), "png");
+            match link {
+                ResourceLink::HardLink => assert_eq!(
+                    fs::metadata(&target).unwrap().ino(),
+                    fs::metadata(&source).unwrap().ino()
+                ),
+                ResourceLink::Symlink => assert_eq!(fs::read_link(&target).unwrap(), source),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_resources_after_linking() {
+        // arrange
+        let fixture = TestFixture::new("jb_copy_after_linking_test");
+        fixture.create_sub_directory("source/_resources");
+        fixture.create_file(&PathBuf::from("source/_resources/a.png"), "png");
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        let linked = Config {
+            link_resources: Some(ResourceLink::Symlink),
+            ..Config::default()
+        };
+        copy_resources(&source_dir, &target_dir, &linked).unwrap();
+
+        // act
+        let result = copy_resources(&source_dir, &target_dir, &Config::default());
+
+        // assert
+        let target = target_dir.join("_resources/a.png");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            fs::read_to_string(source_dir.join("_resources/a.png")).unwrap(),
+            "png"
+        );
+        assert!(fs::symlink_metadata(&target).unwrap().is_file());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "png");
+    }
+
+    #[test]
+    fn test_copy_resources_resume() {
+        // arrange
+        let fixture = TestFixture::new("jb_copy_resources_resume_test");
+        fixture.create_sub_directory("source/_resources");
+        fixture.create_sub_directory("target/_resources");
+        fixture.create_file(&PathBuf::from("source/_resources/a.png"), "png");
+        fixture.create_file(&PathBuf::from("source/_resources/b.png"), "png");
+        // Written anew by the interrupted run, a scaled down copy say
+        fixture.create_file(&PathBuf::from("target/_resources/a.png"), "scaled");
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        let mut journal = Journal::open_resources(&target_dir, false).unwrap();
+        let copied = fingerprint(&source_dir.join("_resources/a.png")).unwrap();
+        journal
+            .record(&target_dir.join("_resources/a.png"), &copied)
+            .unwrap();
+        drop(journal);
+        let config = Config {
+            resume: true,
+            ..Config::default()
+        };
+
+        // act
+        let result = copy_resources(&source_dir, &target_dir, &config);
+
+        // assert
+        assert_eq!(result, Ok(()));
+        let read = |name: &str| fs::read_to_string(target_dir.join("_resources").join(name));
+        assert_eq!(read("a.png").unwrap(), "scaled");
+        assert_eq!(read("b.png").unwrap(), "png");
+        assert!(
+            !target_dir
+                .join(crate::journal::RESOURCE_JOURNAL_FILE_NAME)
+                .exists()
+        );
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_copy_resources_strip_exif_over_copy() {
+        // arrange
+        let fixture = TestFixture::new("jb_strip_exif_over_copy_test");
+        fixture.create_sub_directory("source/_resources");
+        let source_dir = fixture.temp_dir.join("source");
+        let target_dir = fixture.temp_dir.join("target");
+        fs::write(
+            source_dir.join("_resources/a.jpg"),
+            crate::images::tests::image_with_exif(image::ImageFormat::Jpeg),
+        )
+        .unwrap();
+        copy_resources(&source_dir, &target_dir, &Config::default()).unwrap();
+        let config = Config {
+            strip_exif: true,
+            ..Config::default()
+        };
+
+        // act
+        let result = copy_resources(&source_dir, &target_dir, &config);
+
+        // assert
+        let mut reader =
+            std::io::BufReader::new(File::open(target_dir.join("_resources/a.jpg")).unwrap());
+        let exif = exif::Reader::new()
+            .read_from_container(&mut reader)
+            .unwrap();
+        assert_eq!(result, Ok(()));
+        assert!(
+            exif.get_field(exif::Tag::GPSLatitudeRef, exif::In::PRIMARY)
+                .is_none()
+        );
+        assert!(exif.get_field(exif::Tag::Make, exif::In::PRIMARY).is_some());
+    }
+
+    #[test]
+    fn test_find_files_extensions() {
+        // arrange
+        let fixture = TestFixture::new("joplin_file_extensions_test");
+        fixture.create_sub_directory("dir.md");
+
+        let a_path = fixture.temp_dir.join("a.md");
+        let b_path = fixture.temp_dir.join("b.markdown");
+        let c_path = fixture.temp_dir.join("c.TXT");
+        let d_path = fixture.temp_dir.join("d.png");
+
+        fixture.create_file(&a_path, "a");
+        fixture.create_file(&b_path, "b");
+        fixture.create_file(&c_path, "c");
+        fixture.create_file(&d_path, "d");
+
+        let config = Config {
+            extensions: vec!["markdown".to_string(), "txt".to_string()],
+            ..Config::default()
+        };
+
+        // act
+        let result = find_files(fixture.temp_dir.to_str().unwrap(), &config);
+
+        // assert
+        let files = result.unwrap();
+        assert_eq!(
+            files,
+            vec![
+                b_path.canonicalize().unwrap(),
+                c_path.canonicalize().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extended_length() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            (r"C:\notes\a.md", r"\\?\C:\notes\a.md"),
+            (r"\\server\share\a.md", r"\\?\UNC\server\share\a.md"),
+            (r"\\?\C:\notes\a.md", r"\\?\C:\notes\a.md"),
+        ];
+
+        for (path, expected) in test_cases {
+            assert_eq!(extended_length(path), expected);
+        }
+    }
+
+    #[test]
+    fn test_target_paths_outside() {
+        let test_cases = vec![
+            ("a/b.md", true),
+            ("../b.md", false),
+            ("a/../../b.md", false),
+        ];
+
+        let content = "---\ntitle: b\ncreated: 2024-03-07T23:22:26Z\n\
+            updated: 2024-04-07T08:34:52Z\n---\n";
+
+        for (relative_path, expected) in test_cases {
+            let joplin_file =
+                JoplinFile::build(relative_path, content, &Config::default()).unwrap();
+
+            let result = target_paths(&[joplin_file], 0, &Config::default());
+
+            assert_eq!(result.is_ok(), expected, "{}", relative_path);
+        }
+    }
+
+    #[test]
+    fn test_target_paths_collisions() {
+        let content = "---\ntitle: a\ncreated: 2024-03-07T23:22:26Z\n\
+            updated: 2024-04-07T08:34:52Z\n---\n";
+        let joplin_files: Vec<JoplinFile> = ["a?.md", "a_.md", "A_.md", "b.md"]
+            .into_iter()
+            .map(|relative_path| {
+                JoplinFile::build(relative_path, content, &Config::default()).unwrap()
+            })
+            .collect();
+
+        let result = target_paths(&joplin_files, 0, &Config::default());
+
+        assert_eq!(
+            result,
+            Ok(vec![
+                PathBuf::from("a_.md"),
+                PathBuf::from("a_ 2.md"),
+                PathBuf::from("A_ 3.md"),
+                PathBuf::from("b.md"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_natural_path_cmp() {
+        let mut paths: Vec<PathBuf> = vec![
+            "b/note10.md",
+            "b/note2.md",
+            "b/Note1.md",
+            "a b/z.md",
+            "a/b/c.md",
+            "a/a.md",
+            "b/note02.md",
+            "b/note1.md",
+            "a.md",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+
+        paths.sort_by(|a, b| natural_path_cmp(a, b));
+
+        let expected: Vec<PathBuf> = vec![
+            "a/a.md",
+            "a/b/c.md",
+            "a b/z.md",
+            "a.md",
+            "b/Note1.md",
+            "b/note1.md",
+            "b/note02.md",
+            "b/note2.md",
+            "b/note10.md",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(paths, expected);
     }
 
     #[test]
     fn test_find_files() {
         // arrange
-        let fixture = TestFixture::new();
+        let fixture = TestFixture::new("joplin_file_finder_test");
         fixture.create_sub_directory("1");
 
         let a_path = fixture.temp_dir.join("a.md");
@@ -208,7 +2821,7 @@ mod tests {
         fixture.create_file(&d_path, "d");
 
         // act
-        let result = find_files(fixture.temp_dir.to_str().unwrap());
+        let result = find_files(fixture.temp_dir.to_str().unwrap(), &Config::default());
 
         // assert
         assert!(result.is_ok());
@@ -0,0 +1,145 @@
+use crate::day_one::md5_hex;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Kept in the target directory while a conversion runs, removed once it finishes
+pub const JOURNAL_FILE_NAME: &str = ".jb_journal";
+/// The journal of the resources copied, removed once they all are
+pub const RESOURCE_JOURNAL_FILE_NAME: &str = ".jb_resource_journal";
+
+/// The notes a conversion has written so far, a line per note with the hash of the content
+/// written and the note's path in the source. Each line is flushed as soon as the note is
+/// written, so after an interrupted run the notes it lists are complete. The resources copied
+/// have a journal of their own, with the size and time of the source in place of the content.
+pub struct Journal {
+    file: File,
+    path: PathBuf,
+    written: HashMap<PathBuf, String>,
+}
+
+impl Journal {
+    /// Opens the journal of the target directory. When resuming, the notes the journal lists are
+    /// kept, otherwise it starts empty.
+    pub fn open<P: AsRef<Path>>(target_dir: P, resume: bool) -> Result<Journal, String> {
+        Journal::open_file(target_dir.as_ref().join(JOURNAL_FILE_NAME), resume)
+    }
+
+    /// Opens the journal of the resources copied into the target directory, like `open`
+    pub fn open_resources<P: AsRef<Path>>(target_dir: P, resume: bool) -> Result<Journal, String> {
+        Journal::open_file(target_dir.as_ref().join(RESOURCE_JOURNAL_FILE_NAME), resume)
+    }
+
+    fn open_file(path: PathBuf, resume: bool) -> Result<Journal, String> {
+        let written = if resume && path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Error reading journal {:?}: {}", path, e))?;
+            parse(&content)
+        } else {
+            HashMap::new()
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&path)
+            .map_err(|e| format!("Error opening journal {:?}: {}", path, e))?;
+
+        Ok(Journal {
+            file,
+            path,
+            written,
+        })
+    }
+
+    /// Whether the note was written with this content by the run being resumed
+    pub fn is_written(&self, relative_path: &Path, content: &str) -> bool {
+        self.written
+            .get(relative_path)
+            .is_some_and(|hash| *hash == md5_hex(content.as_bytes()))
+    }
+
+    pub fn record(&mut self, relative_path: &Path, content: &str) -> Result<(), String> {
+        let hash = md5_hex(content.as_bytes());
+        writeln!(self.file, "{}\t{}", hash, relative_path.to_string_lossy())
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("Error writing journal: {}", e))?;
+        self.written.insert(relative_path.to_path_buf(), hash);
+
+        Ok(())
+    }
+
+    /// Removes the journal once every note is written, there's nothing left to resume
+    pub fn finish(self) -> Result<(), String> {
+        drop(self.file);
+        std::fs::remove_file(&self.path).map_err(|e| format!("Error removing journal: {}", e))
+    }
+}
+
+/// What the journal of the resources records of a source file, its size and modification time,
+/// as hashing the content would take as long as copying it
+pub fn fingerprint(source: &Path) -> std::io::Result<String> {
+    let metadata = std::fs::metadata(source)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(format!("{} {}", metadata.len(), modified.as_nanos()))
+}
+
+/// The lines of the journal, a line torn by a crash is left out
+fn parse(content: &str) -> HashMap<PathBuf, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(hash, _)| hash.len() == 32)
+        .map(|(hash, relative_path)| (PathBuf::from(relative_path), hash.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        // arrange
+        let target_dir = std::env::temp_dir().join("jb_journal_test");
+        std::fs::create_dir_all(&target_dir).unwrap();
+        let mut journal = Journal::open(&target_dir, false).unwrap();
+        journal.record(Path::new("Work/a.md"), "A").unwrap();
+        journal.record(Path::new("b.md"), "B").unwrap();
+        drop(journal);
+        // A run killed halfway through a line
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(target_dir.join(JOURNAL_FILE_NAME))
+            .unwrap();
+        write!(file, "0123").unwrap();
+
+        // act
+        let resumed = Journal::open(&target_dir, true).unwrap();
+        let restarted = Journal::open(&target_dir, false).unwrap();
+
+        // assert
+        let test_cases: Vec<(&str, &str, bool)> = vec![
+            ("Work/a.md", "A", true),
+            ("b.md", "B", true),
+            ("b.md", "B changed", false),
+            ("c.md", "C", false),
+        ];
+        for (relative_path, content, expected) in test_cases {
+            assert_eq!(
+                resumed.is_written(Path::new(relative_path), content),
+                expected
+            );
+            assert!(!restarted.is_written(Path::new(relative_path), content));
+        }
+        restarted.finish().unwrap();
+        assert!(!target_dir.join(JOURNAL_FILE_NAME).exists());
+        std::fs::remove_dir_all(&target_dir).unwrap();
+    }
+}
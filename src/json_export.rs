@@ -0,0 +1,160 @@
+use crate::JoplinFile;
+use crate::text_stats::TextStats;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::Write;
+
+/// A parsed note as it is written to JSON
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NoteRecord<'a> {
+    pub title: &'a str,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Without the leading `#`
+    pub tags: Vec<&'a str>,
+    pub body: &'a str,
+    pub relative_path: String,
+    /// The resources referenced in the body, as they are written there
+    pub resources: Vec<String>,
+    #[serde(flatten)]
+    pub stats: TextStats,
+    /// What was worked around to convert the note
+    pub warnings: Vec<String>,
+}
+
+impl<'a> NoteRecord<'a> {
+    pub fn new(joplin_file: &'a JoplinFile) -> NoteRecord<'a> {
+        NoteRecord {
+            title: &joplin_file.title,
+            created: joplin_file.created,
+            updated: joplin_file.updated,
+            tags: joplin_file
+                .tags
+                .iter()
+                .flat_map(|tags| tags.split_whitespace())
+                .map(|tag| tag.trim_start_matches('#'))
+                .collect(),
+            body: &joplin_file.body,
+            relative_path: joplin_file.relative_path.to_string_lossy().to_string(),
+            resources: joplin_file
+                .referenced_resources()
+                .iter()
+                .map(|resource_ref| resource_ref.raw().to_string())
+                .collect(),
+            stats: joplin_file.stats,
+            warnings: joplin_file
+                .warnings
+                .iter()
+                .map(|warning| warning.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Writes the notes as a single JSON array
+pub fn write_json<W: Write>(mut writer: W, joplin_files: &[JoplinFile]) -> Result<(), String> {
+    let records: Vec<NoteRecord> = joplin_files.iter().map(NoteRecord::new).collect();
+    serde_json::to_writer_pretty(&mut writer, &records)
+        .map_err(|e| format!("Error writing JSON: {}", e))?;
+    // A buffered writer would drop the error of its last write when dropped
+    writeln!(writer)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Error writing JSON: {}", e))
+}
+
+/// Writes the notes as newline delimited JSON, one note per line
+pub fn write_ndjson<W: Write>(mut writer: W, joplin_files: &[JoplinFile]) -> Result<(), String> {
+    for joplin_file in joplin_files {
+        serde_json::to_writer(&mut writer, &NoteRecord::new(joplin_file))
+            .map_err(|e| format!("Error writing JSON: {}", e))?;
+        writeln!(writer).map_err(|e| format!("Error writing JSON: {}", e))?;
+    }
+
+    writer
+        .flush()
+        .map_err(|e| format!("Error writing JSON: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn joplin_files() -> Vec<JoplinFile> {
+        ["Work/a.md", "b.md"]
+            .iter()
+            .map(|relative_path| {
+                JoplinFile::build(
+                    relative_path,
+                    "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+                    ![](../_resources/a.png)",
+                    &Config::default(),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        // arrange
+        let mut output = Vec::new();
+
+        // act
+        write_ndjson(&mut output, &joplin_files()).unwrap();
+
+        // assert
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "{\"title\":\"Test\",\"created\":\"2024-03-07T23:22:26Z\",\
+            \"updated\":\"2024-04-07T08:34:52Z\",\"tags\":[\"Work\"],\
+            \"body\":\"![](../_resources/a.png)\",\"relative_path\":\"Work/a.md\",\
+            \"resources\":[\"../_resources/a.png\"],\"word_count\":1,\"character_count\":24,\
+            \"reading_minutes\":1,\"warnings\":[]}\n\
+            {\"title\":\"Test\",\"created\":\"2024-03-07T23:22:26Z\",\
+            \"updated\":\"2024-04-07T08:34:52Z\",\"tags\":[],\
+            \"body\":\"![](../_resources/a.png)\",\"relative_path\":\"b.md\",\
+            \"resources\":[\"../_resources/a.png\"],\"word_count\":1,\"character_count\":24,\
+            \"reading_minutes\":1,\"warnings\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_json() {
+        // arrange
+        let mut output = Vec::new();
+
+        // act
+        write_json(&mut output, &joplin_files()).unwrap();
+
+        // assert
+        let value: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let paths: Vec<&str> = value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|note| note["relative_path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths, vec!["Work/a.md", "b.md"]);
+    }
+
+    /// Takes the writes, like a buffer does, and fails when they're flushed to a full disk
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(std::io::Error::other("No space left on device"))
+        }
+    }
+
+    #[test]
+    fn test_write_flush_error() {
+        assert!(write_json(FullDisk, &joplin_files()).is_err());
+        assert!(write_ndjson(FullDisk, &joplin_files()).is_err());
+    }
+}
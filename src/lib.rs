@@ -1,4 +1,72 @@
+pub mod alt_text;
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod async_pipeline;
+pub mod bear;
+#[cfg(feature = "bear-db")]
+pub mod bear_db;
+pub mod cancel;
+pub mod config;
+pub mod conflict;
+pub mod console;
+pub mod csv_index;
+pub mod data_uri;
+pub mod day_one;
+pub mod diff;
+pub mod duplicate;
+pub mod emoji;
+pub mod enex;
+pub mod existing;
+pub mod fidelity;
+pub mod file_limit;
+pub mod filter;
+pub mod headings;
+pub mod html_export;
+#[cfg(feature = "image")]
+pub mod images;
+pub mod io_errors;
 pub mod joplin_file;
 pub mod joplin_file_io;
+pub mod journal;
+pub mod json_export;
+pub mod manifest;
+pub mod merge;
+pub mod note_links;
+pub mod obsidian;
+pub mod path_template;
+pub mod preflight;
+pub mod punctuation;
+pub mod reflink;
+pub mod resource_limits;
+pub mod resource_ref;
+pub mod resource_volumes;
+#[cfg(feature = "tui")]
+pub mod review;
+pub mod sanitize;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod smoke_test;
+pub mod sniff;
+pub mod split;
+pub mod standard_notes;
+pub mod state;
+pub mod streaming;
+pub mod tasks;
+#[cfg(feature = "templates")]
+pub mod template;
+pub mod text_stats;
+pub mod tidy;
+pub mod timings;
+pub mod toc;
+pub mod verify;
+pub mod warning;
+#[cfg(feature = "wayback")]
+pub mod wayback;
+pub mod xattrs;
 
+pub use cancel::Cancellation;
+pub use config::Config;
+pub use io_errors::IoErrors;
 pub use joplin_file::JoplinFile;
+pub use resource_ref::{ResourceKind, ResourceRef, ResourceTarget};
+pub use timings::Timings;
@@ -1,29 +1,1532 @@
-use std::env;
+use chrono_tz::Tz;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use jb::config::{
+    ConfigFile, ConflictPolicy, Downscale, DuplicatePolicy, ExistingPolicy, InvertedDates,
+    LineBreaks, NoteIdMarker, OutputFormat, Punctuation, ResourceLimitPolicy, ResourceLink,
+    SourceFlavor, TagOverflow, TagPlacement, TocMarker, UnicodeNormalization,
+};
+use jb::console::{ColorChoice, Output, Summary};
+use jb::diff::DiffKind;
+use jb::file_limit::{DEFAULT_MAX_OPEN_FILES, FileLimit};
+use jb::manifest::{Manifest, Snapshot};
+use jb::path_template::PathTemplate;
+use jb::state::{ChangeKind, State};
+use jb::tasks::summarize_tasks;
+use jb::timings::Phase;
+use jb::warning::Warning;
+use jb::{Cancellation, Config, JoplinFile, Timings, error, info, notice, warning};
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(
+    name = "jb",
+    about = "Converts a Joplin markdown export into notes for Bear",
+    after_help = EXIT_CODES
+)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// When to color errors red and warnings yellow. `auto` colors in a terminal unless
+    /// NO_COLOR is set
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    #[command(flatten)]
+    convert: ConvertArgs,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    /// The Joplin export directory, or a zip of it
+    #[arg(required = true)]
+    source_dir: Option<PathBuf>,
+
+    /// The directory to write the Bear notes to, or the file with `--output-format zip`, `json`,
+    /// `ndjson`, `csv`, `day-one`, `enex` or `standard-notes`, or Bear's `database.sqlite` with
+    /// `bear-db`. `-` writes JSON and CSV to stdout
+    #[arg(required = true)]
+    target_dir: Option<String>,
+
+    /// Confirms that Bear is closed and that writing into its database is at your own risk,
+    /// required by `--output-format bear-db`
+    #[arg(long)]
+    i_know_what_im_doing: bool,
+
+    /// Convert into a target directory that isn't empty. One holding an earlier conversion is
+    /// updated without it
+    #[arg(long)]
+    allow_nonempty: bool,
+
+    /// Print how long discovery, parsing, transforming, writing and copying the resources took,
+    /// and the slowest notes
+    #[arg(long)]
+    timings: bool,
+
+    /// Review the notes in a terminal UI before they're written: leave some out and edit their
+    /// tags. Needs jb to be built with the `tui` feature
+    #[arg(long)]
+    review: bool,
+
+    /// Print only the errors
+    #[arg(long, conflicts_with = "porcelain")]
+    quiet: bool,
+
+    /// Print only the errors, and a `STATUS<TAB>source<TAB>target` line per note written into
+    /// a directory: WRITTEN, RESUMED, SKIPPED or FAILED. The format stays the same across
+    /// versions
+    #[arg(long)]
+    porcelain: bool,
+
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+#[derive(Args)]
+struct ConfigArgs {
+    /// The file extensions of notes
+    #[arg(long, value_delimiter = ',', default_value = "md")]
+    extensions: Vec<String>,
+
+    /// Use the note's file name as the last component of its tag
+    #[arg(long)]
+    tag_with_filename: bool,
+
+    /// The maximum number of components in a tag
+    #[arg(long)]
+    max_tag_depth: Option<usize>,
+
+    /// What to do with the tag components beyond --max-tag-depth
+    #[arg(long, value_enum, default_value_t = TagOverflow::Truncate)]
+    tag_overflow: TagOverflow,
+
+    /// Drop tags that are an ancestor of another tag of the same note, e.g. `#work` next to
+    /// `#work/project`
+    #[arg(long)]
+    prune_ancestor_tags: bool,
+
+    /// Where to put the tags in the note
+    #[arg(long, value_enum, default_value_t = TagPlacement::Bottom)]
+    tag_placement: TagPlacement,
+
+    /// A Jinja template laying out the notes, with `{{ title }}`, `{{ body }}`, `{{ tags }}`,
+    /// `{{ created }}`, `{{ updated }}`, `{{ id }}`, `{{ path }}` and the front matter values as
+    /// `{{ metadata.key }}`. Takes the place of --tag-placement. Needs jb to be built with the
+    /// `templates` feature
+    #[arg(long, value_name = "PATH")]
+    template: Option<PathBuf>,
+
+    /// What to do with Joplin's `[toc]` markers, Bear has no table of contents
+    #[arg(long, value_enum, default_value_t = TocMarker::Keep)]
+    toc_marker: TocMarker,
+
+    /// Trim trailing whitespace and squeeze runs of three or more blank lines into one, so the
+    /// notes diff cleanly. Hard breaks and code blocks are kept as they are
+    #[arg(long)]
+    tidy_whitespace: bool,
+
+    /// How to write the line breaks inside paragraphs, Bear doesn't render Joplin's soft breaks
+    /// as breaks
+    #[arg(long, value_enum, default_value_t = LineBreaks::Keep)]
+    line_breaks: LineBreaks,
+
+    /// Make the quotes, dashes and ellipses of the text typographic or straight, so clipped
+    /// notes are consistent. Code and links are left as they are
+    #[arg(long, value_enum, default_value_t = Punctuation::Keep)]
+    punctuation: Punctuation,
+
+    /// Replace `:smile:` style emoji shortcodes, which Joplin renders and Bear doesn't, with the
+    /// emoji. Code is left as it is
+    #[arg(long)]
+    expand_emoji: bool,
+
+    /// Give the images of resources that have no alt text, `![](../_resources/diagram.png)`,
+    /// their file name as alt text
+    #[arg(long)]
+    fill_alt_text: bool,
+
+    /// A RAW export of the same notes, a JEX one unpacked, for --fill-alt-text to take the alt
+    /// text of the resources named by their Joplin id from: the titles of its resource items
+    #[arg(long, value_name = "DIR")]
+    resource_metadata: Option<PathBuf>,
+
+    /// Tag the notes with open `- [ ]` tasks or a due date that isn't completed, with #todo
+    /// unless another tag is given
+    #[arg(
+        long,
+        value_name = "TAG",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "#todo"
+    )]
+    todo_tag: Option<String>,
+
+    /// List the open tasks and the due dates of the notes after the summary, the ones due
+    /// first
+    #[arg(long)]
+    task_summary: bool,
+
+    /// The Unicode normalization form of titles, tags and target paths
+    #[arg(long, value_enum, default_value_t = UnicodeNormalization::Nfc)]
+    unicode_normalization: UnicodeNormalization,
+
+    /// Append a link that opens the original note in Joplin, when the export includes note ids
+    #[arg(long)]
+    link_to_joplin: bool,
+
+    /// Keep the Joplin id of the notes at their end, to match them with the Joplin notes later
+    #[arg(long, value_enum, default_value_t = NoteIdMarker::Omit)]
+    note_id: NoteIdMarker,
+
+    /// Tag notes with at least this many conversion problems (unsupported markdown, unresolved
+    /// links, missing resources) with #jb/review
+    #[arg(long)]
+    review_threshold: Option<usize>,
+
+    /// A Rhai script run on every note that can change its title, tags, body and path. Needs jb
+    /// to be built with the `scripting` feature
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    /// A JSON file with the script and filter to use, e.g. `{"script": "notes.rhai"}`, and the
+    /// options of the notes of some notebooks under `notebooks`, e.g. `{"notebooks": {"Journal":
+    /// {"tags": ["#journal"]}}}`. The script path is relative to the file, options given here win
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// A shell command every note body is piped through, e.g. `--filter 'pandoc -t gfm'`. The
+    /// title, path, tags, dates and id are in the JB_TITLE, JB_PATH, JB_TAGS, JB_CREATED,
+    /// JB_UPDATED and JB_ID environment variables
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Directories to spill the resources across when they don't fit on the target, e.g.
+    /// `--resource-volumes /Volumes/a,/Volumes/b`. Re-running resumes an interrupted copy
+    #[arg(long, value_delimiter = ',')]
+    resource_volumes: Vec<PathBuf>,
+
+    /// What to do with the notes Joplin copied because of a sync conflict, the ones titled
+    /// `... (conflict)`
+    #[arg(long, value_enum, default_value_t = ConflictPolicy::Import)]
+    conflict_policy: ConflictPolicy,
+
+    /// What to do with notes whose body is the same as another note's, ignoring differences in
+    /// whitespace
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::Import)]
+    duplicate_policy: DuplicatePolicy,
+
+    /// Split the notes longer than this many bytes, Bear slows down on notes of a MB and more.
+    /// The note is split at its top-level headings into a note per section, with the tags and
+    /// dates of the note, and keeps a list of links to them
+    #[arg(long, value_name = "BYTES")]
+    split_notes: Option<usize>,
+
+    /// Merge the notes directly in this notebook, e.g. `Work/Scraps`, into a single note named
+    /// after it with a section per note, its title and dates. Can be given more than once
+    #[arg(long, value_name = "NOTEBOOK")]
+    merge_notebook: Vec<PathBuf>,
+
+    /// What to do when a note's file already exists in the target with other content: `ask`
+    /// shows a prompt for each one, with a diff on request
+    #[arg(long, value_enum, default_value_t = ExistingPolicy::Overwrite)]
+    existing_policy: ExistingPolicy,
+
+    /// What to do with notes updated before they were created, which old sync bugs left
+    /// behind and which confuse incremental conversions. A minute of clock skew is allowed
+    #[arg(long, value_enum, default_value_t = InvertedDates::Warn)]
+    inverted_dates: InvertedDates,
+
+    /// Replaces the characters in file and directory names that are invalid on some
+    /// filesystems, like `:` and `?`, or trailing dots
+    #[arg(long, default_value_t = '_')]
+    filename_replacement: char,
+
+    /// The maximum length of the path of a note, including the target directory. Longer
+    /// directory and file names are shortened, with a hash to keep them unique
+    #[arg(long)]
+    max_path_len: Option<usize>,
+
+    /// Where to put the notes in the target instead of their notebook, e.g.
+    /// `{{created:%Y}}/{{created:%m}}/{{title|slug}}.md`. The placeholders are title, id,
+    /// created, updated, notebook and name, dates take a strftime format after a `:`, and the
+    /// `slug` and `lower` filters go after a `|`. Notes given the same path are numbered
+    #[arg(long, value_name = "TEMPLATE")]
+    path_template: Option<PathTemplate>,
+
+    /// Write the notes and resources into a directory or a single zip file, the parsed notes as
+    /// JSON, only a CSV index of the notes, HTML pages, an Obsidian vault, a Day One import zip,
+    /// an Evernote export, or a Standard Notes import
+    #[arg(long, value_enum, default_value_t = OutputFormat::Dir)]
+    output_format: OutputFormat,
+
+    /// Also write a CSV index of the notes (title, dates, tags, path and word count) to this
+    /// file, `-` writes it to stdout
+    #[arg(long)]
+    csv_index: Option<PathBuf>,
+
+    /// Only convert the notes whose path in the export matches one of these glob patterns, e.g.
+    /// `--include 'Journal/*'`
+    #[arg(long, value_delimiter = ',')]
+    include: Vec<String>,
+
+    /// The app that wrote the notes, for the front matter keys it uses: Notable and Zettlr write
+    /// `modified` instead of `updated`
+    #[arg(long, value_enum, default_value_t = SourceFlavor::Joplin)]
+    source_flavor: SourceFlavor,
+
+    /// Continue an interrupted conversion into the same target, skipping the notes and the
+    /// resources it already wrote unless they changed since
+    #[arg(long)]
+    resume: bool,
+
+    /// Hard link the resources into the target instead of copying them, or symlink them with
+    /// `--link-resources=symlink`. Resources on another filesystem than the target are copied
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "hard-link"
+    )]
+    link_resources: Option<ResourceLink>,
+
+    /// Compare the resources already in the target with the source by content to decide
+    /// whether to copy them again, rather than by size and modification time
+    #[arg(long)]
+    paranoid: bool,
+
+    /// Keep the extended attributes of the resources, like their Finder tags and comments on
+    /// macOS, on the files written in their place: scaled down, stripped of their EXIF data or
+    /// converted to PNG. On Linux, on the copies too
+    #[arg(long)]
+    preserve_xattrs: bool,
+
+    /// Remove the quarantine macOS gives downloaded files, `com.apple.quarantine`, from the
+    /// copied resources, so clipped PDFs and downloads don't prompt when opened after the
+    /// import. Only on macOS, for Bear notes written to a directory from an export directory
+    #[arg(long)]
+    strip_quarantine: bool,
+
+    /// Scale down JPEG and PNG resources wider or taller than this many pixels as they're
+    /// copied, keeping their names. Needs jb to be built with the `image` feature
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..))]
+    max_image_dimension: Option<u32>,
+
+    /// The JPEG quality of the scaled down images, from 1 to 100
+    #[arg(long, default_value_t = 85, value_parser = clap::value_parser!(u8).range(1..=100))]
+    image_quality: u8,
+
+    /// Drop the GPS position and the camera and lens serial numbers from the EXIF data of JPEG
+    /// and PNG resources as they're copied, keeping the orientation. Needs jb to be built with
+    /// the `image` feature
+    #[arg(long)]
+    strip_exif: bool,
+
+    /// The extensions of attachments Bear can't preview, the notes linking to them get a
+    /// warning
+    #[arg(long, value_delimiter = ',', default_value = "webp,svg")]
+    unsupported_attachments: Vec<String>,
+
+    /// Convert WebP resources to PNG as they're copied and point the links at the PNG files.
+    /// Only for Bear notes written to a directory from an export directory, and needs jb to be
+    /// built with the `image` feature
+    #[arg(long)]
+    convert_webp: bool,
+
+    /// Give the resources without an extension the one of their content as they're copied,
+    /// `.png`, `.pdf` and so on, and point the links at them, Bear can't preview them otherwise.
+    /// Only for Bear notes written to a directory from an export directory
+    #[arg(long)]
+    sniff_resource_types: bool,
+
+    /// A directory of the export holding resources besides `_resources`, relative to it, like
+    /// the `resources/` or `assets/` of other tools. Its resources are merged into `_resources`
+    /// and the links to them follow. Can be repeated; without it, a `resources`, `assets` or
+    /// `attachments` directory with no notes in it is taken as one. Only for notes written to a
+    /// directory, an HTML snapshot or an Obsidian vault from an export directory, without
+    /// --resource-volumes
+    #[arg(long = "resource-dir", value_name = "DIR")]
+    resource_dirs: Vec<PathBuf>,
+
+    /// Stop when the export has no `_resources` directory, rather than warn when notes link to
+    /// resources. An export of notes without attachments has none
+    #[arg(long)]
+    require_resources: bool,
+
+    /// Resources bigger than this many bytes are over the limits, see --resource-limit-policy
+    #[arg(long, value_name = "BYTES")]
+    max_resource_size: Option<u64>,
+
+    /// The resources beyond this many, in path order, are over the limits, see
+    /// --resource-limit-policy
+    #[arg(long, value_name = "COUNT")]
+    max_resources: Option<usize>,
+
+    /// What to do with the resources over --max-resource-size or --max-resources, so a stray
+    /// video doesn't bloat the target. Only for Bear notes written to a directory from an export
+    /// directory, without --resource-volumes
+    #[arg(long, value_enum, default_value_t = ResourceLimitPolicy::Skip)]
+    resource_limit_policy: ResourceLimitPolicy,
+
+    /// Inline the resources of at most this many bytes into the notes as base64 data URIs
+    /// instead of copying them, so each note stands on its own. Only for the html, json and
+    /// ndjson output formats
+    #[arg(long, value_name = "BYTES")]
+    inline_resources: Option<u64>,
+
+    /// Append links to the Wayback Machine snapshots of the web pages each note links to, so
+    /// they survive the pages going away. Looks up one page a second, needs jb to be built
+    /// with the `wayback` feature
+    #[arg(long)]
+    archive_links: bool,
+
+    /// The file the Wayback Machine lookups are cached in, the next runs only look up new pages
+    #[arg(long, value_name = "PATH", default_value = ".jb_wayback.json")]
+    archive_cache: PathBuf,
+
+    /// The most files open at once, lower it if the conversion runs out of file descriptors
+    #[arg(long, default_value_t = DEFAULT_MAX_OPEN_FILES)]
+    max_open_files: usize,
+
+    /// The time zone to show dates in, an IANA name like `Europe/Stockholm`. The notes keep
+    /// their times as instants whatever it is
+    #[arg(long, default_value_t = Tz::UTC)]
+    timezone: Tz,
+}
+
+impl ConfigArgs {
+    fn into_config(self) -> Config {
+        let config_file = self
+            .config
+            .map(|path| {
+                ConfigFile::load(path).unwrap_or_else(|e| {
+                    error!("{}", e);
+                    std::process::exit(INVALID_ARGUMENTS);
+                })
+            })
+            .unwrap_or_default();
+        let notebooks = config_file.notebooks(self.unicode_normalization);
+        let note_template = self.template.map(|path| {
+            std::fs::read_to_string(&path).unwrap_or_else(|e| {
+                error!("Error reading template {:?}: {}", path, e);
+                std::process::exit(INVALID_ARGUMENTS);
+            })
+        });
+
+        Config {
+            extensions: self
+                .extensions
+                .into_iter()
+                .map(|extension| extension.trim_start_matches('.').to_string())
+                .collect(),
+            tag_with_filename: self.tag_with_filename,
+            max_tag_depth: self.max_tag_depth,
+            tag_overflow: self.tag_overflow,
+            prune_ancestor_tags: self.prune_ancestor_tags,
+            tag_placement: self.tag_placement,
+            note_template,
+            toc_marker: self.toc_marker,
+            tidy_whitespace: self.tidy_whitespace,
+            line_breaks: self.line_breaks,
+            punctuation: self.punctuation,
+            expand_emoji: self.expand_emoji,
+            fill_alt_text: self.fill_alt_text,
+            resource_metadata: self.resource_metadata,
+            todo_tag: self.todo_tag,
+            task_summary: self.task_summary,
+            notebooks,
+            unicode_normalization: self.unicode_normalization,
+            link_to_joplin: self.link_to_joplin,
+            note_id: self.note_id,
+            review_threshold: self.review_threshold,
+            script: self.script.or(config_file.script),
+            filter: self.filter.or(config_file.filter),
+            resource_volumes: self.resource_volumes,
+            conflict_policy: self.conflict_policy,
+            duplicate_policy: self.duplicate_policy,
+            split_notes: self.split_notes,
+            merge_notebooks: self.merge_notebook,
+            existing_policy: self.existing_policy,
+            inverted_dates: self.inverted_dates,
+            filename_replacement: self.filename_replacement,
+            max_path_len: self.max_path_len,
+            path_template: self.path_template,
+            output_format: self.output_format,
+            csv_index: self.csv_index,
+            include: self.include,
+            source_flavor: self.source_flavor,
+            resume: self.resume,
+            link_resources: self.link_resources,
+            downscale_images: self.max_image_dimension.map(|max_dimension| Downscale {
+                max_dimension,
+                quality: self.image_quality,
+            }),
+            strip_exif: self.strip_exif,
+            unsupported_attachments: self
+                .unsupported_attachments
+                .into_iter()
+                .map(|extension| extension.trim_start_matches('.').to_string())
+                .collect(),
+            convert_webp: self.convert_webp,
+            sniff_resource_types: self.sniff_resource_types,
+            resource_dirs: self.resource_dirs,
+            require_resources: self.require_resources,
+            max_resource_size: self.max_resource_size,
+            max_resources: self.max_resources,
+            resource_limit_policy: self.resource_limit_policy,
+            inline_resources: self.inline_resources,
+            archive_links: self.archive_links.then_some(self.archive_cache),
+            paranoid: self.paranoid,
+            preserve_xattrs: self.preserve_xattrs,
+            strip_quarantine: self.strip_quarantine,
+            open_files: FileLimit::new(self.max_open_files),
+            timezone: self.timezone,
+            ..Config::default()
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Converts a few notes, imports them into Bear and reports what survived (macOS only)
+    SmokeTest {
+        /// The Joplin export directory, or a zip of it
+        source_dir: PathBuf,
+
+        /// The number of notes to import
+        #[arg(long, default_value_t = 3)]
+        notes: usize,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Converts a single note and writes the Bear note to stdout
+    ConvertOne {
+        /// The Joplin note, `-` reads it from stdin
+        input: String,
+
+        /// The path of the note relative to the export, its tags are built from it. Defaults to
+        /// the file name of the input
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Converts a single note and writes the Bear note to stdout, followed by a diff against the
+    /// original, to see what the options do
+    Preview {
+        /// The Joplin note, `-` reads it from stdin
+        input: String,
+
+        /// The path of the note relative to the export, its tags are built from it. Defaults to
+        /// the file name of the input
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Removes the files and directories a conversion created, as its manifest lists them
+    Rollback {
+        /// The `.jb_manifest.json` the conversion wrote into its target directory
+        manifest: PathBuf,
+    },
+    /// Lists the notes added, modified and deleted in the source since the last conversion
+    Changes {
+        /// The Joplin export directory, or a zip of it
+        source_dir: PathBuf,
+
+        /// The directory the last conversion wrote to
+        target_dir: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Lists the target notes that are missing, older than the source note, or differ from what
+    /// converting now would write, without writing anything
+    #[command(after_help = DIFF_EXIT_CODES)]
+    Diff {
+        /// The Joplin export directory, or a zip of it
+        source_dir: PathBuf,
+
+        /// The directory a conversion wrote to
+        target_dir: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Checks a completed conversion: every note is in the target with the expected content and
+    /// modification time, and the resources it links to are there. Fails when a note doesn't pass
+    Verify {
+        /// The Joplin export directory, or a zip of it
+        source_dir: PathBuf,
+
+        /// The directory the conversion wrote to
+        target_dir: String,
+
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Writes the completion script for a shell to stdout, e.g. `jb completions zsh > _jb`. It
+    /// completes the subcommands, the options and their values
+    Completions {
+        /// The shell to complete in
+        shell: Shell,
+    },
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let cli = Cli::parse();
+    cli.color.apply();
+
+    match cli.command {
+        Some(Command::SmokeTest {
+            source_dir,
+            notes,
+            config,
+        }) => smoke_test(&source_dir, notes, &config.into_config()),
+        Some(Command::Changes {
+            source_dir,
+            target_dir,
+            config,
+        }) => changes(&source_dir, &target_dir, &config.into_config()),
+        Some(Command::ConvertOne {
+            input,
+            path,
+            config,
+        }) => convert_one(&input, path, &config.into_config()),
+        Some(Command::Preview {
+            input,
+            path,
+            config,
+        }) => preview(&input, path, &config.into_config()),
+        Some(Command::Rollback { manifest }) => rollback(&manifest),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "jb", &mut std::io::stdout())
+        }
+        Some(Command::Diff {
+            source_dir,
+            target_dir,
+            config,
+        }) => diff(&source_dir, &target_dir, &config.into_config()),
+        Some(Command::Verify {
+            source_dir,
+            target_dir,
+            config,
+        }) => verify(&source_dir, &target_dir, &config.into_config()),
+        None => convert(cli.convert),
+    }
+}
+
+fn convert(args: ConvertArgs) {
+    let source_dir = args.source_dir.expect("source_dir is required");
+    let target_dir = args.target_dir.expect("target_dir is required");
+    let mut config = args.config.into_config();
+    if args.timings {
+        config.timings = Timings::enabled();
+    }
+    if args.porcelain {
+        Output::Porcelain.apply();
+    } else if args.quiet {
+        Output::Quiet.apply();
+    }
+    let merges_resources = matches!(
+        config.output_format,
+        OutputFormat::Dir | OutputFormat::Html | OutputFormat::Obsidian
+    ) && config.resource_volumes.is_empty();
+    if config.resource_dirs.is_empty() {
+        let resource_dirs = jb::joplin_file_io::find_resource_dirs(&source_dir, &config);
+        let merged_into = match config.output_format {
+            OutputFormat::Obsidian => "attachments",
+            _ => "_resources",
+        };
+        for resource_dir in &resource_dirs {
+            if merges_resources {
+                notice!(
+                    "Merging the resources of {:?} into {}",
+                    resource_dir,
+                    merged_into
+                );
+            } else {
+                warning!(
+                    "The resources of {:?} are left out, other resource directories than \
+                    _resources are only merged into a directory, an HTML snapshot or an Obsidian \
+                    vault, without --resource-volumes",
+                    resource_dir
+                );
+            }
+        }
+        if merges_resources {
+            config.resource_dirs = resource_dirs;
+        }
+    }
+
+    if config.output_format == OutputFormat::BearDb && !args.i_know_what_im_doing {
+        error!(
+            "Writing into the Bear database is experimental, quit Bear and pass \
+            --i-know-what-im-doing to go ahead"
+        );
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if config.existing_policy == ExistingPolicy::Ask && !std::io::stdin().is_terminal() {
+        error!("--existing-policy ask needs a terminal to ask in");
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if config.convert_webp
+        && (config.output_format != OutputFormat::Dir
+            || !config.resource_volumes.is_empty()
+            || jb::archive::is_zip(&source_dir))
+    {
+        error!(
+            "--convert-webp only works for notes written to a directory from an export \
+            directory, without --resource-volumes"
+        );
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if config.sniff_resource_types
+        && (config.output_format != OutputFormat::Dir
+            || !config.resource_volumes.is_empty()
+            || jb::archive::is_zip(&source_dir))
+    {
+        error!(
+            "--sniff-resource-types only works for notes written to a directory from an export \
+            directory, without --resource-volumes"
+        );
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if !config.resource_dirs.is_empty() && (!merges_resources || jb::archive::is_zip(&source_dir)) {
+        error!(
+            "--resource-dir only works for notes written to a directory, an HTML snapshot or an \
+            Obsidian vault from an export directory, without --resource-volumes"
+        );
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if (config.max_resource_size.is_some() || config.max_resources.is_some())
+        && (config.output_format != OutputFormat::Dir
+            || !config.resource_volumes.is_empty()
+            || jb::archive::is_zip(&source_dir))
+    {
+        error!(
+            "--max-resource-size and --max-resources only work for notes written to a directory \
+            from an export directory, without --resource-volumes"
+        );
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if config.strip_quarantine
+        && (config.output_format != OutputFormat::Dir
+            || !config.resource_volumes.is_empty()
+            || config.link_resources.is_some()
+            || jb::archive::is_zip(&source_dir))
+    {
+        error!(
+            "--strip-quarantine only works for notes written to a directory from an export \
+            directory, without --resource-volumes or --link-resources"
+        );
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if config.resource_metadata.is_some() && !config.fill_alt_text {
+        error!("--resource-metadata only works with --fill-alt-text");
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    if config.inline_resources.is_some()
+        && !matches!(
+            config.output_format,
+            OutputFormat::Html | OutputFormat::Json | OutputFormat::Ndjson
+        )
+    {
+        error!("--inline-resources only works with the html, json and ndjson output formats");
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    #[cfg(not(feature = "templates"))]
+    if config.note_template.is_some() {
+        error!("Note templates need jb to be built with the templates feature");
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+    #[cfg(feature = "templates")]
+    if let Some(Err(e)) = config.note_template.as_deref().map(jb::template::check) {
+        error!("{}", e);
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    if config.strip_quarantine {
+        error!("--strip-quarantine only works on macOS, the only system quarantining files");
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    #[cfg(not(feature = "image"))]
+    if config.downscale_images.is_some() || config.strip_exif || config.convert_webp {
+        error!("Converting images needs jb to be built with the image feature");
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+
+    // What a conversion into a directory creates is recorded, so `jb rollback` can remove it.
+    // Taken before the preflight check, which creates the target
+    let into_dir = matches!(
+        config.output_format,
+        OutputFormat::Dir | OutputFormat::Html | OutputFormat::Obsidian
+    );
+    let snapshot = into_dir.then(|| {
+        Snapshot::take(&target_dir).unwrap_or_else(|e| {
+            error!("Error listing the target: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        })
+    });
+    if into_dir
+        && let Err(e) = jb::preflight::check_target(&source_dir, &target_dir, args.allow_nonempty)
+    {
+        error!("{}", e);
+        std::process::exit(TARGET_UNWRITABLE);
+    }
+
+    handle_ctrl_c(&config.cancellation);
+    let summary = write_target(&source_dir, &target_dir, snapshot, args.review, &config);
+
+    if config.timings.is_enabled() {
+        eprint!("{}", config.timings);
+    }
+    if !config.io_errors.is_empty() {
+        let style = jb::console::ERROR;
+        anstream::eprint!("{style}{}{style:#}", config.io_errors);
+    }
+    if Output::is_normal() {
+        anstream::eprint!("{}", summary);
+    }
+    if !config.io_errors.is_empty() || config.skipped_notes.count() > 0 {
+        std::process::exit(PARTIAL);
+    }
+}
+
+/// Converts the source into the target, in the configured output format, and sums up what it
+/// ran into. The snapshot of a target directory is taken before anything is written into it
+fn write_target(
+    source_dir: &Path,
+    target_dir: &str,
+    snapshot: Option<Snapshot>,
+    review: bool,
+    config: &Config,
+) -> Summary {
+    if !review && jb::streaming::applies(config) {
+        return write_streamed(source_dir, target_dir, snapshot, config);
+    }
+
+    let mut joplin_files = jb::joplin_file_io::build_joplin_files(source_dir, config)
+        .unwrap_or_else(|e| exit_unread(e, config));
+    let (warnings, over_limits) = check_resources(source_dir, &joplin_files, config);
+    jb::joplin_file_io::mark_left_out_resources(&mut joplin_files, &over_limits, config);
+
+    if let Some(cache_path) = &config.archive_links {
+        archive_links(&mut joplin_files, cache_path, config);
+    }
+
+    if review {
+        joplin_files = review_notes(target_dir, joplin_files, config);
+    }
 
-    if args.len() < 2 {
-        eprintln!("Usage: jb <source_dir> <target_dir>");
+    write_output(source_dir, target_dir, snapshot, &mut joplin_files, config);
+    let warnings = [warnings, over_limits].concat();
+    let mut summary = Summary::build(&joplin_files, &warnings, &config.io_errors);
+    if config.task_summary {
+        summary.tasks = summarize_tasks(&joplin_files);
     }
+    summary
+}
 
-    let source_dir = args[1].clone();
-    let target_dir = args[2].clone();
+/// Converts the source into a directory a note at a time, so it only takes the memory of the
+/// largest note, see `jb::streaming`
+fn write_streamed(
+    source_dir: &Path,
+    target_dir: &str,
+    snapshot: Option<Snapshot>,
+    config: &Config,
+) -> Summary {
+    let mut outline = jb::streaming::build_joplin_files(source_dir, config)
+        .unwrap_or_else(|e| exit_unread(e, config));
+    let (warnings, over_limits) = check_resources(source_dir, &outline.joplin_files, config);
 
-    let joplin_files = jb::joplin_file_io::build_joplin_files(&source_dir).unwrap_or_else(|e| {
-        eprintln!("Error building Joplin files: {}", e);
-        std::process::exit(1);
+    if let Some(csv_index) = &config.csv_index {
+        write_csv_index(csv_index, &outline.joplin_files, config.timezone).unwrap_or_else(|e| {
+            error!("Error writing CSV index: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+    }
+    copy_resources(source_dir, target_dir, config);
+    let written = jb::streaming::write_joplin_files_until_cancelled(
+        source_dir,
+        Path::new(target_dir),
+        &mut outline,
+        &over_limits,
+        config,
+    )
+    .unwrap_or_else(|e| {
+        error!("Error writing Joplin files: {}", e);
+        std::process::exit(TARGET_UNWRITABLE);
     });
+    finish_notes(target_dir, snapshot, &outline.joplin_files, written);
+
+    let warnings = [warnings, over_limits].concat();
+    Summary::build(&outline.joplin_files, &warnings, &config.io_errors)
+}
+
+/// Exits as building the notes failed, or was interrupted
+fn exit_unread(e: String, config: &Config) -> ! {
+    if config.cancellation.is_cancelled() {
+        error!("Interrupted while reading the notes, nothing was written");
+        std::process::exit(INTERRUPTED);
+    }
+    error!("Error building Joplin files: {}", e);
+    std::process::exit(SOURCE_UNREADABLE);
+}
+
+/// Warns about the resources no note links to, a missing resources directory and the resources
+/// over the limits, or exits when they're over the limits with `ResourceLimitPolicy::Abort`.
+/// Returns the warnings, and those about the resources over the limits apart
+fn check_resources(
+    source_dir: &Path,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> (Vec<Warning>, Vec<Warning>) {
+    let orphaned = jb::joplin_file_io::find_orphaned_resources(source_dir, joplin_files, config)
+        .unwrap_or_else(|e| {
+            error!("Error listing the resources: {}", e);
+            std::process::exit(SOURCE_UNREADABLE);
+        });
+    for warning in &orphaned {
+        warning!("{}", warning);
+    }
+    let no_resources_dir =
+        jb::joplin_file_io::check_resources_dir(source_dir, joplin_files, config).unwrap_or_else(
+            |e| {
+                error!("{}", e);
+                std::process::exit(SOURCE_UNREADABLE);
+            },
+        );
+    for warning in &no_resources_dir {
+        warning!("{}", warning);
+    }
+    let over_limits = jb::joplin_file_io::find_resources_over_limits(source_dir, config)
+        .unwrap_or_else(|e| {
+            error!("Error listing the resources: {}", e);
+            std::process::exit(SOURCE_UNREADABLE);
+        });
+    if !over_limits.is_empty() && config.resource_limit_policy == ResourceLimitPolicy::Abort {
+        for warning in &over_limits {
+            error!("{}", warning);
+        }
+        error!(
+            "{} resources are over the limits, nothing was written",
+            over_limits.len()
+        );
+        std::process::exit(INVALID_ARGUMENTS);
+    }
+    for warning in &over_limits {
+        warning!("{}", warning);
+    }
+
+    ([orphaned, no_resources_dir].concat(), over_limits)
+}
+
+/// Writes the notes, and the resources for the formats that have them
+fn write_output(
+    source_dir: &Path,
+    target_dir: &str,
+    snapshot: Option<Snapshot>,
+    joplin_files: &mut [JoplinFile],
+    config: &Config,
+) {
+    if let Some(csv_index) = &config.csv_index {
+        write_csv_index(csv_index, joplin_files, config.timezone).unwrap_or_else(|e| {
+            error!("Error writing CSV index: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+    }
+
+    if let Some(max_size) = config.inline_resources {
+        let inlined = jb::data_uri::inline_resources(source_dir, joplin_files, max_size)
+            .unwrap_or_else(|e| {
+                error!("Error inlining resources: {}", e);
+                std::process::exit(SOURCE_UNREADABLE);
+            });
+        info!("{} resources inlined\n", inlined);
+    }
+
+    if matches!(
+        config.output_format,
+        OutputFormat::Json | OutputFormat::Ndjson
+    ) {
+        write_json(target_dir, joplin_files, config.output_format).unwrap_or_else(|e| {
+            error!("Error writing JSON: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+        return;
+    }
+
+    if config.output_format == OutputFormat::BearDb {
+        write_bear_db(source_dir, target_dir, joplin_files, config);
+
+        info!("Done\n");
+        return;
+    }
+
+    if config.output_format == OutputFormat::StandardNotes {
+        open_output(target_dir)
+            .and_then(|writer| jb::standard_notes::write_standard_notes(writer, joplin_files))
+            .unwrap_or_else(|e| {
+                error!("Error writing Standard Notes import: {}", e);
+                std::process::exit(TARGET_UNWRITABLE);
+            });
+        return;
+    }
+
+    if config.output_format == OutputFormat::Enex {
+        jb::enex::write_enex(source_dir, Path::new(target_dir), joplin_files).unwrap_or_else(|e| {
+            error!("Error writing Evernote export: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+
+        info!("Done\n");
+        return;
+    }
+
+    if config.output_format == OutputFormat::DayOne {
+        jb::day_one::write_day_one(source_dir, Path::new(target_dir), joplin_files).unwrap_or_else(
+            |e| {
+                error!("Error writing Day One import: {}", e);
+                std::process::exit(TARGET_UNWRITABLE);
+            },
+        );
+
+        info!("Done\n");
+        return;
+    }
+
+    if config.output_format == OutputFormat::Obsidian {
+        jb::obsidian::write_obsidian(source_dir, Path::new(target_dir), joplin_files, config)
+            .unwrap_or_else(|e| {
+                error!("Error writing Obsidian vault: {}", e);
+                std::process::exit(TARGET_UNWRITABLE);
+            });
+        save_manifest(snapshot.as_ref());
+
+        info!("Done\n");
+        return;
+    }
 
-    jb::joplin_file_io::write_joplin_files(&target_dir, &joplin_files).unwrap_or_else(|e| {
-        eprintln!("Error writing Joplin files: {}", e);
-        std::process::exit(1);
+    if config.output_format == OutputFormat::Html {
+        jb::html_export::write_html(source_dir, Path::new(target_dir), joplin_files, config)
+            .unwrap_or_else(|e| {
+                error!("Error writing HTML: {}", e);
+                std::process::exit(TARGET_UNWRITABLE);
+            });
+        save_manifest(snapshot.as_ref());
+
+        info!("Done\n");
+        return;
+    }
+
+    if config.output_format == OutputFormat::Csv {
+        write_csv_index(target_dir, joplin_files, config.timezone).unwrap_or_else(|e| {
+            error!("Error writing CSV index: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+        return;
+    }
+
+    if config.output_format == OutputFormat::Zip {
+        if !config.resource_volumes.is_empty() {
+            error!("Resource volumes can't be used with zip output");
+            std::process::exit(INVALID_ARGUMENTS);
+        }
+
+        // There is no state for a zip, it's a snapshot rather than a directory to update
+        jb::archive::write_zip(target_dir, source_dir, joplin_files, config).unwrap_or_else(|e| {
+            error!("Error writing zip file: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+
+        info!("Done\n");
+        return;
+    }
+
+    if config.resource_volumes.is_empty() {
+        copy_resources(source_dir, target_dir, config);
+    } else {
+        // The resources are copied first, the links depend on the volume each one lands on
+        let placement = config
+            .timings
+            .time(Phase::ResourceCopy, || {
+                jb::resource_volumes::copy_resources_to_volumes(
+                    source_dir,
+                    &config.resource_volumes,
+                )
+            })
+            .unwrap_or_else(|e| {
+                error!("Error copying resources: {}", e);
+                std::process::exit(TARGET_UNWRITABLE);
+            });
+
+        for joplin_file in joplin_files.iter_mut() {
+            placement.rewrite_links(joplin_file, target_dir);
+        }
+
+        for (volume, count) in placement.counts() {
+            info!("{} resources on {}", count, volume.display());
+        }
+    }
+
+    let written =
+        jb::joplin_file_io::write_joplin_files_until_cancelled(target_dir, joplin_files, config)
+            .unwrap_or_else(|e| {
+                error!("Error writing Joplin files: {}", e);
+                std::process::exit(TARGET_UNWRITABLE);
+            });
+    finish_notes(target_dir, snapshot, joplin_files, written);
+}
+
+/// Copies the resources into the target directory, before the notes
+fn copy_resources(source_dir: &Path, target_dir: &str, config: &Config) {
+    config
+        .timings
+        .time(Phase::ResourceCopy, || {
+            jb::joplin_file_io::copy_resources(source_dir, Path::new(target_dir), config)
+        })
+        .unwrap_or_else(|e| {
+            if config.cancellation.is_cancelled() {
+                error!(
+                    "Interrupted while copying the resources, no notes were written. Run the \
+                    same command again with --resume to continue where it stopped"
+                );
+                std::process::exit(INTERRUPTED);
+            }
+            error!("Error copying resources: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+}
+
+/// Saves the state and the manifest once the notes are written into the target directory, and
+/// exits when the conversion was interrupted before they all were
+fn finish_notes(
+    target_dir: &str,
+    snapshot: Option<Snapshot>,
+    joplin_files: &[JoplinFile],
+    written: usize,
+) {
+    // Saved for the notes that made it, so `jb changes` knows what's in the target
+    State::build(&joplin_files[..written], chrono::Utc::now())
+        .save(target_dir)
+        .unwrap_or_else(|e| {
+            error!("Error saving state: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+
+    save_manifest(snapshot.as_ref());
+
+    if written < joplin_files.len() {
+        error!(
+            "Interrupted after writing {} of {} notes. Run the same command again with --resume \
+            to continue where it stopped",
+            written,
+            joplin_files.len()
+        );
+        std::process::exit(INTERRUPTED);
+    }
+
+    info!("Done\n");
+}
+
+fn save_manifest(snapshot: Option<&Snapshot>) {
+    let Some(snapshot) = snapshot else {
+        return;
+    };
+
+    let path = snapshot
+        .manifest()
+        .and_then(|manifest| manifest.save())
+        .unwrap_or_else(|e| {
+            error!("Error saving manifest: {}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        });
+    info!(
+        "`jb rollback {}` removes what this conversion created",
+        path.display()
+    );
+}
+
+fn rollback(manifest_path: &Path) {
+    let manifest = Manifest::load(manifest_path).unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(SOURCE_UNREADABLE);
     });
 
-    jb::joplin_file_io::copy_resources(&source_dir, &target_dir).unwrap_or_else(|e| {
-        eprintln!("Error copying resources: {}", e);
-        std::process::exit(1);
+    let kept = manifest.rollback().unwrap_or_else(|e| {
+        error!("Error rolling back: {}", e);
+        std::process::exit(TARGET_UNWRITABLE);
     });
+    for relative_path in &kept {
+        eprintln!(
+            "Kept {:?}, it has files the conversion didn't create",
+            relative_path
+        );
+    }
+
+    println!(
+        "Removed {} files and directories\n",
+        manifest.created.len() - kept.len()
+    );
+}
+
+/// Some notes were skipped, or some notes or resources couldn't be read or written or didn't
+/// pass a check; the rest were converted
+const PARTIAL: i32 = 1;
+/// The same code clap exits with when it can't parse the arguments
+const INVALID_ARGUMENTS: i32 = 2;
+const SOURCE_UNREADABLE: i32 = 3;
+const TARGET_UNWRITABLE: i32 = 4;
+/// The exit code of a run stopped with Ctrl-C, as shells report it
+const INTERRUPTED: i32 = 130;
+
+const EXIT_CODES: &str = "Exit codes:
+  0    Success
+  1    Partial success: some notes were skipped, or some notes or resources couldn't be read
+       or written or didn't pass a check
+  2    Invalid arguments
+  3    The source couldn't be read
+  4    The target couldn't be written
+  130  Interrupted with Ctrl-C";
+
+/// `jb diff` tells with its exit code whether the target is up to date, like `diff` does
+const DIFF_EXIT_CODES: &str = "Exit codes:
+  0    No notes differ
+  1    Some notes are missing, stale or diverged
+  2    Invalid arguments
+  3    The source couldn't be read
+  4    The target couldn't be read";
+
+/// The first Ctrl-C stops the conversion once the file in flight is written, a second one quits
+/// right away
+fn handle_ctrl_c(cancellation: &Cancellation) {
+    let cancellation = cancellation.clone();
+    ctrlc::set_handler(move || {
+        if cancellation.is_cancelled() {
+            std::process::exit(INTERRUPTED);
+        }
+        eprintln!("Stopping after the current file, press Ctrl-C again to quit right away");
+        cancellation.cancel();
+    })
+    .unwrap_or_else(|e| warning!("Ctrl-C can't stop the conversion cleanly: {}", e));
+}
+
+#[cfg(feature = "tui")]
+fn review_notes(
+    target_dir: &str,
+    joplin_files: Vec<JoplinFile>,
+    config: &Config,
+) -> Vec<JoplinFile> {
+    jb::review::review(target_dir, joplin_files, config)
+        .unwrap_or_else(|e| {
+            error!("{}", e);
+            std::process::exit(TARGET_UNWRITABLE);
+        })
+        .unwrap_or_else(|| {
+            info!("Review cancelled, nothing was written");
+            std::process::exit(0);
+        })
+}
+
+#[cfg(not(feature = "tui"))]
+fn review_notes(_: &str, _: Vec<JoplinFile>, _: &Config) -> Vec<JoplinFile> {
+    error!("Reviewing the notes needs jb to be built with the tui feature");
+    std::process::exit(INVALID_ARGUMENTS);
+}
+
+#[cfg(feature = "wayback")]
+fn archive_links(joplin_files: &mut [JoplinFile], cache_path: &Path, config: &Config) {
+    let mut wayback = jb::wayback::Wayback::open(cache_path).unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(INVALID_ARGUMENTS);
+    });
+    let archived = jb::wayback::archive_links(joplin_files, &mut wayback, config);
+    // What was looked up before an interruption is kept for the next run
+    wayback
+        .save()
+        .unwrap_or_else(|e| warning!("Error saving the Wayback Machine cache: {}", e));
+
+    let archived = archived.unwrap_or_else(|e| {
+        error!(
+            "Interrupted while archiving the links, nothing was written: {}",
+            e
+        );
+        std::process::exit(INTERRUPTED);
+    });
+    info!("{} archived links appended\n", archived);
+}
+
+#[cfg(not(feature = "wayback"))]
+fn archive_links(_: &mut [JoplinFile], _: &Path, _: &Config) {
+    error!("Archiving links needs jb to be built with the wayback feature");
+    std::process::exit(INVALID_ARGUMENTS);
+}
+
+#[cfg(feature = "bear-db")]
+fn write_bear_db(source_dir: &Path, db_path: &str, joplin_files: &[JoplinFile], config: &Config) {
+    let backup_path =
+        jb::bear_db::write_bear_db(source_dir, Path::new(db_path), joplin_files, config)
+            .unwrap_or_else(|e| {
+                error!("Error writing Bear database: {}", e);
+                std::process::exit(TARGET_UNWRITABLE);
+            });
+
+    info!("Backed up the Bear database to {}", backup_path.display());
+}
+
+#[cfg(not(feature = "bear-db"))]
+fn write_bear_db(_: &Path, _: &str, _: &[JoplinFile], _: &Config) {
+    error!("Writing into the Bear database needs jb to be built with the bear-db feature");
+    std::process::exit(INVALID_ARGUMENTS);
+}
+
+/// A file to write to, or stdout for `-`
+fn open_output<P: AsRef<Path>>(target: P) -> Result<Box<dyn Write>, String> {
+    let target = target.as_ref();
+    if target == Path::new("-") {
+        return Ok(Box::new(std::io::stdout().lock()));
+    }
+
+    let file = std::fs::File::create(target)
+        .map_err(|e| format!("Error creating file {}: {}", target.display(), e))?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+fn write_csv_index<P: AsRef<Path>>(
+    target: P,
+    joplin_files: &[JoplinFile],
+    timezone: Tz,
+) -> Result<(), String> {
+    jb::csv_index::write_csv_index(open_output(target)?, joplin_files, timezone)
+}
+
+fn write_json(
+    target: &str,
+    joplin_files: &[JoplinFile],
+    format: OutputFormat,
+) -> Result<(), String> {
+    let writer = open_output(target)?;
+
+    if format == OutputFormat::Ndjson {
+        jb::json_export::write_ndjson(writer, joplin_files)
+    } else {
+        jb::json_export::write_json(writer, joplin_files)
+    }
+}
+
+fn smoke_test(source_dir: &Path, notes: usize, config: &Config) {
+    let results = jb::smoke_test::run(source_dir, notes, config).unwrap_or_else(|e| {
+        error!("Error running smoke test: {}", e);
+        std::process::exit(SOURCE_UNREADABLE);
+    });
+
+    let check = |ok: bool| if ok { "ok" } else { "FAILED" };
+    for result in &results {
+        match &result.error {
+            Some(e) => println!("{}: import FAILED ({})", result.title, e),
+            None => println!(
+                "{}: tags {}, timestamps {}, attachments {}",
+                result.title,
+                check(result.tags_survived),
+                check(result.timestamps_survived),
+                check(result.attachments_survived)
+            ),
+        }
+    }
+
+    if !results.iter().all(|result| result.passed()) {
+        std::process::exit(PARTIAL);
+    }
 
     println!("Done\n");
 }
+
+fn convert_one(input: &str, path: Option<PathBuf>, config: &Config) {
+    let (_, content) = convert_input(input, path, config);
+    print!("{}", content);
+}
+
+fn preview(input: &str, path: Option<PathBuf>, config: &Config) {
+    let (original, content) = convert_input(input, path, config);
+    print!("{}", content);
+
+    let diff = jb::diff::unified_diff(&original, &content, input, "converted");
+    if !diff.is_empty() {
+        println!();
+    }
+    // The first two lines name the files
+    for (i, line) in diff.lines().enumerate() {
+        let style = match line.chars().next() {
+            _ if i < 2 => anstyle::Style::new(),
+            Some('+') => jb::console::ADDED,
+            Some('-') => jb::console::REMOVED,
+            Some('@') => jb::console::HUNK,
+            _ => anstyle::Style::new(),
+        };
+        anstream::println!("{style}{}{style:#}", line);
+    }
+}
+
+/// Reads the note given on the command line and converts it, returns its text and the content
+/// of the Bear note
+fn convert_input(input: &str, path: Option<PathBuf>, config: &Config) -> (String, String) {
+    let bytes = if input == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        std::fs::read(input)
+    }
+    .unwrap_or_else(|e| {
+        error!("Error reading {}: {}", input, e);
+        std::process::exit(SOURCE_UNREADABLE);
+    });
+
+    let relative_path = path
+        .or_else(|| Path::new(input).file_name().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("note.md"));
+
+    let content =
+        jb::joplin_file_io::convert_one(&relative_path, &bytes, config).unwrap_or_else(|e| {
+            error!("Error converting note: {}", e);
+            std::process::exit(SOURCE_UNREADABLE);
+        });
+
+    (String::from_utf8_lossy(&bytes).into_owned(), content)
+}
+
+fn changes(source_dir: &Path, target_dir: &str, config: &Config) {
+    let state = State::load(target_dir)
+        .unwrap_or_else(|e| {
+            error!("Error loading state: {}", e);
+            std::process::exit(SOURCE_UNREADABLE);
+        })
+        .unwrap_or_else(|| {
+            error!("No previous conversion found in {}", target_dir);
+            std::process::exit(SOURCE_UNREADABLE);
+        });
+
+    let joplin_files =
+        jb::joplin_file_io::build_joplin_files(source_dir, config).unwrap_or_else(|e| {
+            error!("Error building Joplin files: {}", e);
+            std::process::exit(SOURCE_UNREADABLE);
+        });
+
+    if let Some(last_run) = state.last_run {
+        println!(
+            "Changes since {}",
+            last_run
+                .with_timezone(&config.timezone)
+                .format("%Y-%m-%d %H:%M:%S %Z")
+        );
+    }
+
+    let changes = state.changes(&joplin_files);
+    for change in &changes {
+        let kind = match change.kind {
+            ChangeKind::Added => "added",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+        };
+        let notebook = change
+            .notebook()
+            .map(|notebook| format!(" [{notebook}]"))
+            .unwrap_or_default();
+        println!(
+            "{:<8} {}{} {}",
+            kind,
+            change.title,
+            notebook,
+            change
+                .updated
+                .with_timezone(&config.timezone)
+                .format("%Y-%m-%d %H:%M")
+        );
+    }
+
+    println!("{} changed notes\n", changes.len());
+}
+
+fn diff(source_dir: &Path, target_dir: &str, config: &Config) {
+    let joplin_files =
+        jb::joplin_file_io::build_joplin_files(source_dir, config).unwrap_or_else(|e| {
+            error!("Error building Joplin files: {}", e);
+            std::process::exit(SOURCE_UNREADABLE);
+        });
+
+    let diffs = jb::diff::diff(target_dir, &joplin_files, config).unwrap_or_else(|e| {
+        error!("Error comparing with the target: {}", e);
+        std::process::exit(TARGET_UNWRITABLE);
+    });
+    for diff in &diffs {
+        let kind = match diff.kind {
+            DiffKind::Missing => "missing",
+            DiffKind::Stale => "stale",
+            DiffKind::Diverged => "diverged",
+        };
+        println!(
+            "{:<8} {} {}",
+            kind,
+            diff.title,
+            diff.relative_path.display()
+        );
+    }
+
+    println!("{} of {} notes differ\n", diffs.len(), joplin_files.len());
+    if !diffs.is_empty() {
+        std::process::exit(PARTIAL);
+    }
+}
+
+fn verify(source_dir: &Path, target_dir: &str, config: &Config) {
+    let joplin_files =
+        jb::joplin_file_io::build_joplin_files(source_dir, config).unwrap_or_else(|e| {
+            error!("Error building Joplin files: {}", e);
+            std::process::exit(SOURCE_UNREADABLE);
+        });
+
+    let verifications = jb::verify::verify(target_dir, &joplin_files, config).unwrap_or_else(|e| {
+        error!("Error verifying the target: {}", e);
+        std::process::exit(SOURCE_UNREADABLE);
+    });
+
+    let mut failed = 0;
+    for verification in &verifications {
+        if verification.problems.is_empty() {
+            println!("PASS {}", verification.relative_path.display());
+            continue;
+        }
+
+        failed += 1;
+        println!("FAIL {}", verification.relative_path.display());
+        for problem in &verification.problems {
+            println!("     {}", problem);
+        }
+    }
+
+    println!(
+        "{} of {} notes passed\n",
+        verifications.len() - failed,
+        verifications.len()
+    );
+    if failed > 0 {
+        std::process::exit(PARTIAL);
+    }
+}
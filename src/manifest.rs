@@ -0,0 +1,402 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+#[cfg(unix)]
+use std::ffi::OsString;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Component, Path, PathBuf};
+
+/// Written to the target directory after a conversion into it
+pub const MANIFEST_FILE_NAME: &str = ".jb_manifest.json";
+
+/// The paths in a target directory before a conversion, to tell which ones it created
+pub struct Snapshot {
+    target_dir: PathBuf,
+    existed: bool,
+    existing: HashSet<PathBuf>,
+}
+
+/// The files and directories a conversion created in its target, relative to it. Files it
+/// overwrote aren't listed, a rollback leaves them as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ManifestJson", into = "ManifestJson")]
+pub struct Manifest {
+    pub target_dir: PathBuf,
+    /// Whether the conversion created the target directory itself
+    pub created_target: bool,
+    /// Parents come before their children
+    pub created: Vec<PathBuf>,
+}
+
+/// The manifest as it's saved, with paths JSON can hold whatever their encoding
+#[derive(Serialize, Deserialize)]
+struct ManifestJson {
+    target_dir: JsonPath,
+    /// Missing from the manifests of older versions
+    #[serde(default)]
+    created_target: bool,
+    created: Vec<JsonPath>,
+}
+
+/// A path as a string, or as its bytes when it isn't valid UTF-8: a lossy path couldn't be
+/// rolled back
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonPath {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<Manifest> for ManifestJson {
+    fn from(manifest: Manifest) -> ManifestJson {
+        ManifestJson {
+            target_dir: JsonPath::from(manifest.target_dir),
+            created_target: manifest.created_target,
+            created: manifest.created.into_iter().map(JsonPath::from).collect(),
+        }
+    }
+}
+
+impl From<ManifestJson> for Manifest {
+    fn from(json: ManifestJson) -> Manifest {
+        Manifest {
+            target_dir: PathBuf::from(json.target_dir),
+            created_target: json.created_target,
+            created: json.created.into_iter().map(PathBuf::from).collect(),
+        }
+    }
+}
+
+impl From<PathBuf> for JsonPath {
+    fn from(path: PathBuf) -> JsonPath {
+        match path.into_os_string().into_string() {
+            Ok(text) => JsonPath::Text(text),
+            #[cfg(unix)]
+            Err(os_string) => JsonPath::Bytes(os_string.into_vec()),
+            #[cfg(not(unix))]
+            Err(os_string) => JsonPath::Text(os_string.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+impl From<JsonPath> for PathBuf {
+    fn from(path: JsonPath) -> PathBuf {
+        match path {
+            JsonPath::Text(text) => PathBuf::from(text),
+            #[cfg(unix)]
+            JsonPath::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes)),
+            #[cfg(not(unix))]
+            JsonPath::Bytes(bytes) => PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn take<P: AsRef<Path>>(target_dir: P) -> Result<Snapshot, String> {
+        let target_dir = target_dir.as_ref().to_path_buf();
+        let existed = target_dir.exists();
+        let existing = walk(&target_dir)?.into_iter().collect();
+
+        Ok(Snapshot {
+            target_dir,
+            existed,
+            existing,
+        })
+    }
+
+    /// What was created in the target since the snapshot was taken
+    pub fn manifest(&self) -> Result<Manifest, String> {
+        let mut created: Vec<PathBuf> = walk(&self.target_dir)?
+            .into_iter()
+            .filter(|relative_path| !self.existing.contains(relative_path))
+            .filter(|relative_path| relative_path != Path::new(MANIFEST_FILE_NAME))
+            .collect();
+        created.sort();
+
+        let target_dir = self
+            .target_dir
+            .canonicalize()
+            .unwrap_or_else(|_| self.target_dir.clone());
+        Ok(Manifest {
+            target_dir,
+            created_target: !self.existed,
+            created,
+        })
+    }
+}
+
+impl Manifest {
+    /// Loads a manifest, for the target directory it's in: whatever `target_dir` says, an edited
+    /// manifest could point anywhere
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Manifest, String> {
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Error reading manifest {:?}: {}", path.as_ref(), e))?;
+        let mut manifest: Manifest = serde_json::from_str(&content)
+            .map_err(|e| format!("Error parsing manifest {:?}: {}", path.as_ref(), e))?;
+        manifest.target_dir = match path.as_ref().parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        Ok(manifest)
+    }
+
+    /// Saves the manifest into the target directory and returns its path
+    pub fn save(&self) -> Result<PathBuf, String> {
+        let path = self.target_dir.join(MANIFEST_FILE_NAME);
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Error serializing manifest: {}", e))?;
+        fs::write(&path, content).map_err(|e| format!("Error writing manifest: {}", e))?;
+
+        Ok(path)
+    }
+
+    /// Removes what the conversion created, children before their parents, and the target
+    /// directory when the conversion created it. Directories that have other files in them by now
+    /// are kept, and returned, except for the target. Nothing is removed when a path leads outside
+    /// the target, see `check_inside`.
+    pub fn rollback(&self) -> Result<Vec<PathBuf>, String> {
+        let target_dir = self
+            .target_dir
+            .canonicalize()
+            .map_err(|e| format!("Error reading {:?}: {}", self.target_dir, e))?;
+        for relative_path in &self.created {
+            check_inside(&target_dir, relative_path)?;
+        }
+        let mut kept = Vec::new();
+
+        for relative_path in self.created.iter().rev() {
+            let path = target_dir.join(relative_path);
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+
+            if metadata.is_dir() {
+                if fs::remove_dir(&path).is_err() {
+                    kept.push(relative_path.clone());
+                }
+            } else {
+                fs::remove_file(&path).map_err(|e| format!("Error removing {:?}: {}", path, e))?;
+            }
+        }
+
+        let manifest_path = target_dir.join(MANIFEST_FILE_NAME);
+        if manifest_path.exists() {
+            fs::remove_file(&manifest_path)
+                .map_err(|e| format!("Error removing {:?}: {}", manifest_path, e))?;
+        }
+        if self.created_target {
+            // Only when it's empty, the directory may have been used for other files since
+            let _ = fs::remove_dir(&target_dir);
+        }
+
+        Ok(kept)
+    }
+}
+
+/// Checks that a path of the manifest is in the target: relative, without `..`, and not below a
+/// link leading out of it. A path that's gone has nothing to remove
+fn check_inside(target_dir: &Path, relative_path: &Path) -> Result<(), String> {
+    let is_relative = relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    let is_inside = || match target_dir
+        .join(relative_path)
+        .parent()
+        .map(Path::canonicalize)
+    {
+        Some(Ok(parent)) => parent.starts_with(target_dir),
+        _ => true,
+    };
+
+    if is_relative && is_inside() {
+        Ok(())
+    } else {
+        Err(format!(
+            "The manifest lists {:?}, which isn't in the target {:?}. Nothing was removed",
+            relative_path, target_dir
+        ))
+    }
+}
+
+/// Every file and directory below `dir`, relative to it. Symlinks aren't followed.
+fn walk(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    if dir.is_dir() {
+        walk_into(dir, Path::new(""), &mut paths)
+            .map_err(|e| format!("Error listing {:?}: {}", dir, e))?;
+    }
+
+    Ok(paths)
+}
+
+fn walk_into(dir: &Path, relative_dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let relative_path = relative_dir.join(entry.file_name());
+        paths.push(relative_path.clone());
+
+        if entry.file_type()?.is_dir() {
+            walk_into(&entry.path(), &relative_path, paths)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        // arrange
+        let target_dir = std::env::temp_dir().join("jb_manifest_test");
+        if target_dir.exists() {
+            fs::remove_dir_all(&target_dir).unwrap();
+        }
+        fs::create_dir_all(target_dir.join("Work")).unwrap();
+        fs::write(target_dir.join("Work/mine.md"), "mine").unwrap();
+
+        let snapshot = Snapshot::take(&target_dir).unwrap();
+        fs::create_dir_all(target_dir.join("Work/Proj")).unwrap();
+        fs::create_dir_all(target_dir.join("Home")).unwrap();
+        fs::write(target_dir.join("Work/Proj/a.md"), "a").unwrap();
+        fs::write(target_dir.join("Home/b.md"), "b").unwrap();
+        let manifest = snapshot.manifest().unwrap();
+        let manifest_path = manifest.save().unwrap();
+        // Added after the conversion, the directory has to stay
+        fs::write(target_dir.join("Home/later.md"), "later").unwrap();
+
+        // act
+        let kept = Manifest::load(&manifest_path).unwrap().rollback().unwrap();
+
+        // assert
+        let mut remaining = walk(&target_dir).unwrap();
+        remaining.sort();
+        fs::remove_dir_all(&target_dir).unwrap();
+        assert_eq!(
+            manifest.created,
+            vec![
+                PathBuf::from("Home"),
+                PathBuf::from("Home/b.md"),
+                PathBuf::from("Work/Proj"),
+                PathBuf::from("Work/Proj/a.md"),
+            ]
+        );
+        assert_eq!(kept, vec![PathBuf::from("Home")]);
+        assert_eq!(
+            remaining,
+            vec![
+                PathBuf::from("Home"),
+                PathBuf::from("Home/later.md"),
+                PathBuf::from("Work"),
+                PathBuf::from("Work/mine.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rollback_created_target() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_manifest_created_target_test");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        let test_cases = vec![("new", &[][..], false), ("used", &["later.md"][..], true)];
+
+        for (name, added, expected) in test_cases {
+            let target_dir = dir.join(name);
+            let snapshot = Snapshot::take(&target_dir).unwrap();
+            fs::create_dir_all(target_dir.join("Work")).unwrap();
+            fs::write(target_dir.join("Work/a.md"), "a").unwrap();
+            let manifest_path = snapshot.manifest().unwrap().save().unwrap();
+            for file_name in added {
+                fs::write(target_dir.join(file_name), "later").unwrap();
+            }
+
+            // act
+            let manifest = Manifest::load(&manifest_path).unwrap();
+            let kept = manifest.rollback().unwrap();
+
+            // assert
+            assert!(manifest.created_target, "{}", name);
+            assert_eq!(kept, Vec::<PathBuf>::new(), "{}", name);
+            assert_eq!(target_dir.exists(), expected, "{}", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_not_utf8() {
+        // arrange
+        let target_dir = std::env::temp_dir().join("jb_manifest_not_utf8_test");
+        fs::create_dir_all(&target_dir).unwrap();
+        let manifest = Manifest {
+            target_dir: target_dir.clone(),
+            created_target: true,
+            created: vec![
+                PathBuf::from(OsString::from_vec(b"caf\xe9".to_vec())),
+                PathBuf::from("ok.md"),
+            ],
+        };
+
+        // act
+        let manifest_path = manifest.save().unwrap();
+
+        // assert
+        let loaded = Manifest::load(&manifest_path).unwrap();
+        fs::remove_dir_all(&target_dir).unwrap();
+        assert_eq!(loaded, manifest);
+    }
+
+    #[test]
+    fn test_rollback_outside() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_manifest_outside_test");
+        let target_dir = dir.join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let outside = dir.join("outside.md");
+        fs::write(&outside, "outside").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, target_dir.join("up")).unwrap();
+        let manifest_path = target_dir.join(MANIFEST_FILE_NAME);
+        let test_cases = vec![
+            ("../outside.md", dir.to_str().unwrap()),
+            (outside.to_str().unwrap(), "."),
+            #[cfg(unix)]
+            ("up/outside.md", "."),
+            // The target is where the manifest is
+            ("outside.md", dir.to_str().unwrap()),
+        ];
+
+        for (created, manifest_target_dir) in test_cases {
+            let content = serde_json::json!({
+                "target_dir": manifest_target_dir,
+                "created": [created],
+            });
+            fs::write(&manifest_path, content.to_string()).unwrap();
+
+            // act
+            let manifest = Manifest::load(&manifest_path).unwrap();
+            let result = manifest.rollback();
+
+            // assert
+            assert_eq!(manifest.target_dir, target_dir);
+            if created == "outside.md" {
+                assert_eq!(result, Ok(Vec::new()));
+            } else {
+                assert!(
+                    result.unwrap_err().contains("isn't in the target"),
+                    "{}",
+                    created
+                );
+            }
+            assert!(outside.exists(), "{}", created);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use crate::fidelity::Fidelity;
+use crate::headings::find_headings;
+use crate::{Config, JoplinFile};
+use std::path::{Path, PathBuf};
+
+/// How the dates of the merged notes are stamped under their heading
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Merges the notes directly in each of the notebooks into a single note named after the
+/// notebook, in it, for scraps that read better together. The notes become `##` sections in the
+/// order they were created, each with its title and dates, under the notebook as the `#` title;
+/// their own headings go down two levels. The merged note has all their tags,
+/// the first created and last updated dates, and their warnings. Links to the merged notes
+/// dangle and are reported as such. Returns how many notes were merged.
+pub fn merge_notebooks(
+    joplin_files: &mut Vec<JoplinFile>,
+    notebooks: &[PathBuf],
+    config: &Config,
+) -> usize {
+    let mut merged_count = 0;
+    for notebook in notebooks {
+        let notebook = normalize(notebook, config);
+        if notebook.file_name().is_none() {
+            crate::warning!("The notes at the top of the export aren't in a notebook to merge");
+            continue;
+        }
+        let (mut merged, kept): (Vec<JoplinFile>, Vec<JoplinFile>) = joplin_files
+            .drain(..)
+            .partition(|joplin_file| joplin_file.relative_path.parent() == Some(&notebook));
+        *joplin_files = kept;
+        if merged.is_empty() {
+            crate::warning!("No notes to merge in {:?}", notebook);
+            continue;
+        }
+
+        merged.sort_by(|a, b| (a.created, &a.title).cmp(&(b.created, &b.title)));
+        crate::notice!("Merged {} notes in {:?}", merged.len(), notebook);
+        merged_count += merged.len();
+        joplin_files.push(merge_notes(&notebook, merged, config));
+    }
+
+    merged_count
+}
+
+/// The notebook as the relative paths of the notes have it
+fn normalize(notebook: &Path, config: &Config) -> PathBuf {
+    let notebook = notebook.to_string_lossy();
+    let notebook = notebook.trim_matches(['/', '\\']);
+    PathBuf::from(config.unicode_normalization.normalize(notebook))
+}
+
+fn merge_notes(notebook: &Path, notes: Vec<JoplinFile>, config: &Config) -> JoplinFile {
+    let title = notebook
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = config
+        .extensions
+        .first()
+        .map_or(String::new(), |extension| format!(".{extension}"));
+
+    let sections: Vec<String> = notes
+        .iter()
+        .map(|joplin_file| {
+            let created = joplin_file.created.with_timezone(&config.timezone);
+            let updated = joplin_file.updated.with_timezone(&config.timezone);
+            // The body may start with the title as a heading already
+            let heading = format!("# {}", joplin_file.title);
+            let body = match joplin_file.body.strip_prefix(&heading) {
+                Some(rest) if rest.is_empty() || rest.starts_with('\n') => rest.trim_start(),
+                _ => &joplin_file.body,
+            };
+
+            let stamp = format!(
+                "_Created {}, updated {}_",
+                created.format(DATE_FORMAT),
+                updated.format(DATE_FORMAT)
+            );
+            let body = demote_headings(body);
+            format!("## {}\n\n{stamp}\n\n{body}", joplin_file.title)
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+
+    let mut tags: Vec<&str> = Vec::new();
+    for tag in notes
+        .iter()
+        .filter_map(|joplin_file| joplin_file.tags.as_deref())
+        .flat_map(str::split_whitespace)
+    {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    let fidelity = notes.iter().fold(Fidelity::default(), |sum, joplin_file| {
+        let fidelity = &joplin_file.fidelity;
+        Fidelity {
+            unsupported_constructs: sum.unsupported_constructs + fidelity.unsupported_constructs,
+            unresolved_links: sum.unresolved_links + fidelity.unresolved_links,
+            missing_resources: sum.missing_resources + fidelity.missing_resources,
+            fallbacks: sum.fallbacks + fidelity.fallbacks,
+        }
+    });
+
+    let mut merged = JoplinFile {
+        title: title.clone(),
+        id: None,
+        created: notes
+            .iter()
+            .map(|joplin_file| joplin_file.created)
+            .min()
+            .unwrap_or_default(),
+        updated: notes
+            .iter()
+            .map(|joplin_file| joplin_file.updated)
+            .max()
+            .unwrap_or_default(),
+        front_matter: String::new(),
+        tags: (!tags.is_empty()).then(|| tags.join(" ")),
+        relative_path: notebook.join(format!("{title}{extension}")),
+        fidelity,
+        warnings: notes
+            .iter()
+            .flat_map(|joplin_file| joplin_file.warnings.clone())
+            .collect(),
+        ..notes[0].clone()
+    };
+    // Bear titles the note after its first line
+    merged.set_body(format!("# {title}\n\n{}", sections.join("\n\n")));
+    merged
+}
+
+/// The body with its `#` headings two levels down, `#` to `###`, so they stay below the `##`
+/// sections of the merged note. `######` is as far down as they go.
+fn demote_headings(body: &str) -> String {
+    let mut body = body.to_string();
+    for heading in find_headings(&body).into_iter().rev() {
+        let Some(hashes) = body[heading.start..]
+            .find(|c| c != ' ')
+            .filter(|&offset| body[heading.start + offset..].starts_with('#'))
+        else {
+            continue;
+        };
+        let added = 6usize.saturating_sub(heading.level).min(2);
+        body.insert_str(heading.start + hashes, &"#".repeat(added));
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(relative_path: &str, title: &str, created: &str, tags: &str, body: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: {title}\ncreated: {created}\nupdated: 2024-04-07T08:34:52Z\ntags: [{tags}]\n---\n{body}"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_merge_notebooks() {
+        let mut joplin_files = vec![
+            note(
+                "Scraps/b.md",
+                "B",
+                "2024-03-08T10:00:00Z",
+                "idea",
+                "# B\n\nSecond\n\n# Part\n\n```\n# not a heading\n```\n\n##### Deep",
+            ),
+            note("Other/c.md", "C", "2024-01-01T00:00:00Z", "", "Elsewhere"),
+            note(
+                "Scraps/a.md",
+                "A",
+                "2024-03-07T09:30:00Z",
+                "idea, todo",
+                "First",
+            ),
+            note(
+                "Scraps/Deeper/d.md",
+                "D",
+                "2024-01-01T00:00:00Z",
+                "",
+                "Nested",
+            ),
+        ];
+
+        let merged_count = merge_notebooks(
+            &mut joplin_files,
+            &[PathBuf::from("Scraps/"), PathBuf::from("Empty")],
+            &Config::default(),
+        );
+
+        assert_eq!(merged_count, 2);
+        let paths: Vec<&Path> = joplin_files
+            .iter()
+            .map(|joplin_file| joplin_file.relative_path.as_path())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("Other/c.md"),
+                Path::new("Scraps/Deeper/d.md"),
+                Path::new("Scraps/Scraps.md"),
+            ]
+        );
+        let merged = &joplin_files[2];
+        assert_eq!(merged.title, "Scraps");
+        assert_eq!(
+            merged.body,
+            "# Scraps\n\n\
+            ## A\n\n_Created 2024-03-07 09:30, updated 2024-04-07 08:34_\n\nFirst\n\n\
+            ## B\n\n_Created 2024-03-08 10:00, updated 2024-04-07 08:34_\n\nSecond\n\n\
+            ### Part\n\n```\n# not a heading\n```\n\n###### Deep"
+        );
+        assert_eq!(merged.tags.as_deref(), Some("#Scraps #idea #todo"));
+        assert_eq!(merged.created.to_rfc3339(), "2024-03-07T09:30:00+00:00");
+    }
+}
@@ -0,0 +1,417 @@
+use crate::bear::percent_encode;
+use crate::headings::moved_slugs;
+use crate::joplin_file_io::{build_content, note_paths};
+use crate::resource_ref::{find_links, percent_decode};
+use crate::warning::Warning;
+use crate::{Config, JoplinFile};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+/// A link from a note to another note by relative path, e.g. `[see](../Notebook/Other%20note.md)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteLink {
+    /// Byte range of the path inside the note body, without the fragment
+    pub span: Range<usize>,
+    /// The linked note relative to the export, `None` when the path leads out of it
+    pub target: Option<PathBuf>,
+    /// The `#fragment` after the path, without the `#`
+    pub fragment: Option<String>,
+}
+
+/// Checks the links between notes by relative path against the notes that were found. The
+/// links to notes whose path changes in the target, as their file names are sanitized or the
+/// path template moves them, are pointed at the new path, as are the resource links of the
+/// notes moved to another depth, and the fragments of the links to headings whose anchor changes,
+/// as headings are added to the note, at the new anchor. The links to notes that aren't there
+/// are left as they are, the note gets a warning and counts them as unresolved. Returns how
+/// many links dangle.
+pub fn link_notes(joplin_files: &mut [JoplinFile], config: &Config) -> usize {
+    let links: Vec<Vec<NoteLink>> = joplin_files
+        .iter()
+        .map(|joplin_file| find_note_links(&joplin_file.body, &joplin_file.relative_path, config))
+        .collect();
+
+    // Only the notes linked to by anchor need their headings compared
+    let anchored: HashSet<&PathBuf> = links
+        .iter()
+        .flatten()
+        .filter(|note_link| note_link.fragment.is_some())
+        .filter_map(|note_link| note_link.target.as_ref())
+        .collect();
+    let moved = joplin_files
+        .iter()
+        .filter(|joplin_file| anchored.contains(&joplin_file.relative_path))
+        .map(|joplin_file| {
+            let slugs = moved_anchors(joplin_file, config);
+            (joplin_file.relative_path.clone(), slugs)
+        })
+        .collect();
+    let link_targets = LinkTargets::new(joplin_files, moved, config);
+
+    joplin_files
+        .iter_mut()
+        .zip(links)
+        .map(|(joplin_file, links)| link_targets.apply(joplin_file, links))
+        .sum()
+}
+
+/// The anchors of a note that change once it's converted, as headings are added to it
+pub fn moved_anchors(joplin_file: &JoplinFile, config: &Config) -> HashMap<String, String> {
+    moved_slugs(&joplin_file.body, &build_content(joplin_file, config))
+}
+
+/// What linking a note needs to know of the others, so notes can be linked one at a time: the
+/// path each goes to in the target, and the anchors that change in them
+pub struct LinkTargets {
+    target_paths: HashMap<PathBuf, PathBuf>,
+    moved: HashMap<PathBuf, HashMap<String, String>>,
+}
+
+impl LinkTargets {
+    /// For the notes, which may be left without their bodies, and the anchors that change in
+    /// them by relative path, see `moved_anchors`
+    pub fn new(
+        joplin_files: &[JoplinFile],
+        moved: HashMap<PathBuf, HashMap<String, String>>,
+        config: &Config,
+    ) -> LinkTargets {
+        let target_paths = joplin_files
+            .iter()
+            .map(|joplin_file| joplin_file.relative_path.clone())
+            .zip(note_paths(joplin_files, config))
+            .collect();
+
+        LinkTargets {
+            target_paths,
+            moved,
+        }
+    }
+
+    /// Links a note as `link_notes` does, returns how many of its links dangle
+    pub fn link(&self, joplin_file: &mut JoplinFile, config: &Config) -> usize {
+        let links = find_note_links(&joplin_file.body, &joplin_file.relative_path, config);
+        self.apply(joplin_file, links)
+    }
+
+    fn apply(&self, joplin_file: &mut JoplinFile, links: Vec<NoteLink>) -> usize {
+        let target_paths = &self.target_paths;
+        let from = &target_paths[&joplin_file.relative_path];
+        let mut replacements = resource_links(joplin_file, from);
+        let mut dangling = Vec::new();
+
+        for note_link in &links {
+            let target = note_link.target.as_ref();
+            let Some(target_path) = target.and_then(|target| target_paths.get(target)) else {
+                dangling.push(joplin_file.body[note_link.span.clone()].to_string());
+                continue;
+            };
+
+            let unmoved = Some(target_path) == target && *from == joplin_file.relative_path;
+            if !note_link.span.is_empty() && !unmoved {
+                replacements.push((note_link.span.clone(), relative_link(from, target_path)));
+            }
+
+            let moved_slug = note_link
+                .fragment
+                .as_ref()
+                .zip(target.and_then(|target| self.moved.get(target)))
+                .and_then(|(fragment, slugs)| slugs.get(&percent_decode(fragment)));
+            if let Some(slug) = moved_slug {
+                replacements.push((note_link.fragment_span(), slug.clone()));
+            }
+        }
+
+        let mut body = joplin_file.body.clone();
+        replacements.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+        for (span, replacement) in replacements {
+            body.replace_range(span, &replacement);
+        }
+        if body != joplin_file.body {
+            joplin_file.set_body(body);
+        }
+        if dangling.is_empty() {
+            return 0;
+        }
+
+        crate::warning!(
+            "{:?}: {}",
+            joplin_file.relative_path,
+            Warning::DanglingNoteLinks(dangling.clone())
+        );
+        let dangling_count = dangling.len();
+        joplin_file.fidelity.unresolved_links += dangling_count;
+        joplin_file
+            .warnings
+            .push(Warning::DanglingNoteLinks(dangling));
+        dangling_count
+    }
+}
+
+/// The resource links of a note that the path template moves to another depth, pointed at the
+/// resources directory from where the note goes. Nothing to change for the notes that stay at
+/// their depth.
+fn resource_links(joplin_file: &JoplinFile, target_path: &Path) -> Vec<(Range<usize>, String)> {
+    let depth = target_path.components().count();
+    if depth == joplin_file.relative_path.components().count() {
+        return Vec::new();
+    }
+
+    joplin_file
+        .referenced_resources()
+        .iter()
+        .filter_map(|resource_ref| {
+            let resource_path = resource_ref.resource_path()?;
+            let link = format!("{}_resources/{}", "../".repeat(depth - 1), resource_path);
+            Some((resource_ref.span.clone(), link))
+        })
+        .collect()
+}
+
+/// The links of a body to other notes: relative paths with a note extension, and anchors into
+/// the note itself, which link to it with an empty path. Web links, absolute paths and links
+/// into the resources directory aren't links to notes.
+pub fn find_note_links(body: &str, relative_path: &Path, config: &Config) -> Vec<NoteLink> {
+    find_links(body)
+        .into_iter()
+        .filter_map(|link| {
+            let dest = &body[link.span.clone()];
+            let (path, fragment) = match dest.split_once('#') {
+                Some((path, fragment)) => (path, Some(fragment.to_string())),
+                None => (dest, None),
+            };
+            if path.is_empty() {
+                return fragment.map(|fragment| NoteLink {
+                    span: link.span.start..link.span.start,
+                    target: Some(relative_path.to_path_buf()),
+                    fragment: Some(fragment),
+                });
+            }
+            if path.starts_with(['/', '\\']) || has_scheme(path) {
+                return None;
+            }
+
+            let decoded = config
+                .unicode_normalization
+                .normalize(&percent_decode(path));
+            if !config.is_note(Path::new(&decoded)) {
+                return None;
+            }
+
+            Some(NoteLink {
+                span: link.span.start..link.span.start + path.len(),
+                target: resolve(relative_path, &decoded),
+                fragment,
+            })
+        })
+        .collect()
+}
+
+impl NoteLink {
+    /// Byte range of the fragment inside the note body, after the `#`
+    pub(crate) fn fragment_span(&self) -> Range<usize> {
+        let start = self.span.end + 1;
+        start..start + self.fragment.as_ref().map_or(0, String::len)
+    }
+}
+
+/// Whether the destination starts with a URL scheme like `https:` or `mailto:`
+pub(crate) fn has_scheme(dest: &str) -> bool {
+    dest.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// The path of the linked note relative to the export, `None` when it leads out of it
+pub(crate) fn resolve(relative_path: &Path, link_path: &str) -> Option<PathBuf> {
+    let mut resolved = relative_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    for component in link_path.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            name => resolved.push(name),
+        }
+    }
+
+    Some(resolved)
+}
+
+/// The link from the note at `from` to the note at `to`, both relative to the target, with each
+/// component percent-encoded
+pub(crate) fn relative_link(from: &Path, to: &Path) -> String {
+    let from_dir: Vec<Component> = from
+        .parent()
+        .map_or(Vec::new(), |parent| parent.components().collect());
+    let to: Vec<Component> = to.components().collect();
+    let common = from_dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    std::iter::repeat_n("..".to_string(), from_dir.len() - common)
+        .chain(
+            to[common..]
+                .iter()
+                .map(|component| percent_encode(&component.as_os_str().to_string_lossy())),
+        )
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TagPlacement;
+
+    /// The path of a link as written, the note it leads to and its fragment
+    type Found<'a> = (&'a str, Option<&'a str>, Option<&'a str>);
+
+    fn note(relative_path: &str, body: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_find_note_links() {
+        let config = Config::default();
+        let test_cases: Vec<(&str, Vec<Found>)> = vec![
+            (
+                "[b](b.md) [c](../Other/C%20note.md#part) ![](../_resources/a.png)",
+                vec![
+                    ("b.md", Some("Notebook/b.md"), None),
+                    (
+                        "../Other/C%20note.md",
+                        Some("Other/C note.md"),
+                        Some("part"),
+                    ),
+                ],
+            ),
+            (
+                "[web](https://example.com/a.md) [abs](/notes/a.md) [here](#part) [out](../../a.md)",
+                vec![
+                    ("", Some("Notebook/a.md"), Some("part")),
+                    ("../../a.md", None, None),
+                ],
+            ),
+            ("`[b](b.md)` [txt](notes.txt)", vec![]),
+        ];
+
+        for (body, expected) in test_cases {
+            let links = find_note_links(body, Path::new("Notebook/a.md"), &config);
+
+            let found: Vec<Found> = links
+                .iter()
+                .map(|link| {
+                    (
+                        &body[link.span.clone()],
+                        link.target.as_deref().and_then(Path::to_str),
+                        link.fragment.as_deref(),
+                    )
+                })
+                .collect();
+            assert_eq!(found, expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_link_notes() {
+        let mut joplin_files = vec![
+            note(
+                "Notebook/a.md",
+                "[b](b.md) [c](../Other/C%3F.md#part) [gone](missing.md)",
+            ),
+            note("Notebook/b.md", "[a](./a.md)"),
+            note("Other/C?.md", "[a](../Notebook/a.md)"),
+        ];
+
+        let dangling = link_notes(&mut joplin_files, &Config::default());
+
+        assert_eq!(dangling, 1);
+        assert_eq!(
+            joplin_files[0].body,
+            "[b](b.md) [c](../Other/C_.md#part) [gone](missing.md)"
+        );
+        assert_eq!(
+            joplin_files[0].warnings,
+            vec![Warning::DanglingNoteLinks(vec!["missing.md".to_string()])]
+        );
+        assert_eq!(joplin_files[0].fidelity.unresolved_links, 1);
+        assert_eq!(joplin_files[1].body, "[a](./a.md)");
+        assert_eq!(joplin_files[2].body, "[a](../Notebook/a.md)");
+    }
+
+    #[test]
+    fn test_link_notes_path_template() {
+        let config = Config {
+            path_template: Some(
+                "{{created:%Y}}/{{created:%m}}/{{title|slug}}.md"
+                    .parse()
+                    .unwrap(),
+            ),
+            ..Config::default()
+        };
+        let mut joplin_files = vec![
+            note(
+                "Notebook/a.md",
+                "[b](../Other/b.md) ![](../_resources/x.png)",
+            ),
+            note("Other/b.md", "[a](../Notebook/a.md)"),
+        ];
+
+        link_notes(&mut joplin_files, &config);
+
+        assert_eq!(
+            joplin_files[0].body,
+            "[b](test%202.md) ![](../../_resources/x.png)"
+        );
+        assert_eq!(joplin_files[1].body, "[a](test.md)");
+    }
+
+    #[test]
+    fn test_link_notes_anchors() {
+        let config = Config {
+            tag_placement: TagPlacement::Top,
+            ..Config::default()
+        };
+        let mut joplin_files = vec![
+            note(
+                "Notebook/a.md",
+                "[b](b.md#test) [self](#test) [other](b.md#other)\n\n## Test",
+            ),
+            note("Notebook/b.md", "## Test\n\n## Other"),
+            // The path gets shorter as it's sanitized, the fragment after it still moves
+            note("Notebook/c.md", "[c](../Other/C%3F.md#test)"),
+            note("Other/C?.md", "## Test"),
+        ];
+
+        link_notes(&mut joplin_files, &config);
+
+        assert_eq!(
+            joplin_files[0].body,
+            "[b](b.md#test-1) [self](#test-1) [other](b.md#other)\n\n## Test"
+        );
+        assert_eq!(joplin_files[2].body, "[c](../Other/C_.md#test-1)");
+    }
+
+    #[test]
+    fn test_relative_link() {
+        let test_cases = vec![
+            ("a.md", "b.md", "b.md"),
+            ("x/a.md", "x/y/b c.md", "y/b%20c.md"),
+            ("x/y/a.md", "z/b.md", "../../z/b.md"),
+        ];
+
+        for (from, to, expected) in test_cases {
+            assert_eq!(relative_link(Path::new(from), Path::new(to)), expected);
+        }
+    }
+}
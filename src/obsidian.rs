@@ -0,0 +1,260 @@
+use crate::archive::{ZipSource, is_zip};
+use crate::joplin_file_io::{copy_dir_recursively, resource_dirs, target_paths};
+use crate::resource_ref::{ResourceKind, ResourceTarget};
+use crate::{Config, JoplinFile};
+use std::collections::HashMap;
+use std::fs::{self, create_dir_all};
+use std::path::{Component, Path, PathBuf};
+
+/// Where the resources go in the vault
+const ATTACHMENTS_DIR: &str = "attachments";
+
+/// Characters that are valid in file names, but break Obsidian links to the file
+const LINK_BREAKING_CHARS: [char; 5] = ['#', '^', '[', ']', '|'];
+
+/// Writes the notes as an Obsidian vault: notes keep their front matter, links between notes
+/// become `[[wikilinks]]` and the resources go into `attachments/`, with those of the other
+/// resource directories. The resources that fail to copy are recorded in `io_errors` for the
+/// caller to report, the others are copied anyway
+pub fn write_obsidian<P: AsRef<Path>>(
+    source_dir: P,
+    target_dir: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<(), String> {
+    let target_dir = target_dir.as_ref();
+    let base_len = target_dir.to_string_lossy().chars().count() + 1;
+    let relative_paths: Vec<PathBuf> = target_paths(joplin_files, base_len, config)?
+        .iter()
+        .map(|relative_path| vault_path(relative_path, config.filename_replacement))
+        .collect();
+
+    // Obsidian resolves wikilinks by file name
+    let names: HashMap<&str, String> = joplin_files
+        .iter()
+        .zip(&relative_paths)
+        .filter_map(|(joplin_file, relative_path)| {
+            let id = joplin_file.id.as_deref()?;
+            let name = relative_path.file_stem()?.to_string_lossy().to_string();
+            Some((id, name))
+        })
+        .collect();
+
+    for (joplin_file, relative_path) in joplin_files.iter().zip(&relative_paths) {
+        let target_path = target_dir.join(relative_path);
+        if let Some(parent) = target_path.parent() {
+            create_dir_all(parent)
+                .map_err(|e| format!("Error creating directory {:?}: {}", parent, e))?;
+        }
+
+        let depth = relative_path.components().count().saturating_sub(1);
+        fs::write(
+            &target_path,
+            build_vault_content(joplin_file, depth, &names),
+        )
+        .map_err(|e| format!("Error writing file {:?}: {}", target_path, e))?;
+    }
+
+    copy_attachments(source_dir.as_ref(), target_dir, config)
+}
+
+/// The content of a vault note: the front matter as it is, and the body with its links rewritten.
+/// `depth` is the number of directories the note is in below the root of the vault.
+fn build_vault_content(
+    joplin_file: &JoplinFile,
+    depth: usize,
+    names: &HashMap<&str, String>,
+) -> String {
+    let mut body = joplin_file.body.clone();
+
+    let mut refs = joplin_file.referenced_resources().to_vec();
+    refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+
+    for resource_ref in refs {
+        match &resource_ref.target {
+            ResourceTarget::Id(_) => {
+                let Some(name) = names.get(resource_ref.file_name()) else {
+                    continue;
+                };
+                let text = link_text(&body, &resource_ref.link, resource_ref.span.start);
+                let wikilink = match (resource_ref.kind, text) {
+                    (ResourceKind::Image, _) => format!("![[{name}]]"),
+                    (ResourceKind::Link, Some(text)) if text != name => {
+                        format!("[[{name}|{text}]]")
+                    }
+                    (ResourceKind::Link, _) => format!("[[{name}]]"),
+                };
+                body.replace_range(resource_ref.link, &wikilink);
+            }
+            ResourceTarget::Path(_) => {
+                let Some(resource_path) = resource_ref.resource_path() else {
+                    continue;
+                };
+                let target = format!("{}{ATTACHMENTS_DIR}/{resource_path}", "../".repeat(depth));
+                body.replace_range(resource_ref.span, &target);
+            }
+        }
+    }
+
+    let front_matter = joplin_file.front_matter.trim_end();
+    if body.is_empty() {
+        format!("{front_matter}\n")
+    } else {
+        format!("{front_matter}\n\n{body}\n")
+    }
+}
+
+/// The text of a markdown link, `None` for `<img>` tags
+fn link_text<'a>(
+    body: &'a str,
+    link: &std::ops::Range<usize>,
+    target_start: usize,
+) -> Option<&'a str> {
+    let link_body = body[link.clone()].trim_start_matches('!');
+    let text_start = link.end - link_body.len() + 1;
+    let text_end = body[..target_start].rfind("](")?;
+
+    link_body
+        .starts_with('[')
+        .then(|| body.get(text_start..text_end))
+        .flatten()
+}
+
+/// A target path with the characters that break Obsidian links replaced in every component
+fn vault_path(relative_path: &Path, replacement: char) -> PathBuf {
+    relative_path
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => match name.to_str() {
+                Some(name) => {
+                    PathBuf::from(name.replace(LINK_BREAKING_CHARS, &replacement.to_string()))
+                }
+                None => PathBuf::from(name),
+            },
+            other => PathBuf::from(other.as_os_str()),
+        })
+        .collect()
+}
+
+fn copy_attachments(source_dir: &Path, target_dir: &Path, config: &Config) -> Result<(), String> {
+    let attachments_dir = target_dir.join(ATTACHMENTS_DIR);
+
+    if is_zip(source_dir) {
+        ZipSource::open(source_dir)?.copy_resources(target_dir, &config.cancellation)?;
+        return fs::rename(target_dir.join("_resources"), &attachments_dir)
+            .map_err(|e| format!("Error moving resources to {:?}: {}", attachments_dir, e));
+    }
+
+    // The first resource directory that has a resource wins, as it does for the links, so it's
+    // copied last
+    let resources_dirs: Vec<PathBuf> = resource_dirs(source_dir, config).collect();
+    for resources_dir in resources_dirs.into_iter().rev() {
+        // `check_resources_dir` tells about a missing resources directory
+        if !resources_dir.exists() {
+            if resources_dir != source_dir.join("_resources") {
+                crate::warning!("The resource directory {:?} does not exist", resources_dir);
+            }
+            continue;
+        }
+        if !resources_dir.is_dir() {
+            return Err(format!(
+                "The source path: {:?} is not a directory",
+                resources_dir
+            ));
+        }
+
+        copy_dir_recursively(&resources_dir, &attachments_dir, config)
+            .map_err(|e| format!("Error copying resources: {}", e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_build_vault_content() {
+        // arrange
+        let joplin_file = JoplinFile::build(
+            "Work/a.md",
+            &format!(
+                "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+                See [the plan](:/{ID}), [Plan](:/{ID}) and [gone](:/fedcba9876543210fedcba9876543210)\n\
+                ![chart](../_resources/chart.png)"
+            ),
+            &Config::default(),
+        )
+        .unwrap();
+        let names = HashMap::from([(ID, "Plan".to_string())]);
+
+        // act
+        let content = build_vault_content(&joplin_file, 1, &names);
+
+        // assert
+        assert_eq!(
+            content,
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\n\
+            See [[Plan|the plan]], [[Plan]] and [gone](:/fedcba9876543210fedcba9876543210)\n\
+            ![chart](../attachments/chart.png)\n"
+        );
+    }
+
+    #[test]
+    fn test_write_obsidian_resource_dirs() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_obsidian_resource_dirs_test");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let source_dir = dir.join("source");
+        create_dir_all(source_dir.join("Work")).unwrap();
+        create_dir_all(source_dir.join("resources")).unwrap();
+        fs::write(source_dir.join("resources/pic.png"), "png").unwrap();
+        fs::write(
+            source_dir.join("Work/a.md"),
+            "---\ntitle: A\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            ![pic](../resources/pic.png)",
+        )
+        .unwrap();
+        let config = Config {
+            output_format: crate::config::OutputFormat::Obsidian,
+            resource_dirs: vec![PathBuf::from("resources")],
+            ..Config::default()
+        };
+        let joplin_files = crate::joplin_file_io::build_joplin_files(&source_dir, &config).unwrap();
+
+        // act
+        let result = write_obsidian(&source_dir, &dir.join("vault"), &joplin_files, &config);
+
+        // assert
+        let content = fs::read_to_string(dir.join("vault/Work/a.md")).unwrap();
+        let copied = dir.join("vault/attachments/pic.png").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_ok());
+        assert!(
+            content.contains("![pic](../attachments/pic.png)"),
+            "{}",
+            content
+        );
+        assert!(copied);
+    }
+
+    #[test]
+    fn test_vault_path() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("Work/plan.md", "Work/plan.md"),
+            ("Work #1/[draft] plan^2.md", "Work _1/_draft_ plan_2.md"),
+        ];
+
+        for (relative_path, expected) in test_cases {
+            assert_eq!(
+                vault_path(Path::new(relative_path), '_'),
+                PathBuf::from(expected)
+            );
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use crate::sanitize::sanitize_path;
+use crate::{Config, JoplinFile};
+use chrono::format::{Item, StrftimeItems};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The format of the dates that have none, `{{created}}`
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Where a note goes in the target, built from its values instead of its path in the export,
+/// e.g. `{{created:%Y}}/{{created:%m}}/{{title|slug}}.md`. Placeholders are `{{field}}`, with a
+/// strftime format after a `:` for dates and filters after `|`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Placeholder {
+        field: Field,
+        format: Option<String>,
+        filters: Vec<Filter>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    Id,
+    Created,
+    Updated,
+    /// The directories of the note in the export, `Work/Projects`
+    Notebook,
+    /// The file name of the note in the export, without the extension
+    Name,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    /// Lowercase, with the runs of other characters than letters and digits turned into dashes
+    Slug,
+    Lower,
+}
+
+impl FromStr for PathTemplate {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| format!("Unclosed placeholder in {:?}", template))?;
+            parts.push(parse_placeholder(&rest[start + 2..start + end])?);
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        Ok(PathTemplate { parts })
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Part, String> {
+    let mut pipes = placeholder.split('|').map(str::trim);
+    let field = pipes.next().unwrap_or_default();
+    let (name, format) = match field.split_once(':') {
+        Some((name, format)) => (name.trim(), Some(format.to_string())),
+        None => (field, None),
+    };
+
+    let field = match name {
+        "title" => Field::Title,
+        "id" => Field::Id,
+        "created" => Field::Created,
+        "updated" => Field::Updated,
+        "notebook" => Field::Notebook,
+        "name" => Field::Name,
+        _ => return Err(format!("Unknown placeholder {{{{{}}}}}", name)),
+    };
+    if let Some(format) = &format {
+        if !matches!(field, Field::Created | Field::Updated) {
+            return Err(format!("Only dates have a format, not {{{{{}}}}}", name));
+        }
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            return Err(format!("Invalid date format {:?}", format));
+        }
+    }
+    let filters = pipes
+        .map(|filter| match filter {
+            "slug" => Ok(Filter::Slug),
+            "lower" => Ok(Filter::Lower),
+            _ => Err(format!("Unknown filter {:?}", filter)),
+        })
+        .collect::<Result<Vec<Filter>, String>>()?;
+
+    Ok(Part::Placeholder {
+        field,
+        format,
+        filters,
+    })
+}
+
+impl PathTemplate {
+    /// The path of the note relative to the target, before it's sanitized. Dates are in the
+    /// configured time zone, and the values other than the notebook can't add directories: the
+    /// separators in them are replaced, as are the dots of a `.` or `..` directory. Falls back
+    /// to the path in the export when the template leaves no file name.
+    pub fn render(&self, joplin_file: &JoplinFile, config: &Config) -> PathBuf {
+        let replacement = config.filename_replacement.to_string();
+        let mut rendered = String::new();
+        for part in &self.parts {
+            let (field, format, filters) = match part {
+                Part::Literal(literal) => {
+                    rendered.push_str(literal);
+                    continue;
+                }
+                Part::Placeholder {
+                    field,
+                    format,
+                    filters,
+                } => (
+                    field,
+                    format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT),
+                    filters,
+                ),
+            };
+
+            let relative_path = &joplin_file.relative_path;
+            let mut value = match field {
+                Field::Title => joplin_file.title.clone(),
+                Field::Id => joplin_file.id.clone().unwrap_or_default(),
+                Field::Created => joplin_file
+                    .created
+                    .with_timezone(&config.timezone)
+                    .format(format)
+                    .to_string(),
+                Field::Updated => joplin_file
+                    .updated
+                    .with_timezone(&config.timezone)
+                    .format(format)
+                    .to_string(),
+                Field::Notebook => relative_path
+                    .parent()
+                    .map(|parent| parent.to_string_lossy().replace('\\', "/"))
+                    .unwrap_or_default(),
+                Field::Name => relative_path
+                    .file_name()
+                    .map(|name| config.strip_extension(&name.to_string_lossy()).to_string())
+                    .unwrap_or_default(),
+            };
+            for filter in filters {
+                value = match filter {
+                    Filter::Slug => slugify(&value),
+                    Filter::Lower => value.to_lowercase(),
+                };
+            }
+            if *field != Field::Notebook {
+                value = value.replace(['/', '\\'], &replacement);
+            }
+            rendered.push_str(&value);
+        }
+
+        // A title of `..` would lead out of the directory
+        let path: PathBuf = rendered
+            .split('/')
+            .filter(|component| !component.trim().is_empty())
+            .map(|component| match component.trim() {
+                "." | ".." => component.replace('.', &replacement),
+                _ => component.to_string(),
+            })
+            .collect();
+        match path.file_name() {
+            Some(_) if !rendered.ends_with('/') => path,
+            _ => joplin_file.relative_path.clone(),
+        }
+    }
+}
+
+fn slugify(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+/// The sanitized paths of the notes rendered from the template, numbered when the template
+/// gives several notes the same one, see `number_duplicates`
+pub fn render_unique(
+    template: &PathTemplate,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Vec<PathBuf> {
+    number_duplicates(joplin_files.iter().map(|joplin_file| {
+        sanitize_path(
+            &template.render(joplin_file, config),
+            config.filename_replacement,
+        )
+    }))
+}
+
+/// The paths with those that are the same as one before numbered `name 2.md`, `name 3.md` and
+/// so on, so no note is written over another. Case is ignored, as macOS does.
+pub fn number_duplicates(paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut taken = HashSet::new();
+    paths
+        .into_iter()
+        .map(|path| {
+            let mut candidates =
+                std::iter::once(path.clone()).chain((2..).map(|n| numbered(&path, n)));
+            candidates
+                .find(|candidate| taken.insert(candidate.to_string_lossy().to_lowercase()))
+                .expect("there is a free number")
+        })
+        .collect()
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    path.with_file_name(format!("{} {}{}", stem, n, extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(relative_path: &str, title: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: {title}\nid: 0123abcd\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let joplin_file = note("Work/Plans/Q1 plan.md", "Q1: Goals / Risks");
+        let config = Config {
+            timezone: chrono_tz::Asia::Tokyo,
+            ..Config::default()
+        };
+        let test_cases = vec![
+            (
+                "{{created:%Y}}/{{created:%m}}/{{title|slug}}.md",
+                "2024/03/q1-goals-risks.md",
+            ),
+            (
+                "{{ created }} {{title}}.md",
+                "2024-03-08 Q1: Goals _ Risks.md",
+            ),
+            (
+                "{{notebook|lower}}/{{name}} {{id}}.md",
+                "work/plans/Q1 plan 0123abcd.md",
+            ),
+            ("Notes/{{id}}/", "Work/Plans/Q1 plan.md"),
+        ];
+
+        for (template, expected) in test_cases {
+            let template: PathTemplate = template.parse().unwrap();
+            assert_eq!(
+                template.render(&joplin_file, &config),
+                PathBuf::from(expected),
+                "{:?}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_dot_titles() {
+        let template: PathTemplate = "{{title}}/{{name}}.md".parse().unwrap();
+        let test_cases = vec![("..", "__/a.md"), (".", "_/a.md"), ("...", ".../a.md")];
+
+        for (title, expected) in test_cases {
+            let joplin_file = note("a.md", title);
+            assert_eq!(
+                template.render(&joplin_file, &Config::default()),
+                PathBuf::from(expected),
+                "{}",
+                title
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let test_cases = vec![
+            ("{{title", "Unclosed placeholder"),
+            ("{{tile}}.md", "Unknown placeholder {{tile}}"),
+            ("{{title:%Y}}.md", "Only dates have a format"),
+            ("{{created:%Q}}.md", "Invalid date format"),
+            ("{{title|upper}}.md", "Unknown filter"),
+        ];
+
+        for (template, expected) in test_cases {
+            let e = template.parse::<PathTemplate>().unwrap_err();
+            assert!(e.starts_with(expected), "{}: {}", template, e);
+        }
+    }
+
+    #[test]
+    fn test_render_unique() {
+        let template: PathTemplate = "{{created:%Y}}/{{title}}.md".parse().unwrap();
+        let joplin_files = vec![
+            note("a.md", "Note"),
+            note("b.md", "note"),
+            note("c.md", "Other"),
+        ];
+
+        let paths = render_unique(&template, &joplin_files, &Config::default());
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("2024/Note.md"),
+                PathBuf::from("2024/note 2.md"),
+                PathBuf::from("2024/Other.md"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::manifest::MANIFEST_FILE_NAME;
+use crate::state::STATE_FILE_NAME;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file written to the target and removed again to tell whether it's writable
+const PROBE_FILE_NAME: &str = ".jb_preflight";
+
+/// Checks a target directory before anything is converted into it: it doesn't overlap the
+/// source, see `check_overlap`, it exists or can be created, it's writable and it's empty
+/// unless `allow_nonempty`. A target holding an earlier conversion, with its manifest or state,
+/// counts as empty, so converting again updates it.
+pub fn check_target<P: AsRef<Path>, Q: AsRef<Path>>(
+    source_dir: P,
+    target_dir: Q,
+    allow_nonempty: bool,
+) -> Result<(), String> {
+    let target_dir = target_dir.as_ref();
+    if target_dir.exists() && !target_dir.is_dir() {
+        return Err(format!(
+            "The target {:?} is a file, give a directory to convert into",
+            target_dir
+        ));
+    }
+    check_overlap(source_dir.as_ref(), target_dir)?;
+    fs::create_dir_all(target_dir).map_err(|e| {
+        format!(
+            "The target {:?} can't be created: {}. Check that its parent is writable",
+            target_dir, e
+        )
+    })?;
+
+    let probe = target_dir.join(PROBE_FILE_NAME);
+    fs::write(&probe, "")
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| {
+            format!(
+                "The target {:?} isn't writable: {}. Check its permissions, or give another one",
+                target_dir, e
+            )
+        })?;
+
+    if !allow_nonempty && !is_empty_or_converted(target_dir)? {
+        return Err(format!(
+            "The target {:?} isn't empty. Give an empty directory, or pass --allow-nonempty to \
+            convert into it anyway",
+            target_dir
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that the source and the target don't overlap once canonical: the discovery would read
+/// the notes written into a target inside the source, and the notes written into a target
+/// holding the source could land on the notes read from it. The target may not exist yet. A
+/// zip source can't overlap
+pub fn check_overlap(source_dir: &Path, target_dir: &Path) -> Result<(), String> {
+    let (Ok(source), Ok(target)) = (source_dir.canonicalize(), canonicalize_new(target_dir)) else {
+        return Ok(());
+    };
+    if !source.is_dir() {
+        return Ok(());
+    }
+
+    if source == target {
+        Err(format!(
+            "The target {:?} is the source, give another directory to convert into",
+            target_dir
+        ))
+    } else if target.starts_with(&source) {
+        Err(format!(
+            "The target {:?} is inside the source {:?}, the next conversion would read the notes \
+            written into it. Give a target outside the source",
+            target_dir, source_dir
+        ))
+    } else if source.starts_with(&target) {
+        Err(format!(
+            "The source {:?} is inside the target {:?}, the notes written could land on the \
+            notes read. Give a target outside the source",
+            source_dir, target_dir
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// The canonical path of a path that may not exist yet: that of its closest existing ancestor,
+/// joined with the rest
+fn canonicalize_new(path: &Path) -> std::io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let mut existing = path.as_path();
+    let mut rest = Vec::new();
+    while !existing.exists() {
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+            break;
+        };
+        rest.push(name);
+        existing = parent;
+    }
+
+    let mut canonical = existing.canonicalize()?;
+    canonical.extend(rest.iter().rev());
+    Ok(canonical)
+}
+
+/// Whether the directory is empty or holds the manifest or state of an earlier conversion
+fn is_empty_or_converted(dir: &Path) -> Result<bool, String> {
+    let mut entries =
+        fs::read_dir(dir).map_err(|e| format!("Error reading directory {:?}: {}", dir, e))?;
+    let converted = [MANIFEST_FILE_NAME, STATE_FILE_NAME]
+        .iter()
+        .any(|file_name| dir.join(file_name).is_file());
+
+    Ok(converted || entries.next().is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_target() {
+        let temp_dir = std::env::temp_dir().join("jb_preflight_test");
+        let source_dir = temp_dir.join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        for dir in ["nonempty", "converted"] {
+            fs::create_dir_all(temp_dir.join(dir)).unwrap();
+            fs::write(temp_dir.join(dir).join("note.md"), "").unwrap();
+        }
+        fs::write(temp_dir.join("converted").join(MANIFEST_FILE_NAME), "").unwrap();
+        fs::write(temp_dir.join("file"), "").unwrap();
+        let test_cases: Vec<(&str, bool, Option<&str>)> = vec![
+            ("new/target", false, None),
+            ("converted", false, None),
+            ("nonempty", true, None),
+            ("nonempty", false, Some("isn't empty")),
+            ("source/target", true, Some("is inside the source")),
+            ("source/new/target", true, Some("is inside the source")),
+            ("source", true, Some("is the source")),
+            ("", true, Some("is inside the target")),
+            ("file", true, Some("is a file")),
+        ];
+
+        for (target, allow_nonempty, expected) in test_cases {
+            let result = check_target(&source_dir, temp_dir.join(target), allow_nonempty);
+
+            match expected {
+                None => assert_eq!(result, Ok(()), "{}", target),
+                Some(e) => assert!(result.unwrap_err().contains(e), "{}", target),
+            }
+        }
+        // The targets rejected inside the source aren't created there
+        assert_eq!(fs::read_dir(&source_dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use crate::config::Punctuation;
+use crate::html_export::markdown_options;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+/// Characters after which a quote opens rather than closes
+const OPENING_CONTEXT: [char; 8] = ['(', '[', '{', '-', '—', '–', '“', '‘'];
+
+/// The body with its quotes, dashes and ellipses made typographic or straight, `None` when
+/// there's nothing to change. Only the text is changed: code, link destinations, html and the
+/// text with escapes are left as they are.
+pub fn convert_punctuation(body: &str, punctuation: Punctuation) -> Option<String> {
+    match punctuation {
+        Punctuation::Keep => None,
+        Punctuation::Smart => map_text(body, |start, text, converted| {
+            smarten(body, start, text, converted)
+        }),
+        Punctuation::Straight => map_text(body, |_, text, converted| straighten(text, converted)),
+    }
+}
+
+/// The body with each stretch of its text written by `write`, which gets where the text starts
+/// in the body, the text, and the body converted so far to push to. Code, link destinations,
+/// html and escaped characters are copied as they are. `None` when nothing changed.
+pub(crate) fn map_text(
+    body: &str,
+    mut write: impl FnMut(usize, &str, &mut String),
+) -> Option<String> {
+    let mut converted = String::with_capacity(body.len());
+    let mut copied = 0;
+    let mut in_code_block = false;
+    for (event, range) in Parser::new_ext(body, markdown_options()).into_offset_iter() {
+        let text = match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                continue;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                continue;
+            }
+            Event::Text(text) if !in_code_block => text,
+            _ => continue,
+        };
+        // Escaped characters and entities are left alone, the writer meant them as they are
+        if body[range.clone()] != *text || body[..range.start].ends_with('\\') {
+            continue;
+        }
+
+        converted.push_str(&body[copied..range.start]);
+        write(range.start, &text, &mut converted);
+        copied = range.end;
+    }
+    converted.push_str(&body[copied..]);
+
+    (converted != body).then_some(converted)
+}
+
+/// Writes the text at `start` in the body with curly quotes, em dashes and ellipses. Whether a
+/// quote opens or closes depends on the characters around it in the body.
+fn smarten(body: &str, start: usize, text: &str, converted: &mut String) {
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let position = start + text.len() - rest.len();
+        // Emphasis markers don't count, `*"quoted"*` opens with its quote
+        let before = body[..position]
+            .chars()
+            .rev()
+            .find(|before| !matches!(before, '*' | '_' | '~'));
+        let opens =
+            before.is_none_or(|before| before.is_whitespace() || OPENING_CONTEXT.contains(&before));
+
+        let (replacement, len) = match c {
+            '"' if opens => ("“", 1),
+            '"' => ("”", 1),
+            '\'' if opens => ("‘", 1),
+            // Apostrophes are closing quotes too
+            '\'' => ("’", 1),
+            '-' if rest.starts_with("---") => ("—", 3),
+            '-' if rest.starts_with("--") => ("—", 2),
+            '.' if rest.starts_with("...") => ("…", 3),
+            _ => {
+                converted.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+        converted.push_str(replacement);
+        rest = &rest[len..];
+    }
+}
+
+fn straighten(text: &str, converted: &mut String) {
+    for c in text.chars() {
+        match c {
+            '“' | '”' | '„' | '″' => converted.push('"'),
+            '‘' | '’' | '‚' | '′' => converted.push('\''),
+            '—' => converted.push_str("--"),
+            '–' => converted.push('-'),
+            '…' => converted.push_str("..."),
+            c => converted.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_punctuation() {
+        let test_cases = vec![
+            (
+                "\"Quoted\" and 'single' -- it's *\"emphasised\"*...",
+                Punctuation::Smart,
+                Some("“Quoted” and ‘single’ — it’s *“emphasised”*…"),
+            ),
+            (
+                "Keep `\"code\"` and [a \"link\"](https://example.com/?q=\"x\") \\\"escaped\\\"",
+                Punctuation::Smart,
+                Some(
+                    "Keep `\"code\"` and [a “link”](https://example.com/?q=\"x\") \\\"escaped\\\"",
+                ),
+            ),
+            ("```\n\"code\"\n```", Punctuation::Smart, None),
+            (
+                "“Quoted” and ‘single’ — it’s… – „low“",
+                Punctuation::Straight,
+                Some("\"Quoted\" and 'single' -- it's... - \"low\""),
+            ),
+            ("\"Quoted\"", Punctuation::Keep, None),
+        ];
+
+        for (body, punctuation, expected) in test_cases {
+            assert_eq!(
+                convert_punctuation(body, punctuation).as_deref(),
+                expected,
+                "{:?}",
+                body
+            );
+        }
+    }
+}
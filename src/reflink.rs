@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Copies a file as a copy-on-write clone when the filesystem can, which takes the same time
+/// whatever the size of the file, and falls back to a plain copy otherwise: across volumes, or
+/// on filesystems without clones. On Linux that's btrfs and XFS through `FICLONE`; on macOS
+/// `std::fs::copy` already clones on APFS with `fclonefileat`. The copy keeps the modification
+/// time of the source, so a later run can tell it's up to date. A sparse file that can't be
+/// cloned keeps its holes on Linux, rather than having them written out as zeros. Sockets, FIFOs
+/// and device nodes aren't copied, see `special_file`.
+pub fn copy(source: &Path, target: &Path) -> io::Result<()> {
+    if let Some(kind) = special_file(source) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is a {kind}, not a file to copy", source),
+        ));
+    }
+    unlink_target(source, target)?;
+    #[cfg(target_os = "linux")]
+    let copied = clone(source, target).is_ok() || copy_sparse(source, target)?;
+    #[cfg(not(target_os = "linux"))]
+    let copied = false;
+
+    if !copied {
+        std::fs::copy(source, target)?;
+    }
+    let modified = std::fs::metadata(source)?.modified()?;
+    File::options()
+        .write(true)
+        .open(target)?
+        .set_modified(modified)
+}
+
+#[cfg(target_os = "linux")]
+fn clone(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source_file = File::open(source)?;
+    let target_file = File::create(target)?;
+    // SAFETY: both file descriptors stay open until the files are dropped, after the call
+    let result = unsafe {
+        libc::ioctl(
+            target_file.as_raw_fd(),
+            libc::FICLONE,
+            source_file.as_raw_fd(),
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    target_file.set_permissions(source_file.metadata()?.permissions())
+}
+
+/// Removes the target when writing to it would write to something else: a symbolic link, left
+/// by an earlier run linking the resources, or a hard link to the source. Either way the copy
+/// would land on the source, truncating it first
+pub fn unlink_target(source: &Path, target: &Path) -> io::Result<()> {
+    let target_metadata = match std::fs::symlink_metadata(target) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    #[cfg(unix)]
+    let is_source = {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(source).is_ok_and(|source_metadata| {
+            (source_metadata.dev(), source_metadata.ino())
+                == (target_metadata.dev(), target_metadata.ino())
+        })
+    };
+    #[cfg(not(unix))]
+    let is_source = false;
+
+    if target_metadata.is_file() && !is_source {
+        return Ok(());
+    }
+    std::fs::remove_file(target)
+}
+
+/// What the path is when it's a socket, a FIFO or a device node, which have no content to copy:
+/// reading a FIFO waits for a writer, and a device may never end
+#[cfg(unix)]
+pub fn special_file(path: &Path) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = std::fs::metadata(path).ok()?.file_type();
+    if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_fifo() {
+        Some("FIFO")
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        Some("device node")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+pub fn special_file(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+/// Copies a sparse file a run of data at a time, leaving the holes between them as holes.
+/// Returns false, having copied nothing, when the file isn't sparse or its filesystem can't
+/// tell where the holes are; the target may be created already then, for the copy to replace
+#[cfg(target_os = "linux")]
+fn copy_sparse(source: &Path, target: &Path) -> io::Result<bool> {
+    use std::io::{Read, Seek, SeekFrom};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut source_file = File::open(source)?;
+    let metadata = source_file.metadata()?;
+    let len = metadata.len();
+    if metadata.blocks() * 512 >= len {
+        return Ok(false);
+    }
+
+    let mut target_file = File::create(target)?;
+    target_file.set_len(len)?;
+    let fd = source_file.as_raw_fd();
+    let seek = |offset: u64, whence: libc::c_int| {
+        // SAFETY: the file descriptor stays open until the file is dropped, after the call
+        let result = unsafe { libc::lseek(fd, offset as libc::off_t, whence) };
+        match result {
+            -1 => Err(io::Error::last_os_error()),
+            offset => Ok(offset as u64),
+        }
+    };
+    let mut offset = 0;
+    while offset < len {
+        let data = match seek(offset, libc::SEEK_DATA) {
+            // There's no data past the offset, the rest is a hole
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => break,
+            // No SEEK_DATA on this filesystem, a plain copy does
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) && offset == 0 => return Ok(false),
+            result => result?,
+        };
+        let hole = seek(data, libc::SEEK_HOLE)?;
+        source_file.seek(SeekFrom::Start(data))?;
+        target_file.seek(SeekFrom::Start(data))?;
+        io::copy(&mut (&source_file).take(hole - data), &mut target_file)?;
+        offset = hole;
+    }
+
+    target_file.set_permissions(metadata.permissions())?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_copy() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_reflink_test");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.png");
+        let target = dir.join("b.png");
+        fs::write(&source, "png").unwrap();
+        fs::write(&target, "an older and longer png").unwrap();
+
+        // act
+        let result = copy(&source, &target);
+
+        // assert
+        let content = fs::read_to_string(&target).unwrap();
+        let modified = (
+            fs::metadata(&source).unwrap().modified().unwrap(),
+            fs::metadata(&target).unwrap().modified().unwrap(),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(content, "png");
+        assert_eq!(modified.0, modified.1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_copy_sparse() {
+        use std::io::{Seek, SeekFrom};
+        use std::os::unix::fs::MetadataExt;
+
+        // arrange
+        let dir = std::env::temp_dir().join("jb_reflink_sparse_test");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.bin");
+        let target = dir.join("b.bin");
+        let mut file = File::create(&source).unwrap();
+        file.set_len(4 * 1024 * 1024).unwrap();
+        file.seek(SeekFrom::Start(1024 * 1024)).unwrap();
+        io::Write::write_all(&mut file, b"data").unwrap();
+        drop(file);
+
+        // act
+        let result = copy(&source, &target);
+
+        // assert
+        let contents = (fs::read(&source).unwrap(), fs::read(&target).unwrap());
+        let allocated = fs::metadata(&target).unwrap().blocks() * 512;
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_ok());
+        assert!(contents.0 == contents.1);
+        // A plain copy would write the holes out as zeros
+        assert!(allocated < 1024 * 1024, "{} bytes allocated", allocated);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_special_file() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_reflink_special_test");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&source).unwrap();
+
+        // act
+        let result = copy(&source, &dir.join("b.sock"));
+
+        // assert
+        let copied = dir.join("b.sock").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.unwrap_err().to_string().contains("is a socket"));
+        assert!(!copied);
+    }
+}
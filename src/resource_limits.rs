@@ -0,0 +1,58 @@
+use crate::Config;
+use std::collections::HashSet;
+
+/// The indexes of the resources over the limits, given the size of each resource in path
+/// order: those bigger than `max_resource_size`, then those beyond the first `max_resources` of
+/// the others, so a single huge file doesn't push the rest out
+pub fn over_limits(sizes: &[u64], config: &Config) -> HashSet<usize> {
+    let mut over: HashSet<usize> = sizes
+        .iter()
+        .enumerate()
+        .filter(|(_, size)| config.max_resource_size.is_some_and(|max| **size > max))
+        .map(|(index, _)| index)
+        .collect();
+
+    if let Some(max_resources) = config.max_resources {
+        let beyond: Vec<usize> = (0..sizes.len())
+            .filter(|index| !over.contains(index))
+            .skip(max_resources)
+            .collect();
+        over.extend(beyond);
+    }
+
+    over
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_over_limits() {
+        let sizes = [10, 5_000, 20, 30, 40];
+        let test_cases: Vec<(Option<u64>, Option<usize>, Vec<usize>)> = vec![
+            (None, None, vec![]),
+            (Some(1_000), None, vec![1]),
+            (None, Some(3), vec![3, 4]),
+            (Some(1_000), Some(3), vec![1, 4]),
+            (Some(1_000), Some(0), vec![0, 1, 2, 3, 4]),
+        ];
+
+        for (max_resource_size, max_resources, expected) in test_cases {
+            let config = Config {
+                max_resource_size,
+                max_resources,
+                ..Config::default()
+            };
+
+            let mut over: Vec<usize> = over_limits(&sizes, &config).into_iter().collect();
+            over.sort();
+
+            assert_eq!(
+                over, expected,
+                "{:?} {:?}",
+                max_resource_size, max_resources
+            );
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use std::ops::Range;
+use std::path::Path;
+
+const RESOURCES_DIR: &str = "_resources";
+const JOPLIN_ID_PREFIX: &str = ":/";
+const JOPLIN_ID_LEN: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceTarget {
+    /// A Joplin internal reference, e.g. `:/0123456789abcdef0123456789abcdef`
+    Id(String),
+    /// A path into the resources directory, e.g. `../_resources/image.png`
+    Path(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// `![alt](target)` or `<img src="target">`
+    Image,
+    /// `[text](target)`
+    Link,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceRef {
+    pub target: ResourceTarget,
+    pub kind: ResourceKind,
+    /// Byte range of the target inside the note body, so it can be replaced in place
+    pub span: Range<usize>,
+    /// Byte range of the whole link inside the note body, `![alt](target)` or the `<img>` tag
+    pub link: Range<usize>,
+}
+
+impl ResourceRef {
+    /// The target as it is written in the note body
+    pub fn raw(&self) -> &str {
+        match &self.target {
+            ResourceTarget::Id(id) => id,
+            ResourceTarget::Path(path) => path,
+        }
+    }
+
+    /// The path of a path target relative to the resources directory, as written in the body
+    pub fn resource_path(&self) -> Option<&str> {
+        let ResourceTarget::Path(path) = &self.target else {
+            return None;
+        };
+
+        ["/", "\\"]
+            .iter()
+            .find_map(|separator| path.split_once(&format!("{RESOURCES_DIR}{separator}")))
+            .map(|(_, resource_path)| resource_path)
+    }
+
+    /// The file name of a path target, or the id of an id target
+    pub fn file_name(&self) -> &str {
+        match &self.target {
+            ResourceTarget::Id(id) => id.trim_start_matches(JOPLIN_ID_PREFIX),
+            ResourceTarget::Path(path) => path.rsplit(['/', '\\']).next().unwrap_or(path),
+        }
+    }
+}
+
+/// Finds every reference to a resource in a markdown body.
+///
+/// Markdown links and images are recognised, as well as html `<img>` tags. References inside
+/// fenced code blocks and inline code spans are ignored.
+pub fn find_resource_refs(body: &str) -> Vec<ResourceRef> {
+    find_links(body)
+        .into_iter()
+        .filter_map(|link| {
+            Some(ResourceRef {
+                target: parse_target(&body[link.span.clone()])?,
+                kind: link.kind,
+                span: link.span,
+                link: link.link,
+            })
+        })
+        .collect()
+}
+
+/// A link or an image in a markdown body, whatever its destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Link {
+    pub kind: ResourceKind,
+    /// Byte range of the destination inside the body
+    pub span: Range<usize>,
+    /// Byte range of the whole link inside the body
+    pub link: Range<usize>,
+}
+
+/// Finds every link and image in a markdown body, the same way as `find_resource_refs`
+pub(crate) fn find_links(body: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    let mut in_fence: Option<&str> = None;
+    let mut line_start = 0;
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let fence = ["```", "~~~"]
+            .into_iter()
+            .find(|fence| trimmed.starts_with(fence));
+
+        match (in_fence, fence) {
+            (None, Some(fence)) => in_fence = Some(fence),
+            (Some(open), Some(fence)) if open == fence => in_fence = None,
+            (None, None) => find_links_in_line(line, line_start, &mut links),
+            _ => {}
+        }
+
+        line_start += line.len();
+    }
+
+    links
+}
+
+fn find_links_in_line(line: &str, offset: usize, links: &mut Vec<Link>) {
+    let code_spans = find_code_spans(line);
+    let in_code = |pos: usize| code_spans.iter().any(|span| span.contains(&pos));
+
+    let mut search_from = 0;
+    while let Some(found) = line[search_from..].find("](") {
+        let close_bracket = search_from + found;
+        search_from = close_bracket + 2;
+
+        if in_code(close_bracket) {
+            continue;
+        }
+
+        let Some(open_bracket) = find_matching_open_bracket(line, close_bracket) else {
+            continue;
+        };
+        let Some(dest) = find_link_destination(line, close_bracket + 2) else {
+            continue;
+        };
+
+        let (kind, link_start) = if open_bracket > 0 && line.as_bytes()[open_bracket - 1] == b'!' {
+            (ResourceKind::Image, open_bracket - 1)
+        } else {
+            (ResourceKind::Link, open_bracket)
+        };
+        let link_end = line[dest.end..]
+            .find(')')
+            .map_or(line.len(), |close| dest.end + close + 1);
+
+        links.push(Link {
+            kind,
+            span: offset + dest.start..offset + dest.end,
+            link: offset + link_start..offset + link_end,
+        });
+    }
+
+    find_img_tags(line, offset, &in_code, links);
+}
+
+fn find_code_spans(line: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut search_from = 0;
+    while let Some(start) = line[search_from..].find('`') {
+        let start = search_from + start;
+        let ticks = line[start..].len() - line[start..].trim_start_matches('`').len();
+        let fence = &line[start..start + ticks];
+
+        match line[start + ticks..].find(fence) {
+            Some(end) => {
+                let end = start + ticks + end + ticks;
+                spans.push(start..end);
+                search_from = end;
+            }
+            None => break,
+        }
+    }
+
+    spans
+}
+
+fn find_matching_open_bracket(line: &str, close_bracket: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in line[..close_bracket].char_indices().rev() {
+        match c {
+            ']' => depth += 1,
+            '[' if depth == 0 => return Some(i),
+            '[' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Returns the range of the destination of a link, excluding any angle brackets and title
+fn find_link_destination(line: &str, start: usize) -> Option<Range<usize>> {
+    let rest = &line[start..];
+    let leading = rest.len() - rest.trim_start().len();
+    let start = start + leading;
+    let rest = &line[start..];
+
+    if let Some(inner) = rest.strip_prefix('<') {
+        let end = inner.find('>')?;
+        return Some(start + 1..start + 1 + end);
+    }
+
+    let mut depth = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(start..start + i),
+            ')' => depth -= 1,
+            c if c.is_whitespace() => {
+                // Anything after whitespace is the link title
+                return rest[i..].contains(')').then_some(start..start + i);
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn find_img_tags(
+    line: &str,
+    offset: usize,
+    in_code: &impl Fn(usize) -> bool,
+    links: &mut Vec<Link>,
+) {
+    const SRC_ATTR: &str = "src=";
+
+    let mut search_from = 0;
+    while let Some(found) = line[search_from..].find("<img") {
+        let tag_start = search_from + found;
+        let Some(tag_len) = line[tag_start..].find('>') else {
+            break;
+        };
+        let tag_end = tag_start + tag_len;
+        search_from = tag_end;
+
+        if in_code(tag_start) {
+            continue;
+        }
+
+        let tag = &line[tag_start..tag_end];
+        let Some(src) = tag.find(SRC_ATTR) else {
+            continue;
+        };
+        let value_start = src + SRC_ATTR.len();
+        let Some(quote) = tag[value_start..].chars().next() else {
+            continue;
+        };
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        let Some(value_len) = tag[value_start + 1..].find(quote) else {
+            continue;
+        };
+
+        let start = tag_start + value_start + 1;
+        let end = start + value_len;
+        links.push(Link {
+            kind: ResourceKind::Image,
+            span: offset + start..offset + end,
+            link: offset + tag_start..offset + tag_end + 1,
+        });
+    }
+}
+
+fn parse_target(dest: &str) -> Option<ResourceTarget> {
+    if let Some(id) = dest.strip_prefix(JOPLIN_ID_PREFIX) {
+        let is_id = id.len() == JOPLIN_ID_LEN && id.chars().all(|c| c.is_ascii_hexdigit());
+        return is_id.then(|| ResourceTarget::Id(dest.to_string()));
+    }
+
+    let is_resource = dest
+        .split(['/', '\\'])
+        .any(|component| component == RESOURCES_DIR);
+    is_resource.then(|| ResourceTarget::Path(dest.to_string()))
+}
+
+/// The MIME type of a resource, by its extension
+pub(crate) fn mime_type(resource_path: &str) -> &'static str {
+    let extension = Path::new(resource_path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decodes `%XX` escapes in a link target, leaving invalid escapes as they are
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = ":/0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn find_resource_refs_targets() {
+        let test_cases: Vec<(String, Vec<(ResourceTarget, ResourceKind)>)> = vec![
+            ("no links".to_string(), vec![]),
+            ("[web](https://example.com)".to_string(), vec![]),
+            (
+                "![](../_resources/a.png)".to_string(),
+                vec![(
+                    ResourceTarget::Path("../_resources/a.png".to_string()),
+                    ResourceKind::Image,
+                )],
+            ),
+            (
+                "[a pdf](../_resources/a.pdf \"title\")".to_string(),
+                vec![(
+                    ResourceTarget::Path("../_resources/a.pdf".to_string()),
+                    ResourceKind::Link,
+                )],
+            ),
+            (
+                "[a pdf](<../_resources/a b.pdf>)".to_string(),
+                vec![(
+                    ResourceTarget::Path("../_resources/a b.pdf".to_string()),
+                    ResourceKind::Link,
+                )],
+            ),
+            (
+                format!("see [note]({ID}) and ![]({ID})"),
+                vec![
+                    (ResourceTarget::Id(ID.to_string()), ResourceKind::Link),
+                    (ResourceTarget::Id(ID.to_string()), ResourceKind::Image),
+                ],
+            ),
+            ("[not an id](:/1234)".to_string(), vec![]),
+            (
+                "[![alt](../_resources/a.png)](../_resources/b.png)".to_string(),
+                vec![
+                    (
+                        ResourceTarget::Path("../_resources/a.png".to_string()),
+                        ResourceKind::Image,
+                    ),
+                    (
+                        ResourceTarget::Path("../_resources/b.png".to_string()),
+                        ResourceKind::Link,
+                    ),
+                ],
+            ),
+            (
+                "<img src=\"../_resources/a.png\" width=\"100\">".to_string(),
+                vec![(
+                    ResourceTarget::Path("../_resources/a.png".to_string()),
+                    ResourceKind::Image,
+                )],
+            ),
+            ("`![](../_resources/a.png)`".to_string(), vec![]),
+            ("```\n![](../_resources/a.png)\n```\n".to_string(), vec![]),
+        ];
+
+        for (body, expected) in test_cases {
+            let result: Vec<(ResourceTarget, ResourceKind)> = find_resource_refs(&body)
+                .into_iter()
+                .map(|r| (r.target, r.kind))
+                .collect();
+            assert_eq!(result, expected, "body: {body}");
+        }
+    }
+
+    #[test]
+    fn find_resource_refs_spans() {
+        let body = "line one\n\nand ![x](../_resources/a.png) and [y](<../_resources/b c.pdf>)\n";
+
+        let refs = find_resource_refs(body);
+
+        assert_eq!(refs.len(), 2);
+        for resource_ref in refs {
+            assert_eq!(&body[resource_ref.span.clone()], resource_ref.raw());
+        }
+    }
+
+    #[test]
+    fn find_resource_refs_links() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            (
+                "see ![x](../_resources/a.png) here",
+                "![x](../_resources/a.png)",
+            ),
+            (
+                "see [x](<../_resources/a b.pdf> \"title\") here",
+                "[x](<../_resources/a b.pdf> \"title\")",
+            ),
+            (
+                "see <img src=\"../_resources/a.png\"> here",
+                "<img src=\"../_resources/a.png\">",
+            ),
+        ];
+
+        for (body, expected) in test_cases {
+            let refs = find_resource_refs(body);
+            assert_eq!(&body[refs[0].link.clone()], expected);
+        }
+    }
+
+    #[test]
+    fn resource_path() {
+        let test_cases: Vec<(&str, Option<&str>)> = vec![
+            ("![](../_resources/a.png)", Some("a.png")),
+            ("![](../../_resources/sub/a%20b.png)", Some("sub/a%20b.png")),
+            ("![](..\\_resources\\a.png)", Some("a.png")),
+            ("![](:/0123456789abcdef0123456789abcdef)", None),
+        ];
+
+        for (body, expected) in test_cases {
+            let refs = find_resource_refs(body);
+            assert_eq!(refs[0].resource_path(), expected);
+        }
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("a.png", "a.png"),
+            ("a%20b.png", "a b.png"),
+            ("%C3%A5.png", "å.png"),
+            ("100%.png", "100%.png"),
+            ("%zz.png", "%zz.png"),
+        ];
+
+        for (value, expected) in test_cases {
+            assert_eq!(percent_decode(value), expected);
+        }
+    }
+
+    #[test]
+    fn file_name() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("![](../_resources/a.png)", "a.png"),
+            ("![](..\\_resources\\a.png)", "a.png"),
+            (
+                "![](:/0123456789abcdef0123456789abcdef)",
+                "0123456789abcdef0123456789abcdef",
+            ),
+        ];
+
+        for (body, expected) in test_cases {
+            let refs = find_resource_refs(body);
+            assert_eq!(refs[0].file_name(), expected);
+        }
+    }
+}
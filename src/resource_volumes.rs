@@ -0,0 +1,301 @@
+use crate::JoplinFile;
+use crate::reflink;
+use crate::resource_ref::percent_decode;
+use std::collections::BTreeMap;
+use std::fs::{self, create_dir_all};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+const RESOURCES_DIR: &str = "_resources";
+
+/// Where each resource ended up when spilling resources across several volumes
+#[derive(Debug, Default, PartialEq)]
+pub struct ResourcePlacement {
+    volumes: Vec<PathBuf>,
+    /// Path relative to the resources directory -> index into `volumes`
+    placed: BTreeMap<PathBuf, usize>,
+}
+
+impl ResourcePlacement {
+    /// The full path of a resource, given its path relative to the resources directory
+    pub fn path_of<P: AsRef<Path>>(&self, resource_path: P) -> Option<PathBuf> {
+        let resource_path = resource_path.as_ref();
+        self.placed
+            .get(resource_path)
+            .map(|&volume| self.volumes[volume].join(RESOURCES_DIR).join(resource_path))
+    }
+
+    /// The number of resources placed on each volume
+    pub fn counts(&self) -> Vec<(&Path, usize)> {
+        self.volumes
+            .iter()
+            .enumerate()
+            .map(|(i, volume)| {
+                let count = self.placed.values().filter(|&&v| v == i).count();
+                (volume.as_path(), count)
+            })
+            .collect()
+    }
+
+    /// Points the resource links of a note at the volume each resource was placed on
+    pub fn rewrite_links<P: AsRef<Path>>(&self, joplin_file: &mut JoplinFile, target_dir: P) {
+        let note_dir = absolute(target_dir.as_ref().join(&joplin_file.relative_path))
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let mut body = std::mem::take(&mut joplin_file.body);
+        let mut refs = joplin_file.referenced_resources().to_vec();
+        refs.sort_by_key(|resource_ref| std::cmp::Reverse(resource_ref.span.start));
+
+        for resource_ref in refs {
+            let Some(written) = resource_ref.resource_path() else {
+                continue;
+            };
+            let Some(&volume) = self.placed.get(Path::new(&percent_decode(written))) else {
+                continue;
+            };
+
+            let resources_dir = self.volumes[volume].join(RESOURCES_DIR);
+            let link = format!("{}/{}", relative_link(&note_dir, &resources_dir), written);
+            body.replace_range(resource_ref.span, &link);
+        }
+        joplin_file.set_body(body);
+    }
+}
+
+/// Copies the resources into the `_resources` directory of the first volume, moving on to the
+/// next volume whenever one is full.
+///
+/// Resources already present on one of the volumes with the same size are not copied again, so
+/// an interrupted copy can be resumed by running it again.
+pub fn copy_resources_to_volumes<P: AsRef<Path>>(
+    source_dir: P,
+    volumes: &[PathBuf],
+) -> Result<ResourcePlacement, String> {
+    if volumes.is_empty() {
+        return Err("No resource volumes given".to_string());
+    }
+
+    let source_resources_dir = source_dir.as_ref().join(RESOURCES_DIR);
+    // `check_resources_dir` tells about a missing resources directory
+    if source_resources_dir.exists() && !source_resources_dir.is_dir() {
+        return Err(format!(
+            "The source path: {:?} is not a directory",
+            source_resources_dir
+        ));
+    }
+
+    let mut files = Vec::new();
+    if source_resources_dir.exists() {
+        find_resource_files(&source_resources_dir, &mut files)
+            .map_err(|e| format!("Error finding resources: {}", e))?;
+    }
+    files.sort();
+
+    let mut placement = ResourcePlacement {
+        volumes: volumes.iter().map(absolute).collect(),
+        placed: BTreeMap::new(),
+    };
+
+    let mut volume = 0;
+    for source in files {
+        let resource_path = source
+            .strip_prefix(&source_resources_dir)
+            .map_err(|e| format!("Error finding resource path: {}", e))?
+            .to_path_buf();
+        let size = fs::metadata(&source)
+            .map_err(|e| format!("Error reading {:?}: {}", source, e))?
+            .len();
+
+        if let Some(existing) = find_existing(&placement.volumes, &resource_path, size) {
+            placement.placed.insert(resource_path, existing);
+            continue;
+        }
+
+        loop {
+            let Some(volume_dir) = placement.volumes.get(volume) else {
+                return Err(format!(
+                    "All resource volumes are full, could not copy {:?}",
+                    resource_path
+                ));
+            };
+            let target = volume_dir.join(RESOURCES_DIR).join(&resource_path);
+
+            match copy_file(&source, &target) {
+                Ok(()) => {
+                    placement.placed.insert(resource_path, volume);
+                    break;
+                }
+                Err(e) if e.kind() == ErrorKind::StorageFull => {
+                    // Don't leave a partial copy behind on the full volume
+                    let _ = fs::remove_file(&target);
+                    volume += 1;
+                }
+                Err(e) => return Err(format!("Error copying {:?}: {}", source, e)),
+            }
+        }
+    }
+
+    Ok(placement)
+}
+
+fn find_resource_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_resource_files(&path, files)?;
+        } else if let Some(kind) = reflink::special_file(&path) {
+            crate::warning!("{:?} is a {kind}, it's not copied", path);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn find_existing(volumes: &[PathBuf], resource_path: &Path, size: u64) -> Option<usize> {
+    volumes.iter().position(|volume| {
+        fs::metadata(volume.join(RESOURCES_DIR).join(resource_path))
+            .is_ok_and(|metadata| metadata.len() == size)
+    })
+}
+
+fn copy_file(source: &Path, target: &Path) -> std::io::Result<()> {
+    if let Some(parent) = target.parent() {
+        create_dir_all(parent)?;
+    }
+
+    reflink::copy(source, target)
+}
+
+fn absolute<P: AsRef<Path>>(path: P) -> PathBuf {
+    std::path::absolute(path.as_ref()).unwrap_or_else(|_| path.as_ref().to_path_buf())
+}
+
+/// A relative link from one absolute directory to another, or the absolute path of `to` when
+/// they don't share a root
+fn relative_link(from: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+
+    if from.first() != to.first() {
+        return to.iter().collect::<PathBuf>().to_string_lossy().to_string();
+    }
+
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let parents = std::iter::repeat_n("..".to_string(), from.len() - common);
+    let children = to[common..]
+        .iter()
+        .map(|component| component.as_os_str().to_string_lossy().to_string());
+
+    let link: Vec<String> = parents.chain(children).collect();
+    if link.is_empty() {
+        ".".to_string()
+    } else {
+        link.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_relative_link() {
+        let test_cases: Vec<(&str, &str, &str)> = vec![
+            ("/a/b", "/a/b", "."),
+            ("/a/b", "/a/b/c", "c"),
+            ("/a/b/c", "/a/d", "../../d"),
+            (
+                "/target/notes",
+                "/volumes/one/_resources",
+                "../../volumes/one/_resources",
+            ),
+        ];
+
+        for (from, to, expected) in test_cases {
+            assert_eq!(relative_link(Path::new(from), Path::new(to)), expected);
+        }
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        // arrange
+        let placement = ResourcePlacement {
+            volumes: vec![PathBuf::from("/volumes/one"), PathBuf::from("/volumes/two")],
+            placed: BTreeMap::from([
+                (PathBuf::from("a.png"), 0),
+                (PathBuf::from("sub/b c.png"), 1),
+            ]),
+        };
+        let mut joplin_file = JoplinFile::build(
+            "notebook/note.md",
+            "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n\
+            ![](../_resources/a.png) [b](../_resources/sub/b%20c.png) ![](../_resources/c.png)",
+            &Config::default(),
+        )
+        .unwrap();
+
+        // act
+        placement.rewrite_links(&mut joplin_file, "/target");
+
+        // assert
+        assert_eq!(
+            joplin_file.body,
+            "![](../../volumes/one/_resources/a.png) \
+            [b](../../volumes/two/_resources/sub/b%20c.png) ![](../_resources/c.png)"
+        );
+    }
+
+    #[test]
+    fn test_copy_resources_to_volumes_resumes() {
+        // arrange
+        let temp_dir = std::env::temp_dir().join("jb_resource_volumes_test");
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir).unwrap();
+        }
+        let source_dir = temp_dir.join("source");
+        create_dir_all(source_dir.join(RESOURCES_DIR).join("sub")).unwrap();
+        fs::write(source_dir.join(RESOURCES_DIR).join("a.png"), "aaa").unwrap();
+        fs::write(
+            source_dir.join(RESOURCES_DIR).join("sub").join("b.png"),
+            "bbb",
+        )
+        .unwrap();
+
+        // b.png is already on the second volume from an earlier run, a.png is a partial copy
+        let volumes = vec![temp_dir.join("one"), temp_dir.join("two")];
+        create_dir_all(volumes[0].join(RESOURCES_DIR)).unwrap();
+        fs::write(volumes[0].join(RESOURCES_DIR).join("a.png"), "a").unwrap();
+        create_dir_all(volumes[1].join(RESOURCES_DIR).join("sub")).unwrap();
+        fs::write(
+            volumes[1].join(RESOURCES_DIR).join("sub").join("b.png"),
+            "bbb",
+        )
+        .unwrap();
+
+        // act
+        let result = copy_resources_to_volumes(&source_dir, &volumes);
+
+        // assert
+        let placement = result.unwrap();
+        assert_eq!(
+            placement.path_of("a.png"),
+            Some(volumes[0].join(RESOURCES_DIR).join("a.png"))
+        );
+        assert_eq!(
+            placement.path_of("sub/b.png"),
+            Some(volumes[1].join(RESOURCES_DIR).join("sub").join("b.png"))
+        );
+        assert_eq!(
+            fs::read_to_string(volumes[0].join(RESOURCES_DIR).join("a.png")).unwrap(),
+            "aaa"
+        );
+        assert!(!volumes[0].join(RESOURCES_DIR).join("sub").exists());
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
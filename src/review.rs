@@ -0,0 +1,296 @@
+//! A terminal UI to review the notes before they're written: each note with its tags and target
+//! path, to leave some out or fix their tags first.
+
+use crate::joplin_file_io::target_paths;
+use crate::{Config, JoplinFile};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::{Path, PathBuf};
+
+const HELP: &str =
+    "↑↓ move  space include/leave out  t edit tags  a include all  w write  q cancel";
+
+/// Shows the notes for review and returns the ones approved, with the tags as edited. `None`
+/// when the review was cancelled.
+pub fn review<P: AsRef<Path>>(
+    target_dir: P,
+    joplin_files: Vec<JoplinFile>,
+    config: &Config,
+) -> Result<Option<Vec<JoplinFile>>, String> {
+    let base_len = target_dir.as_ref().to_string_lossy().chars().count() + 1;
+    let relative_paths = target_paths(&joplin_files, base_len, config)?;
+    let mut review = Review::new(joplin_files, relative_paths);
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut review);
+    ratatui::restore();
+    result.map_err(|e| format!("Error running the review: {}", e))?;
+
+    Ok(review.into_approved())
+}
+
+fn run(terminal: &mut DefaultTerminal, review: &mut Review) -> std::io::Result<()> {
+    while review.outcome.is_none() {
+        terminal.draw(|frame| draw(frame, review))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            review.handle(key.code);
+        }
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, review: &mut Review) {
+    let [list_area, status_area] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+    let items: Vec<ListItem> = review
+        .joplin_files
+        .iter()
+        .zip(&review.relative_paths)
+        .zip(&review.included)
+        .map(|((joplin_file, relative_path), &included)| {
+            let mark = if included { "[x] " } else { "[ ] " };
+            let line = Line::from(vec![
+                Span::raw(mark),
+                Span::raw(joplin_file.title.as_str()).bold(),
+                Span::raw("  "),
+                Span::raw(joplin_file.tags.as_deref().unwrap_or("")).cyan(),
+                Span::raw("  → "),
+                Span::raw(relative_path.to_string_lossy()).dim(),
+            ]);
+            if included {
+                ListItem::new(line)
+            } else {
+                ListItem::new(line).add_modifier(Modifier::CROSSED_OUT)
+            }
+        })
+        .collect();
+    let title = format!(
+        " {} of {} notes to write ",
+        review.included.iter().filter(|&&included| included).count(),
+        review.included.len()
+    );
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().reversed());
+    review.list.select(Some(review.selected));
+    frame.render_stateful_widget(list, list_area, &mut review.list);
+
+    let status = match &review.editing {
+        Some(tags) => format!("Tags: {}▏ (enter to keep, esc to drop)", tags),
+        None => HELP.to_string(),
+    };
+    frame.render_widget(Paragraph::new(status), status_area);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Write,
+    Cancel,
+}
+
+/// The notes under review and what was decided about them so far
+struct Review {
+    joplin_files: Vec<JoplinFile>,
+    /// Where each note will be written, relative to the target
+    relative_paths: Vec<PathBuf>,
+    included: Vec<bool>,
+    selected: usize,
+    /// Keeps the list scrolled between frames
+    list: ListState,
+    /// The tags of the selected note as they're being edited
+    editing: Option<String>,
+    outcome: Option<Outcome>,
+}
+
+impl Review {
+    fn new(joplin_files: Vec<JoplinFile>, relative_paths: Vec<PathBuf>) -> Self {
+        Self {
+            included: vec![true; joplin_files.len()],
+            joplin_files,
+            relative_paths,
+            selected: 0,
+            list: ListState::default(),
+            editing: None,
+            outcome: None,
+        }
+    }
+
+    fn handle(&mut self, key: KeyCode) {
+        if let Some(tags) = &mut self.editing {
+            match key {
+                KeyCode::Char(c) => tags.push(c),
+                KeyCode::Backspace => {
+                    tags.pop();
+                }
+                KeyCode::Enter => {
+                    let tags = normalize_tags(tags);
+                    if let Some(joplin_file) = self.joplin_files.get_mut(self.selected) {
+                        joplin_file.tags = tags;
+                    }
+                    self.editing = None;
+                }
+                KeyCode::Esc => self.editing = None,
+                _ => {}
+            }
+            return;
+        }
+
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.included.len().saturating_sub(1))
+            }
+            KeyCode::Char(' ') => {
+                if let Some(included) = self.included.get_mut(self.selected) {
+                    *included = !*included;
+                }
+            }
+            KeyCode::Char('a') => self.included.fill(true),
+            KeyCode::Char('t') => {
+                self.editing = self
+                    .joplin_files
+                    .get(self.selected)
+                    .map(|joplin_file| joplin_file.tags.clone().unwrap_or_default());
+            }
+            KeyCode::Char('w') | KeyCode::Enter => self.outcome = Some(Outcome::Write),
+            KeyCode::Char('q') | KeyCode::Esc => self.outcome = Some(Outcome::Cancel),
+            _ => {}
+        }
+    }
+
+    fn into_approved(self) -> Option<Vec<JoplinFile>> {
+        if self.outcome != Some(Outcome::Write) {
+            return None;
+        }
+
+        Some(
+            self.joplin_files
+                .into_iter()
+                .zip(self.included)
+                .filter_map(|(joplin_file, included)| included.then_some(joplin_file))
+                .collect(),
+        )
+    }
+}
+
+/// The tags as typed, each with a leading `#`. `None` when there are none
+fn normalize_tags(tags: &str) -> Option<String> {
+    let tags: Vec<String> = tags
+        .split_whitespace()
+        .map(|tag| format!("#{}", tag.trim_start_matches('#')))
+        .collect();
+    (!tags.is_empty()).then(|| tags.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The relative path and tags of each approved note
+    type Approved<'a> = Option<Vec<(&'a str, Option<&'a str>)>>;
+
+    fn review() -> Review {
+        let joplin_files = ["a.md", "b.md", "c.md"]
+            .iter()
+            .map(|relative_path| {
+                JoplinFile::build(
+                    relative_path,
+                    "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\ntags: old\n---\nBody",
+                    &Config::default(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let relative_paths = ["a.md", "b.md", "c.md"].map(PathBuf::from).to_vec();
+        Review::new(joplin_files, relative_paths)
+    }
+
+    #[test]
+    fn test_handle() {
+        let test_cases: Vec<(Vec<KeyCode>, Approved)> = vec![
+            (vec![KeyCode::Char('q')], None),
+            (
+                vec![KeyCode::Down, KeyCode::Char(' '), KeyCode::Char('w')],
+                Some(vec![("a.md", Some("#old")), ("c.md", Some("#old"))]),
+            ),
+            (
+                vec![
+                    KeyCode::Down,
+                    KeyCode::Down,
+                    KeyCode::Down,
+                    KeyCode::Char(' '),
+                    KeyCode::Char('a'),
+                    KeyCode::Enter,
+                ],
+                Some(vec![
+                    ("a.md", Some("#old")),
+                    ("b.md", Some("#old")),
+                    ("c.md", Some("#old")),
+                ]),
+            ),
+            (
+                vec![
+                    KeyCode::Char('t'),
+                    KeyCode::Backspace,
+                    KeyCode::Backspace,
+                    KeyCode::Backspace,
+                    KeyCode::Char('w'),
+                    KeyCode::Char(' '),
+                    KeyCode::Char('x'),
+                    KeyCode::Enter,
+                    KeyCode::Down,
+                    KeyCode::Char('t'),
+                    KeyCode::Backspace,
+                    KeyCode::Backspace,
+                    KeyCode::Backspace,
+                    KeyCode::Backspace,
+                    KeyCode::Enter,
+                    KeyCode::Down,
+                    KeyCode::Char('t'),
+                    KeyCode::Char('y'),
+                    KeyCode::Esc,
+                    KeyCode::Char('w'),
+                ],
+                Some(vec![
+                    ("a.md", Some("#w #x")),
+                    ("b.md", None),
+                    ("c.md", Some("#old")),
+                ]),
+            ),
+        ];
+
+        for (keys, expected) in test_cases {
+            let mut review = review();
+
+            for key in keys {
+                review.handle(key);
+            }
+
+            let approved = review.into_approved().map(|joplin_files| {
+                joplin_files
+                    .into_iter()
+                    .map(|joplin_file| {
+                        (
+                            joplin_file.relative_path.to_string_lossy().to_string(),
+                            joplin_file.tags,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            });
+            let expected = expected.map(|notes| {
+                notes
+                    .into_iter()
+                    .map(|(path, tags)| (path.to_string(), tags.map(str::to_string)))
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(approved, expected);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+/// Characters that Windows, and exFAT and FAT volumes on any system, don't allow in file names
+const INVALID_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Names Windows reserves for devices, with or without an extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Length of the hash that keeps truncated names unique
+const HASH_LEN: usize = 6;
+
+/// Truncated names keep at least one character of the original, a `~` and the hash
+const MIN_NAME_LEN: usize = 1 + 1 + HASH_LEN;
+
+/// Whether a character can replace invalid ones, it has to be valid itself
+pub fn is_valid_replacement(replacement: char) -> bool {
+    !INVALID_CHARS.contains(&replacement)
+        && !replacement.is_control()
+        && replacement != '.'
+        && replacement != ' '
+}
+
+/// Makes a single file or directory name valid on the common filesystems. Invalid and control
+/// characters are replaced, as are trailing dots and spaces, and reserved names get prefixed with
+/// the replacement.
+pub fn sanitize_component(component: &str, replacement: char) -> String {
+    let mut sanitized: String = component
+        .chars()
+        .map(|c| {
+            if INVALID_CHARS.contains(&c) || c.is_control() {
+                replacement
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    let trimmed_len = sanitized.trim_end_matches(['.', ' ']).len();
+    if trimmed_len < sanitized.len() {
+        let trailing = sanitized[trimmed_len..].chars().count();
+        sanitized.truncate(trimmed_len);
+        sanitized.extend(std::iter::repeat_n(replacement, trailing));
+    }
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        sanitized.insert(0, replacement);
+    }
+
+    sanitized
+}
+
+/// Sanitizes every component of a relative path. Components that aren't valid UTF-8 are left as
+/// they are.
+pub fn sanitize_path(relative_path: &Path, replacement: char) -> PathBuf {
+    relative_path
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => match name.to_str() {
+                Some(name) => PathBuf::from(sanitize_component(name, replacement)),
+                None => PathBuf::from(name),
+            },
+            other => PathBuf::from(other.as_os_str()),
+        })
+        .collect()
+}
+
+/// Shortens the paths so they fit in `max_len` characters, `base_len` of which are taken by the
+/// target directory and a separator. Directory names are all capped at the same length so notes in
+/// the same directory stay together, file names are shortened as far as each path needs.
+pub fn fit_paths(
+    relative_paths: &[PathBuf],
+    base_len: usize,
+    max_len: usize,
+) -> Result<Vec<PathBuf>, String> {
+    let longest_dir = relative_paths
+        .iter()
+        .filter_map(|path| path.parent())
+        .flat_map(Path::components)
+        .map(|component| name_len(component.as_os_str()))
+        .max()
+        .unwrap_or_default();
+
+    for max_dir_len in (MIN_NAME_LEN..=longest_dir.max(MIN_NAME_LEN)).rev() {
+        let fitted: Option<Vec<PathBuf>> = relative_paths
+            .iter()
+            .map(|path| fit_path(path, base_len, max_len, max_dir_len))
+            .collect();
+        if let Some(fitted) = fitted {
+            return Ok(fitted);
+        }
+    }
+
+    Err(format!(
+        "The notes don't fit in target paths of {} characters",
+        max_len
+    ))
+}
+
+fn fit_path(
+    relative_path: &Path,
+    base_len: usize,
+    max_len: usize,
+    max_dir_len: usize,
+) -> Option<PathBuf> {
+    let components: Vec<&OsStr> = relative_path
+        .components()
+        .map(Component::as_os_str)
+        .collect();
+    let (file_name, dirs) = components.split_last()?;
+
+    let mut fitted = PathBuf::new();
+    for dir in dirs {
+        fitted.push(truncate_name(dir, max_dir_len, false)?);
+    }
+
+    let separator = usize::from(!dirs.is_empty());
+    let used = base_len + name_len(fitted.as_os_str()) + separator;
+    fitted.push(truncate_name(file_name, max_len.checked_sub(used)?, true)?);
+
+    Some(fitted)
+}
+
+fn name_len(name: &OsStr) -> usize {
+    name.to_string_lossy().chars().count()
+}
+
+/// Shortens a name to at most `max_len` characters by replacing its end with `~` and a hash of
+/// the whole name, keeping the extension of file names. Names that aren't valid UTF-8 can't be
+/// shortened.
+fn truncate_name(name: &OsStr, max_len: usize, keep_extension: bool) -> Option<PathBuf> {
+    if name_len(name) <= max_len {
+        return Some(PathBuf::from(name));
+    }
+
+    let name = name.to_str()?;
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if keep_extension && !stem.is_empty() => {
+            (stem, format!(".{extension}"))
+        }
+        _ => (name, String::new()),
+    };
+
+    let kept = max_len.checked_sub(extension.chars().count() + 1 + HASH_LEN)?;
+    if kept == 0 {
+        return None;
+    }
+
+    let stem: String = stem.chars().take(kept).collect();
+    Some(PathBuf::from(format!(
+        "{}~{}{}",
+        stem.trim_end(),
+        name_hash(name),
+        extension
+    )))
+}
+
+/// FNV-1a, which unlike the std hasher is the same across runs and Rust versions, so running again
+/// truncates names the same way
+fn name_hash(name: &str) -> String {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+
+    format!("{:06x}", hash & 0xff_ffff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_component() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("note.md", "note.md"),
+            ("What? Why: now.md", "What_ Why_ now.md"),
+            ("a<b>c|d*e\"f.md", "a_b_c_d_e_f.md"),
+            ("tab\there.md", "tab_here.md"),
+            ("notebook.", "notebook_"),
+            ("notebook. .", "notebook___"),
+            ("CON.md", "_CON.md"),
+            ("con", "_con"),
+            ("lpt1.txt", "_lpt1.txt"),
+            ("CONSOLE.md", "CONSOLE.md"),
+            ("smörgåsbord.md", "smörgåsbord.md"),
+        ];
+
+        for (component, expected) in test_cases {
+            assert_eq!(sanitize_component(component, '_'), expected);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_path() {
+        let result = sanitize_path(Path::new("Work: 2024/Q1?/plan.md"), '-');
+
+        assert_eq!(result, PathBuf::from("Work- 2024/Q1-/plan.md"));
+    }
+
+    #[test]
+    fn test_is_valid_replacement() {
+        let test_cases: Vec<(char, bool)> = vec![
+            ('_', true),
+            ('-', true),
+            (':', false),
+            ('.', false),
+            ('\n', false),
+        ];
+
+        for (replacement, expected) in test_cases {
+            assert_eq!(is_valid_replacement(replacement), expected);
+        }
+    }
+
+    #[test]
+    fn test_truncate_name() {
+        let test_cases: Vec<(&str, usize, bool, Option<String>)> = vec![
+            ("short.md", 8, true, Some("short.md".to_string())),
+            (
+                "a long note.md",
+                12,
+                true,
+                Some(format!("a~{}.md", name_hash("a long note.md"))),
+            ),
+            (
+                "a long notebook",
+                10,
+                false,
+                Some(format!("a l~{}", name_hash("a long notebook"))),
+            ),
+            ("a long note.md", 10, true, None),
+        ];
+
+        for (name, max_len, keep_extension, expected) in test_cases {
+            let result = truncate_name(OsStr::new(name), max_len, keep_extension);
+            assert_eq!(result, expected.map(PathBuf::from), "name: {name}");
+        }
+    }
+
+    #[test]
+    fn test_fit_paths() {
+        // arrange
+        let relative_paths = vec![
+            PathBuf::from("A very long notebook name/first note.md"),
+            PathBuf::from("A very long notebook name/a note with a much longer title.md"),
+            PathBuf::from("A very long notebook name/a note with a much longer title too.md"),
+            PathBuf::from("short.md"),
+        ];
+
+        // act
+        let result = fit_paths(&relative_paths, 10, 40).unwrap();
+
+        // assert
+        let notebooks: Vec<_> = result.iter().filter_map(|path| path.parent()).collect();
+        assert_eq!(notebooks[0], notebooks[1]);
+        assert_eq!(notebooks[1], notebooks[2]);
+        assert_ne!(result[1], result[2]);
+        assert_eq!(result[3], PathBuf::from("short.md"));
+        for path in &result {
+            assert!(10 + name_len(path.as_os_str()) <= 40, "path: {path:?}");
+            assert_eq!(path.extension(), Some(OsStr::new("md")));
+        }
+    }
+
+    #[test]
+    fn test_fit_paths_too_long() {
+        let relative_paths = vec![PathBuf::from("a/b/c/d/e/f/note.md")];
+
+        let result = fit_paths(&relative_paths, 10, 20);
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::JoplinFile;
+use rhai::{AST, Array, Dynamic, Engine, Map, Scope};
+use std::path::{Path, PathBuf};
+
+/// A user supplied Rhai script that runs once for every note.
+///
+/// The script sees the note as the variables `title`, `body`, `tags` (an array of tags without
+/// the leading `#`), `path` (the relative target path) and `front_matter` (a read-only map of
+/// the front matter keys). Whatever it assigns to the first four ends up in the converted note.
+/// Setting `skip = true` leaves the note out of the conversion.
+pub struct ScriptHook {
+    engine: Engine,
+    ast: AST,
+}
+
+impl ScriptHook {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ScriptHook, String> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(path.as_ref().to_path_buf())
+            .map_err(|e| format!("Error compiling script {:?}: {}", path.as_ref(), e))?;
+
+        Ok(ScriptHook { engine, ast })
+    }
+
+    #[cfg(test)]
+    fn compile(script: &str) -> ScriptHook {
+        let engine = Engine::new();
+        let ast = engine.compile(script).unwrap();
+        ScriptHook { engine, ast }
+    }
+
+    /// Runs the script on the note, returns `false` when the script vetoed it
+    pub fn apply(&self, joplin_file: &mut JoplinFile) -> Result<bool, String> {
+        let tags: Array = joplin_file
+            .tags
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|tag| Dynamic::from(tag.trim_start_matches('#').to_string()))
+            .collect();
+
+        let mut scope = Scope::new();
+        scope.push("title", joplin_file.title.clone());
+        // Taken rather than copied, the script's value replaces it
+        scope.push("body", std::mem::take(&mut joplin_file.body));
+        scope.push("tags", tags);
+        scope.push(
+            "path",
+            joplin_file.relative_path.to_string_lossy().to_string(),
+        );
+        scope.push_constant("front_matter", front_matter_map(&joplin_file.front_matter));
+        scope.push("skip", false);
+
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| {
+                format!(
+                    "Error running script on {:?}: {}",
+                    joplin_file.relative_path, e
+                )
+            })?;
+
+        let string = |name: &str| {
+            scope
+                .get_value::<String>(name)
+                .ok_or_else(|| format!("The script variable `{name}` must be a string"))
+        };
+        let skip = scope
+            .get_value::<bool>("skip")
+            .ok_or("The script variable `skip` must be a boolean")?;
+        if skip {
+            return Ok(false);
+        }
+
+        joplin_file.title = string("title")?;
+        joplin_file.set_body(string("body")?);
+        joplin_file.relative_path = PathBuf::from(string("path")?);
+
+        let tags = scope
+            .get_value::<Array>("tags")
+            .ok_or("The script variable `tags` must be an array")?
+            .into_iter()
+            .map(|tag| format!("#{}", tag.to_string().trim_start_matches('#')))
+            .collect::<Vec<String>>();
+        joplin_file.tags = (!tags.is_empty()).then(|| tags.join(" "));
+
+        Ok(true)
+    }
+}
+
+fn front_matter_map(front_matter: &str) -> Map {
+    front_matter
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().into(), Dynamic::from(value.trim().to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_apply() {
+        // arrange
+        let mut joplin_file = JoplinFile::build(
+            "Inbox/note.md",
+            "---\ntitle: Test\nsource: web\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\nThe content",
+            &Config::default(),
+        )
+        .unwrap();
+        let hook = ScriptHook::compile(
+            r#"
+            title = title.to_upper();
+            if front_matter.source == "web" {
+                tags.push("clippings");
+                path = "Clippings/" + path;
+            }
+            body += "\n\nprocessed";
+            "#,
+        );
+
+        // act
+        let result = hook.apply(&mut joplin_file);
+
+        // assert
+        assert_eq!(result, Ok(true));
+        assert_eq!(joplin_file.title, "TEST");
+        assert_eq!(joplin_file.tags.as_deref(), Some("#Inbox #clippings"));
+        assert_eq!(
+            joplin_file.relative_path,
+            PathBuf::from("Clippings/Inbox/note.md")
+        );
+        assert_eq!(joplin_file.body, "The content\n\nprocessed");
+    }
+
+    #[test]
+    fn test_apply_skip() {
+        let test_cases: Vec<(&str, Result<bool, String>)> = vec![
+            ("skip = title == \"Test\";", Ok(false)),
+            ("skip = title == \"Other\";", Ok(true)),
+            (
+                "skip = \"yes\";",
+                Err("The script variable `skip` must be a boolean".to_string()),
+            ),
+        ];
+
+        for (script, expected) in test_cases {
+            let mut joplin_file = JoplinFile::build(
+                "note.md",
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+                &Config::default(),
+            )
+            .unwrap();
+
+            let result = ScriptHook::compile(script).apply(&mut joplin_file);
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_apply_wrong_type() {
+        let mut joplin_file = JoplinFile::build(
+            "note.md",
+            "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n",
+            &Config::default(),
+        )
+        .unwrap();
+        let hook = ScriptHook::compile("title = 42;");
+
+        let result = hook.apply(&mut joplin_file);
+
+        assert_eq!(
+            result,
+            Err("The script variable `title` must be a string".to_string())
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::bear::{self, BearNote};
+use crate::joplin_file_io::{build_joplin_files, copy_resources, write_joplin_files};
+use crate::{Config, JoplinFile};
+use std::fs;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// How long to give Bear to finish an import before reading the note back
+const IMPORT_WAIT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq)]
+pub struct SmokeTestResult {
+    pub title: String,
+    pub error: Option<String>,
+    pub tags_survived: bool,
+    pub timestamps_survived: bool,
+    pub attachments_survived: bool,
+}
+
+impl SmokeTestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+            && self.tags_survived
+            && self.timestamps_survived
+            && self.attachments_survived
+    }
+}
+
+/// Converts the first `notes` notes of the source, imports them into Bear and checks what survived
+pub fn run<P: AsRef<Path>>(
+    source_dir: P,
+    notes: usize,
+    config: &Config,
+) -> Result<Vec<SmokeTestResult>, String> {
+    if !cfg!(target_os = "macos") {
+        return Err("The smoke test is only supported on macOS".to_string());
+    }
+
+    let mut joplin_files = build_joplin_files(&source_dir, config)?;
+    joplin_files.truncate(notes);
+
+    let target_dir = std::env::temp_dir().join("jb_smoke_test");
+    if target_dir.exists() {
+        fs::remove_dir_all(&target_dir)
+            .map_err(|e| format!("Error removing old smoke test output: {}", e))?;
+    }
+
+    write_joplin_files(&target_dir, &joplin_files, config)?;
+    if source_dir.as_ref().join("_resources").is_dir() {
+        let io_errors = config.io_errors.len();
+        copy_resources(source_dir.as_ref(), target_dir.as_path(), config)?;
+        config.io_errors.check_since(io_errors)?;
+    }
+
+    let results = joplin_files
+        .iter()
+        .map(|joplin_file| {
+            let imported =
+                bear::import_file(target_dir.join(&joplin_file.relative_path)).and_then(|_| {
+                    sleep(IMPORT_WAIT);
+                    bear::open_note(&joplin_file.title)
+                });
+
+            match imported {
+                Ok(note) => check(joplin_file, &note),
+                Err(e) => SmokeTestResult {
+                    title: joplin_file.title.clone(),
+                    error: Some(e),
+                    tags_survived: false,
+                    timestamps_survived: false,
+                    attachments_survived: false,
+                },
+            }
+        })
+        .collect();
+
+    Ok(results)
+}
+
+fn check(joplin_file: &JoplinFile, note: &BearNote) -> SmokeTestResult {
+    let tags_survived = joplin_file.tags.as_deref().is_none_or(|tags| {
+        tags.split_whitespace()
+            .map(|tag| tag.trim_start_matches('#'))
+            .all(|tag| note.tags.iter().any(|t| t == tag))
+    });
+
+    let timestamps_survived =
+        note.created == Some(joplin_file.created) && note.modified == Some(joplin_file.updated);
+
+    let attachments_survived = joplin_file
+        .referenced_resources()
+        .iter()
+        .all(|resource_ref| note.text.contains(resource_ref.file_name()));
+
+    SmokeTestResult {
+        title: joplin_file.title.clone(),
+        error: None,
+        tags_survived,
+        timestamps_survived,
+        attachments_survived,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let joplin_file = JoplinFile::build(
+            "foo/bar.md",
+            "\
+---
+title: Test
+created: 2024-03-07T23:22:26Z
+updated: 2024-04-07T08:34:52Z
+---
+
+![](../_resources/a.png)\n",
+            &Config::default(),
+        )
+        .unwrap();
+
+        let note = BearNote {
+            identifier: "ABC".to_string(),
+            title: "Test".to_string(),
+            text: "![](a.png)".to_string(),
+            tags: vec!["foo".to_string()],
+            created: Some(joplin_file.created),
+            modified: Some(joplin_file.created),
+        };
+
+        let result = check(&joplin_file, &note);
+
+        assert_eq!(
+            result,
+            SmokeTestResult {
+                title: "Test".to_string(),
+                error: None,
+                tags_survived: true,
+                timestamps_survived: false,
+                attachments_survived: true,
+            }
+        );
+        assert!(!result.passed());
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of a file is read to tell its type, the text formats need more than the magic
+/// bytes of the binary ones
+const HEAD_LEN: usize = 512;
+
+/// The extension of a file by its first bytes, for the resources Joplin saved without one,
+/// which Bear can't preview. `None` when the type isn't one Bear knows.
+pub fn sniff_extension(head: &[u8]) -> Option<&'static str> {
+    let brand = head.get(8..12);
+    let extension = match head {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [0xff, 0xd8, 0xff, ..] => "jpg",
+        [b'G', b'I', b'F', b'8', ..] => "gif",
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'E',
+            b'B',
+            b'P',
+            ..,
+        ] => "webp",
+        [b'B', b'M', _, _, _, _, 0, 0, 0, 0, ..] => "bmp",
+        [b'I', b'I', 0x2a, 0x00, ..] | [b'M', b'M', 0x00, 0x2a, ..] => "tiff",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "pdf",
+        [b'I', b'D', b'3', ..] | [0xff, 0xfb, ..] => "mp3",
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => match brand {
+            Some(b"heic" | b"heix" | b"mif1") => "heic",
+            Some(b"qt  ") => "mov",
+            Some(b"M4A ") => "m4a",
+            _ => "mp4",
+        },
+        [b'P', b'K', 0x03, 0x04, ..] => "zip",
+        _ => {
+            let text = String::from_utf8_lossy(head);
+            let text = text.trim_start_matches('\u{feff}').trim_start();
+            if text.starts_with("<svg") || (text.starts_with("<?xml") && text.contains("<svg")) {
+                "svg"
+            } else {
+                return None;
+            }
+        }
+    };
+
+    Some(extension)
+}
+
+/// The extension of the file by its first bytes, see `sniff_extension`
+pub fn sniff_file_extension(path: &Path) -> std::io::Result<Option<&'static str>> {
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    File::open(path)?
+        .take(HEAD_LEN as u64)
+        .read_to_end(&mut head)?;
+
+    Ok(sniff_extension(&head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_extension() {
+        let test_cases: Vec<(&[u8], Option<&str>)> = vec![
+            (b"\x89PNG\r\n\x1a\n....", Some("png")),
+            (b"\xff\xd8\xff\xe0..JFIF", Some("jpg")),
+            (b"GIF89a", Some("gif")),
+            (b"RIFF\x10\0\0\0WEBPVP8 ", Some("webp")),
+            (b"RIFF\x10\0\0\0WAVEfmt ", None),
+            (b"%PDF-1.7\n", Some("pdf")),
+            (b"\0\0\0\x18ftypheic", Some("heic")),
+            (b"\0\0\0\x18ftypisom", Some("mp4")),
+            (
+                b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<svg xmlns=",
+                Some("svg"),
+            ),
+            (b"<?xml version=\"1.0\"?>\n<note/>", None),
+            (b"Plain text", None),
+            (b"", None),
+        ];
+
+        for (head, expected) in test_cases {
+            assert_eq!(sniff_extension(head), expected, "{:?}", head);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::bear::percent_encode;
+use crate::fidelity::Fidelity;
+use crate::headings::{Heading, find_headings};
+use crate::note_links::{find_note_links, relative_link};
+use crate::resource_ref::percent_decode;
+use crate::{Config, JoplinFile};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Where the headings of a split note went: the slug of each heading in a part and the part
+/// with its slug there
+type Moved = HashMap<String, (PathBuf, String)>;
+
+/// The headings moved of each split note, by its path
+type Anchors = HashMap<PathBuf, Moved>;
+
+/// Splits the notes whose body is longer than `max_len` bytes at their top-level headings, Bear
+/// slows down on notes of a MB and more. The note becomes an index of the parts, with what came
+/// before the first heading, and each section a part next to it linking back, with the tags
+/// and dates of the note. The links to the headings of a split note, from other notes or within
+/// it, are pointed at the part the heading went to. Returns how many notes were split.
+pub fn split_large_notes(
+    joplin_files: &mut Vec<JoplinFile>,
+    max_len: usize,
+    config: &Config,
+) -> usize {
+    let taken: HashSet<PathBuf> = joplin_files
+        .iter()
+        .map(|joplin_file| joplin_file.relative_path.clone())
+        .collect();
+
+    let mut split_count = 0;
+    let mut anchors = Anchors::new();
+    let mut split = Vec::with_capacity(joplin_files.len());
+    for joplin_file in joplin_files.drain(..) {
+        if joplin_file.body.len() <= max_len {
+            split.push(joplin_file);
+            continue;
+        }
+
+        match split_note(&joplin_file, config, &taken) {
+            Some((notes, moved)) => {
+                crate::notice!(
+                    "Split {:?} into {} parts",
+                    joplin_file.relative_path,
+                    notes.len() - 1
+                );
+                split_count += 1;
+                anchors.insert(joplin_file.relative_path.clone(), moved);
+                split.extend(notes);
+            }
+            None => {
+                crate::warning!(
+                    "{:?}: {} bytes, but it has no headings to split it at",
+                    joplin_file.relative_path,
+                    joplin_file.body.len()
+                );
+                split.push(joplin_file);
+            }
+        }
+    }
+    *joplin_files = split;
+    if !anchors.is_empty() {
+        retarget_anchors(joplin_files, &anchors, config);
+    }
+
+    split_count
+}
+
+/// Points the links to the headings of the split notes at the parts they went to. A link to
+/// `Big.md#section` leads to `Big (part 2).md#section`, and so does `#section` in a part that
+/// doesn't have the heading.
+fn retarget_anchors(joplin_files: &mut [JoplinFile], anchors: &Anchors, config: &Config) {
+    // The parts and the note they were split from, for the links within the note
+    let split_from: HashMap<&PathBuf, &PathBuf> = anchors
+        .iter()
+        .flat_map(|(note, moved)| moved.values().map(move |(part, _)| (part, note)))
+        .collect();
+
+    for joplin_file in joplin_files.iter_mut() {
+        let from = &joplin_file.relative_path;
+        let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+        for note_link in find_note_links(&joplin_file.body, from, config) {
+            let (Some(target), Some(fragment)) = (&note_link.target, &note_link.fragment) else {
+                continue;
+            };
+            let note = match split_from.get(target) {
+                Some(note) if note_link.span.is_empty() => *note,
+                _ => target,
+            };
+            let Some((part, slug)) = anchors
+                .get(note)
+                .and_then(|moved| moved.get(&percent_decode(fragment)))
+            else {
+                continue;
+            };
+
+            if part != target {
+                replacements.push((note_link.span.clone(), relative_link(from, part)));
+            }
+            if slug != fragment {
+                replacements.push((note_link.fragment_span(), slug.clone()));
+            }
+        }
+
+        if replacements.is_empty() {
+            continue;
+        }
+        let mut body = joplin_file.body.clone();
+        replacements.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+        for (span, replacement) in replacements {
+            body.replace_range(span, &replacement);
+        }
+        joplin_file.set_body(body);
+    }
+}
+
+/// The index note followed by the parts, and where the headings of the note went, `None` when
+/// the note has no level with two headings or a part would take the path of another note. The
+/// top level is the highest with two headings, so the `# Title` heading of a note doesn't count.
+fn split_note(
+    joplin_file: &JoplinFile,
+    config: &Config,
+    taken: &HashSet<PathBuf>,
+) -> Option<(Vec<JoplinFile>, Moved)> {
+    let headings = find_headings(&joplin_file.body);
+    let level = (1..=6).find(|level| {
+        headings
+            .iter()
+            .filter(|heading| heading.level == *level)
+            .count()
+            >= 2
+    })?;
+    let sections: Vec<&Heading> = headings
+        .iter()
+        .filter(|heading| heading.level == level)
+        .collect();
+
+    let file_name = joplin_file.relative_path.file_name()?.to_string_lossy();
+    let stem = config.strip_extension(&file_name);
+    let extension = &file_name[stem.len()..];
+    let back_link = format!(
+        "[{}]({})",
+        escape_brackets(&joplin_file.title),
+        percent_encode(&file_name)
+    );
+
+    let mut contents = String::new();
+    let mut moved = HashMap::new();
+    let mut parts = Vec::with_capacity(sections.len());
+    for (n, heading) in sections.iter().enumerate() {
+        let end = sections
+            .get(n + 1)
+            .map_or(joplin_file.body.len(), |next| next.start);
+        let part_name = format!("{stem} (part {}){extension}", n + 1);
+        let relative_path = joplin_file.relative_path.with_file_name(&part_name);
+        if taken.contains(&relative_path) {
+            return None;
+        }
+
+        let section = joplin_file.body[heading.start..end].trim_end();
+        // The headings keep their order in the part, their slugs change where they were taken
+        let within = headings
+            .iter()
+            .filter(|other| (heading.start..end).contains(&other.start));
+        for (before, after) in within.zip(find_headings(section)) {
+            moved.insert(before.slug.clone(), (relative_path.clone(), after.slug));
+        }
+        let mut part = JoplinFile {
+            title: heading.text.clone(),
+            id: None,
+            relative_path,
+            fidelity: Fidelity::default(),
+            warnings: Vec::new(),
+            ..joplin_file.clone()
+        };
+        part.set_body(format!("{section}\n\n{back_link}"));
+        parts.push(part);
+
+        contents.push_str(&format!(
+            "- [{}]({})\n",
+            escape_brackets(&heading.text),
+            percent_encode(&part_name)
+        ));
+    }
+
+    let intro = joplin_file.body[..sections[0].start].trim_end();
+    let mut index = joplin_file.clone();
+    index.set_body(format!("{intro}\n\n{contents}").trim().to_string());
+
+    Some((std::iter::once(index).chain(parts).collect(), moved))
+}
+
+fn escape_brackets(text: &str) -> String {
+    text.replace('[', "\\[").replace(']', "\\]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(relative_path: &str, body: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: Big\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\ntags: [log]\n---\n{body}"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_split_large_notes() {
+        let mut joplin_files = vec![
+            note(
+                "Work/Big note.md",
+                "# Big\n\nIntro\n\n## One [1]\n\nFirst\n\n### Sub\n\n## Two\n\n![](../_resources/a.png)",
+            ),
+            note("Work/Small.md", "## One\n\n## Two"),
+            note("Work/Flat.md", "No headings at all, long enough to split"),
+        ];
+
+        let split_count = split_large_notes(&mut joplin_files, 20, &Config::default());
+
+        assert_eq!(split_count, 1);
+        let found: Vec<(&str, &str, &str)> = joplin_files
+            .iter()
+            .map(|joplin_file| {
+                (
+                    joplin_file.relative_path.to_str().unwrap(),
+                    joplin_file.title.as_str(),
+                    joplin_file.body.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "Work/Big note.md",
+                    "Big",
+                    "# Big\n\nIntro\n\n- [One \\[1\\]](Big%20note%20%28part%201%29.md)\n\
+                    - [Two](Big%20note%20%28part%202%29.md)"
+                ),
+                (
+                    "Work/Big note (part 1).md",
+                    "One [1]",
+                    "## One [1]\n\nFirst\n\n### Sub\n\n[Big](Big%20note.md)"
+                ),
+                (
+                    "Work/Big note (part 2).md",
+                    "Two",
+                    "## Two\n\n![](../_resources/a.png)\n\n[Big](Big%20note.md)"
+                ),
+                ("Work/Small.md", "Big", "## One\n\n## Two"),
+                (
+                    "Work/Flat.md",
+                    "Big",
+                    "No headings at all, long enough to split"
+                ),
+            ]
+        );
+        assert_eq!(joplin_files[1].tags, joplin_files[0].tags);
+        assert_eq!(joplin_files[2].created, joplin_files[0].created);
+        assert_eq!(joplin_files[2].resources.len(), 1);
+    }
+
+    #[test]
+    fn test_split_large_notes_anchors() {
+        let mut joplin_files = vec![
+            note(
+                "Work/Big.md",
+                "# Big\n\n[one](#one) [sub](#sub)\n\n## One\n\n[two](#two) [sub](#sub)\n\n\
+                ### Sub\n\n## Two\n\n### Sub",
+            ),
+            note("Work/Other.md", "[two](Big.md#two) [sub](Big.md#sub-1)"),
+        ];
+
+        split_large_notes(&mut joplin_files, 20, &Config::default());
+
+        let bodies: Vec<&str> = joplin_files
+            .iter()
+            .map(|joplin_file| joplin_file.body.as_str())
+            .collect();
+        assert_eq!(
+            bodies,
+            vec![
+                "# Big\n\n[one](Big%20%28part%201%29.md#one) [sub](Big%20%28part%201%29.md#sub)\n\n\
+                - [One](Big%20%28part%201%29.md)\n- [Two](Big%20%28part%202%29.md)",
+                "## One\n\n[two](Big%20%28part%202%29.md#two) [sub](#sub)\n\n### Sub\n\n[Big](Big.md)",
+                "## Two\n\n### Sub\n\n[Big](Big.md)",
+                "[two](Big%20%28part%202%29.md#two) [sub](Big%20%28part%202%29.md#sub)",
+            ]
+        );
+    }
+}
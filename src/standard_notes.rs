@@ -0,0 +1,206 @@
+use crate::JoplinFile;
+use crate::day_one::md5_hex;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+#[derive(Debug, Serialize)]
+struct Backup {
+    version: &'static str,
+    items: Vec<Item>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Item {
+    uuid: String,
+    content_type: &'static str,
+    created_at: String,
+    updated_at: String,
+    content: Content,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Content {
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    references: Vec<Reference>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Reference {
+    uuid: String,
+    content_type: &'static str,
+}
+
+/// The notes of a tag, it's as old as the oldest of them and updated with the newest
+struct Tag {
+    references: Vec<Reference>,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+}
+
+/// Writes the notes in the Standard Notes import format: an item per note, and an item per tag
+/// that references its notes
+pub fn write_standard_notes<W: Write>(
+    mut writer: W,
+    joplin_files: &[JoplinFile],
+) -> Result<(), String> {
+    let backup = Backup {
+        version: "004",
+        items: build_items(joplin_files),
+    };
+
+    serde_json::to_writer_pretty(&mut writer, &backup)
+        .map_err(|e| format!("Error writing Standard Notes import: {}", e))?;
+    writeln!(writer)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Error writing Standard Notes import: {}", e))
+}
+
+fn build_items(joplin_files: &[JoplinFile]) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut tags: BTreeMap<&str, Tag> = BTreeMap::new();
+
+    for joplin_file in joplin_files {
+        let note_uuid = uuid(
+            joplin_file
+                .id
+                .as_deref()
+                .unwrap_or(&joplin_file.relative_path.to_string_lossy()),
+        );
+
+        for tag in joplin_file
+            .tags
+            .iter()
+            .flat_map(|tags| tags.split_whitespace())
+        {
+            let tag = tags.entry(tag.trim_start_matches('#')).or_insert(Tag {
+                references: Vec::new(),
+                created: joplin_file.created,
+                updated: joplin_file.updated,
+            });
+            tag.references.push(Reference {
+                uuid: note_uuid.clone(),
+                content_type: "Note",
+            });
+            tag.created = tag.created.min(joplin_file.created);
+            tag.updated = tag.updated.max(joplin_file.updated);
+        }
+
+        items.push(Item {
+            uuid: note_uuid,
+            content_type: "Note",
+            created_at: standard_notes_date(joplin_file.created),
+            updated_at: standard_notes_date(joplin_file.updated),
+            content: Content {
+                title: joplin_file.title.clone(),
+                text: Some(joplin_file.body.clone()),
+                references: Vec::new(),
+            },
+        });
+    }
+
+    for (title, tag) in tags {
+        items.push(Item {
+            uuid: uuid(&format!("tag:{title}")),
+            content_type: "Tag",
+            created_at: standard_notes_date(tag.created),
+            updated_at: standard_notes_date(tag.updated),
+            content: Content {
+                title: title.to_string(),
+                text: None,
+                references: tag.references,
+            },
+        });
+    }
+
+    items
+}
+
+/// A uuid derived from `seed`, so converting again gives the same uuids
+fn uuid(seed: &str) -> String {
+    let hex = md5_hex(seed.as_bytes()).to_lowercase();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn standard_notes_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_build_items() {
+        // arrange
+        let joplin_files: Vec<JoplinFile> = [
+            ("Work/a.md", "2024-03-07T23:22:26Z"),
+            ("Work/b.md", "2024-01-07T23:22:26Z"),
+            ("c.md", "2024-02-07T23:22:26Z"),
+        ]
+        .iter()
+        .map(|(relative_path, created)| {
+            JoplinFile::build(
+                relative_path,
+                &format!(
+                    "---\ntitle: Test\ncreated: {created}\nupdated: 2024-04-07T08:34:52Z\n---\nBody"
+                ),
+                &Config::default(),
+            )
+            .unwrap()
+        })
+        .collect();
+
+        // act
+        let items = build_items(&joplin_files);
+
+        // assert
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].content_type, "Note");
+        assert_eq!(items[0].created_at, "2024-03-07T23:22:26.000Z");
+        assert_eq!(items[0].content.text.as_deref(), Some("Body"));
+        assert_eq!(
+            items[3],
+            Item {
+                uuid: uuid("tag:Work"),
+                content_type: "Tag",
+                created_at: "2024-01-07T23:22:26.000Z".to_string(),
+                updated_at: "2024-04-07T08:34:52.000Z".to_string(),
+                content: Content {
+                    title: "Work".to_string(),
+                    text: None,
+                    references: vec![
+                        Reference {
+                            uuid: items[0].uuid.clone(),
+                            content_type: "Note",
+                        },
+                        Reference {
+                            uuid: items[1].uuid.clone(),
+                            content_type: "Note",
+                        },
+                    ],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_uuid() {
+        let uuid = uuid("Work/a.md");
+
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(uuid.matches('-').count(), 4);
+        assert_eq!(uuid, uuid.to_lowercase());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::JoplinFile;
+use crate::fidelity::Fidelity;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Written to the target directory after every conversion
+pub const STATE_FILE_NAME: &str = ".jb_state.json";
+
+/// What the last conversion into a target directory wrote
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub last_run: Option<DateTime<Utc>>,
+    pub notes: BTreeMap<PathBuf, NoteState>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoteState {
+    pub title: String,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    #[serde(default)]
+    pub fidelity: Fidelity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub relative_path: PathBuf,
+    pub title: String,
+    pub updated: DateTime<Utc>,
+}
+
+impl Change {
+    /// The notebook the note is in, `None` for notes in the root of the export
+    pub fn notebook(&self) -> Option<String> {
+        self.relative_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(|parent| parent.to_string_lossy().to_string())
+    }
+}
+
+impl State {
+    pub fn build(joplin_files: &[JoplinFile], run_at: DateTime<Utc>) -> State {
+        let notes = joplin_files
+            .iter()
+            .map(|joplin_file| {
+                (
+                    state_key(&joplin_file.relative_path),
+                    NoteState {
+                        title: joplin_file.title.clone(),
+                        created: joplin_file.created,
+                        updated: joplin_file.updated,
+                        fidelity: joplin_file.fidelity.clone(),
+                    },
+                )
+            })
+            .collect();
+
+        State {
+            last_run: Some(run_at),
+            notes,
+        }
+    }
+
+    /// Loads the state from the target directory, `None` if nothing has been converted into it yet
+    pub fn load<P: AsRef<Path>>(target_dir: P) -> Result<Option<State>, String> {
+        let path = target_dir.as_ref().join(STATE_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Error reading state file: {}", e))?;
+        let state = serde_json::from_str(&content)
+            .map_err(|e| format!("Error parsing state file {:?}: {}", path, e))?;
+
+        Ok(Some(state))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, target_dir: P) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Error serializing state: {}", e))?;
+
+        std::fs::write(target_dir.as_ref().join(STATE_FILE_NAME), content)
+            .map_err(|e| format!("Error writing state file: {}", e))
+    }
+
+    /// The notes added, modified and deleted in the source since this state was recorded
+    pub fn changes(&self, joplin_files: &[JoplinFile]) -> Vec<Change> {
+        let mut changes: Vec<Change> = joplin_files
+            .iter()
+            .filter_map(|joplin_file| {
+                let kind = match self.notes.get(&state_key(&joplin_file.relative_path)) {
+                    None => ChangeKind::Added,
+                    Some(note) if note.updated != joplin_file.updated => ChangeKind::Modified,
+                    Some(_) => return None,
+                };
+
+                Some(Change {
+                    kind,
+                    relative_path: joplin_file.relative_path.clone(),
+                    title: joplin_file.title.clone(),
+                    updated: joplin_file.updated,
+                })
+            })
+            .collect();
+
+        let deleted = self
+            .notes
+            .iter()
+            .filter(|(relative_path, _)| {
+                !joplin_files
+                    .iter()
+                    .any(|joplin_file| state_key(&joplin_file.relative_path) == **relative_path)
+            })
+            .map(|(relative_path, note)| Change {
+                kind: ChangeKind::Deleted,
+                relative_path: relative_path.clone(),
+                title: note.title.clone(),
+                updated: note.updated,
+            });
+        changes.extend(deleted);
+
+        changes.sort_by(|a, b| (a.kind, &a.relative_path).cmp(&(b.kind, &b.relative_path)));
+        changes
+    }
+}
+
+/// JSON can only hold UTF-8, so paths that aren't are stored lossily
+fn state_key(relative_path: &Path) -> PathBuf {
+    PathBuf::from(relative_path.to_string_lossy().as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn joplin_file(relative_path: &str, title: &str, updated: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: {title}\ncreated: 2024-03-07T23:22:26Z\nupdated: {updated}\n---\n"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_changes() {
+        // arrange
+        let before = vec![
+            joplin_file("a.md", "A", "2024-04-07T08:34:52Z"),
+            joplin_file("b/b.md", "B", "2024-04-07T08:34:52Z"),
+            joplin_file("c.md", "C", "2024-04-07T08:34:52Z"),
+        ];
+        let state = State::build(&before, Utc::now());
+
+        let after = vec![
+            joplin_file("a.md", "A", "2024-04-07T08:34:52Z"),
+            joplin_file("b/b.md", "B", "2024-05-07T08:34:52Z"),
+            joplin_file("d/e/d.md", "D", "2024-04-07T08:34:52Z"),
+        ];
+
+        // act
+        let changes = state.changes(&after);
+
+        // assert
+        let result: Vec<(ChangeKind, &str, Option<String>)> = changes
+            .iter()
+            .map(|change| (change.kind, change.title.as_str(), change.notebook()))
+            .collect();
+        assert_eq!(
+            result,
+            vec![
+                (ChangeKind::Added, "D", Some("d/e".to_string())),
+                (ChangeKind::Modified, "B", Some("b".to_string())),
+                (ChangeKind::Deleted, "C", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let target_dir = std::env::temp_dir().join("jb_state_test");
+        std::fs::create_dir_all(&target_dir).unwrap();
+
+        let state = State::build(
+            &[joplin_file("b/b.md", "B", "2024-04-07T08:34:52Z")],
+            Utc::now(),
+        );
+        state.save(&target_dir).unwrap();
+        let loaded = State::load(&target_dir);
+
+        std::fs::remove_dir_all(&target_dir).unwrap();
+        assert_eq!(loaded, Ok(Some(state)));
+    }
+}
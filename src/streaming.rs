@@ -0,0 +1,243 @@
+//! The conversion into a directory a note at a time, so it takes the memory of the largest note
+//! rather than that of all of them. A first pass builds every note for what the others need to
+//! know of it: its path, title and dates for where it goes in the target, the anchors that change
+//! in it, its resources and the hash of its body, and drops the body. The second reads each note
+//! again, builds, links and writes it, and drops it before reading the next.
+
+use crate::config::{ConflictPolicy, DuplicatePolicy, OutputFormat};
+use crate::conflict::resolve_conflicts;
+use crate::duplicate::{body_hash, find_duplicates};
+use crate::joplin_file_io::{
+    Hooks, NoteSource, NoteWriter, base_len, build_note, find_notes, mark_left_out_resources,
+    read_note, rebuild_note, target_paths,
+};
+use crate::note_links::{LinkTargets, moved_anchors};
+use crate::timings::Phase;
+use crate::warning::Warning;
+use crate::{Config, JoplinFile};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Whether the conversion can go a note at a time: into a directory, without a pass that needs
+/// the bodies of all the notes at once. Merging and splitting notes do, as do the conflict and
+/// duplicate policies other than importing, which leave notes out or tag them, and the task
+/// summary. The script and the filter would run twice, and resource volumes rewrite the links
+/// as the resources are copied. The review, which also needs every note, is up to the caller
+pub fn applies(config: &Config) -> bool {
+    config.output_format == OutputFormat::Dir
+        && config.resource_volumes.is_empty()
+        && config.merge_notebooks.is_empty()
+        && config.split_notes.is_none()
+        && config.conflict_policy == ConflictPolicy::Import
+        && config.duplicate_policy == DuplicatePolicy::Import
+        && !config.task_summary
+        && config.script.is_none()
+        && config.filter.is_none()
+        && config.archive_links.is_none()
+}
+
+/// The notes of the export without their bodies, as the first pass leaves them
+pub struct Outline {
+    /// In the order they're written, with the warnings and the fidelity found converting them
+    pub joplin_files: Vec<JoplinFile>,
+    link_targets: LinkTargets,
+}
+
+/// The first pass, the streamed variant of `joplin_file_io::build_joplin_files`. The notes are
+/// reported on as they're built; the conflict copies and the duplicates, which are imported
+/// like the other notes, once they're all built
+pub fn build_joplin_files<P: AsRef<Path>>(
+    source_dir: P,
+    config: &Config,
+) -> Result<Outline, String> {
+    let (mut source, relative_paths) = config
+        .timings
+        .time(Phase::Discovery, || find_notes(source_dir, config))?;
+    let hooks = Hooks::load(config)?;
+
+    let mut joplin_files = Vec::new();
+    let mut hashes = Vec::new();
+    let mut moved = HashMap::new();
+    for (done, relative_path) in relative_paths.iter().enumerate() {
+        config
+            .cancellation
+            .check(done, relative_paths.len(), "notes read")?;
+        let Some(bytes) = config
+            .timings
+            .time_note(Phase::Parse, Some(relative_path), || {
+                read_note(&mut source, relative_path, config)
+            })
+        else {
+            continue;
+        };
+        let Some(mut joplin_file) = build_note(relative_path, bytes, &source, &hooks, config)?
+        else {
+            continue;
+        };
+
+        hashes.push(body_hash(&joplin_file.body));
+        let slugs = moved_anchors(&joplin_file, config);
+        if !slugs.is_empty() {
+            moved.insert(relative_path.clone(), slugs);
+        }
+        joplin_file.body = String::new();
+        joplin_files.push(joplin_file);
+    }
+
+    config.timings.time(Phase::Transform, || {
+        for (relative_path, decision) in
+            resolve_conflicts(&mut joplin_files, ConflictPolicy::Import)
+        {
+            crate::notice!("Conflict copy {:?}: {}", relative_path, decision);
+        }
+        for duplicate in find_duplicates(&joplin_files, &hashes) {
+            crate::notice!(
+                "Duplicate {:?} of {:?}: {}",
+                duplicate.relative_path,
+                duplicate.original,
+                duplicate.decision
+            );
+        }
+    });
+    let link_targets = LinkTargets::new(&joplin_files, moved, config);
+
+    Ok(Outline {
+        joplin_files,
+        link_targets,
+    })
+}
+
+/// The second pass, the streamed variant of `joplin_file_io::write_joplin_files_until_cancelled`:
+/// reads, builds, links and writes the notes of the outline one at a time, and returns how many
+/// it wrote before the conversion was cancelled. The resources over the limits are marked in
+/// each note as `mark_left_out_resources` does. The notes of the outline are given what linking
+/// and marking them found
+pub fn write_joplin_files_until_cancelled<P: AsRef<Path>>(
+    source_dir: P,
+    target_dir: P,
+    outline: &mut Outline,
+    over_limits: &[Warning],
+    config: &Config,
+) -> Result<usize, String> {
+    let relative_paths = target_paths(&outline.joplin_files, base_len(&target_dir), config)?;
+    let mut source = NoteSource::open(&source_dir)?;
+    let hooks = Hooks::load(config)?;
+    let mut writer = NoteWriter::open(&target_dir, config)?;
+
+    let outlined = outline.joplin_files.iter_mut().zip(relative_paths);
+    for (done, (outlined, relative_path)) in outlined.enumerate() {
+        if config.cancellation.is_cancelled() {
+            return Ok(done);
+        }
+        let source_path = &outlined.relative_path;
+        let Some(bytes) = config
+            .timings
+            .time_note(Phase::Parse, Some(source_path), || {
+                read_note(&mut source, source_path, config)
+            })
+        else {
+            continue;
+        };
+        let Some(mut joplin_file) = rebuild_note(source_path, bytes, &source, &hooks, config)?
+        else {
+            continue;
+        };
+
+        outline.link_targets.link(&mut joplin_file, config);
+        mark_left_out_resources(std::slice::from_mut(&mut joplin_file), over_limits, config);
+        writer.write(&joplin_file, &relative_path, config)?;
+        joplin_file.body = String::new();
+        *outlined = joplin_file;
+    }
+
+    writer.finish()?;
+    Ok(outline.joplin_files.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TagPlacement;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn read_notes(dir: &Path) -> Vec<(PathBuf, String)> {
+        let dir = dir.canonicalize().unwrap();
+        crate::joplin_file_io::find_files(&dir, &Config::default())
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                let content = fs::read_to_string(&path).unwrap();
+                (path.strip_prefix(&dir).unwrap().to_path_buf(), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_joplin_files_streamed() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_streaming_test");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let source_dir = dir.join("source");
+        let notes = [
+            (
+                "Notebook/a.md",
+                "[b](b.md#test) [c](../Other/C%3F.md#test) [gone](missing.md)\n\n## Test",
+            ),
+            ("Notebook/b.md", "## Test\n\n## Other"),
+            ("Other/C?.md", "## Test"),
+            ("Other/d.md", "## Test"),
+        ];
+        for (relative_path, body) in notes {
+            let path = source_dir.join(relative_path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let content = format!(
+                "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n\
+                ---\n{body}"
+            );
+            fs::write(path, content).unwrap();
+        }
+        let config = Config {
+            tag_placement: TagPlacement::Top,
+            ..Config::default()
+        };
+        let held = crate::joplin_file_io::build_joplin_files(&source_dir, &config).unwrap();
+        crate::joplin_file_io::write_joplin_files(dir.join("held"), &held, &config).unwrap();
+
+        // act
+        let mut outline = build_joplin_files(&source_dir, &config).unwrap();
+        let written = write_joplin_files_until_cancelled(
+            &source_dir,
+            &dir.join("streamed"),
+            &mut outline,
+            &[],
+            &config,
+        );
+
+        // assert
+        let streamed_notes = read_notes(&dir.join("streamed"));
+        let held_notes = read_notes(&dir.join("held"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, Ok(4));
+        assert_eq!(streamed_notes, held_notes);
+        assert!(streamed_notes[0].1.contains("[c](../Other/C_.md#test-1)"));
+        assert!(
+            outline
+                .joplin_files
+                .iter()
+                .all(|joplin_file| joplin_file.body.is_empty())
+        );
+        let warnings: Vec<&Vec<Warning>> = outline
+            .joplin_files
+            .iter()
+            .map(|joplin_file| &joplin_file.warnings)
+            .collect();
+        let held_warnings: Vec<&Vec<Warning>> = held
+            .iter()
+            .map(|joplin_file| &joplin_file.warnings)
+            .collect();
+        assert_eq!(warnings, held_warnings);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::JoplinFile;
+use crate::html_export::markdown_options;
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use std::path::PathBuf;
+
+/// What's left to do in a note: its open `- [ ]` tasks and when it's due, for Joplin to-dos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteTasks {
+    pub title: String,
+    pub relative_path: PathBuf,
+    pub due: Option<DateTime<Utc>>,
+    /// The text of the open tasks, in order
+    pub open: Vec<String>,
+}
+
+impl NoteTasks {
+    /// The tasks of a note, `None` when it has nothing to do
+    pub fn find(joplin_file: &JoplinFile) -> Option<Self> {
+        let due = joplin_file.due();
+        let open = find_open_tasks(&joplin_file.body);
+        if due.is_none() && open.is_empty() {
+            return None;
+        }
+
+        Some(NoteTasks {
+            title: joplin_file.title.clone(),
+            relative_path: joplin_file.relative_path.clone(),
+            due,
+            open,
+        })
+    }
+}
+
+/// The tasks of the notes that have something to do, the ones due first by their due date,
+/// then the others by title
+pub fn summarize_tasks(joplin_files: &[JoplinFile]) -> Vec<NoteTasks> {
+    let mut tasks: Vec<NoteTasks> = joplin_files.iter().filter_map(NoteTasks::find).collect();
+    tasks.sort_by(|a, b| {
+        // `None` would sort first, the notes without a due date go last instead
+        (a.due.is_none(), a.due, &a.title, &a.relative_path).cmp(&(
+            b.due.is_none(),
+            b.due,
+            &b.title,
+            &b.relative_path,
+        ))
+    });
+    tasks
+}
+
+/// The text of the unchecked task list items of a body, code blocks aside. The text of a task
+/// ends where a list nested in it starts.
+pub fn find_open_tasks(body: &str) -> Vec<String> {
+    let mut tasks = Vec::new();
+    let mut current: Option<String> = None;
+
+    for event in Parser::new_ext(body, markdown_options()) {
+        match event {
+            Event::TaskListMarker(false) => current = Some(String::new()),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(task) = &mut current {
+                    task.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(task) = &mut current {
+                    task.push(' ');
+                }
+            }
+            Event::Start(Tag::List(_)) | Event::End(TagEnd::Item) => {
+                if let Some(task) = current.take() {
+                    tasks.push(task.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn note(relative_path: &str, front_matter: &str, body: &str) -> JoplinFile {
+        let content = format!(
+            "---\ntitle: {relative_path}\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n{front_matter}---\n{body}"
+        );
+        JoplinFile::build(relative_path, &content, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_find_open_tasks() {
+        let test_cases = vec![
+            (
+                "- [ ] Buy `milk`\n- [x] Done\n- [ ] Call\n  Bob",
+                vec!["Buy milk", "Call Bob"],
+            ),
+            (
+                "- [ ] Plan\n  - [ ] Book *hotel*",
+                vec!["Plan", "Book hotel"],
+            ),
+            ("```\n- [ ] in code\n```\n- not a task", vec![]),
+        ];
+
+        for (body, expected) in test_cases {
+            assert_eq!(find_open_tasks(body), expected, "{:?}", body);
+        }
+    }
+
+    #[test]
+    fn test_summarize_tasks() {
+        let joplin_files = vec![
+            note("b.md", "", "- [ ] Open"),
+            note("none.md", "", "- [x] Done"),
+            note(
+                "late.md",
+                "due: 2024-06-01T10:00:00.000Z\ncompleted?: no\n",
+                "",
+            ),
+            note(
+                "done.md",
+                "due: 2024-05-01T10:00:00.000Z\ncompleted?: yes\n",
+                "",
+            ),
+            note("a.md", "", "- [ ] Open"),
+            note("soon.md", "due: 2024-05-01T10:00:00.000Z\n", "- [ ] Open"),
+        ];
+
+        let tasks = summarize_tasks(&joplin_files);
+
+        let titles: Vec<&str> = tasks.iter().map(|tasks| tasks.title.as_str()).collect();
+        assert_eq!(titles, vec!["soon.md", "late.md", "a.md", "b.md"]);
+        assert_eq!(tasks[0].open, vec!["Open"]);
+        assert!(tasks[1].open.is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::JoplinFile;
+use minijinja::{Environment, context};
+use std::collections::BTreeMap;
+
+/// Checks the syntax of a note template, so a mistake stops the conversion before any note is
+/// written
+pub fn check(template: &str) -> Result<(), String> {
+    Environment::new()
+        .template_from_str(template)
+        .map(|_| ())
+        .map_err(|e| format!("Error in the note template: {:#}", e))
+}
+
+/// The content of the Bear note laid out by a Jinja template, which gets the `title`, the
+/// `body`, the `tags` line, the `created` and `updated` dates as RFC 3339, the note `id`, its
+/// `path` in the export and the `metadata` of its front matter by key. The content ends with a
+/// newline.
+pub fn render_note(template: &str, joplin_file: &JoplinFile, body: &str) -> Result<String, String> {
+    let context = context! {
+        title => joplin_file.title,
+        body => body,
+        tags => joplin_file.tags.as_deref().unwrap_or_default(),
+        created => joplin_file.created.to_rfc3339(),
+        updated => joplin_file.updated.to_rfc3339(),
+        id => joplin_file.id.as_deref().unwrap_or_default(),
+        path => joplin_file.relative_path.to_string_lossy(),
+        metadata => front_matter_values(&joplin_file.front_matter),
+    };
+
+    let mut content = Environment::new()
+        .render_str(template, context)
+        .map_err(|e| format!("Error in the note template: {:#}", e))?;
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    Ok(content)
+}
+
+/// The `key: value` lines at the top level of the front matter, lists and nested values aside
+fn front_matter_values(front_matter: &str) -> BTreeMap<&str, &str> {
+    front_matter
+        .lines()
+        .filter(|line| !line.starts_with([' ', '\t', '-']))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_render_note() {
+        let content = "---\ntitle: Trip\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\nauthor: Ann\ntags:\n  - travel\n---\nPack";
+        let joplin_file = JoplinFile::build("Plans/Trip.md", content, &Config::default()).unwrap();
+        let test_cases = vec![
+            (
+                "# {{ title }}\n{{ tags }}\n\n{{ body }}",
+                Ok("# Trip\n#Plans #travel\n\nPack\n"),
+            ),
+            (
+                "{{ body }}\n\nBy {{ metadata.author }} on {{ created[:10] }} in {{ path }}{{ metadata.tags }}\n",
+                Ok("Pack\n\nBy Ann on 2024-03-07 in Plans/Trip.md\n"),
+            ),
+            ("{{ body | nope }}", Err("Error in the note template")),
+        ];
+
+        for (template, expected) in test_cases {
+            let result = render_note(template, &joplin_file, &joplin_file.body);
+
+            match expected {
+                Ok(expected) => assert_eq!(result.as_deref(), Ok(expected), "{}", template),
+                Err(e) => assert!(result.unwrap_err().starts_with(e), "{}", template),
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use serde::Serialize;
+
+/// The reading speed the reading time is estimated at
+const WORDS_PER_MINUTE: usize = 200;
+
+/// How long a note is, counted from its body as it was read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TextStats {
+    pub word_count: usize,
+    /// In Unicode code points, whitespace included
+    pub character_count: usize,
+    /// Rounded up to the next minute, so only empty notes take none
+    pub reading_minutes: usize,
+}
+
+impl TextStats {
+    pub fn count(body: &str) -> Self {
+        let word_count = body.split_whitespace().count();
+        TextStats {
+            word_count,
+            character_count: body.chars().count(),
+            reading_minutes: word_count.div_ceil(WORDS_PER_MINUTE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let long = "word ".repeat(201);
+        let test_cases: Vec<(&str, (usize, usize, usize))> = vec![
+            ("", (0, 0, 0)),
+            ("Three words here", (3, 16, 1)),
+            ("Smörgåsbord\n\nmed  räkor", (3, 23, 1)),
+            (&long, (201, 1005, 2)),
+        ];
+
+        for (body, (word_count, character_count, reading_minutes)) in test_cases {
+            let expected = TextStats {
+                word_count,
+                character_count,
+                reading_minutes,
+            };
+            assert_eq!(TextStats::count(body), expected);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::config::LineBreaks;
+use crate::html_export::markdown_options;
+use pulldown_cmark::{Event, Parser, Tag};
+use std::ops::Range;
+
+/// Two or more spaces at the end of a line break it in markdown
+const HARD_BREAK: &str = "  ";
+const FENCES: [&str; 2] = ["```", "~~~"];
+
+/// The body with trailing whitespace trimmed from every line, runs of three or more blank lines
+/// squeezed into one and no blank lines at either end, so the notes diff cleanly. Lines ending
+/// in a hard break keep it as two spaces, and code blocks, fenced or indented, are left as they
+/// are.
+pub fn tidy_whitespace(body: &str) -> String {
+    let code_blocks = code_blocks(body);
+    let mut tidied = String::with_capacity(body.len());
+    let mut blank_run = 0;
+    let mut start = 0;
+
+    for line in body.split_inclusive('\n') {
+        let span = start..start + line.len();
+        start = span.end;
+        let line = line.trim_end_matches(['\n', '\r']);
+        let in_code = in_code(&code_blocks, &span);
+        let trimmed = line.trim_end();
+
+        if trimmed.is_empty() && !in_code {
+            blank_run += 1;
+            continue;
+        }
+        if !tidied.is_empty() {
+            let blank_lines = if blank_run >= 3 { 1 } else { blank_run };
+            tidied.push_str(&"\n".repeat(blank_lines));
+        }
+        blank_run = 0;
+
+        if in_code {
+            tidied.push_str(line);
+        } else {
+            tidied.push_str(trimmed);
+            if line.ends_with(HARD_BREAK) {
+                tidied.push_str(HARD_BREAK);
+            }
+        }
+        tidied.push('\n');
+    }
+
+    tidied.truncate(tidied.trim_end_matches('\n').len());
+    tidied
+}
+
+/// Where the code blocks of the body are, fenced or indented, as markdown parses them: an
+/// indented line continuing a list item isn't code
+fn code_blocks(body: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(body, markdown_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| {
+            matches!(event, Event::Start(Tag::CodeBlock(_))).then_some(range)
+        })
+        .collect()
+}
+
+fn in_code(code_blocks: &[Range<usize>], span: &Range<usize>) -> bool {
+    code_blocks
+        .iter()
+        .any(|code_block| code_block.start < span.end && span.start < code_block.end)
+}
+
+/// The body with the line breaks inside its paragraphs written the given way, `None` to keep
+/// them. Code blocks are left as they are, and a line only breaks when text follows it in the
+/// same paragraph: a break at the end of a paragraph is either ignored or a stray backslash.
+pub fn convert_line_breaks(body: &str, line_breaks: LineBreaks) -> Option<String> {
+    if line_breaks == LineBreaks::Keep {
+        return None;
+    }
+
+    let code_blocks = code_blocks(body);
+    let mut start = 0;
+    let lines: Vec<(&str, Range<usize>)> = body
+        .split_inclusive('\n')
+        .map(|line| {
+            let span = start..start + line.len();
+            start = span.end;
+            (line.trim_end_matches(['\n', '\r']), span)
+        })
+        .collect();
+    let mut converted: Vec<String> = Vec::with_capacity(lines.len());
+    for (i, (line, span)) in lines.iter().enumerate() {
+        let in_code = in_code(&code_blocks, span);
+        let trimmed = line.trim_end();
+        let breaks = match line_breaks {
+            LineBreaks::Keep => false,
+            LineBreaks::Backslash => line.ends_with(HARD_BREAK),
+            LineBreaks::Hard => !trimmed.ends_with('\\'),
+        };
+        let continued = lines
+            .get(i + 1)
+            .is_some_and(|(next, _)| continues_paragraph(next));
+        if in_code || !breaks || !continued || is_single_line_block(trimmed) {
+            converted.push(line.to_string());
+        } else {
+            converted.push(format!("{trimmed}\\"));
+        }
+    }
+
+    let mut converted = converted.join("\n");
+    if body.ends_with('\n') {
+        converted.push('\n');
+    }
+    Some(converted)
+}
+
+/// Whether a line following a paragraph line is more of the paragraph
+fn continues_paragraph(line: &str) -> bool {
+    !line.trim().is_empty() && !starts_block(line)
+}
+
+/// Whether a line is a heading, a list item, a quote, a table row, a fence or a thematic break,
+/// which end the paragraph before them
+fn starts_block(line: &str) -> bool {
+    let line = line.trim_start();
+    let is_bullet = ["- ", "* ", "+ "]
+        .iter()
+        .any(|marker| line.starts_with(marker));
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let is_numbered = digits > 0
+        && [". ", ") "]
+            .iter()
+            .any(|marker| line[digits..].starts_with(marker));
+
+    is_single_line_block(line)
+        || is_bullet
+        || is_numbered
+        || line.starts_with('>')
+        || FENCES.iter().any(|fence| line.starts_with(fence))
+}
+
+/// Whether a line is a heading, a table row or a thematic break, which a break can't continue
+fn is_single_line_block(line: &str) -> bool {
+    let line = line.trim_start();
+    let is_heading = line.starts_with('#') && {
+        let rest = line.trim_start_matches('#');
+        rest.is_empty() || rest.starts_with(' ')
+    };
+    let is_thematic = line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|c| line.chars().all(|found| found == *c || found == ' '));
+
+    is_heading || is_thematic || line.starts_with('|')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tidy_whitespace() {
+        let test_cases = vec![
+            ("a \t\nb\n", "a\nb"),
+            ("\n\na\n\n\n\nb\n\n\nc\n\nd\n\n\n", "a\n\nb\n\n\nc\n\nd"),
+            ("a   \nb\r\nc", "a  \nb\nc"),
+            (
+                "```\ncode  \n\n\n\nmore\n```\n\n\n\nafter",
+                "```\ncode  \n\n\n\nmore\n```\n\nafter",
+            ),
+            (
+                "Text\n\n    code  \n\n\n\n    more \n\n\n\nafter ",
+                "Text\n\n    code  \n\n\n\n    more \n\nafter",
+            ),
+        ];
+
+        for (body, expected) in test_cases {
+            assert_eq!(tidy_whitespace(body), expected, "{:?}", body);
+        }
+    }
+
+    #[test]
+    fn test_convert_line_breaks() {
+        let body = "one  \ntwo\nthree  \n\n# Head\nfour\n- item\n- item  \n  more\n\n```\ncode  \ncode\n```\n";
+        let test_cases = vec![
+            (LineBreaks::Keep, None),
+            (
+                LineBreaks::Backslash,
+                Some(
+                    "one\\\ntwo\nthree  \n\n# Head\nfour\n- item\n- item\\\n  more\n\n```\ncode  \ncode\n```\n",
+                ),
+            ),
+            (
+                LineBreaks::Hard,
+                Some(
+                    "one\\\ntwo\\\nthree  \n\n# Head\nfour\n- item\n- item\\\n  more\n\n```\ncode  \ncode\n```\n",
+                ),
+            ),
+        ];
+
+        for (line_breaks, expected) in test_cases {
+            assert_eq!(
+                convert_line_breaks(body, line_breaks).as_deref(),
+                expected,
+                "{:?}",
+                line_breaks
+            );
+        }
+    }
+
+    #[test]
+    fn test_convert_line_breaks_indented() {
+        let test_cases = vec![
+            ("- item  \n    more\n", "- item\\\n    more\n"),
+            (
+                "1. one  \n    two  \n    three",
+                "1. one\\\n    two\\\n    three",
+            ),
+            (
+                "Text\n\n    code  \n    more\n",
+                "Text\n\n    code  \n    more\n",
+            ),
+            ("Text\n\n\tcode  \n\tmore\n", "Text\n\n\tcode  \n\tmore\n"),
+        ];
+
+        for (body, expected) in test_cases {
+            assert_eq!(
+                convert_line_breaks(body, LineBreaks::Backslash).as_deref(),
+                Some(expected),
+                "{:?}",
+                body
+            );
+        }
+    }
+}
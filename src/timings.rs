@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of slowest notes the report lists
+const OUTLIERS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Finding the notes in the source
+    Discovery,
+    /// Reading, decoding and parsing the notes
+    Parse,
+    /// The script, filter, fidelity check and the conflict and duplicate policies
+    Transform,
+    /// Writing the notes to the target
+    Write,
+    /// Copying the resources to the target
+    ResourceCopy,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Discovery,
+        Phase::Parse,
+        Phase::Transform,
+        Phase::Write,
+        Phase::ResourceCopy,
+    ];
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Discovery => write!(f, "discovery"),
+            Phase::Parse => write!(f, "parse"),
+            Phase::Transform => write!(f, "transform"),
+            Phase::Write => write!(f, "write"),
+            Phase::ResourceCopy => write!(f, "resource copy"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    phases: HashMap<Phase, Duration>,
+    notes: HashMap<PathBuf, Duration>,
+}
+
+/// How long each phase of the conversion took, and each note across the phases. Disabled by
+/// default, timing then costs nothing; clones share what was recorded.
+#[derive(Debug, Clone, Default)]
+pub struct Timings(Option<Arc<Mutex<Recorded>>>);
+
+impl Timings {
+    pub fn enabled() -> Timings {
+        Timings(Some(Arc::default()))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Runs `f`, adding the time it took to `phase`
+    pub fn time<T>(&self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        self.time_note(phase, None, f)
+    }
+
+    /// Runs `f`, adding the time it took to `phase` and, if given, to the note
+    pub fn time_note<T>(
+        &self,
+        phase: Phase,
+        relative_path: Option<&Path>,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let Some(recorded) = &self.0 else {
+            return f();
+        };
+
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+
+        let mut recorded = recorded.lock().unwrap_or_else(|e| e.into_inner());
+        *recorded.phases.entry(phase).or_default() += elapsed;
+        if let Some(relative_path) = relative_path {
+            *recorded
+                .notes
+                .entry(relative_path.to_path_buf())
+                .or_default() += elapsed;
+        }
+        result
+    }
+
+    pub fn phase(&self, phase: Phase) -> Duration {
+        self.0
+            .as_ref()
+            .and_then(|recorded| {
+                let recorded = recorded.lock().unwrap_or_else(|e| e.into_inner());
+                recorded.phases.get(&phase).copied()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The slowest notes, slowest first
+    pub fn outliers(&self, count: usize) -> Vec<(PathBuf, Duration)> {
+        let Some(recorded) = &self.0 else {
+            return Vec::new();
+        };
+
+        let recorded = recorded.lock().unwrap_or_else(|e| e.into_inner());
+        let mut notes: Vec<(PathBuf, Duration)> = recorded
+            .notes
+            .iter()
+            .map(|(relative_path, duration)| (relative_path.clone(), *duration))
+            .collect();
+        notes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        notes.truncate(count);
+        notes
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: Duration = Phase::ALL.iter().map(|phase| self.phase(*phase)).sum();
+        for phase in Phase::ALL {
+            let duration = self.phase(phase);
+            writeln!(
+                f,
+                "{:<14} {:>10.1}ms {:>5.1}%",
+                phase.to_string(),
+                millis(duration),
+                100.0 * duration.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON)
+            )?;
+        }
+
+        let outliers = self.outliers(OUTLIERS);
+        if !outliers.is_empty() {
+            writeln!(f, "Slowest notes:")?;
+        }
+        for (relative_path, duration) in outliers {
+            writeln!(
+                f,
+                "{:>10.1}ms {}",
+                millis(duration),
+                relative_path.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_note() {
+        // arrange
+        let timings = Timings::enabled();
+        let clone = timings.clone();
+        let sleep = |millis| std::thread::sleep(Duration::from_millis(millis));
+
+        // act
+        clone.time_note(Phase::Parse, Some(Path::new("a.md")), || sleep(2));
+        clone.time_note(Phase::Write, Some(Path::new("a.md")), || sleep(2));
+        clone.time_note(Phase::Parse, Some(Path::new("b.md")), || sleep(1));
+        let value = clone.time(Phase::Discovery, || 42);
+
+        // assert
+        assert_eq!(value, 42);
+        assert!(timings.phase(Phase::Parse) >= Duration::from_millis(3));
+        assert_eq!(timings.phase(Phase::ResourceCopy), Duration::ZERO);
+        let outliers: Vec<PathBuf> = timings
+            .outliers(1)
+            .into_iter()
+            .map(|(relative_path, _)| relative_path)
+            .collect();
+        assert_eq!(outliers, vec![PathBuf::from("a.md")]);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let timings = Timings::default();
+
+        assert_eq!(timings.time(Phase::Parse, || 42), 42);
+        assert_eq!(timings.phase(Phase::Parse), Duration::ZERO);
+        assert!(timings.outliers(OUTLIERS).is_empty());
+    }
+}
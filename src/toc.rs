@@ -0,0 +1,99 @@
+use crate::config::TocMarker;
+use crate::headings::find_headings;
+
+const MARKER: &str = "[toc]";
+
+/// The body with its `[toc]` markers, lines of their own outside code blocks, removed or
+/// replaced with a list of links to the headings. `None` when there's nothing to change.
+pub fn replace_toc_markers(body: &str, toc_marker: TocMarker) -> Option<String> {
+    if toc_marker == TocMarker::Keep || !body.to_lowercase().contains(MARKER) {
+        return None;
+    }
+
+    let toc = match toc_marker {
+        TocMarker::Expand => build_toc(body),
+        _ => String::new(),
+    };
+
+    let mut replaced = String::with_capacity(body.len() + toc.len());
+    let mut in_fence: Option<&str> = None;
+    let mut found = false;
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let fence = ["```", "~~~"]
+            .into_iter()
+            .find(|fence| trimmed.starts_with(fence));
+
+        match (in_fence, fence) {
+            (None, Some(fence)) => in_fence = Some(fence),
+            (Some(open), Some(fence)) if open == fence => in_fence = None,
+            (None, None) if trimmed.eq_ignore_ascii_case(MARKER) => {
+                found = true;
+                replaced.push_str(&toc);
+                continue;
+            }
+            _ => {}
+        }
+        replaced.push_str(line);
+    }
+
+    // The body of a note has no surrounding whitespace, the marker may have been at either end
+    found.then(|| replaced.trim().to_string())
+}
+
+/// A nested list of links to the headings of the body, indented from the highest level up
+fn build_toc(body: &str) -> String {
+    let headings = find_headings(body);
+    let top_level = headings
+        .iter()
+        .map(|heading| heading.level)
+        .min()
+        .unwrap_or_default();
+
+    headings
+        .iter()
+        .map(|heading| {
+            format!(
+                "{}- [{}](#{})\n",
+                "  ".repeat(heading.level - top_level),
+                heading.text.replace('[', "\\[").replace(']', "\\]"),
+                heading.slug
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_toc_markers() {
+        let body = "[TOC]\n\n# Intro\n\n## Why [this]\n\n```\n[toc]\n# code\n```\n\n# End";
+        let test_cases = vec![
+            (TocMarker::Keep, None),
+            (
+                TocMarker::Strip,
+                Some("# Intro\n\n## Why [this]\n\n```\n[toc]\n# code\n```\n\n# End"),
+            ),
+            (
+                TocMarker::Expand,
+                Some(
+                    "- [Intro](#intro)\n  - [Why \\[this\\]](#why-this)\n- [End](#end)\n\n\
+                    # Intro\n\n## Why [this]\n\n```\n[toc]\n# code\n```\n\n# End",
+                ),
+            ),
+        ];
+
+        for (toc_marker, expected) in test_cases {
+            assert_eq!(
+                replace_toc_markers(body, toc_marker).as_deref(),
+                expected,
+                "{:?}",
+                toc_marker
+            );
+        }
+
+        assert_eq!(replace_toc_markers("No [toc] here", TocMarker::Strip), None);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::joplin_file_io::{build_content, long_path, target_paths};
+use crate::resource_ref::{ResourceTarget, find_resource_refs, percent_decode};
+use crate::{Config, JoplinFile};
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// The target has no note at the path the note would be written to
+    Missing,
+    /// A resource the target note links to isn't there, as written in the note
+    UnresolvedResource(String),
+    /// The modification time of the target note isn't the updated time of the note
+    Timestamp {
+        expected: DateTime<Utc>,
+        found: DateTime<Utc>,
+    },
+    /// The target note isn't what converting the note gives
+    Content,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing in the target"),
+            Problem::UnresolvedResource(resource) => {
+                write!(f, "the resource {} is missing", resource)
+            }
+            Problem::Timestamp { expected, found } => write!(
+                f,
+                "modified {} instead of {}",
+                found.format("%Y-%m-%d %H:%M:%S%.3f"),
+                expected.format("%Y-%m-%d %H:%M:%S%.3f")
+            ),
+            Problem::Content => write!(f, "the content differs from the source note"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Verification {
+    /// Relative to the target
+    pub relative_path: PathBuf,
+    pub title: String,
+    /// Empty when the note passed
+    pub problems: Vec<Problem>,
+}
+
+/// Checks a completed conversion: every note has its target note, with the content converting
+/// it gives, the updated time as its modification time and the resources it links to in place
+pub fn verify<P: AsRef<Path>>(
+    target_dir: P,
+    joplin_files: &[JoplinFile],
+    config: &Config,
+) -> Result<Vec<Verification>, String> {
+    let target_dir = target_dir.as_ref();
+    let base_len = target_dir.to_string_lossy().chars().count() + 1;
+    let relative_paths = target_paths(joplin_files, base_len, config)?;
+
+    let verifications = joplin_files
+        .iter()
+        .zip(relative_paths)
+        .map(|(joplin_file, relative_path)| {
+            let target_path = long_path(&target_dir.join(&relative_path));
+            Verification {
+                problems: verify_note(&target_path, joplin_file, config),
+                relative_path,
+                title: joplin_file.title.clone(),
+            }
+        })
+        .collect();
+
+    Ok(verifications)
+}
+
+fn verify_note(target_path: &Path, joplin_file: &JoplinFile, config: &Config) -> Vec<Problem> {
+    let Ok(content) = std::fs::read_to_string(target_path) else {
+        return vec![Problem::Missing];
+    };
+
+    let note_dir = target_path.parent().unwrap_or(Path::new(""));
+    let mut problems: Vec<Problem> = find_resource_refs(&content)
+        .into_iter()
+        .filter_map(|resource_ref| match resource_ref.target {
+            ResourceTarget::Path(path) if !note_dir.join(percent_decode(&path)).exists() => {
+                Some(Problem::UnresolvedResource(path))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let modified = std::fs::metadata(target_path)
+        .and_then(|metadata| metadata.modified())
+        .map(DateTime::<Utc>::from);
+    if let Ok(found) = modified
+        && found.timestamp_millis() != joplin_file.updated.timestamp_millis()
+    {
+        problems.push(Problem::Timestamp {
+            expected: joplin_file.updated,
+            found,
+        });
+    }
+
+    if content != build_content(joplin_file, config) {
+        problems.push(Problem::Content);
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joplin_file_io::write_joplin_files;
+    use std::fs;
+
+    #[test]
+    fn test_verify() {
+        // arrange
+        let target_dir = std::env::temp_dir().join("jb_verify_test");
+        if target_dir.exists() {
+            fs::remove_dir_all(&target_dir).unwrap();
+        }
+        let config = Config::default();
+        let joplin_files: Vec<JoplinFile> = [
+            ("ok.md", "![](../_resources/a.png)"),
+            ("no_resource.md", "![](../_resources/b.png)"),
+            ("edited.md", "Body"),
+            ("missing.md", "Body"),
+        ]
+        .iter()
+        .map(|(relative_path, body)| {
+            JoplinFile::build(
+                relative_path,
+                &format!(
+                    "---\ntitle: Test\ncreated: 2024-03-07T23:22:26Z\nupdated: 2024-04-07T08:34:52Z\n---\n{body}"
+                ),
+                &config,
+            )
+            .unwrap()
+        })
+        .collect();
+        let notes_dir = target_dir.join("notes");
+        write_joplin_files(&notes_dir, &joplin_files[..3], &config).unwrap();
+        fs::create_dir_all(target_dir.join("_resources")).unwrap();
+        fs::write(target_dir.join("_resources/a.png"), b"png").unwrap();
+        fs::write(notes_dir.join("edited.md"), "Edited").unwrap();
+
+        // act
+        let verifications = verify(&notes_dir, &joplin_files, &config).unwrap();
+
+        // assert
+        let edited_modified = DateTime::<Utc>::from(
+            fs::metadata(notes_dir.join("edited.md"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+        );
+        fs::remove_dir_all(&target_dir).unwrap();
+        let problems: Vec<&[Problem]> = verifications
+            .iter()
+            .map(|verification| verification.problems.as_slice())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                &[][..],
+                &[Problem::UnresolvedResource(
+                    "../_resources/b.png".to_string()
+                )][..],
+                &[
+                    Problem::Timestamp {
+                        expected: joplin_files[2].updated,
+                        found: edited_modified,
+                    },
+                    Problem::Content,
+                ][..],
+                &[Problem::Missing][..],
+            ]
+        );
+    }
+}
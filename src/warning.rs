@@ -0,0 +1,131 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Something about a note that was worked around rather than stopping the conversion. Unlike
+/// an error, the note is still converted; the warnings are shown as the notes are read and are
+/// part of the JSON output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The note was transcoded to UTF-8 from the named encoding
+    Transcoded(&'static str),
+    /// The path of the note isn't valid UTF-8, the tags built from it use replacement characters
+    LossyPath,
+    /// The front matter has no title, the file name is used instead
+    TitleFromFileName,
+    /// A front matter tag had spaces, which would end a Bear tag, they were replaced
+    TagSanitized { tag: String, sanitized: String },
+    /// A date of the front matter has no UTC offset, it's taken to be in UTC. Holds the key
+    DateAssumedUtc(&'static str),
+    /// The note was updated before it was created, old sync bugs left some notes like that.
+    /// Whether the updated date was set to the created one
+    UpdatedBeforeCreated { repaired: bool },
+    /// Bear can't preview these attachments of the note, given by file name
+    UnsupportedAttachments(Vec<String>),
+    /// These links to other notes, as written in the note, lead to no note of the export
+    DanglingNoteLinks(Vec<String>),
+    /// The Wayback Machine couldn't be asked for snapshots of these web pages the note links to
+    UnarchivedLinks(Vec<String>),
+    /// No note links to this resource, relative to the resources directory. It's copied anyway
+    OrphanedResource(PathBuf),
+    /// The export has no resources directory, though notes link to resources
+    NoResourcesDir,
+    /// The resource, relative to the resources directory, is over the resource limits. Whether
+    /// it's linked to in the source rather than left out
+    ResourceOverLimits {
+        resource_path: PathBuf,
+        size: u64,
+        linked: bool,
+    },
+    /// The note links to these resources, as written in the note, which were left out for
+    /// being over the resource limits
+    LeftOutResources(Vec<String>),
+}
+
+impl Warning {
+    /// What kind of warning it is, the summary counts them by it
+    pub fn category(&self) -> &'static str {
+        match self {
+            Warning::Transcoded(_) => "transcoded",
+            Warning::LossyPath => "lossy path",
+            Warning::TitleFromFileName => "title from file name",
+            Warning::TagSanitized { .. } => "tag sanitized",
+            Warning::DateAssumedUtc(_) => "date assumed UTC",
+            Warning::UpdatedBeforeCreated { .. } => "updated before created",
+            Warning::UnsupportedAttachments(_) => "unsupported attachment",
+            Warning::DanglingNoteLinks(_) => "dangling note link",
+            Warning::UnarchivedLinks(_) => "unarchived link",
+            Warning::OrphanedResource(_) => "orphaned resource",
+            Warning::NoResourcesDir => "no resources directory",
+            Warning::ResourceOverLimits { .. } => "resource over limits",
+            Warning::LeftOutResources(_) => "resource left out",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::Transcoded(encoding) => write!(f, "transcoded from {}", encoding),
+            Warning::LossyPath => write!(
+                f,
+                "the path is not valid UTF-8, its tags use replacement characters"
+            ),
+            Warning::TitleFromFileName => {
+                write!(
+                    f,
+                    "there's no title in the front matter, using the file name"
+                )
+            }
+            Warning::TagSanitized { tag, sanitized } => {
+                write!(f, "the tag {:?} became {}", tag, sanitized)
+            }
+            Warning::DateAssumedUtc(key) => {
+                write!(f, "the {} date has no UTC offset, taking it as UTC", key)
+            }
+            Warning::UpdatedBeforeCreated { repaired: false } => {
+                write!(f, "the updated date is before the created date")
+            }
+            Warning::UpdatedBeforeCreated { repaired: true } => write!(
+                f,
+                "the updated date is before the created date, setting it to the created date"
+            ),
+            Warning::UnsupportedAttachments(file_names) => {
+                write!(f, "Bear can't preview {}", file_names.join(", "))
+            }
+            Warning::DanglingNoteLinks(links) => {
+                write!(f, "no note at {}", links.join(", "))
+            }
+            Warning::UnarchivedLinks(urls) => {
+                write!(f, "couldn't look up archived copies of {}", urls.join(", "))
+            }
+            Warning::OrphanedResource(resource_path) => {
+                write!(f, "no note links to the resource {:?}", resource_path)
+            }
+            Warning::NoResourcesDir => write!(
+                f,
+                "the export has no _resources directory, the resources the notes link to are missing"
+            ),
+            Warning::ResourceOverLimits {
+                resource_path,
+                size,
+                linked,
+            } => {
+                let decision = if *linked {
+                    "linked to in the source"
+                } else {
+                    "left out"
+                };
+                write!(
+                    f,
+                    "the resource {:?} of {} bytes is over the limits, {}",
+                    resource_path, size, decision
+                )
+            }
+            Warning::LeftOutResources(resource_paths) => write!(
+                f,
+                "{} over the resource limits and left out",
+                resource_paths.join(", ")
+            ),
+        }
+    }
+}
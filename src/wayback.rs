@@ -0,0 +1,269 @@
+//! Appending Wayback Machine snapshot links to the web links of the notes, for the `wayback`
+//! feature
+
+use crate::html_export::markdown_options;
+use crate::warning::Warning;
+use crate::{Config, JoplinFile};
+use pulldown_cmark::{Event, Parser, Tag};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const AVAILABILITY_API: &str = "https://archive.org/wayback/available";
+const ARCHIVE_HOSTS: [&str; 2] = ["web.archive.org", "archive.org"];
+/// archive.org turns away clients asking much faster than this
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Looks up the latest snapshots of web pages in the Wayback Machine, no more than one a
+/// second, and keeps the answers in a cache file so the next runs don't ask again
+pub struct Wayback {
+    cache_path: PathBuf,
+    /// The snapshot of each page looked up, `None` for the pages archive.org has none of
+    cache: BTreeMap<String, Option<String>>,
+    agent: ureq::Agent,
+    last_request: Option<Instant>,
+}
+
+#[derive(Deserialize)]
+struct Availability {
+    archived_snapshots: ArchivedSnapshots,
+}
+
+#[derive(Deserialize)]
+struct ArchivedSnapshots {
+    closest: Option<Snapshot>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    available: bool,
+    url: String,
+}
+
+impl Wayback {
+    /// Starts from the cache file when there is one
+    pub fn open<P: AsRef<Path>>(cache_path: P) -> Result<Wayback, String> {
+        let cache_path = cache_path.as_ref();
+        let cache = if cache_path.exists() {
+            let content = std::fs::read_to_string(cache_path)
+                .map_err(|e| format!("Error reading file {:?}: {}", cache_path, e))?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Error parsing file {:?}: {}", cache_path, e))?
+        } else {
+            BTreeMap::new()
+        };
+
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .build()
+            .into();
+
+        Ok(Wayback {
+            cache_path: cache_path.to_path_buf(),
+            cache,
+            agent,
+            last_request: None,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(&self.cache)
+            .map_err(|e| format!("Error serializing the Wayback cache: {}", e))?;
+        std::fs::write(&self.cache_path, content)
+            .map_err(|e| format!("Error writing file {:?}: {}", self.cache_path, e))
+    }
+
+    /// The URL of the latest snapshot of the page, `None` when it was never archived. Failed
+    /// lookups aren't cached, the next run asks again
+    pub fn snapshot(&mut self, url: &str) -> Result<Option<String>, String> {
+        if let Some(snapshot) = self.cache.get(url) {
+            return Ok(snapshot.clone());
+        }
+
+        if let Some(elapsed) = self.last_request.map(|last_request| last_request.elapsed())
+            && elapsed < MIN_INTERVAL
+        {
+            thread::sleep(MIN_INTERVAL - elapsed);
+        }
+        self.last_request = Some(Instant::now());
+
+        let body = self
+            .agent
+            .get(AVAILABILITY_API)
+            .query("url", url)
+            .call()
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|e| format!("Error looking up {} in the Wayback Machine: {}", url, e))?;
+        let snapshot = parse_availability(&body).map_err(|e| {
+            format!(
+                "Error parsing the Wayback Machine answer for {}: {}",
+                url, e
+            )
+        })?;
+
+        self.cache.insert(url.to_string(), snapshot.clone());
+        Ok(snapshot)
+    }
+}
+
+/// Appends a list of the Wayback Machine snapshots of the web pages each note links to, so the
+/// references survive the pages going away. Pages that couldn't be looked up are left out with
+/// a warning on the note. Returns how many snapshot links were appended.
+pub fn archive_links(
+    joplin_files: &mut [JoplinFile],
+    wayback: &mut Wayback,
+    config: &Config,
+) -> Result<usize, String> {
+    let total = joplin_files.len();
+    let mut appended = 0;
+    for (done, joplin_file) in joplin_files.iter_mut().enumerate() {
+        config.cancellation.check(done, total, "notes")?;
+
+        let mut snapshots = Vec::new();
+        let mut failed = Vec::new();
+        for url in web_links(&joplin_file.body) {
+            match wayback.snapshot(&url) {
+                Ok(Some(snapshot)) => snapshots.push((url, snapshot)),
+                Ok(None) => {}
+                Err(_) => failed.push(url),
+            }
+        }
+
+        if !failed.is_empty() {
+            joplin_file.warnings.push(Warning::UnarchivedLinks(failed));
+        }
+        if !snapshots.is_empty() {
+            appended += snapshots.len();
+            let body = append_snapshots(&joplin_file.body, &snapshots);
+            joplin_file.set_body(body);
+        }
+    }
+
+    Ok(appended)
+}
+
+/// The http(s) URLs the body links to, in order and once each: the targets of its links and
+/// the bare URLs in its text. Links into the Wayback Machine itself are left out
+fn web_links(body: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut push = |url: &str| {
+        let is_web = url.starts_with("http://") || url.starts_with("https://");
+        if is_web && !is_archive(url) && !urls.iter().any(|known| known == url) {
+            urls.push(url.to_string());
+        }
+    };
+
+    for event in Parser::new_ext(body, markdown_options()) {
+        match event {
+            Event::Start(Tag::Link { dest_url, .. }) => push(&dest_url),
+            Event::Text(text) => text
+                .split_whitespace()
+                .map(|word| {
+                    word.trim_start_matches(['(', '"', '\''])
+                        .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\''])
+                })
+                .for_each(&mut push),
+            _ => {}
+        }
+    }
+
+    urls
+}
+
+fn is_archive(url: &str) -> bool {
+    let host = url
+        .split_once("://")
+        .map(|(_, rest)| rest.split(['/', '?', '#']).next().unwrap_or(rest))
+        .unwrap_or_default();
+    ARCHIVE_HOSTS.contains(&host)
+}
+
+fn append_snapshots(body: &str, snapshots: &[(String, String)]) -> String {
+    let mut body = body.trim_end().to_string();
+    body.push_str("\n\nArchived links:\n\n");
+    for (url, snapshot) in snapshots {
+        body.push_str(&format!("- [{}]({})\n", url, snapshot));
+    }
+    body
+}
+
+/// The snapshot in an answer of the availability API, `None` when there is none
+fn parse_availability(body: &str) -> Result<Option<String>, serde_json::Error> {
+    let availability: Availability = serde_json::from_str(body)?;
+    Ok(availability
+        .archived_snapshots
+        .closest
+        .filter(|snapshot| snapshot.available)
+        .map(|snapshot| snapshot.url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_links() {
+        let test_cases: Vec<(&str, Vec<&str>)> = vec![
+            (
+                "See [the docs](https://example.com/docs) and <http://example.org>.",
+                vec!["https://example.com/docs", "http://example.org"],
+            ),
+            (
+                "Bare https://example.com/a, then (https://example.com/b).",
+                vec!["https://example.com/a", "https://example.com/b"],
+            ),
+            (
+                "[https://example.com](https://example.com) again https://example.com",
+                vec!["https://example.com"],
+            ),
+            (
+                "![](../_resources/a.png) [old](https://web.archive.org/web/2020/https://a.b) \
+                ftp://example.com",
+                vec![],
+            ),
+        ];
+
+        for (body, expected) in test_cases {
+            assert_eq!(web_links(body), expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_append_snapshots() {
+        let snapshots = vec![(
+            "https://example.com".to_string(),
+            "http://web.archive.org/web/20240101000000/https://example.com".to_string(),
+        )];
+
+        let body = append_snapshots("A [link](https://example.com)\n\n", &snapshots);
+
+        assert_eq!(
+            body,
+            "A [link](https://example.com)\n\nArchived links:\n\n\
+            - [https://example.com](http://web.archive.org/web/20240101000000/https://example.com)\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_availability() {
+        let test_cases: Vec<(&str, Option<&str>)> = vec![
+            (
+                r#"{"url": "example.com", "archived_snapshots": {"closest": {"status": "200",
+                "available": true, "url": "http://web.archive.org/web/2024/https://example.com",
+                "timestamp": "20240101000000"}}}"#,
+                Some("http://web.archive.org/web/2024/https://example.com"),
+            ),
+            (
+                r#"{"url": "example.com/gone", "archived_snapshots": {}}"#,
+                None,
+            ),
+        ];
+
+        for (body, expected) in test_cases {
+            assert_eq!(parse_availability(body).unwrap().as_deref(), expected);
+        }
+    }
+}
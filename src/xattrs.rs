@@ -0,0 +1,265 @@
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::ffi::{CString, OsStr};
+use std::io;
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Copies the extended attributes of the source onto the target, replacing those of the same
+/// name. On macOS they hold the Finder tags and comments, and where the file was downloaded
+/// from. A plain copy on macOS keeps them already, the files written anew in place of a
+/// resource don't: scaled down, stripped of their EXIF data, converted to PNG. Also on Linux,
+/// where the copies don't keep them; nothing to copy elsewhere. An attribute the target can't
+/// have, on a filesystem without them or one only the system may set, is left out with a
+/// warning rather than failing the copy.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub fn copy_xattrs(source: &Path, target: &Path) -> io::Result<()> {
+    let source_path = c_path(source.as_os_str())?;
+    let target_path = c_path(target.as_os_str())?;
+    for name in list(&source_path)?.into_iter().filter(is_copied) {
+        let value = get(&source_path, &name)?;
+        match set(&target_path, &name, &value) {
+            Err(e) if is_refused(&e) => crate::warning!(
+                "The extended attribute {:?} of {:?} isn't kept: {}",
+                name,
+                source,
+                e
+            ),
+            result => result?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether an extended attribute is one of the user's, which a copy keeps. On Linux the other
+/// namespaces belong to the system: SELinux labels, ACLs, `trusted.*`
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn is_copied(name: &CString) -> bool {
+    cfg!(target_os = "macos") || name.as_bytes().starts_with(b"user.")
+}
+
+/// Whether setting an extended attribute failed for the attribute rather than the file
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn is_refused(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::Unsupported
+    )
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn copy_xattrs(_source: &Path, _target: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The extended attribute macOS gives downloaded files, Gatekeeper asks before they're opened
+pub const QUARANTINE: &str = "com.apple.quarantine";
+
+/// Removes the quarantine of a file, see `QUARANTINE`, so the resources clipped or downloaded
+/// before don't prompt when opened from Bear. Only macOS quarantines files
+#[cfg(target_os = "macos")]
+pub fn strip_quarantine(path: &Path) -> io::Result<()> {
+    let name = CString::new(QUARANTINE).map_err(io::Error::other)?;
+    remove(&c_path(path.as_os_str())?, &name)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn strip_quarantine(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn c_path(path: &OsStr) -> io::Result<CString> {
+    CString::new(path.as_bytes()).map_err(io::Error::other)
+}
+
+/// The names of the extended attributes of the file
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn list(path: &CString) -> io::Result<Vec<CString>> {
+    let names = read_value(|buffer, size| {
+        // SAFETY: the buffer holds `size` bytes, or is null with a size of 0
+        unsafe {
+            #[cfg(target_os = "macos")]
+            let len = libc::listxattr(path.as_ptr(), buffer.cast(), size, libc::XATTR_NOFOLLOW);
+            #[cfg(target_os = "linux")]
+            let len = libc::llistxattr(path.as_ptr(), buffer.cast(), size);
+            len
+        }
+    })?;
+
+    Ok(names
+        .split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| CString::new(name).ok())
+        .collect())
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn get(path: &CString, name: &CString) -> io::Result<Vec<u8>> {
+    read_value(|buffer, size| {
+        // SAFETY: the buffer holds `size` bytes, or is null with a size of 0
+        unsafe {
+            #[cfg(target_os = "macos")]
+            let len = libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buffer,
+                size,
+                0,
+                libc::XATTR_NOFOLLOW,
+            );
+            #[cfg(target_os = "linux")]
+            let len = libc::lgetxattr(path.as_ptr(), name.as_ptr(), buffer, size);
+            len
+        }
+    })
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn set(path: &CString, name: &CString, value: &[u8]) -> io::Result<()> {
+    // SAFETY: the value holds `value.len()` bytes
+    let result = unsafe {
+        #[cfg(target_os = "macos")]
+        let result = libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+            libc::XATTR_NOFOLLOW,
+        );
+        #[cfg(target_os = "linux")]
+        let result = libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        );
+        result
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Removes an extended attribute of the file, one it doesn't have is removed already. Only
+/// `strip_quarantine` needs it, Linux for the tests
+#[cfg(any(target_os = "macos", all(target_os = "linux", test)))]
+fn remove(path: &CString, name: &CString) -> io::Result<()> {
+    // SAFETY: the path and the name are nul-terminated
+    let result = unsafe {
+        #[cfg(target_os = "macos")]
+        let result = libc::removexattr(path.as_ptr(), name.as_ptr(), libc::XATTR_NOFOLLOW);
+        #[cfg(target_os = "linux")]
+        let result = libc::lremovexattr(path.as_ptr(), name.as_ptr());
+        result
+    };
+    if result == -1 {
+        let e = io::Error::last_os_error();
+        #[cfg(target_os = "macos")]
+        let missing = libc::ENOATTR;
+        #[cfg(target_os = "linux")]
+        let missing = libc::ENODATA;
+        if e.raw_os_error() != Some(missing) {
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a value of unknown length: `read` is called once with no buffer for the length, then
+/// with a buffer that long
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn read_value(read: impl Fn(*mut libc::c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+    let len = read(std::ptr::null_mut(), 0);
+    if len == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut buffer = vec![0u8; len as usize];
+    match read(buffer.as_mut_ptr().cast(), buffer.len()) {
+        -1 => Err(io::Error::last_os_error()),
+        len => {
+            buffer.truncate(len as usize);
+            Ok(buffer)
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_copy_xattrs() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_xattrs_test");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.pdf");
+        let target = dir.join("b.pdf");
+        fs::write(&source, "pdf").unwrap();
+        fs::write(&target, "pdf").unwrap();
+        let source_path = c_path(source.as_os_str()).unwrap();
+        let name = CString::new("user.jb.tags").unwrap();
+        set(&source_path, &name, b"Red\n6").unwrap();
+
+        // act
+        let result = copy_xattrs(&source, &target);
+
+        // assert
+        let target_path = c_path(target.as_os_str()).unwrap();
+        let names = list(&target_path).unwrap();
+        let value = get(&target_path, &name).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(names, vec![name]);
+        assert_eq!(value, b"Red\n6");
+    }
+
+    #[test]
+    fn test_is_copied() {
+        let test_cases = vec![
+            ("user.xdg.tags", true),
+            ("security.selinux", false),
+            ("system.posix_acl_access", false),
+            ("trusted.overlay.opaque", false),
+        ];
+
+        for (name, expected) in test_cases {
+            assert_eq!(
+                is_copied(&CString::new(name).unwrap()),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        // arrange
+        let dir = std::env::temp_dir().join("jb_xattrs_remove_test");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.pdf");
+        fs::write(&file, "pdf").unwrap();
+        let path = c_path(file.as_os_str()).unwrap();
+        let name = CString::new("user.jb.quarantine").unwrap();
+        set(&path, &name, b"0081;65f1c2a0;Safari;").unwrap();
+
+        // act
+        let results = (remove(&path, &name), remove(&path, &name));
+
+        // assert
+        let names = list(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(results.0.is_ok());
+        assert!(results.1.is_ok());
+        assert!(names.is_empty());
+    }
+}